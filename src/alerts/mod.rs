@@ -0,0 +1,519 @@
+//! Threshold alerting on the stored measurements.
+//!
+//! Rules are read from a TOML rules file (see `rules`) and evaluated after every import: a
+//! rule fires an alert when the daily average of its parameter within its scope breaches the
+//! threshold on `duration_days` consecutive days, and the alert resolves on the first later
+//! day that does not breach. Alerts are tracked in the `alerts` table, at most one firing per
+//! rule, and every firing and resolution is sent to the rule's notifiers (see `notifiers`).
+
+mod notifiers;
+mod rules;
+
+pub use notifiers::*;
+pub use rules::*;
+
+use crate::db::Database;
+use crate::error::{AppError, Result};
+use crate::models::{Alert, AlertEvent, AlertState, MeasurementFilter, ScopeDailyValue};
+use chrono::{DateTime, Duration, Utc};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+/// Rules file used when `ALERT_RULES` is not set, if it exists.
+pub const DEFAULT_ALERT_RULES: &str = "alerts.toml";
+
+/// Outcome of evaluating a rule against the latest daily values of its scope.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Evaluation {
+    /// No values in the scope.
+    NoData,
+    /// The latest day does not breach the threshold.
+    Clear(ScopeDailyValue),
+    /// The latest day breaches, but not on `duration_days` consecutive days yet.
+    Pending(ScopeDailyValue),
+    /// The latest `duration_days` days are consecutive and all breach; `first_day` is the
+    /// first of them.
+    Breaching {
+        first_day: DateTime<Utc>,
+        latest: ScopeDailyValue,
+    },
+}
+
+/// Evaluates `rule` against the daily values of its scope, newest first. Only the first
+/// `duration_days` values are considered.
+pub fn evaluate_rule(rule: &AlertRule, values: &[ScopeDailyValue]) -> Evaluation {
+    let Some(latest) = values.first() else {
+        return Evaluation::NoData;
+    };
+    let breaches = |v: &ScopeDailyValue| rule.comparator.breaches(v.value, rule.threshold);
+    if !breaches(latest) {
+        return Evaluation::Clear(latest.clone());
+    }
+
+    let mut first_day = latest.day;
+    let mut run = 1;
+    for pair in values.windows(2).take(rule.duration_days as usize - 1) {
+        let (newer, older) = (&pair[0], &pair[1]);
+        let consecutive = newer.day.date_naive() - older.day.date_naive() == Duration::days(1);
+        if !consecutive || !breaches(older) {
+            break;
+        }
+        first_day = older.day;
+        run += 1;
+    }
+    if run >= rule.duration_days {
+        Evaluation::Breaching {
+            first_day,
+            latest: latest.clone(),
+        }
+    } else {
+        Evaluation::Pending(latest.clone())
+    }
+}
+
+/// What to do with a rule's alert after an evaluation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    None,
+    /// Store and announce a new firing alert.
+    Fire {
+        first_day: DateTime<Utc>,
+        latest: ScopeDailyValue,
+    },
+    /// Record a newer breaching day on the firing alert.
+    Update(ScopeDailyValue),
+    /// Resolve the firing alert on a day that no longer breaches.
+    Resolve(ScopeDailyValue),
+    /// The rule breaches again, but fired less than `cooldown_hours` ago.
+    CoolingDown,
+}
+
+/// Decides the action for `rule` given its evaluation, its firing alert (if any) and when it
+/// last fired.
+pub fn decide(
+    rule: &AlertRule,
+    evaluation: Evaluation,
+    firing: Option<&Alert>,
+    last_fired_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Action {
+    match (firing, evaluation) {
+        (_, Evaluation::NoData) => Action::None,
+        (Some(_), Evaluation::Clear(latest)) => Action::Resolve(latest),
+        (Some(alert), Evaluation::Pending(latest) | Evaluation::Breaching { latest, .. }) => {
+            if latest.day > alert.last_day {
+                Action::Update(latest)
+            } else {
+                Action::None
+            }
+        },
+        (None, Evaluation::Breaching { first_day, latest }) => {
+            let cooldown = Duration::hours(rule.cooldown_hours as i64);
+            match last_fired_at {
+                Some(fired_at) if now - fired_at < cooldown => Action::CoolingDown,
+                _ => Action::Fire { first_day, latest },
+            }
+        },
+        (None, Evaluation::Clear(_) | Evaluation::Pending(_)) => Action::None,
+    }
+}
+
+/// The rules of a rules file with their notifiers.
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl fmt::Debug for AlertEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlertEngine")
+            .field(
+                "rules",
+                &self.rules.iter().map(|r| &r.name).collect::<Vec<_>>(),
+            )
+            .field(
+                "notifiers",
+                &self.notifiers.iter().map(|n| n.name()).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl AlertEngine {
+    /// Sets up the notifiers of a validated rules file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `AppError` if a notifier cannot be set up.
+    pub fn new(config: AlertConfig) -> Result<Self> {
+        let notifiers = config
+            .notifiers
+            .iter()
+            .map(build_notifier)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            rules: config.rules,
+            notifiers,
+        })
+    }
+
+    /// Loads the rules file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `AppError` if the file cannot be read, is invalid, or a notifier cannot be
+    /// set up.
+    pub fn load(path: &Path) -> Result<Self> {
+        let engine = Self::new(AlertConfig::load(path)?)?;
+        info!(
+            "Loaded {} alert rules and {} notifiers from {}",
+            engine.rules.len(),
+            engine.notifiers.len(),
+            path.display()
+        );
+        Ok(engine)
+    }
+
+    /// Loads the rules file named by `ALERT_RULES`, or `alerts.toml` if that variable is not
+    /// set. Returns `None` (alerting disabled) if `ALERT_RULES` is not set and `alerts.toml`
+    /// does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `AppError` if the rules file cannot be loaded (see `load`).
+    pub fn from_env() -> Result<Option<Self>> {
+        let path = match std::env::var("ALERT_RULES") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) if Path::new(DEFAULT_ALERT_RULES).exists() => PathBuf::from(DEFAULT_ALERT_RULES),
+            Err(_) => {
+                info!("No alert rules file; alerting disabled");
+                return Ok(None);
+            },
+        };
+        Self::load(&path).map(Some).map_err(|e| {
+            error!("Failed to load alert rules: {}", e);
+            e
+        })
+    }
+
+    /// Evaluates every rule against the stored measurements, updates the `alerts` table and
+    /// notifies the alerts that fired or resolved, returning them.
+    ///
+    /// Notifier failures are logged and do not stop the evaluation.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if a query fails.
+    pub async fn evaluate(
+        &self,
+        db: &Database,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<AlertEvent>> {
+        let now = Utc::now();
+        let mut events = Vec::new();
+        for rule in &self.rules {
+            let values = db
+                .get_scope_daily_values(
+                    &rule.parameter,
+                    &rule.scope,
+                    rule.duration_days as i64,
+                    filter,
+                )
+                .await?;
+            let evaluation = evaluate_rule(rule, &values);
+            let firing = db.get_firing_alert(&rule.name).await?;
+            let last_fired_at = db.get_last_fired_at(&rule.name).await?;
+
+            let (state, alert) = match decide(rule, evaluation, firing.as_ref(), last_fired_at, now)
+            {
+                Action::None => continue,
+                Action::CoolingDown => {
+                    info!(
+                        "Rule {} breaches again within its cooldown; not firing",
+                        rule.name
+                    );
+                    continue;
+                },
+                Action::Update(latest) => {
+                    if let Some(alert) = &firing {
+                        db.update_firing_alert(alert.id, &latest).await?;
+                    }
+                    continue;
+                },
+                Action::Fire { first_day, latest } => {
+                    let alert = db
+                        .fire_alert(&rule.name, first_day, &latest, rule.threshold, now)
+                        .await?;
+                    (AlertState::Firing, alert)
+                },
+                Action::Resolve(latest) => match &firing {
+                    Some(alert) => (
+                        AlertState::Resolved,
+                        db.resolve_alert(alert.id, &latest, now).await?,
+                    ),
+                    None => continue,
+                },
+            };
+
+            let event = AlertEvent {
+                state,
+                parameter: rule.parameter.clone(),
+                scope: rule.scope.clone(),
+                comparator: rule.comparator.as_str().to_string(),
+                duration_days: rule.duration_days,
+                alert,
+            };
+            info!("{}", event.summary());
+            self.notify(rule, &event).await;
+            events.push(event);
+        }
+        Ok(events)
+    }
+
+    /// Sends `event` to the notifiers of `rule`, logging failures.
+    async fn notify(&self, rule: &AlertRule, event: &AlertEvent) {
+        let notifiers = self
+            .notifiers
+            .iter()
+            .filter(|n| rule.notify.is_empty() || rule.notify.iter().any(|name| name == n.name()));
+        for notifier in notifiers {
+            if let Err(e) = notifier.notify(event).await {
+                error!(
+                    "Notifier {} failed to deliver alert {} of rule {}: {}",
+                    notifier.name(),
+                    event.alert.id,
+                    rule.name,
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Evaluates the alert rules after an import, if alerting is enabled.
+///
+/// # Errors
+///
+/// Returns `AppError::Alert` wrapping the evaluation failure, so imports can report it
+/// without failing.
+pub async fn evaluate_after_import(
+    engine: Option<&AlertEngine>,
+    db: &Database,
+    filter: &MeasurementFilter,
+) -> Result<Vec<AlertEvent>> {
+    let Some(engine) = engine else {
+        return Ok(Vec::new());
+    };
+    engine.evaluate(db, filter).await.map_err(|e| {
+        error!("Failed to evaluate alert rules: {}", e);
+        AppError::Alert(format!("evaluating the rules failed: {}", e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AlertScope;
+    use chrono::TimeZone;
+
+    fn rule(duration_days: u32, cooldown_hours: u32) -> AlertRule {
+        AlertRule {
+            name: "pm25".to_string(),
+            parameter: "pm25".to_string(),
+            scope: AlertScope {
+                country: Some("NL".to_string()),
+                ..AlertScope::default()
+            },
+            comparator: Comparator::Above,
+            threshold: 25.0,
+            duration_days,
+            cooldown_hours,
+            notify: Vec::new(),
+        }
+    }
+
+    fn day(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, day, 0, 0, 0).unwrap()
+    }
+
+    /// Daily values, newest first.
+    fn values(days: &[(u32, f64)]) -> Vec<ScopeDailyValue> {
+        days.iter()
+            .map(|&(d, value)| ScopeDailyValue {
+                day: day(d),
+                value,
+                unit: Some("µg/m³".to_string()),
+            })
+            .collect()
+    }
+
+    fn alert(last_day: u32) -> Alert {
+        Alert {
+            id: 1,
+            rule: "pm25".to_string(),
+            status: "firing".to_string(),
+            fired_at: day(last_day),
+            resolved_at: None,
+            first_day: day(last_day - 2),
+            last_day: day(last_day),
+            value: Some(30.0),
+            threshold: 25.0,
+            unit: None,
+        }
+    }
+
+    #[test]
+    fn test_evaluate_rule() {
+        let rule = rule(3, 0);
+        assert_eq!(evaluate_rule(&rule, &[]), Evaluation::NoData);
+        assert!(matches!(
+            evaluate_rule(&rule, &values(&[(10, 20.0), (9, 30.0), (8, 30.0)])),
+            Evaluation::Clear(_)
+        ));
+        assert_eq!(
+            evaluate_rule(&rule, &values(&[(10, 30.0), (9, 31.0), (8, 26.0)])),
+            Evaluation::Breaching {
+                first_day: day(8),
+                latest: values(&[(10, 30.0)])[0].clone(),
+            }
+        );
+        // A day below the threshold or a missing day breaks the run.
+        assert!(matches!(
+            evaluate_rule(&rule, &values(&[(10, 30.0), (9, 20.0), (8, 30.0)])),
+            Evaluation::Pending(_)
+        ));
+        assert!(matches!(
+            evaluate_rule(&rule, &values(&[(10, 30.0), (9, 30.0), (7, 30.0)])),
+            Evaluation::Pending(_)
+        ));
+        // Too few days of data.
+        assert!(matches!(
+            evaluate_rule(&rule, &values(&[(10, 30.0), (9, 30.0)])),
+            Evaluation::Pending(_)
+        ));
+    }
+
+    #[test]
+    fn test_decide() {
+        let rule = rule(1, 24);
+        let now = day(11);
+        let breaching = |d| evaluate_rule(&rule, &values(&[(d, 30.0)]));
+        let clear = |d| evaluate_rule(&rule, &values(&[(d, 10.0)]));
+
+        assert!(matches!(
+            decide(&rule, breaching(10), None, None, now),
+            Action::Fire { .. }
+        ));
+        // Within the cooldown of the previous firing.
+        assert_eq!(
+            decide(
+                &rule,
+                breaching(10),
+                None,
+                Some(now - Duration::hours(2)),
+                now
+            ),
+            Action::CoolingDown
+        );
+        assert!(matches!(
+            decide(
+                &rule,
+                breaching(10),
+                None,
+                Some(now - Duration::hours(30)),
+                now
+            ),
+            Action::Fire { .. }
+        ));
+        // A firing alert is updated by newer days only, and resolved by a clear day.
+        assert_eq!(
+            decide(&rule, breaching(10), Some(&alert(10)), None, now),
+            Action::None
+        );
+        assert!(matches!(
+            decide(&rule, breaching(11), Some(&alert(10)), None, now),
+            Action::Update(_)
+        ));
+        assert!(matches!(
+            decide(&rule, clear(11), Some(&alert(10)), None, now),
+            Action::Resolve(_)
+        ));
+        assert_eq!(decide(&rule, clear(11), None, None, now), Action::None);
+        assert_eq!(
+            decide(&rule, Evaluation::NoData, Some(&alert(10)), None, now),
+            Action::None
+        );
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod integration_tests {
+    use super::*;
+    use crate::models::DbMeasurement;
+
+    use sqlx::PgPool;
+
+    fn measurement(sensor_id: i64, days_ago: i64, value: f64) -> DbMeasurement {
+        DbMeasurement::test(
+            sensor_id,
+            "NL",
+            "pm25",
+            Utc::now() - Duration::days(days_ago),
+        )
+        .with_value(value)
+    }
+
+    /// Tests that an alert fires after the required consecutive days, is notified once, and
+    /// resolves on a clear day.
+    #[sqlx::test]
+    async fn test_engine_fires_and_resolves(pool: PgPool) {
+        let db = Database::from_pool(pool);
+        db.init_schema().await.expect("Failed to init schema");
+        let path = std::env::temp_dir().join(format!("alerts-engine-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = AlertConfig::parse(&format!(
+            r#"
+            [[notifiers]]
+            name = "log"
+            type = "file"
+            path = "{}"
+
+            [[rules]]
+            name = "utrecht-pm25"
+            parameter = "pm25"
+            country = "NL"
+            locality = "Utrecht"
+            comparator = ">"
+            threshold = 25.0
+            duration_days = 2
+            "#,
+            path.display()
+        ))
+        .unwrap();
+        let engine = AlertEngine::new(config).unwrap();
+        let filter = MeasurementFilter::default();
+
+        db.insert_measurements(&[measurement(1, 3, 30.0), measurement(1, 2, 30.0)])
+            .await
+            .unwrap();
+        let events = engine.evaluate(&db, &filter).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].state, AlertState::Firing);
+        // Evaluating the same data again does not fire twice.
+        assert!(engine.evaluate(&db, &filter).await.unwrap().is_empty());
+
+        db.insert_measurements(&[measurement(1, 1, 12.0)])
+            .await
+            .unwrap();
+        let events = engine.evaluate(&db, &filter).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].state, AlertState::Resolved);
+        assert_eq!(events[0].alert.value, Some(12.0));
+
+        let lines = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(lines.lines().count(), 2);
+    }
+}
@@ -0,0 +1,351 @@
+//! Notifier backends delivering alert events: generic webhooks, SMTP email and JSON lines
+//! appended to a local file.
+
+use super::{NotifierConfig, NotifierKind, SmtpSecurity, SmtpSettings};
+use crate::error::{AppError, Result};
+use crate::models::AlertEvent;
+use async_trait::async_trait;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::path::PathBuf;
+use std::time::Duration as StdDuration;
+use tokio::io::AsyncWriteExt;
+use tracing::debug;
+
+/// Timeout of a webhook request or an SMTP session.
+const NOTIFY_TIMEOUT: StdDuration = StdDuration::from_secs(30);
+
+/// A destination for alert events.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Name of the notifier in the rules file.
+    fn name(&self) -> &str;
+
+    /// Delivers an alert event.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `AppError` if the event cannot be delivered.
+    async fn notify(&self, event: &AlertEvent) -> Result<()>;
+}
+
+/// Returns the event as JSON with its one-line `summary` added.
+fn event_json(event: &AlertEvent) -> Result<serde_json::Value> {
+    let mut json = serde_json::to_value(event)?;
+    json["summary"] = event.summary().into();
+    Ok(json)
+}
+
+/// Posts every event as JSON to a URL.
+pub struct WebhookNotifier {
+    name: String,
+    url: String,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    /// Creates a webhook notifier sending `headers` with every request.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Alert` if a header is invalid, or `AppError::Api` if the HTTP client
+    /// cannot be built.
+    pub fn new(name: &str, url: &str, headers: &[(String, String)]) -> Result<Self> {
+        let mut header_map = HeaderMap::new();
+        for (key, value) in headers {
+            let invalid =
+                || AppError::Alert(format!("notifier '{}': invalid header {}", name, key));
+            header_map.insert(
+                HeaderName::from_bytes(key.as_bytes()).map_err(|_| invalid())?,
+                HeaderValue::from_str(value).map_err(|_| invalid())?,
+            );
+        }
+        let client = reqwest::Client::builder()
+            .default_headers(header_map)
+            .timeout(NOTIFY_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            client,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn notify(&self, event: &AlertEvent) -> Result<()> {
+        debug!("Posting alert {} to {}", event.alert.id, self.url);
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&event_json(event)?)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(AppError::Alert(format!(
+                "webhook {} returned {}",
+                self.url,
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Emails every event through an SMTP server.
+pub struct SmtpNotifier {
+    name: String,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl SmtpNotifier {
+    /// Creates an SMTP notifier. The password is read from the environment variable
+    /// `settings.password_env`, if given.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Alert` if an address is invalid, there are no recipients, the
+    /// transport cannot be set up or the password variable is not set.
+    pub fn new(name: &str, settings: &SmtpSettings) -> Result<Self> {
+        let invalid = |what: String| AppError::Alert(format!("notifier '{}': {}", name, what));
+        let host = settings.host.as_str();
+        let mut builder = match settings.security {
+            SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .map_err(|e| invalid(e.to_string()))?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                .map_err(|e| invalid(e.to_string()))?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        if let Some(port) = settings.port {
+            builder = builder.port(port);
+        }
+        if let Some(username) = &settings.username {
+            let password = match &settings.password_env {
+                Some(var) => std::env::var(var)
+                    .map_err(|_| invalid(format!("environment variable {} is not set", var)))?,
+                None => String::new(),
+            };
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+
+        let parse = |address: &String| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| invalid(format!("invalid address '{}': {}", address, e)))
+        };
+        if settings.to.is_empty() {
+            return Err(invalid("no recipients".to_string()));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            transport: builder.timeout(Some(NOTIFY_TIMEOUT)).build(),
+            from: parse(&settings.from)?,
+            to: settings.to.iter().map(parse).collect::<Result<_>>()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for SmtpNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn notify(&self, event: &AlertEvent) -> Result<()> {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(event.summary())
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        let body = format!(
+            "{}\n\n{}\n",
+            event.summary(),
+            serde_json::to_string_pretty(&event_json(event)?)?
+        );
+        let message = builder
+            .body(body)
+            .map_err(|e| AppError::Alert(format!("failed to build email: {}", e)))?;
+        debug!("Emailing alert {}", event.alert.id);
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| AppError::Alert(format!("failed to send email: {}", e)))?;
+        Ok(())
+    }
+}
+
+/// Appends every event as a JSON line to a file.
+pub struct FileNotifier {
+    name: String,
+    path: PathBuf,
+}
+
+impl FileNotifier {
+    pub fn new(name: &str, path: PathBuf) -> Self {
+        Self {
+            name: name.to_string(),
+            path,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for FileNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn notify(&self, event: &AlertEvent) -> Result<()> {
+        let mut line = serde_json::to_string(&event_json(event)?)?;
+        line.push('\n');
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        // Tokio files write in the background; flush so the line is written before returning.
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// Creates the notifier described by `config`.
+///
+/// # Errors
+///
+/// Returns an `AppError` if the notifier cannot be set up (see the constructors).
+pub fn build_notifier(config: &NotifierConfig) -> Result<Box<dyn Notifier>> {
+    let name = config.name.as_str();
+    Ok(match &config.kind {
+        NotifierKind::Webhook { url, headers } => {
+            let headers: Vec<_> = headers.clone().into_iter().collect();
+            Box::new(WebhookNotifier::new(name, url, &headers)?)
+        },
+        NotifierKind::Smtp(settings) => Box::new(SmtpNotifier::new(name, settings)?),
+        NotifierKind::File { path } => Box::new(FileNotifier::new(name, path.clone())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Alert, AlertScope, AlertState};
+    use chrono::{TimeZone, Utc};
+
+    fn event() -> AlertEvent {
+        let day = Utc.with_ymd_and_hms(2024, 3, 10, 0, 0, 0).unwrap();
+        AlertEvent {
+            state: AlertState::Firing,
+            parameter: "pm25".to_string(),
+            scope: AlertScope {
+                country: Some("NL".to_string()),
+                locality: Some("Utrecht".to_string()),
+                location_id: None,
+            },
+            comparator: ">".to_string(),
+            duration_days: 3,
+            alert: Alert {
+                id: 7,
+                rule: "utrecht-pm25".to_string(),
+                status: "firing".to_string(),
+                fired_at: day,
+                resolved_at: None,
+                first_day: day - chrono::Duration::days(2),
+                last_day: day,
+                value: Some(31.24),
+                threshold: 25.0,
+                unit: Some("µg/m³".to_string()),
+            },
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(
+            event().summary(),
+            "[FIRING] utrecht-pm25: pm25 in NL/Utrecht > 25 µg/m³ for 3 days (31.2 on 2024-03-10)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_notifier_posts_json() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/hook")
+            .match_header("authorization", "Bearer secret")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"state": "firing", "alert": {"rule": "utrecht-pm25"}}"#.to_string(),
+            ))
+            .with_status(204)
+            .create_async()
+            .await;
+        let notifier = WebhookNotifier::new(
+            "ops",
+            &format!("{}/hook", server.url()),
+            &[("Authorization".to_string(), "Bearer secret".to_string())],
+        )
+        .unwrap();
+        notifier.notify(&event()).await.unwrap();
+        mock.assert_async().await;
+
+        let failing = server
+            .mock("POST", "/broken")
+            .with_status(500)
+            .create_async()
+            .await;
+        let notifier =
+            WebhookNotifier::new("ops", &format!("{}/broken", server.url()), &[]).unwrap();
+        assert!(notifier.notify(&event()).await.is_err());
+        failing.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_file_notifier_appends_lines() {
+        let path = std::env::temp_dir().join(format!("alerts-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let notifier = FileNotifier::new("log", path.clone());
+        notifier.notify(&event()).await.unwrap();
+        notifier.notify(&event()).await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let json: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(json["alert"]["value"], 31.24);
+        assert!(json["summary"].as_str().unwrap().starts_with("[FIRING]"));
+    }
+
+    #[test]
+    fn test_smtp_notifier_validates_addresses() {
+        let build = |from: &str, password_env: Option<&str>| {
+            SmtpNotifier::new(
+                "mail",
+                &SmtpSettings {
+                    host: "smtp.example.com".to_string(),
+                    port: Some(2525),
+                    security: SmtpSecurity::Starttls,
+                    username: Some("alerts".to_string()),
+                    password_env: password_env.map(str::to_string),
+                    from: from.to_string(),
+                    to: vec!["oncall@example.com".to_string()],
+                },
+            )
+        };
+        assert!(build("Air Quality <alerts@example.com>", None).is_ok());
+        assert!(build("not an address", None).is_err());
+        assert!(build("alerts@example.com", Some("UNSET_SMTP_PASSWORD_FOR_TEST")).is_err());
+    }
+}
@@ -0,0 +1,340 @@
+//! The alert rules file: rules watching a parameter within a scope, and the notifiers they
+//! send their alerts to.
+//!
+//! ```toml
+//! [[notifiers]]
+//! name = "ops"
+//! type = "webhook"
+//! url = "https://hooks.example.com/air-quality"
+//!
+//! [[rules]]
+//! name = "utrecht-pm25"
+//! parameter = "pm25"
+//! country = "NL"
+//! locality = "Utrecht"
+//! comparator = ">"
+//! threshold = 25.0
+//! duration_days = 3
+//! cooldown_hours = 24
+//! notify = ["ops"]
+//! ```
+
+use crate::error::{AppError, Result};
+use crate::models::AlertScope;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// How a daily value is compared with a rule's threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Comparator {
+    #[serde(rename = ">")]
+    Above,
+    #[serde(rename = ">=")]
+    AtLeast,
+    #[serde(rename = "<")]
+    Below,
+    #[serde(rename = "<=")]
+    AtMost,
+}
+
+impl Comparator {
+    /// Returns the comparison operator.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Comparator::Above => ">",
+            Comparator::AtLeast => ">=",
+            Comparator::Below => "<",
+            Comparator::AtMost => "<=",
+        }
+    }
+
+    /// Returns `true` if `value` breaches `threshold`.
+    pub fn breaches(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparator::Above => value > threshold,
+            Comparator::AtLeast => value >= threshold,
+            Comparator::Below => value < threshold,
+            Comparator::AtMost => value <= threshold,
+        }
+    }
+}
+
+fn default_duration_days() -> u32 {
+    1
+}
+
+/// A rule firing an alert when the daily average of a parameter within its scope breaches the
+/// threshold on `duration_days` consecutive days.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AlertRule {
+    /// Unique name, identifying the rule's alerts in the `alerts` table.
+    pub name: String,
+    /// Parameter name (e.g. `pm25`). The threshold is in the parameter's normalised unit.
+    pub parameter: String,
+    #[serde(flatten)]
+    pub scope: AlertScope,
+    pub comparator: Comparator,
+    pub threshold: f64,
+    #[serde(default = "default_duration_days")]
+    pub duration_days: u32,
+    /// Minimum time between two firings of the rule.
+    #[serde(default)]
+    pub cooldown_hours: u32,
+    /// Names of the notifiers to send the alerts to; all notifiers if empty.
+    #[serde(default)]
+    pub notify: Vec<String>,
+}
+
+/// Security of the connection to an SMTP server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Upgrade a plain connection with STARTTLS (port 587 by default).
+    #[default]
+    Starttls,
+    /// Implicit TLS (port 465 by default).
+    Tls,
+    /// No encryption, e.g. for a local relay (port 25 by default).
+    None,
+}
+
+/// Connection settings of an SMTP notifier.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SmtpSettings {
+    pub host: String,
+    /// Port; defaults to the usual port of `security`.
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    /// Environment variable holding the password, so it is not stored in the rules file.
+    pub password_env: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+/// Where a notifier sends the alerts.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierKind {
+    /// POST every alert event as JSON to a URL.
+    Webhook {
+        url: String,
+        /// Extra request headers, e.g. an authorization token.
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    /// Email every alert event.
+    Smtp(SmtpSettings),
+    /// Append every alert event as a JSON line to a file.
+    File { path: PathBuf },
+}
+
+/// A named notifier.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct NotifierConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: NotifierKind,
+}
+
+/// The contents of an alert rules file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct AlertConfig {
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+    #[serde(default)]
+    pub notifiers: Vec<NotifierConfig>,
+}
+
+impl AlertConfig {
+    /// Parses and validates a TOML rules file's contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Alert` if the contents are not valid TOML, miss required fields or
+    /// fail validation (see `validate`).
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut config: AlertConfig = toml::from_str(contents)
+            .map_err(|e| AppError::Alert(format!("invalid rules file: {}", e)))?;
+        for rule in &mut config.rules {
+            if let Some(country) = &mut rule.scope.country {
+                *country = country.to_uppercase();
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads and parses the rules file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Io` if the file cannot be read, or `AppError::Alert` if it is invalid.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::parse(&contents).map_err(|e| AppError::Alert(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Checks that names are unique, every rule has a scope, a finite threshold and a duration
+    /// of at least one day, and only references defined notifiers.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Alert` describing the first problem found.
+    pub fn validate(&self) -> Result<()> {
+        let mut notifier_names = HashSet::new();
+        for notifier in &self.notifiers {
+            if !notifier_names.insert(notifier.name.as_str()) {
+                return Err(AppError::Alert(format!(
+                    "duplicate notifier '{}'",
+                    notifier.name
+                )));
+            }
+        }
+
+        let mut rule_names = HashSet::new();
+        for rule in &self.rules {
+            let invalid =
+                |reason: &str| Err(AppError::Alert(format!("rule '{}' {}", rule.name, reason)));
+            if !rule_names.insert(rule.name.as_str()) {
+                return invalid("is defined twice");
+            }
+            if rule.scope.is_unrestricted() {
+                return invalid("needs a country, locality or location_id");
+            }
+            if !rule.threshold.is_finite() {
+                return invalid("has a threshold that is not a number");
+            }
+            if rule.duration_days == 0 {
+                return invalid("needs a duration_days of at least 1");
+            }
+            if let Some(name) = rule
+                .notify
+                .iter()
+                .find(|name| !notifier_names.contains(name.as_str()))
+            {
+                return invalid(&format!("notifies unknown notifier '{}'", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+        [[notifiers]]
+        name = "ops"
+        type = "webhook"
+        url = "https://hooks.example.com/air"
+        headers = { Authorization = "Bearer secret" }
+
+        [[notifiers]]
+        name = "mail"
+        type = "smtp"
+        host = "smtp.example.com"
+        username = "alerts"
+        password_env = "SMTP_PASSWORD"
+        from = "Air Quality <alerts@example.com>"
+        to = ["oncall@example.com"]
+
+        [[notifiers]]
+        name = "log"
+        type = "file"
+        path = "alerts.jsonl"
+
+        [[rules]]
+        name = "utrecht-pm25"
+        parameter = "pm25"
+        country = "nl"
+        locality = "Utrecht"
+        comparator = ">"
+        threshold = 25.0
+        duration_days = 3
+        cooldown_hours = 24
+        notify = ["ops", "log"]
+
+        [[rules]]
+        name = "station-o3"
+        parameter = "o3"
+        location_id = 1234
+        comparator = ">="
+        threshold = 120
+    "#;
+
+    #[test]
+    fn test_parse_rules_file() {
+        let config = AlertConfig::parse(RULES).unwrap();
+        assert_eq!(config.notifiers.len(), 3);
+        assert!(matches!(
+            &config.notifiers[1].kind,
+            NotifierKind::Smtp(SmtpSettings {
+                security: SmtpSecurity::Starttls,
+                port: None,
+                ..
+            })
+        ));
+
+        let rule = &config.rules[0];
+        assert_eq!(rule.scope.country.as_deref(), Some("NL"));
+        assert_eq!(rule.scope.locality.as_deref(), Some("Utrecht"));
+        assert_eq!(rule.comparator, Comparator::Above);
+        assert_eq!(rule.duration_days, 3);
+        assert_eq!(rule.notify, ["ops", "log"]);
+
+        let rule = &config.rules[1];
+        assert_eq!(rule.scope.location_id, Some(1234));
+        assert_eq!(rule.threshold, 120.0);
+        assert_eq!(rule.duration_days, 1);
+        assert_eq!(rule.cooldown_hours, 0);
+        assert!(rule.notify.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_rules_are_rejected() {
+        let rule = |extra: &str| {
+            format!(
+                "[[rules]]\nname = \"r\"\nparameter = \"pm25\"\ncomparator = \">\"\nthreshold = 1.0\n{}",
+                extra
+            )
+        };
+        assert!(AlertConfig::parse(&rule("country = \"NL\"")).is_ok());
+        for (contents, expected) in [
+            (rule(""), "needs a country"),
+            (rule("country = \"NL\"\nduration_days = 0"), "duration_days"),
+            (
+                rule("country = \"NL\"\nnotify = [\"ops\"]"),
+                "unknown notifier 'ops'",
+            ),
+            (
+                format!("{}\n{}", rule("country = \"NL\""), rule("country = \"DE\"")),
+                "defined twice",
+            ),
+            (
+                rule("country = \"NL\"").replace("\">\"", "\"!=\""),
+                "invalid rules file",
+            ),
+        ] {
+            let err = AlertConfig::parse(&contents).unwrap_err().to_string();
+            assert!(
+                err.contains(expected),
+                "{} does not mention {}",
+                err,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_comparator() {
+        assert!(Comparator::Above.breaches(25.1, 25.0));
+        assert!(!Comparator::Above.breaches(25.0, 25.0));
+        assert!(Comparator::AtLeast.breaches(25.0, 25.0));
+        assert!(Comparator::Below.breaches(9.0, 10.0));
+        assert!(!Comparator::AtMost.breaches(10.1, 10.0));
+    }
+}
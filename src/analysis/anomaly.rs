@@ -0,0 +1,346 @@
+//! Detects anomalies in imported daily measurements.
+//!
+//! Four independent detectors are applied per parameter:
+//! - **Outlier:** robust z-score of each value against the sensor's own median and MAD.
+//! - **Flat line:** runs of identical values over several consecutive days.
+//! - **Spike:** a value that is a large multiple of the median of the sensor's preceding days.
+//! - **Neighbour deviation:** a value far from the median of nearby sensors on the same day.
+
+use super::haversine_km;
+use crate::models::{AnomalyFlag, AnomalyKind, SensorReading};
+use std::collections::{BTreeMap, HashMap};
+
+/// Scale factor converting the MAD into a consistent estimator of the standard deviation.
+const MAD_SCALE: f64 = 0.6745;
+
+/// Tunable thresholds for the anomaly detectors.
+#[derive(Debug, Clone)]
+pub struct AnomalyConfig {
+    /// Absolute robust z-score above which a value is an outlier.
+    pub z_threshold: f64,
+    /// Minimum number of readings a sensor needs before the outlier detector runs.
+    pub min_readings: usize,
+    /// Minimum number of consecutive identical values that form a flat line.
+    pub flat_line_days: usize,
+    /// Factor over the preceding median that counts as a spike.
+    pub spike_factor: f64,
+    /// Number of preceding days used as the spike baseline.
+    pub spike_window: usize,
+    /// Values below this level are never considered spikes (avoids flagging 0.1 -> 1.0).
+    pub spike_min_value: f64,
+    /// Radius in kilometres within which other sensors are considered neighbours.
+    pub neighbour_radius_km: f64,
+    /// Minimum number of neighbours with a value on the same day for the comparison to run.
+    pub min_neighbours: usize,
+    /// Factor by which a value must exceed (or fall below) the neighbour median to be flagged.
+    pub neighbour_factor: f64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            z_threshold: 3.5,
+            min_readings: 5,
+            flat_line_days: 3,
+            spike_factor: 10.0,
+            spike_window: 7,
+            spike_min_value: 5.0,
+            neighbour_radius_km: 25.0,
+            min_neighbours: 2,
+            neighbour_factor: 4.0,
+        }
+    }
+}
+
+/// Runs all detectors over the given readings and returns the detected anomalies,
+/// ordered by sensor, date and kind.
+///
+/// A measurement may be flagged by several detectors; each detector produces at most one
+/// flag per measurement.
+pub fn detect_anomalies(readings: &[SensorReading], config: &AnomalyConfig) -> Vec<AnomalyFlag> {
+    // Group readings per sensor, ordered by date.
+    let mut by_sensor: BTreeMap<i64, Vec<&SensorReading>> = BTreeMap::new();
+    for r in readings.iter().filter(|r| r.value.is_finite()) {
+        by_sensor.entry(r.sensor_id).or_default().push(r);
+    }
+    for series in by_sensor.values_mut() {
+        series.sort_by_key(|r| r.date_utc);
+    }
+
+    let mut flags = Vec::new();
+    for series in by_sensor.values() {
+        flags.extend(detect_outliers(series, config));
+        flags.extend(detect_flat_lines(series, config));
+        flags.extend(detect_spikes(series, config));
+    }
+    flags.extend(detect_neighbour_deviations(readings, config));
+
+    flags.sort_by_key(|f| (f.sensor_id, f.date_utc, f.kind));
+    flags
+}
+
+/// Flags values whose robust z-score against the sensor's median exceeds the threshold.
+fn detect_outliers(series: &[&SensorReading], config: &AnomalyConfig) -> Vec<AnomalyFlag> {
+    if series.len() < config.min_readings {
+        return Vec::new();
+    }
+    let values: Vec<f64> = series.iter().map(|r| r.value).collect();
+    let med = median(&values);
+    let deviations: Vec<f64> = values.iter().map(|v| (v - med).abs()).collect();
+    let mad = median(&deviations);
+    if mad <= f64::EPSILON {
+        // Constant series: the flat-line detector covers this case.
+        return Vec::new();
+    }
+
+    series
+        .iter()
+        .filter_map(|r| {
+            let z = MAD_SCALE * (r.value - med) / mad;
+            (z.abs() > config.z_threshold).then(|| {
+                flag(
+                    r,
+                    AnomalyKind::Outlier,
+                    z,
+                    format!(
+                        "robust z-score {:.2} (value {:.2}, sensor median {:.2}, MAD {:.2})",
+                        z, r.value, med, mad
+                    ),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Flags every value that belongs to a run of identical values on consecutive days.
+fn detect_flat_lines(series: &[&SensorReading], config: &AnomalyConfig) -> Vec<AnomalyFlag> {
+    let mut flags = Vec::new();
+    let mut run_start = 0;
+    for i in 1..=series.len() {
+        let continues = i < series.len()
+            && (series[i].value - series[i - 1].value).abs() <= f64::EPSILON
+            && (series[i].date_utc - series[i - 1].date_utc).num_days() == 1;
+        if continues {
+            continue;
+        }
+        let run_len = i - run_start;
+        if run_len >= config.flat_line_days.max(2) {
+            for r in &series[run_start..i] {
+                flags.push(flag(
+                    r,
+                    AnomalyKind::FlatLine,
+                    run_len as f64,
+                    format!(
+                        "value {:.2} repeated for {} consecutive days",
+                        r.value, run_len
+                    ),
+                ));
+            }
+        }
+        run_start = i;
+    }
+    flags
+}
+
+/// Flags values that exceed the median of the preceding `spike_window` readings by `spike_factor`.
+fn detect_spikes(series: &[&SensorReading], config: &AnomalyConfig) -> Vec<AnomalyFlag> {
+    let mut flags = Vec::new();
+    for i in 1..series.len() {
+        let r = series[i];
+        if r.value < config.spike_min_value {
+            continue;
+        }
+        let window_start = i.saturating_sub(config.spike_window.max(1));
+        let baseline: Vec<f64> = series[window_start..i].iter().map(|p| p.value).collect();
+        let base = median(&baseline);
+        if base <= 0.0 {
+            continue;
+        }
+        let factor = r.value / base;
+        if factor >= config.spike_factor {
+            flags.push(flag(
+                r,
+                AnomalyKind::Spike,
+                factor,
+                format!(
+                    "value {:.2} is {:.1}x the median {:.2} of the preceding {} readings",
+                    r.value,
+                    factor,
+                    base,
+                    baseline.len()
+                ),
+            ));
+        }
+    }
+    flags
+}
+
+/// Compares each value against the median of nearby sensors measuring the same parameter
+/// on the same day and flags values that differ by more than `neighbour_factor`.
+fn detect_neighbour_deviations(
+    readings: &[SensorReading],
+    config: &AnomalyConfig,
+) -> Vec<AnomalyFlag> {
+    // Group readings that have coordinates by (parameter, day).
+    let mut groups: HashMap<(&str, chrono::NaiveDate), Vec<&SensorReading>> = HashMap::new();
+    for r in readings
+        .iter()
+        .filter(|r| r.value.is_finite() && r.latitude.is_some() && r.longitude.is_some())
+    {
+        groups
+            .entry((r.parameter_name.as_str(), r.date_utc.date_naive()))
+            .or_default()
+            .push(r);
+    }
+
+    let mut flags = Vec::new();
+    for group in groups.values() {
+        for r in group {
+            let (lat, lon) = (
+                r.latitude.unwrap_or_default(),
+                r.longitude.unwrap_or_default(),
+            );
+            let neighbour_values: Vec<f64> = group
+                .iter()
+                .filter(|n| n.sensor_id != r.sensor_id)
+                .filter(|n| {
+                    haversine_km(
+                        lat,
+                        lon,
+                        n.latitude.unwrap_or_default(),
+                        n.longitude.unwrap_or_default(),
+                    ) <= config.neighbour_radius_km
+                })
+                .map(|n| n.value)
+                .collect();
+            if neighbour_values.len() < config.min_neighbours {
+                continue;
+            }
+            let neighbour_median = median(&neighbour_values);
+            if neighbour_median <= 0.0 || r.value <= 0.0 {
+                continue;
+            }
+            let ratio = r.value / neighbour_median;
+            if ratio >= config.neighbour_factor || ratio <= 1.0 / config.neighbour_factor {
+                flags.push(flag(
+                    r,
+                    AnomalyKind::NeighbourDeviation,
+                    ratio,
+                    format!(
+                        "value {:.2} vs median {:.2} of {} sensors within {:.0} km",
+                        r.value,
+                        neighbour_median,
+                        neighbour_values.len(),
+                        config.neighbour_radius_km
+                    ),
+                ));
+            }
+        }
+    }
+    flags
+}
+
+/// Builds an `AnomalyFlag` for a reading.
+fn flag(r: &SensorReading, kind: AnomalyKind, score: f64, detail: String) -> AnomalyFlag {
+    AnomalyFlag {
+        measurement_id: r.measurement_id,
+        sensor_id: r.sensor_id,
+        date_utc: r.date_utc,
+        kind,
+        score,
+        detail,
+    }
+}
+
+/// Returns the median of a slice (0.0 for an empty slice).
+pub fn median(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    fn day(n: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap() + Duration::days(n)
+    }
+
+    fn readings(sensor_id: i64, values: &[f64], lat: f64, lon: f64) -> Vec<SensorReading> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| SensorReading {
+                measurement_id: (sensor_id * 100 + i as i64) as i32,
+                sensor_id,
+                parameter_name: "pm25".to_string(),
+                date_utc: day(i as i64),
+                value: *v,
+                latitude: Some(lat),
+                longitude: Some(lon),
+            })
+            .collect()
+    }
+
+    fn kinds(flags: &[AnomalyFlag], kind: AnomalyKind) -> Vec<i32> {
+        flags
+            .iter()
+            .filter(|f| f.kind == kind)
+            .map(|f| f.measurement_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&[3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&[4.0, 1.0, 2.0, 3.0]), 2.5);
+        assert_eq!(median(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_detects_spike_and_outlier() {
+        let data = readings(1, &[10.0, 11.0, 9.0, 12.0, 10.0, 150.0, 11.0], 52.0, 5.0);
+        let flags = detect_anomalies(&data, &AnomalyConfig::default());
+        assert_eq!(kinds(&flags, AnomalyKind::Spike), vec![105]);
+        assert_eq!(kinds(&flags, AnomalyKind::Outlier), vec![105]);
+    }
+
+    #[test]
+    fn test_detects_flat_line() {
+        let data = readings(2, &[8.0, 7.5, 7.5, 7.5, 7.5, 9.0], 52.0, 5.0);
+        let flags = detect_anomalies(&data, &AnomalyConfig::default());
+        assert_eq!(
+            kinds(&flags, AnomalyKind::FlatLine),
+            vec![201, 202, 203, 204]
+        );
+    }
+
+    #[test]
+    fn test_flat_line_requires_consecutive_days() {
+        let mut data = readings(3, &[5.0, 5.0, 5.0], 52.0, 5.0);
+        data[2].date_utc = day(10); // Gap breaks the run
+        let flags = detect_anomalies(&data, &AnomalyConfig::default());
+        assert!(kinds(&flags, AnomalyKind::FlatLine).is_empty());
+    }
+
+    #[test]
+    fn test_detects_neighbour_deviation() {
+        let mut data = readings(1, &[10.0], 52.00, 5.00);
+        data.extend(readings(2, &[12.0], 52.01, 5.01));
+        data.extend(readings(3, &[11.0], 52.02, 5.00));
+        data.extend(readings(4, &[80.0], 52.01, 5.02)); // Broken sensor
+        data.extend(readings(5, &[500.0], 40.0, 3.0)); // Far away, not a neighbour
+        let flags = detect_anomalies(&data, &AnomalyConfig::default());
+        assert_eq!(kinds(&flags, AnomalyKind::NeighbourDeviation), vec![400]);
+    }
+}
@@ -0,0 +1,169 @@
+//! Comparison of the averages of two periods, over all sensors and over the sensors that
+//! reported in both periods.
+
+use crate::models::{
+    ParameterComparison, PeriodAverage, PeriodComparison, SensorPeriodStats, SensorPresence,
+};
+
+/// Averages the daily values of `sensors`; each entry is the `(total, days)` of a sensor.
+fn average<'a>(sensors: impl Iterator<Item = &'a (f64, i64)>) -> PeriodAverage {
+    let (total, sensor_days, count) = sensors.fold((0.0, 0, 0), |(total, days, count), s| {
+        (total + s.0, days + s.1, count + 1)
+    });
+    PeriodAverage {
+        value: (sensor_days > 0).then(|| total / sensor_days as f64),
+        sensor_days,
+        sensors: count,
+    }
+}
+
+/// Builds the comparison from the per-sensor sums of both periods.
+///
+/// Parameters keep the order of their first row. Averages weigh every sensor day equally, like
+/// the country averages.
+pub fn compare_periods(stats: &[SensorPeriodStats]) -> PeriodComparison {
+    // (parameter row, sensor ID, location name, baseline, current)
+    type SensorEntry<'a> = (i64, &'a str, Option<(f64, i64)>, Option<(f64, i64)>);
+    let mut parameters: Vec<(&SensorPeriodStats, Vec<SensorEntry>)> = Vec::new();
+    for row in stats {
+        let index = match parameters
+            .iter()
+            .position(|(first, _)| first.parameter == row.parameter)
+        {
+            Some(index) => index,
+            None => {
+                parameters.push((row, Vec::new()));
+                parameters.len() - 1
+            },
+        };
+        let sensors = &mut parameters[index].1;
+        let sensor = match sensors.iter().position(|s| s.0 == row.sensor_id) {
+            Some(sensor) => sensor,
+            None => {
+                sensors.push((row.sensor_id, &row.location_name, None, None));
+                sensors.len() - 1
+            },
+        };
+        let values = Some((row.total, row.days));
+        if row.in_current {
+            sensors[sensor].3 = values;
+        } else {
+            sensors[sensor].2 = values;
+        }
+    }
+
+    let mut comparison = PeriodComparison {
+        parameters: Vec::new(),
+        sensors: Vec::new(),
+    };
+    for (first, sensors) in parameters {
+        let common = || sensors.iter().filter(|s| s.2.is_some() && s.3.is_some());
+        comparison.parameters.push(ParameterComparison {
+            parameter: first.parameter.clone(),
+            display_name: first.display_name.clone(),
+            unit: first.unit.clone(),
+            baseline: average(sensors.iter().filter_map(|s| s.2.as_ref())),
+            current: average(sensors.iter().filter_map(|s| s.3.as_ref())),
+            common_baseline: average(common().filter_map(|s| s.2.as_ref())),
+            common_current: average(common().filter_map(|s| s.3.as_ref())),
+        });
+        comparison
+            .sensors
+            .extend(sensors.iter().map(|s| SensorPresence {
+                sensor_id: s.0,
+                location_name: s.1.to_string(),
+                parameter: first.parameter.clone(),
+                in_baseline: s.2.is_some(),
+                in_current: s.3.is_some(),
+            }));
+    }
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Change;
+
+    fn stats(
+        parameter: &str,
+        sensor_id: i64,
+        in_current: bool,
+        values: &[f64],
+    ) -> SensorPeriodStats {
+        SensorPeriodStats {
+            parameter: parameter.to_string(),
+            display_name: None,
+            unit: Some("µg/m³".to_string()),
+            sensor_id,
+            location_name: format!("Station {}", sensor_id),
+            in_current,
+            total: values.iter().sum(),
+            days: values.len() as i64,
+        }
+    }
+
+    #[test]
+    fn test_compare_periods() {
+        let comparison = compare_periods(&[
+            // Sensor 1 reported in both periods, 2 only in the baseline, 3 only now.
+            stats("pm25", 1, false, &[10.0, 10.0]),
+            stats("pm25", 1, true, &[12.0, 12.0]),
+            stats("pm25", 2, false, &[40.0]),
+            stats("pm25", 3, true, &[6.0, 6.0]),
+            stats("no2", 4, true, &[20.0]),
+        ]);
+        assert_eq!(comparison.parameters.len(), 2);
+
+        let pm25 = &comparison.parameters[0];
+        assert_eq!(pm25.label(), "PM25");
+        assert_eq!(pm25.baseline.value, Some(20.0));
+        assert_eq!((pm25.baseline.sensor_days, pm25.baseline.sensors), (3, 2));
+        assert_eq!(pm25.current.value, Some(9.0));
+        // All sensors: 20 -> 9 is a drop, mostly caused by sensor churn.
+        let change = pm25.change().unwrap();
+        assert_eq!(change.absolute, -11.0);
+        assert!((change.percent.unwrap() + 55.0).abs() < 1e-9);
+        // The sensor present in both periods went up by 20%.
+        assert_eq!(pm25.common_baseline.sensors, 1);
+        let common = pm25.common_change().unwrap();
+        assert_eq!(common.absolute, 2.0);
+        assert!((common.percent.unwrap() - 20.0).abs() < 1e-9);
+
+        let no2 = &comparison.parameters[1];
+        assert_eq!(no2.baseline, PeriodAverage::default());
+        assert!(no2.change().is_none());
+        assert!(no2.common_change().is_none());
+
+        let presence: Vec<_> = comparison
+            .sensors
+            .iter()
+            .map(|s| (s.sensor_id, s.in_baseline, s.in_current))
+            .collect();
+        assert_eq!(
+            presence,
+            [
+                (1, true, true),
+                (2, true, false),
+                (3, false, true),
+                (4, false, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_change_from_zero_baseline() {
+        let zero = PeriodAverage {
+            value: Some(0.0),
+            sensor_days: 1,
+            sensors: 1,
+        };
+        let current = PeriodAverage {
+            value: Some(3.0),
+            ..zero
+        };
+        let change = Change::between(&zero, &current).unwrap();
+        assert_eq!(change.absolute, 3.0);
+        assert!(change.percent.is_none());
+    }
+}
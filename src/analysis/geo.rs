@@ -0,0 +1,83 @@
+//! Geographic helper functions.
+
+use crate::models::{LocationValues, NearbyLocation};
+
+/// Mean Earth radius in kilometres, as used by the haversine formula.
+pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// Calculates the great-circle distance in kilometres between two points given in
+/// decimal degrees, using the haversine formula.
+pub fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let d_phi = (lat2 - lat1).to_radians();
+    let d_lambda = (lon2 - lon1).to_radians();
+
+    let a = (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+/// Returns the locations within `radius_km` of the given point, closest first, keeping at
+/// most `limit` of them.
+pub fn nearest_locations(
+    locations: Vec<LocationValues>,
+    latitude: f64,
+    longitude: f64,
+    radius_km: f64,
+    limit: usize,
+) -> Vec<NearbyLocation> {
+    let mut nearby: Vec<NearbyLocation> = locations
+        .into_iter()
+        .map(|location| NearbyLocation {
+            distance_km: haversine_km(latitude, longitude, location.latitude, location.longitude),
+            location,
+        })
+        .filter(|n| n.distance_km <= radius_km)
+        .collect();
+    nearby.sort_by(|a, b| a.distance_km.total_cmp(&b.distance_km));
+    nearby.truncate(limit);
+    nearby
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_haversine_known_distance() {
+        // Amsterdam to Rotterdam is roughly 57 km.
+        let d = haversine_km(52.3676, 4.9041, 51.9244, 4.4777);
+        assert!((d - 57.0).abs() < 1.5, "unexpected distance {}", d);
+    }
+
+    #[test]
+    fn test_haversine_same_point_is_zero() {
+        assert!(haversine_km(10.0, 20.0, 10.0, 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_nearest_locations_filters_sorts_and_limits() {
+        let location = |location_id: i64, latitude: f64, longitude: f64| LocationValues {
+            location_id,
+            name: None,
+            locality: None,
+            country: "NL".to_string(),
+            latitude,
+            longitude,
+            unit: None,
+            latest_value: None,
+            latest_date: None,
+            recent_average: None,
+            days: 0,
+        };
+        let locations = vec![
+            location(1, 52.3676, 4.9041), // Amsterdam
+            location(2, 51.9244, 4.4777), // Rotterdam
+            location(3, 52.0907, 5.1214), // Utrecht
+            location(4, 50.8503, 4.3517), // Brussels
+        ];
+        let nearby = nearest_locations(locations, 52.3676, 4.9041, 100.0, 2);
+        let ids: Vec<i64> = nearby.iter().map(|n| n.location.location_id).collect();
+        assert_eq!(ids, vec![1, 3]);
+        assert!(nearby[0].distance_km < 1e-9);
+    }
+}
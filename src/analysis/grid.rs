@@ -0,0 +1,208 @@
+//! Regular latitude/longitude grids of interpolated values.
+//!
+//! Grids use square cells of `cell_size` decimal degrees (WGS84). The lower-left corner is the
+//! south-west corner of the bounding box and rows are stored north to south, matching the
+//! ESRI ASCII grid layout.
+
+use super::{idw_estimate, InterpolationMethod, SamplePoint, SimpleKriging};
+
+/// Largest number of cells a grid may have.
+pub const MAX_GRID_CELLS: usize = 1_000_000;
+
+/// A geographic bounding box in decimal degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    /// Parses a bounding box given as `min_lon,min_lat,max_lon,max_lat`.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let parts: Vec<f64> = input
+            .split(',')
+            .map(|p| {
+                p.trim()
+                    .parse::<f64>()
+                    .map_err(|_| format!("'{}' is not a number", p.trim()))
+            })
+            .collect::<Result<_, _>>()?;
+        let [min_lon, min_lat, max_lon, max_lat] = parts[..] else {
+            return Err("Expected four values: min_lon,min_lat,max_lon,max_lat".to_string());
+        };
+        let bbox = Self {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        };
+        bbox.validate()?;
+        Ok(bbox)
+    }
+
+    /// Checks that the box lies within valid coordinates and is not empty.
+    pub fn validate(&self) -> Result<(), String> {
+        let lon_ok = |v: f64| (-180.0..=180.0).contains(&v);
+        let lat_ok = |v: f64| (-90.0..=90.0).contains(&v);
+        if !(lon_ok(self.min_lon)
+            && lon_ok(self.max_lon)
+            && lat_ok(self.min_lat)
+            && lat_ok(self.max_lat))
+        {
+            return Err("Bounding box lies outside valid coordinates".to_string());
+        }
+        if self.min_lon >= self.max_lon || self.min_lat >= self.max_lat {
+            return Err("Bounding box minimum must be below its maximum".to_string());
+        }
+        Ok(())
+    }
+
+    /// Returns the number of columns and rows needed to cover the box with cells of
+    /// `cell_size` degrees.
+    pub fn dimensions(&self, cell_size: f64) -> (usize, usize) {
+        let ncols = ((self.max_lon - self.min_lon) / cell_size).ceil().max(1.0) as usize;
+        let nrows = ((self.max_lat - self.min_lat) / cell_size).ceil().max(1.0) as usize;
+        (ncols, nrows)
+    }
+}
+
+/// A regular grid of optional values (`None` where no estimate could be made).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    /// Longitude of the west edge.
+    pub xll_corner: f64,
+    /// Latitude of the south edge.
+    pub yll_corner: f64,
+    /// Cell size in decimal degrees.
+    pub cell_size: f64,
+    pub ncols: usize,
+    pub nrows: usize,
+    /// Values in row-major order, starting with the northernmost row.
+    pub values: Vec<Option<f64>>,
+}
+
+impl Grid {
+    /// Creates an empty grid covering `bbox`.
+    pub fn new(bbox: &BoundingBox, cell_size: f64) -> Self {
+        let (ncols, nrows) = bbox.dimensions(cell_size);
+        Self {
+            xll_corner: bbox.min_lon,
+            yll_corner: bbox.min_lat,
+            cell_size,
+            ncols,
+            nrows,
+            values: vec![None; ncols * nrows],
+        }
+    }
+
+    /// Returns the value of a cell (row 0 is the northernmost row).
+    pub fn value(&self, row: usize, col: usize) -> Option<f64> {
+        self.values[row * self.ncols + col]
+    }
+
+    /// Returns the bounds of a cell as `(min_lon, min_lat, max_lon, max_lat)`.
+    pub fn cell_bounds(&self, row: usize, col: usize) -> (f64, f64, f64, f64) {
+        let min_lon = self.xll_corner + col as f64 * self.cell_size;
+        let max_lat = self.yll_corner + (self.nrows - row) as f64 * self.cell_size;
+        (
+            min_lon,
+            max_lat - self.cell_size,
+            min_lon + self.cell_size,
+            max_lat,
+        )
+    }
+
+    /// Returns the centre of a cell as `(latitude, longitude)`.
+    pub fn cell_centre(&self, row: usize, col: usize) -> (f64, f64) {
+        let (min_lon, min_lat, max_lon, max_lat) = self.cell_bounds(row, col);
+        ((min_lat + max_lat) / 2.0, (min_lon + max_lon) / 2.0)
+    }
+}
+
+/// Interpolates the samples onto a grid covering `bbox`, estimating each cell at its centre.
+///
+/// Returns `None` if there are no samples or the kriging model cannot be fitted.
+pub fn interpolate_grid(
+    samples: &[SamplePoint],
+    bbox: &BoundingBox,
+    cell_size: f64,
+    method: InterpolationMethod,
+) -> Option<Grid> {
+    if samples.is_empty() {
+        return None;
+    }
+    let kriging = match method {
+        InterpolationMethod::SimpleKriging => Some(SimpleKriging::fit(samples)?),
+        InterpolationMethod::Idw { .. } => None,
+    };
+    let mut grid = Grid::new(bbox, cell_size);
+    for row in 0..grid.nrows {
+        for col in 0..grid.ncols {
+            let (latitude, longitude) = grid.cell_centre(row, col);
+            grid.values[row * grid.ncols + col] = match (method, &kriging) {
+                (InterpolationMethod::Idw { power }, _) => {
+                    idw_estimate(samples, latitude, longitude, power)
+                },
+                (_, Some(model)) => Some(model.estimate(latitude, longitude)),
+                (InterpolationMethod::SimpleKriging, None) => None,
+            };
+        }
+    }
+    Some(grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::DEFAULT_IDW_POWER;
+
+    #[test]
+    fn test_bounding_box_parse() {
+        let bbox = BoundingBox::parse("3.3, 50.7, 7.3, 53.6").unwrap();
+        assert_eq!(bbox.min_lon, 3.3);
+        assert_eq!(bbox.max_lat, 53.6);
+        assert!(BoundingBox::parse("3.3,50.7,7.3").is_err());
+        assert!(BoundingBox::parse("7.3,50.7,3.3,53.6").is_err());
+        assert!(BoundingBox::parse("3.3,50.7,x,53.6").is_err());
+    }
+
+    #[test]
+    fn test_grid_layout_is_north_to_south() {
+        let bbox = BoundingBox::parse("4,50,6,51").unwrap();
+        let grid = Grid::new(&bbox, 0.5);
+        assert_eq!((grid.ncols, grid.nrows), (4, 2));
+        assert_eq!(grid.cell_bounds(0, 0), (4.0, 50.5, 4.5, 51.0));
+        assert_eq!(grid.cell_centre(1, 3), (50.25, 5.75));
+    }
+
+    #[test]
+    fn test_interpolate_grid() {
+        let bbox = BoundingBox::parse("4,50,6,51").unwrap();
+        let samples = [
+            SamplePoint {
+                latitude: 50.75,
+                longitude: 4.25,
+                value: 10.0,
+            },
+            SamplePoint {
+                latitude: 50.25,
+                longitude: 5.75,
+                value: 30.0,
+            },
+        ];
+        for method in [
+            InterpolationMethod::Idw {
+                power: DEFAULT_IDW_POWER,
+            },
+            InterpolationMethod::SimpleKriging,
+        ] {
+            let grid = interpolate_grid(&samples, &bbox, 0.5, method).unwrap();
+            assert!((grid.value(0, 0).unwrap() - 10.0).abs() < 0.01);
+            assert!((grid.value(1, 3).unwrap() - 30.0).abs() < 0.01);
+            assert!(grid.values.iter().all(|v| v.is_some()));
+        }
+        assert!(interpolate_grid(&[], &bbox, 0.5, InterpolationMethod::SimpleKriging).is_none());
+    }
+}
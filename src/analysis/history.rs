@@ -0,0 +1,260 @@
+//! Derivation of the metadata changes (moves, new providers, added and removed sensors) from
+//! the stored location and sensor versions.
+
+use crate::models::{LocationVersion, MetadataChange, SensorVersion};
+
+fn text(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("-")
+}
+
+fn coordinates(version: &LocationVersion) -> String {
+    match (version.latitude, version.longitude) {
+        (Some(lat), Some(lon)) => format!("({:.5}, {:.5})", lat, lon),
+        _ => "(unknown)".to_string(),
+    }
+}
+
+fn kind(is_monitor: bool) -> &'static str {
+    if is_monitor {
+        "monitor"
+    } else {
+        "low-cost sensor"
+    }
+}
+
+/// Describes what changed between two versions of a location.
+fn location_differences(old: &LocationVersion, new: &LocationVersion) -> Vec<String> {
+    let mut differences = Vec::new();
+    if (old.latitude, old.longitude) != (new.latitude, new.longitude) {
+        differences.push(format!(
+            "Moved from {} to {}",
+            coordinates(old),
+            coordinates(new)
+        ));
+    }
+    for (field, old_value, new_value) in [
+        ("Name", &old.name, &new.name),
+        ("Locality", &old.locality, &new.locality),
+        ("Provider", &old.provider_name, &new.provider_name),
+        ("Owner", &old.owner_name, &new.owner_name),
+    ] {
+        if old_value != new_value {
+            differences.push(format!(
+                "{} changed from '{}' to '{}'",
+                field,
+                text(old_value),
+                text(new_value)
+            ));
+        }
+    }
+    if old.country_code != new.country_code {
+        differences.push(format!(
+            "Country changed from {} to {}",
+            old.country_code, new.country_code
+        ));
+    }
+    if old.timezone != new.timezone {
+        differences.push(format!(
+            "Timezone changed from {} to {}",
+            old.timezone, new.timezone
+        ));
+    }
+    if old.is_monitor != new.is_monitor {
+        differences.push(format!("Reclassified as {}", kind(new.is_monitor)));
+    }
+    if old.is_mobile != new.is_mobile {
+        differences.push(
+            if new.is_mobile {
+                "Became mobile"
+            } else {
+                "Became stationary"
+            }
+            .to_string(),
+        );
+    }
+    differences
+}
+
+/// Describes what changed between two versions of a sensor.
+fn sensor_differences(old: &SensorVersion, new: &SensorVersion) -> Vec<String> {
+    let mut differences = Vec::new();
+    if old.location_id != new.location_id {
+        differences.push(format!(
+            "Moved from location {} to {}",
+            old.location_id, new.location_id
+        ));
+    }
+    if old.parameter_name != new.parameter_name {
+        differences.push(format!(
+            "Parameter changed from {} to {}",
+            old.parameter_name, new.parameter_name
+        ));
+    }
+    if old.units != new.units {
+        differences.push(format!("Units changed from {} to {}", old.units, new.units));
+    }
+    if old.name != new.name {
+        differences.push(format!("Renamed from '{}' to '{}'", old.name, new.name));
+    }
+    differences
+}
+
+/// Lists the changes recorded in the versions of a location and its sensors, oldest first.
+///
+/// `locations` and `sensors` must be ordered by ID and `valid_from`, as loaded from the
+/// database. The first version of a location or sensor is reported as first seen or added; a
+/// sensor whose last version was closed is reported as removed.
+pub fn metadata_changes(
+    locations: &[LocationVersion],
+    sensors: &[SensorVersion],
+) -> Vec<MetadataChange> {
+    let mut changes = Vec::new();
+    for (index, version) in locations.iter().enumerate() {
+        match index.checked_sub(1).map(|i| &locations[i]) {
+            Some(previous) if previous.location_id == version.location_id => {
+                changes.extend(location_differences(previous, version).into_iter().map(
+                    |description| MetadataChange {
+                        at: version.valid_from,
+                        sensor_id: None,
+                        description,
+                    },
+                ));
+            },
+            _ => changes.push(MetadataChange {
+                at: version.valid_from,
+                sensor_id: None,
+                description: format!(
+                    "First seen as {} by {}",
+                    kind(version.is_monitor),
+                    text(&version.provider_name)
+                ),
+            }),
+        }
+    }
+
+    for (index, version) in sensors.iter().enumerate() {
+        let sensor_id = Some(version.sensor_id);
+        let previous = index
+            .checked_sub(1)
+            .map(|i| &sensors[i])
+            .filter(|previous| previous.sensor_id == version.sensor_id);
+        match previous {
+            // A gap between two versions means the sensor was removed and re-added.
+            Some(previous) if previous.valid_to == Some(version.valid_from) => {
+                changes.extend(sensor_differences(previous, version).into_iter().map(
+                    |description| MetadataChange {
+                        at: version.valid_from,
+                        sensor_id,
+                        description,
+                    },
+                ));
+            },
+            _ => changes.push(MetadataChange {
+                at: version.valid_from,
+                sensor_id,
+                description: format!(
+                    "Sensor added ({}, {})",
+                    version.parameter_name, version.units
+                ),
+            }),
+        }
+
+        let next = sensors
+            .get(index + 1)
+            .filter(|next| next.sensor_id == version.sensor_id);
+        if let Some(valid_to) = version.valid_to {
+            if next.is_none_or(|next| next.valid_from != valid_to) {
+                changes.push(MetadataChange {
+                    at: valid_to,
+                    sensor_id,
+                    description: format!("Sensor removed ({})", version.parameter_name),
+                });
+            }
+        }
+    }
+
+    // Stable, so the location changes come before the sensor changes of the same moment.
+    changes.sort_by_key(|change| change.at);
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn at(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, day, 0, 0, 0).unwrap()
+    }
+
+    fn location(from: u32, to: Option<u32>, provider: &str, latitude: f64) -> LocationVersion {
+        LocationVersion {
+            location_id: 1,
+            name: Some("Station".to_string()),
+            locality: Some("Utrecht".to_string()),
+            country_code: "NL".to_string(),
+            timezone: "Europe/Amsterdam".to_string(),
+            latitude: Some(latitude),
+            longitude: Some(5.0),
+            is_mobile: false,
+            is_monitor: true,
+            owner_name: None,
+            provider_name: Some(provider.to_string()),
+            datetime_last: None,
+            valid_from: at(from),
+            valid_to: to.map(at),
+        }
+    }
+
+    fn sensor(id: i64, from: u32, to: Option<u32>, units: &str) -> SensorVersion {
+        SensorVersion {
+            sensor_id: id,
+            location_id: 1,
+            name: format!("pm25 {}", units),
+            parameter_name: "pm25".to_string(),
+            units: units.to_string(),
+            valid_from: at(from),
+            valid_to: to.map(at),
+        }
+    }
+
+    #[test]
+    fn test_metadata_changes() {
+        let changes = metadata_changes(
+            &[
+                location(1, Some(5), "AirNow", 52.0),
+                location(5, None, "EEA", 52.1),
+            ],
+            &[
+                sensor(11, 1, Some(3), "ppm"),
+                sensor(11, 3, Some(8), "µg/m³"),
+                sensor(12, 6, Some(7), "µg/m³"),
+                sensor(12, 9, None, "µg/m³"),
+            ],
+        );
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (c.at, c.sensor_id, c.description.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (at(1), None, "First seen as monitor by AirNow"),
+                (at(1), Some(11), "Sensor added (pm25, ppm)"),
+                (at(3), Some(11), "Units changed from ppm to µg/m³"),
+                // The sensor name contains the units, so it changed as well.
+                (at(3), Some(11), "Renamed from 'pm25 ppm' to 'pm25 µg/m³'"),
+                (
+                    at(5),
+                    None,
+                    "Moved from (52.00000, 5.00000) to (52.10000, 5.00000)"
+                ),
+                (at(5), None, "Provider changed from 'AirNow' to 'EEA'"),
+                (at(6), Some(12), "Sensor added (pm25, µg/m³)"),
+                (at(7), Some(12), "Sensor removed (pm25)"),
+                (at(8), Some(11), "Sensor removed (pm25)"),
+                (at(9), Some(12), "Sensor added (pm25, µg/m³)"),
+            ]
+        );
+    }
+}
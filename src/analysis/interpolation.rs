@@ -0,0 +1,260 @@
+//! Spatial interpolation of point measurements.
+//!
+//! Two methods are provided:
+//! - **Inverse distance weighting (IDW):** the weighted mean of the sample values, with weights
+//!   `1 / d^power` based on the great-circle distance `d`.
+//! - **Simple kriging:** the sample mean plus a weighted sum of the sample residuals, with
+//!   weights derived from an exponential covariance model fitted to the samples. The sill is
+//!   the sample variance and the practical range half the largest distance between samples.
+
+use super::haversine_km;
+
+/// Default IDW power; 2 is the customary choice and favours the closest samples.
+pub const DEFAULT_IDW_POWER: f64 = 2.0;
+
+/// Samples closer than this distance (in kilometres) are treated as coinciding with the point.
+const COINCIDENT_KM: f64 = 1e-6;
+
+/// A measured value at a geographic position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplePoint {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub value: f64,
+}
+
+/// Estimates the value at (`latitude`, `longitude`) by inverse distance weighting.
+///
+/// Returns the value of a sample located at the point itself, if any, and `None` when there
+/// are no samples.
+pub fn idw_estimate(
+    samples: &[SamplePoint],
+    latitude: f64,
+    longitude: f64,
+    power: f64,
+) -> Option<f64> {
+    let mut weighted_sum = 0.0;
+    let mut weight_total = 0.0;
+    for s in samples {
+        let distance = haversine_km(latitude, longitude, s.latitude, s.longitude);
+        if distance < COINCIDENT_KM {
+            return Some(s.value);
+        }
+        let weight = distance.powf(-power);
+        weighted_sum += weight * s.value;
+        weight_total += weight;
+    }
+    (weight_total > 0.0).then(|| weighted_sum / weight_total)
+}
+
+/// Share of the sill added to the diagonal of the covariance matrix, which keeps the kriging
+/// system solvable when samples (nearly) coincide.
+const KRIGING_NUGGET: f64 = 1e-6;
+
+/// Method used to estimate values between samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InterpolationMethod {
+    /// Inverse distance weighting with the given power.
+    Idw { power: f64 },
+    /// Simple kriging with an exponential covariance model fitted to the samples.
+    SimpleKriging,
+}
+
+impl InterpolationMethod {
+    /// Returns a short label for the method.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InterpolationMethod::Idw { .. } => "idw",
+            InterpolationMethod::SimpleKriging => "simple-kriging",
+        }
+    }
+}
+
+/// A simple kriging model fitted to a set of samples.
+#[derive(Debug, Clone)]
+pub struct SimpleKriging {
+    samples: Vec<SamplePoint>,
+    mean: f64,
+    sill: f64,
+    range_km: f64,
+    /// `C⁻¹ (z - mean)`: the estimate is `mean + c(p) · residual_weights`, where `c(p)` holds the
+    /// covariances between the point and the samples.
+    residual_weights: Vec<f64>,
+}
+
+impl SimpleKriging {
+    /// Fits the covariance model to the samples. Returns `None` when there are no samples or
+    /// the covariance matrix cannot be inverted.
+    pub fn fit(samples: &[SamplePoint]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let n = samples.len() as f64;
+        let mean = samples.iter().map(|s| s.value).sum::<f64>() / n;
+        let sill = samples
+            .iter()
+            .map(|s| (s.value - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        let mut max_distance: f64 = 0.0;
+        for (i, a) in samples.iter().enumerate() {
+            for b in &samples[i + 1..] {
+                max_distance = max_distance.max(haversine_km(
+                    a.latitude,
+                    a.longitude,
+                    b.latitude,
+                    b.longitude,
+                ));
+            }
+        }
+        let mut model = Self {
+            samples: samples.to_vec(),
+            mean,
+            sill,
+            range_km: (max_distance / 2.0).max(1.0),
+            residual_weights: Vec::new(),
+        };
+        if sill == 0.0 {
+            // Constant field: every estimate is the mean.
+            model.residual_weights = vec![0.0; samples.len()];
+            return Some(model);
+        }
+
+        let matrix: Vec<Vec<f64>> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                samples
+                    .iter()
+                    .enumerate()
+                    .map(|(j, b)| {
+                        let c = model.covariance(haversine_km(
+                            a.latitude,
+                            a.longitude,
+                            b.latitude,
+                            b.longitude,
+                        ));
+                        if i == j {
+                            c + KRIGING_NUGGET * sill
+                        } else {
+                            c
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let residuals: Vec<f64> = samples.iter().map(|s| s.value - mean).collect();
+        model.residual_weights = solve(matrix, residuals)?;
+        Some(model)
+    }
+
+    /// Exponential covariance at distance `h` (km).
+    fn covariance(&self, h: f64) -> f64 {
+        self.sill * (-3.0 * h / self.range_km).exp()
+    }
+
+    /// Estimates the value at (`latitude`, `longitude`).
+    pub fn estimate(&self, latitude: f64, longitude: f64) -> f64 {
+        self.mean
+            + self
+                .samples
+                .iter()
+                .zip(&self.residual_weights)
+                .map(|(s, w)| {
+                    w * self.covariance(haversine_km(latitude, longitude, s.latitude, s.longitude))
+                })
+                .sum::<f64>()
+    }
+}
+
+/// Solves `a x = b` by Gaussian elimination with partial pivoting. Returns `None` if `a` is
+/// singular.
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let pivot_row = a[col].clone();
+        for row in col + 1..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (x, p) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *x -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(latitude: f64, longitude: f64, value: f64) -> SamplePoint {
+        SamplePoint {
+            latitude,
+            longitude,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_idw_estimate_weights_by_distance() {
+        let samples = [sample(52.0, 5.0, 10.0), sample(52.0, 5.2, 30.0)];
+        // The midpoint is equidistant to both samples.
+        let mid = idw_estimate(&samples, 52.0, 5.1, DEFAULT_IDW_POWER).unwrap();
+        assert!((mid - 20.0).abs() < 0.01, "unexpected estimate {}", mid);
+        // Closer to the first sample, so the estimate leans towards its value.
+        let near_first = idw_estimate(&samples, 52.0, 5.05, DEFAULT_IDW_POWER).unwrap();
+        assert!(near_first > 10.0 && near_first < 20.0);
+    }
+
+    #[test]
+    fn test_idw_estimate_edge_cases() {
+        assert_eq!(idw_estimate(&[], 52.0, 5.0, DEFAULT_IDW_POWER), None);
+        let samples = [sample(52.0, 5.0, 10.0), sample(53.0, 6.0, 30.0)];
+        assert_eq!(
+            idw_estimate(&samples, 52.0, 5.0, DEFAULT_IDW_POWER),
+            Some(10.0)
+        );
+    }
+
+    #[test]
+    fn test_simple_kriging_honours_samples_and_reverts_to_mean() {
+        let samples = [
+            sample(52.0, 5.0, 10.0),
+            sample(52.0, 5.2, 30.0),
+            sample(52.2, 5.1, 20.0),
+        ];
+        let model = SimpleKriging::fit(&samples).unwrap();
+        // Exact interpolator (up to the tiny nugget).
+        assert!((model.estimate(52.0, 5.0) - 10.0).abs() < 0.01);
+        assert!((model.estimate(52.0, 5.2) - 30.0).abs() < 0.01);
+        // Far away from all samples, the estimate approaches the mean.
+        assert!((model.estimate(40.0, -3.0) - 20.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_simple_kriging_edge_cases() {
+        assert!(SimpleKriging::fit(&[]).is_none());
+        let constant =
+            SimpleKriging::fit(&[sample(52.0, 5.0, 7.0), sample(53.0, 5.0, 7.0)]).unwrap();
+        assert_eq!(constant.estimate(52.5, 5.0), 7.0);
+    }
+
+    #[test]
+    fn test_solve_linear_system() {
+        let x = solve(vec![vec![2.0, 1.0], vec![1.0, 3.0]], vec![3.0, 5.0]).unwrap();
+        assert!((x[0] - 0.8).abs() < 1e-9 && (x[1] - 1.4).abs() < 1e-9);
+        assert!(solve(vec![vec![1.0, 2.0], vec![2.0, 4.0]], vec![1.0, 2.0]).is_none());
+    }
+}
@@ -0,0 +1,23 @@
+//! Provides analytical routines that operate on measurements loaded from the database.
+//!
+//! Includes:
+//! - `anomaly`: Detection of broken-sensor patterns (outliers, flat lines, spikes, neighbour deviations).
+//! - `compare`: Comparison of the averages of two periods, accounting for sensor churn.
+//! - `geo`: Geographic helpers such as great-circle distances and nearest-location lookups.
+//! - `history`: Changes of the location and sensor metadata derived from their versions.
+//! - `grid`: Regular latitude/longitude grids of interpolated values.
+//! - `interpolation`: Spatial interpolation (inverse distance weighting, simple kriging) of point values.
+
+mod anomaly;
+mod compare;
+mod geo;
+mod grid;
+mod history;
+mod interpolation;
+
+pub use anomaly::*;
+pub use compare::*;
+pub use geo::*;
+pub use grid::*;
+pub use history::*;
+pub use interpolation::*;
@@ -1,8 +1,11 @@
 //! Provides clients and utilities for interacting with external APIs.
 //!
 //! Includes:
+//! - `source`: The `DataSource` trait implemented by every provider that feeds the import.
 //! - `openaq`: Client for the real OpenAQ API.
 
 mod openaq;
+mod source;
 
 pub use openaq::*;
+pub use source::*;
@@ -1,10 +1,14 @@
 //! Provides an asynchronous client for interacting with the OpenAQ v3 API.
 //!
-//! Defines the `OpenAQClient` for fetching air quality measurements.
+//! Defines the `OpenAQClient` for fetching air quality measurements, which also implements
+//! the `DataSource` trait.
 
-use crate::error::Result;
+use super::DataSource;
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
 #[allow(unused_imports)] // Allow imports used only in signatures
 use crate::models::{DailyMeasurement, DailyMeasurementResponse, Location, LocationsResponse};
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use reqwest::header::HeaderMap;
 use reqwest::Client;
@@ -15,6 +19,18 @@ use tracing::{debug, error, info, warn};
 /// Base URL for the OpenAQ API v3.
 const BASE_URL: &str = "https://api.openaq.org/v3";
 
+/// A mapping from country codes to their corresponding IDs in the OpenAQ API.
+pub fn get_country_id_map() -> std::collections::HashMap<&'static str, u32> {
+    let mut map = std::collections::HashMap::new();
+    map.insert("PK", 109); // Pakistan
+    map.insert("NL", 94); // Netherlands
+    map.insert("DE", 50); // Germany
+    map.insert("GR", 80); // Greece
+    map.insert("ES", 67); // Spain
+    map.insert("FR", 22); // France
+    map
+}
+
 /// An asynchronous client for fetching air quality data from the OpenAQ API v3.
 ///
 /// Holds a `reqwest::Client` instance for making HTTP requests and the API key.
@@ -77,10 +93,16 @@ impl OpenAQClient {
                         "Rate limit low ({} remaining). Sleeping for {:?} seconds...",
                         rem, sleep_duration
                     );
+                    metrics().rate_limit_sleeps.inc();
+                    metrics()
+                        .rate_limit_sleep_seconds
+                        .inc_by(sleep_duration.as_secs_f64());
                     sleep(sleep_duration).await;
                 } else {
                     // Fallback sleep if reset header is missing/invalid but remaining is low
                     warn!("Rate limit low ({} remaining) but reset header missing/invalid. Sleeping for 10s as fallback.", rem);
+                    metrics().rate_limit_sleeps.inc();
+                    metrics().rate_limit_sleep_seconds.inc_by(10.0);
                     sleep(StdDuration::from_secs(10)).await;
                 }
             }
@@ -140,6 +162,10 @@ impl OpenAQClient {
             .send()
             .await;
 
+        metrics().record_api_request(
+            "locations",
+            response_result.as_ref().ok().map(|r| r.status().as_u16()),
+        );
         let response = match response_result {
             Ok(resp) => resp,
             Err(e) => {
@@ -186,6 +212,71 @@ impl OpenAQClient {
         Ok(locations) // Return the fetched locations directly
     }
 
+    /// Fetches the parameter catalogue (all pollutants and other quantities OpenAQ knows about).
+    ///
+    /// The catalogue is small, so a single page with a generous limit is requested.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Api` if the request fails, the API returns an error,
+    /// or the response cannot be parsed.
+    pub async fn get_parameters(&self) -> Result<Vec<crate::models::Parameter>> {
+        info!("Fetching parameter catalogue");
+        let url = format!("{}/parameters", self.base_url);
+        debug!("Requesting parameters URL: {}", url);
+
+        let response_result = self
+            .client
+            .get(&url)
+            .header("X-API-Key", &self.api_key)
+            .query(&[("limit", "1000"), ("page", "1")])
+            .send()
+            .await;
+
+        metrics().record_api_request(
+            "parameters",
+            response_result.as_ref().ok().map(|r| r.status().as_u16()),
+        );
+        let response = match response_result {
+            Ok(resp) => resp,
+            Err(e) => {
+                error!("Network request failed for parameters: {}", e);
+                return Err(e.into());
+            },
+        };
+
+        let response = match response.error_for_status() {
+            Ok(resp) => resp,
+            Err(e) => {
+                let status = e.status().unwrap_or_default();
+                error!(
+                    "API request for parameters to {} failed with status {}: {}",
+                    url, status, e
+                );
+                return Err(e.into());
+            },
+        };
+
+        // Clone headers before consuming the body
+        let headers = response.headers().clone();
+
+        let api_response: crate::models::ParametersResponse = match response.json().await {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                error!("Failed to parse parameters JSON response: {}", e);
+                return Err(e.into());
+            },
+        };
+
+        self.handle_rate_limit(&headers).await;
+
+        info!(
+            "Successfully fetched {} parameters",
+            api_response.results.len()
+        );
+        Ok(api_response.results)
+    }
+
     /// Fetches daily aggregated measurements for a specific sensor within a given date range.
     ///
     /// Handles pagination to retrieve all available daily measurements within the range.
@@ -231,6 +322,10 @@ impl OpenAQClient {
                 .send()
                 .await;
 
+            metrics().record_api_request(
+                "measurements",
+                response_result.as_ref().ok().map(|r| r.status().as_u16()),
+            );
             let response = match response_result {
                 Ok(resp) => resp,
                 Err(e) => {
@@ -306,3 +401,44 @@ impl OpenAQClient {
         Ok(all_measurements)
     }
 }
+
+#[async_trait]
+impl DataSource for OpenAQClient {
+    fn name(&self) -> &str {
+        crate::models::OPENAQ_SOURCE
+    }
+
+    async fn get_parameters(&self) -> Result<Vec<crate::models::Parameter>> {
+        OpenAQClient::get_parameters(self).await
+    }
+
+    /// Fetches the top 10 reference monitors of the country.
+    async fn discover_locations(&self, country: &str) -> Result<Vec<Location>> {
+        let country_id = *get_country_id_map().get(country).ok_or_else(|| {
+            error!("No country ID mapping found for {}", country);
+            AppError::Import(format!("No OpenAQ country ID mapping for {}", country))
+        })?;
+        self.get_locations_for_country(&[country_id]).await
+    }
+
+    /// Returns the sensors listed with the location by `/v3/locations`.
+    async fn list_sensors(&self, location: &Location) -> Result<Vec<crate::models::SensorBase>> {
+        Ok(location.sensors.clone())
+    }
+
+    async fn fetch_measurements(
+        &self,
+        location: &Location,
+        sensor: &crate::models::SensorBase,
+        date_from: DateTime<Utc>,
+        date_to: DateTime<Utc>,
+    ) -> Result<Vec<crate::models::DbMeasurement>> {
+        let measurements = self
+            .get_measurements_for_sensor(sensor.id, date_from, date_to)
+            .await?;
+        Ok(measurements
+            .iter()
+            .map(|m| crate::models::DbMeasurement::from_daily_measurement(m, location, sensor))
+            .collect())
+    }
+}
@@ -0,0 +1,61 @@
+//! Defines the `DataSource` trait through which providers feed the import pipeline.
+//!
+//! The import discovers the locations of each country, lists their sensors and fetches the
+//! daily measurements of every sensor for the requested range. Every row it stores is tagged
+//! with the name of the source in the `source` column of the `locations`, `sensors` and
+//! `measurements` tables.
+
+use crate::error::Result;
+use crate::models::{DbMeasurement, Location, Parameter, SensorBase};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+/// A provider of locations, sensors and daily measurements (e.g. OpenAQ, the EEA, a folder
+/// of station exports or an in-house sensor network).
+///
+/// Location and sensor IDs share the ID space of all sources, because they are the primary
+/// keys of the `locations` and `sensors` tables. Sources other than OpenAQ should therefore
+/// use IDs that cannot collide with OpenAQ IDs, such as negative IDs.
+#[async_trait]
+pub trait DataSource: Send + Sync {
+    /// Short name stored in the `source` column (e.g. "openaq").
+    fn name(&self) -> &str;
+
+    /// Fetches the parameter catalogue of the source. Sources without a catalogue return an
+    /// empty list, which keeps the stored catalogue unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `AppError` if the catalogue cannot be fetched.
+    async fn get_parameters(&self) -> Result<Vec<Parameter>> {
+        Ok(Vec::new())
+    }
+
+    /// Discovers the locations to import for a country (2-letter code).
+    ///
+    /// # Errors
+    ///
+    /// Returns an `AppError` if the locations cannot be fetched.
+    async fn discover_locations(&self, country: &str) -> Result<Vec<Location>>;
+
+    /// Lists the sensors of a location.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `AppError` if the sensors cannot be fetched.
+    async fn list_sensors(&self, location: &Location) -> Result<Vec<SensorBase>>;
+
+    /// Fetches the daily measurements of a sensor between `date_from` and `date_to` (UTC),
+    /// ready for insertion and tagged with the name of the source.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `AppError` if the measurements cannot be fetched.
+    async fn fetch_measurements(
+        &self,
+        location: &Location,
+        sensor: &SensorBase,
+        date_from: DateTime<Utc>,
+        date_to: DateTime<Utc>,
+    ) -> Result<Vec<DbMeasurement>>;
+}
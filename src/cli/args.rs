@@ -0,0 +1,985 @@
+//! Defines the command-line arguments selecting how the application runs.
+
+use super::{CompareArgs, DistributionArgs, FileImportArgs, NearArgs, QUERY_ROW_LIMIT};
+use crate::db::DistributionGroup;
+use crate::export::{ColumnarFormat, ExportTable};
+use crate::import::{ColumnMapping, FILE_SOURCE};
+use crate::models::{
+    DateRange, HealthThresholds, InventoryFilter, MeasurementQuery, SpatialGrouping, TimeBucket,
+};
+use chrono::{Duration, NaiveDate, NaiveTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use cron::Schedule;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Air quality analysis: an interactive menu by default, or one of the modes below.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct CliArgs {
+    #[command(subcommand)]
+    pub mode: Option<RunMode>,
+}
+
+/// Non-interactive run modes.
+#[derive(Debug, Subcommand)]
+pub enum RunMode {
+    /// Serve the analytic queries as a JSON REST API.
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+    },
+    /// Import data periodically on a cron-like schedule until SIGTERM or Ctrl+C.
+    Daemon(Box<DaemonArgs>),
+    /// Show the latest and the latest successful run of each daemon job.
+    Status,
+    /// Write a self-contained HTML report of the stored data.
+    Report {
+        /// Path of the HTML file to write.
+        #[arg(long, short, default_value = "report.html")]
+        output: PathBuf,
+        /// Number of past days covered by the report.
+        #[arg(long, default_value_t = 7, value_parser = clap::value_parser!(i64).range(1..=90))]
+        days: i64,
+        /// Custom MiniJinja template (defaults to `REPORT_TEMPLATE`, then the built-in one).
+        #[arg(long)]
+        template: Option<PathBuf>,
+        /// Title shown at the top of the report.
+        #[arg(long, default_value = "Air Quality Report")]
+        title: String,
+    },
+    /// Show a full-screen dashboard of the stored data, with imports on a key press.
+    Dashboard {
+        /// Number of past days fetched by an import started from the dashboard.
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(i64).range(1..=30))]
+        import_days: i64,
+    },
+    /// List silent and offline sensors and localities with shrinking coverage.
+    Health(HealthArgs),
+    /// Browse the stored locations.
+    Locations {
+        #[command(subcommand)]
+        command: InventoryCommand,
+    },
+    /// Browse the stored sensors.
+    Sensors {
+        #[command(subcommand)]
+        command: InventoryCommand,
+    },
+    /// Delete the measurements beyond their retention, optionally archiving them first.
+    ///
+    /// Only daily aggregates are stored (both the API and the file import aggregate to one row
+    /// per sensor and day), so the policies have a single tier: how many days of daily
+    /// measurements are kept. Monthly partitions entirely past every cutoff are dropped whole.
+    Prune(PruneArgs),
+    /// Export the stored tables as Parquet or Arrow IPC files.
+    Export(TableExportArgs),
+    /// Import measurements from CSV or gzipped CSV files.
+    ImportFile(ImportFileArgs),
+    /// Aggregate the stored measurements per group and period, one row per parameter.
+    Query(QueryArgs),
+    /// Compare the per-parameter averages of two periods for a country or locality.
+    Compare(ComparePeriodsArgs),
+    /// Find the stored locations nearest to a coordinate.
+    Near(NearPointArgs),
+    /// Show the quantiles of the daily values of one parameter per locality and per sensor.
+    Distribution(DistributionPeriodArgs),
+}
+
+/// Arguments of the `export` run mode and the Export Tables menu entry.
+#[derive(Debug, Clone, Args)]
+pub struct TableExportArgs {
+    /// File format.
+    #[arg(long, value_enum, default_value_t = ColumnarFormat::Parquet)]
+    pub format: ColumnarFormat,
+    /// Directory the files and partitions are written to.
+    #[arg(long = "out", default_value = "exports")]
+    pub output_dir: PathBuf,
+    /// Tables to export, comma-separated.
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = ExportTable::ALL)]
+    pub tables: Vec<ExportTable>,
+}
+
+/// Arguments of the `import-file` run mode.
+#[derive(Debug, Args)]
+pub struct ImportFileArgs {
+    /// Files, directories (all their CSV files) or glob patterns, e.g. 'archive/2024-*.csv.gz'.
+    #[arg(required = true)]
+    pub paths: Vec<String>,
+    /// Column mapping file (JSON); defaults to the layout of the OpenAQ archive.
+    #[arg(long)]
+    pub mapping: Option<PathBuf>,
+    /// Two-letter country code for rows without a country column.
+    #[arg(long, default_value = "NL")]
+    pub country: String,
+    /// Provider name stored with the measurements (defaults to "OpenAQ archive", or "Local
+    /// export" with a mapping file).
+    #[arg(long)]
+    pub provider: Option<String>,
+    /// Data source name stored with the measurements.
+    #[arg(long, default_value = FILE_SOURCE)]
+    pub source: String,
+    /// Number of observations expected per day (e.g. 24 for hourly data), if known.
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub expected_per_day: Option<i32>,
+}
+
+impl ImportFileArgs {
+    /// Converts the arguments into those of the `ImportFile` command, reading the mapping file.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `ColumnMapping::from_json_file` if the mapping file cannot be read.
+    pub fn file_import(&self) -> crate::error::Result<FileImportArgs> {
+        let (mapping, provider) = match &self.mapping {
+            Some(path) => (ColumnMapping::from_json_file(path)?, "Local export"),
+            None => (ColumnMapping::default(), "OpenAQ archive"),
+        };
+        Ok(FileImportArgs {
+            paths: self.paths.clone(),
+            mapping,
+            country: self.country.trim().to_uppercase(),
+            provider: self
+                .provider
+                .clone()
+                .unwrap_or_else(|| provider.to_string()),
+            source: self.source.trim().to_lowercase(),
+            expected_per_day: self.expected_per_day,
+        })
+    }
+}
+
+/// Filters and grouping of the `query` run mode. List filters take comma-separated values;
+/// omitted filters do not restrict the query.
+#[derive(Debug, Args)]
+pub struct QueryArgs {
+    /// Two-letter country codes, e.g. NL,DE.
+    #[arg(long = "country", value_delimiter = ',')]
+    pub countries: Vec<String>,
+    /// Localities (cities).
+    #[arg(long = "locality", value_delimiter = ',')]
+    pub localities: Vec<String>,
+    /// OpenAQ location IDs.
+    #[arg(long = "location", value_delimiter = ',')]
+    pub location_ids: Vec<i64>,
+    /// OpenAQ sensor IDs.
+    #[arg(long = "sensor", value_delimiter = ',')]
+    pub sensor_ids: Vec<i64>,
+    /// Parameter names, e.g. pm25,no2.
+    #[arg(long = "parameter", value_delimiter = ',')]
+    pub parameters: Vec<String>,
+    /// Provider name (exact).
+    #[arg(long)]
+    pub provider: Option<String>,
+    /// Reference monitors only.
+    #[arg(long)]
+    pub monitor_only: bool,
+    /// Number of past days to include.
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..), conflicts_with = "from")]
+    pub days: Option<i64>,
+    /// First day to include (YYYY-MM-DD, UTC).
+    #[arg(long)]
+    pub from: Option<NaiveDate>,
+    /// Last day to include (YYYY-MM-DD, UTC).
+    #[arg(long)]
+    pub to: Option<NaiveDate>,
+    /// Spatial level the rows are grouped by.
+    #[arg(long, value_enum, default_value_t = SpatialGrouping::Country)]
+    pub group_by: SpatialGrouping,
+    /// Time bucket the rows are grouped by.
+    #[arg(long, value_enum, default_value_t = TimeBucket::Day)]
+    pub per: TimeBucket,
+    /// Maximum number of rows.
+    #[arg(long, default_value_t = QUERY_ROW_LIMIT, value_parser = clap::value_parser!(i64).range(1..))]
+    pub limit: i64,
+}
+
+impl QueryArgs {
+    /// Converts the arguments into the measurement query, with country codes in upper case and
+    /// parameter names in lower case.
+    pub fn query(&self) -> MeasurementQuery {
+        let midnight = |day: NaiveDate| day.and_time(NaiveTime::MIN).and_utc();
+        MeasurementQuery {
+            countries: self
+                .countries
+                .iter()
+                .map(|c| c.trim().to_uppercase())
+                .collect(),
+            localities: self.localities.clone(),
+            location_ids: self.location_ids.clone(),
+            sensor_ids: self.sensor_ids.clone(),
+            parameters: self
+                .parameters
+                .iter()
+                .map(|p| p.trim().to_lowercase())
+                .collect(),
+            date_from: match self.days {
+                Some(days) => Some(Utc::now() - Duration::days(days)),
+                None => self.from.map(midnight),
+            },
+            date_to: self.to.map(|day| midnight(day) + Duration::days(1)),
+            monitor_only: self.monitor_only,
+            provider: self.provider.clone(),
+            spatial: self.group_by,
+            bucket: self.per,
+            limit: Some(self.limit),
+            offset: None,
+        }
+    }
+}
+
+/// Arguments of the `compare` run mode. The current period is `--current` or the last `--days`
+/// days; it is compared with `--baseline`, the same days a year earlier (`--year-over-year`)
+/// or otherwise the period of the same length right before it.
+#[derive(Debug, Args)]
+pub struct ComparePeriodsArgs {
+    /// Two-letter country code, e.g. NL.
+    pub country: String,
+    /// Restrict the comparison to one locality (city) of the country.
+    #[arg(long)]
+    pub locality: Option<String>,
+    /// Period of interest (YYYY-MM-DD..YYYY-MM-DD, inclusive).
+    #[arg(long, value_parser = parse_date_range, conflicts_with = "days")]
+    pub current: Option<DateRange>,
+    /// Number of past days (up to and including today) forming the period of interest.
+    #[arg(long, default_value_t = 7, value_parser = clap::value_parser!(i64).range(1..))]
+    pub days: i64,
+    /// Period compared with (YYYY-MM-DD..YYYY-MM-DD, inclusive).
+    #[arg(long, value_parser = parse_date_range, conflicts_with = "year_over_year")]
+    pub baseline: Option<DateRange>,
+    /// Compare with the same days one year earlier.
+    #[arg(long)]
+    pub year_over_year: bool,
+}
+
+impl ComparePeriodsArgs {
+    /// Resolves the periods relative to `today` into the arguments of the `Compare` command.
+    pub fn compare(&self, today: NaiveDate) -> CompareArgs {
+        let current = self
+            .current
+            .unwrap_or_else(|| DateRange::last_days(today, self.days));
+        let baseline = match self.baseline {
+            Some(baseline) => baseline,
+            None if self.year_over_year => current.year_before(),
+            None => current.preceding(),
+        };
+        CompareArgs {
+            country: self.country.trim().to_uppercase(),
+            locality: self.locality.clone(),
+            current,
+            baseline,
+        }
+    }
+}
+
+/// Arguments of the `near` run mode.
+#[derive(Debug, Args)]
+pub struct NearPointArgs {
+    /// Latitude of the point, in decimal degrees.
+    #[arg(long, allow_negative_numbers = true)]
+    pub lat: f64,
+    /// Longitude of the point, in decimal degrees.
+    #[arg(long, allow_negative_numbers = true)]
+    pub lon: f64,
+    /// Search radius in kilometres.
+    #[arg(long, default_value_t = 25.0)]
+    pub radius_km: f64,
+    /// Maximum number of locations to show.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u16).range(1..))]
+    pub limit: u16,
+    /// Parameter to show values for, e.g. pm25.
+    #[arg(long, default_value = "pm25")]
+    pub parameter: String,
+    /// Number of past days the recent average covers.
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(i64).range(1..=365))]
+    pub days: i64,
+    /// Estimate the value at the point by inverse distance weighting.
+    #[arg(long)]
+    pub idw: bool,
+}
+
+impl NearPointArgs {
+    /// Converts the arguments into those of the `Near` command, with the parameter name in
+    /// lower case.
+    pub fn near(&self) -> NearArgs {
+        NearArgs {
+            latitude: self.lat,
+            longitude: self.lon,
+            radius_km: self.radius_km,
+            limit: self.limit.into(),
+            parameter: self.parameter.trim().to_lowercase(),
+            days: self.days,
+            estimate: self.idw,
+        }
+    }
+}
+
+/// Arguments of the `distribution` run mode.
+#[derive(Debug, Args)]
+pub struct DistributionPeriodArgs {
+    /// Two-letter country code, e.g. NL.
+    pub country: String,
+    /// Parameter to summarise, e.g. pm25.
+    #[arg(long, default_value = "pm25")]
+    pub parameter: String,
+    /// Number of past days to include.
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(i64).range(1..=365))]
+    pub days: i64,
+    /// Show only the table of this grouping (both by default).
+    #[arg(long, value_enum)]
+    pub group_by: Option<DistributionGroup>,
+}
+
+impl DistributionPeriodArgs {
+    /// Converts the arguments into those of the `Distribution` command, with the country code
+    /// in upper case and the parameter name in lower case.
+    pub fn distribution(&self) -> DistributionArgs {
+        DistributionArgs {
+            country: self.country.trim().to_uppercase(),
+            parameter: self.parameter.trim().to_lowercase(),
+            days: self.days,
+            groups: match self.group_by {
+                Some(group) => vec![group],
+                None => vec![DistributionGroup::Locality, DistributionGroup::Sensor],
+            },
+        }
+    }
+}
+
+/// Arguments of the `prune` run mode.
+#[derive(Debug, Args)]
+pub struct PruneArgs {
+    /// Only report what would be pruned, per country and parameter.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Retention policies file (defaults to `RETENTION_POLICIES`, then `retention.toml`).
+    #[arg(long)]
+    pub policies: Option<PathBuf>,
+    /// Days of daily measurements kept when no policy rule matches (overrides the policies file).
+    #[arg(long, value_parser = clap::value_parser!(i64).range(1..))]
+    pub keep_days: Option<i64>,
+    /// Write the pruned measurements as Parquet files below this directory before deleting.
+    #[arg(long)]
+    pub archive_dir: Option<PathBuf>,
+}
+
+/// Arguments of the `health` run mode.
+#[derive(Debug, Args)]
+pub struct HealthArgs {
+    /// Days without a stored value after which a sensor is silent.
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(i64).range(1..))]
+    pub stale_days: i64,
+    /// Days without a measurement according to OpenAQ after which a location is offline.
+    #[arg(long, default_value_t = 7, value_parser = clap::value_parser!(i64).range(1..))]
+    pub source_stale_days: i64,
+    /// Length of the two compared coverage windows, in days.
+    #[arg(long, default_value_t = 14, value_parser = clap::value_parser!(i64).range(1..=180))]
+    pub coverage_days: i64,
+    /// Drop of the stored sensor days (percent) from which a locality's coverage shrinks.
+    #[arg(long, default_value_t = 25.0)]
+    pub shrink_percent: f64,
+    /// Mark the silent and offline sensors inactive, so the imports skip them.
+    #[arg(long, conflicts_with = "reactivate")]
+    pub mark_inactive: bool,
+    /// Clear all inactive marks, so the imports fetch every sensor again.
+    #[arg(long)]
+    pub reactivate: bool,
+}
+
+impl HealthArgs {
+    pub fn thresholds(&self) -> HealthThresholds {
+        HealthThresholds {
+            stale_days: self.stale_days,
+            source_stale_days: self.source_stale_days,
+            coverage_days: self.coverage_days,
+            shrink_percent: self.shrink_percent,
+        }
+    }
+}
+
+/// Subcommands of the `locations` and `sensors` run modes.
+#[derive(Debug, Subcommand)]
+pub enum InventoryCommand {
+    /// List the entries matching the filters, with the extent of their stored data.
+    List(InventoryArgs),
+    /// Show the details of one entry.
+    Show {
+        /// OpenAQ ID of the location or sensor.
+        id: i64,
+    },
+    /// Show how the metadata of one entry changed over time.
+    History {
+        /// OpenAQ ID of the location or sensor.
+        id: i64,
+    },
+}
+
+/// Filters of the `locations list` and `sensors list` subcommands.
+#[derive(Debug, Args)]
+pub struct InventoryArgs {
+    /// Two-letter country code, e.g. NL.
+    #[arg(long)]
+    pub country: Option<String>,
+    /// Locality (city), case-insensitive.
+    #[arg(long)]
+    pub locality: Option<String>,
+    /// Only entries of this location ID.
+    #[arg(long)]
+    pub location: Option<i64>,
+    /// Part of the provider name, case-insensitive.
+    #[arg(long)]
+    pub provider: Option<String>,
+    /// Part of the owner name, case-insensitive.
+    #[arg(long)]
+    pub owner: Option<String>,
+    /// Parameter name, e.g. pm25; locations match if any of their sensors measures it.
+    #[arg(long)]
+    pub parameter: Option<String>,
+    /// Reference monitors or low-cost sensors only.
+    #[arg(long, value_enum)]
+    pub kind: Option<SensorKind>,
+    /// Only entries with values on or after this day (YYYY-MM-DD).
+    #[arg(long)]
+    pub active_since: Option<NaiveDate>,
+}
+
+impl InventoryArgs {
+    /// Converts the arguments into the database filter.
+    pub fn filter(&self) -> InventoryFilter {
+        InventoryFilter {
+            location_id: self.location,
+            sensor_id: None,
+            country: self.country.clone(),
+            locality: self.locality.clone(),
+            provider: self.provider.clone(),
+            owner: self.owner.clone(),
+            parameter: self.parameter.clone(),
+            monitor: self.kind.map(|kind| kind == SensorKind::Monitor),
+            active_since: self.active_since,
+        }
+    }
+}
+
+/// Kind of the instruments at a location.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SensorKind {
+    /// Reference-grade monitors.
+    Monitor,
+    /// Low-cost sensors.
+    LowCost,
+}
+
+/// Arguments of the `daemon` run mode.
+#[derive(Debug, Args)]
+pub struct DaemonArgs {
+    /// Schedule of the imports (sec min hour day-of-month month day-of-week).
+    #[arg(long, default_value = "0 0 * * * *", value_parser = parse_schedule)]
+    pub import_schedule: Schedule,
+    /// Schedule of the location and sensor discovery.
+    #[arg(long, default_value = "0 30 3 * * *", value_parser = parse_schedule)]
+    pub discovery_schedule: Schedule,
+    /// Number of past days fetched by every import.
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(i64).range(1..=30))]
+    pub days: i64,
+    /// Address to serve the Prometheus metrics on.
+    #[arg(long)]
+    pub metrics_addr: Option<SocketAddr>,
+}
+
+/// Parses an inclusive range of days, e.g. `2024-03-01..2024-03-31`.
+fn parse_date_range(value: &str) -> Result<DateRange, String> {
+    let (start, end) = value
+        .split_once("..")
+        .ok_or_else(|| format!("invalid period '{}': expected START..END", value))?;
+    let day = |day: &str| {
+        NaiveDate::parse_from_str(day.trim(), "%Y-%m-%d")
+            .map_err(|e| format!("invalid period '{}': {}", value, e))
+    };
+    Ok(DateRange::new(day(start)?, day(end)?))
+}
+
+/// Parses a cron expression with a seconds field, e.g. `0 0 * * * *` for every hour.
+fn parse_schedule(value: &str) -> Result<Schedule, String> {
+    Schedule::from_str(value).map_err(|e| format!("invalid schedule '{}': {}", value, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_without_mode() {
+        let args = CliArgs::try_parse_from(["app"]).unwrap();
+        assert!(args.mode.is_none());
+    }
+
+    #[test]
+    fn test_parse_serve() {
+        let args = CliArgs::try_parse_from(["app", "serve", "--addr", "0.0.0.0:9000"]).unwrap();
+        match args.mode {
+            Some(RunMode::Serve { addr }) => assert_eq!(addr.port(), 9000),
+            other => panic!("unexpected mode {:?}", other),
+        }
+        assert!(CliArgs::try_parse_from(["app", "serve", "--addr", "nowhere"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_daemon() {
+        let args =
+            CliArgs::try_parse_from(["app", "daemon", "--import-schedule", "0 */15 * * * *"])
+                .unwrap();
+        match args.mode {
+            Some(RunMode::Daemon(daemon)) => {
+                assert_eq!(daemon.import_schedule.to_string(), "0 */15 * * * *");
+                assert_eq!(daemon.days, 2);
+                assert!(daemon.metrics_addr.is_none());
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+        assert!(CliArgs::try_parse_from(["app", "daemon", "--import-schedule", "hourly"]).is_err());
+        assert!(CliArgs::try_parse_from(["app", "daemon", "--days", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_status() {
+        assert!(matches!(
+            CliArgs::try_parse_from(["app", "status"]).unwrap().mode,
+            Some(RunMode::Status)
+        ));
+    }
+
+    #[test]
+    fn test_parse_report() {
+        let args = CliArgs::try_parse_from(["app", "report", "-o", "weekly.html", "--days", "14"])
+            .unwrap();
+        match args.mode {
+            Some(RunMode::Report {
+                output,
+                days,
+                template,
+                title,
+            }) => {
+                assert_eq!(output, PathBuf::from("weekly.html"));
+                assert_eq!(days, 14);
+                assert!(template.is_none());
+                assert_eq!(title, "Air Quality Report");
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+        assert!(CliArgs::try_parse_from(["app", "report", "--days", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_dashboard() {
+        assert!(matches!(
+            CliArgs::try_parse_from(["app", "dashboard"]).unwrap().mode,
+            Some(RunMode::Dashboard { import_days: 2 })
+        ));
+        assert!(CliArgs::try_parse_from(["app", "dashboard", "--import-days", "31"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_health() {
+        let args =
+            CliArgs::try_parse_from(["app", "health", "--stale-days", "5", "--mark-inactive"])
+                .unwrap();
+        match args.mode {
+            Some(RunMode::Health(health)) => {
+                let thresholds = health.thresholds();
+                assert_eq!(thresholds.stale_days, 5);
+                assert_eq!(thresholds.coverage_days, 14);
+                assert!(health.mark_inactive);
+                assert!(!health.reactivate);
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+        assert!(CliArgs::try_parse_from(["app", "health", "--stale-days", "0"]).is_err());
+        assert!(
+            CliArgs::try_parse_from(["app", "health", "--mark-inactive", "--reactivate"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_locations() {
+        let args = CliArgs::try_parse_from([
+            "app",
+            "locations",
+            "list",
+            "--country",
+            "NL",
+            "--kind",
+            "low-cost",
+            "--active-since",
+            "2024-03-01",
+        ])
+        .unwrap();
+        match args.mode {
+            Some(RunMode::Locations {
+                command: InventoryCommand::List(list),
+            }) => {
+                let filter = list.filter();
+                assert_eq!(filter.country.as_deref(), Some("NL"));
+                assert_eq!(filter.monitor, Some(false));
+                assert_eq!(filter.active_since, NaiveDate::from_ymd_opt(2024, 3, 1));
+                assert!(filter.provider.is_none());
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+        assert!(matches!(
+            CliArgs::try_parse_from(["app", "locations", "history", "7"])
+                .unwrap()
+                .mode,
+            Some(RunMode::Locations {
+                command: InventoryCommand::History { id: 7 }
+            })
+        ));
+        assert!(
+            CliArgs::try_parse_from(["app", "locations", "list", "--active-since", "March"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_sensors() {
+        assert!(matches!(
+            CliArgs::try_parse_from(["app", "sensors", "show", "42"])
+                .unwrap()
+                .mode,
+            Some(RunMode::Sensors {
+                command: InventoryCommand::Show { id: 42 }
+            })
+        ));
+        assert!(CliArgs::try_parse_from(["app", "sensors", "list", "--kind", "cheap"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_prune() {
+        let args = CliArgs::try_parse_from([
+            "app",
+            "prune",
+            "--dry-run",
+            "--keep-days",
+            "365",
+            "--archive-dir",
+            "archive",
+        ])
+        .unwrap();
+        match args.mode {
+            Some(RunMode::Prune(prune)) => {
+                assert!(prune.dry_run);
+                assert_eq!(prune.keep_days, Some(365));
+                assert_eq!(prune.archive_dir, Some(PathBuf::from("archive")));
+                assert!(prune.policies.is_none());
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+        assert!(CliArgs::try_parse_from(["app", "prune", "--keep-days", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_export() {
+        let args = CliArgs::try_parse_from(["app", "export"]).unwrap();
+        match args.mode {
+            Some(RunMode::Export(export)) => {
+                assert_eq!(export.format, ColumnarFormat::Parquet);
+                assert_eq!(export.output_dir, PathBuf::from("exports"));
+                assert_eq!(export.tables, ExportTable::ALL);
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+
+        let args = CliArgs::try_parse_from([
+            "app",
+            "export",
+            "--format",
+            "arrow",
+            "--out",
+            "dump",
+            "--tables",
+            "locations,sensors",
+        ])
+        .unwrap();
+        match args.mode {
+            Some(RunMode::Export(export)) => {
+                assert_eq!(export.format, ColumnarFormat::Arrow);
+                assert_eq!(export.output_dir, PathBuf::from("dump"));
+                assert_eq!(
+                    export.tables,
+                    [ExportTable::Locations, ExportTable::Sensors]
+                );
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+        assert!(CliArgs::try_parse_from(["app", "export", "--format", "csv"]).is_err());
+        assert!(CliArgs::try_parse_from(["app", "export", "--tables", "alerts"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_import_file() {
+        let args = CliArgs::try_parse_from([
+            "app",
+            "import-file",
+            "archive/2024-*.csv.gz",
+            "extra.csv",
+            "--country",
+            "de",
+            "--expected-per-day",
+            "24",
+        ])
+        .unwrap();
+        match args.mode {
+            Some(RunMode::ImportFile(import)) => {
+                assert_eq!(import.paths, ["archive/2024-*.csv.gz", "extra.csv"]);
+                assert!(import.mapping.is_none());
+                let file_import = import.file_import().unwrap();
+                assert_eq!(file_import.country, "DE");
+                assert_eq!(file_import.provider, "OpenAQ archive");
+                assert_eq!(file_import.source, FILE_SOURCE);
+                assert_eq!(file_import.expected_per_day, Some(24));
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+        assert!(CliArgs::try_parse_from(["app", "import-file"]).is_err());
+        assert!(CliArgs::try_parse_from([
+            "app",
+            "import-file",
+            "a.csv",
+            "--expected-per-day",
+            "0"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let args = CliArgs::try_parse_from([
+            "app",
+            "query",
+            "--country",
+            "nl,de",
+            "--parameter",
+            "PM25",
+            "--sensor",
+            "7,8",
+            "--from",
+            "2024-03-01",
+            "--to",
+            "2024-03-31",
+            "--group-by",
+            "locality",
+            "--per",
+            "week",
+        ])
+        .unwrap();
+        match args.mode {
+            Some(RunMode::Query(args)) => {
+                let query = args.query();
+                assert_eq!(query.countries, ["NL", "DE"]);
+                assert_eq!(query.parameters, ["pm25"]);
+                assert_eq!(query.sensor_ids, [7, 8]);
+                assert_eq!(
+                    query.date_from.unwrap().to_rfc3339(),
+                    "2024-03-01T00:00:00+00:00"
+                );
+                assert_eq!(
+                    query.date_to.unwrap().to_rfc3339(),
+                    "2024-04-01T00:00:00+00:00"
+                );
+                assert_eq!(query.spatial, SpatialGrouping::Locality);
+                assert_eq!(query.bucket, TimeBucket::Week);
+                assert_eq!(query.limit, Some(QUERY_ROW_LIMIT));
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+
+        let args = CliArgs::try_parse_from(["app", "query"]).unwrap();
+        match args.mode {
+            Some(RunMode::Query(args)) => assert_eq!(
+                args.query(),
+                MeasurementQuery {
+                    limit: Some(QUERY_ROW_LIMIT),
+                    ..MeasurementQuery::default()
+                }
+            ),
+            other => panic!("unexpected mode {:?}", other),
+        }
+        assert!(
+            CliArgs::try_parse_from(["app", "query", "--days", "7", "--from", "2024-03-01"])
+                .is_err()
+        );
+        assert!(CliArgs::try_parse_from(["app", "query", "--per", "hour"]).is_err());
+        assert!(CliArgs::try_parse_from(["app", "query", "--location", "abc"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_near() {
+        let args =
+            CliArgs::try_parse_from(["app", "near", "--lat", "52.09", "--lon", "-5.12", "--idw"])
+                .unwrap();
+        match args.mode {
+            Some(RunMode::Near(near)) => {
+                let near = near.near();
+                assert_eq!((near.latitude, near.longitude), (52.09, -5.12));
+                assert_eq!(near.radius_km, 25.0);
+                assert_eq!(near.limit, 5);
+                assert_eq!(near.parameter, "pm25");
+                assert_eq!(near.days, 30);
+                assert!(near.estimate);
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+
+        let args = CliArgs::try_parse_from([
+            "app",
+            "near",
+            "--lat",
+            "48.1",
+            "--lon",
+            "11.6",
+            "--radius-km",
+            "10",
+            "--limit",
+            "3",
+            "--parameter",
+            "NO2",
+        ])
+        .unwrap();
+        match args.mode {
+            Some(RunMode::Near(near)) => {
+                let near = near.near();
+                assert_eq!((near.radius_km, near.limit), (10.0, 3));
+                assert_eq!(near.parameter, "no2");
+                assert!(!near.estimate);
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+        assert!(CliArgs::try_parse_from(["app", "near", "--lat", "52.1"]).is_err());
+        assert!(CliArgs::try_parse_from([
+            "app", "near", "--lat", "52.1", "--lon", "5.1", "--limit", "0"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_distribution() {
+        let args = CliArgs::try_parse_from(["app", "distribution", "nl"]).unwrap();
+        match args.mode {
+            Some(RunMode::Distribution(distribution)) => {
+                let distribution = distribution.distribution();
+                assert_eq!(distribution.country, "NL");
+                assert_eq!(distribution.parameter, "pm25");
+                assert_eq!(distribution.days, 30);
+                assert_eq!(
+                    distribution.groups,
+                    [DistributionGroup::Locality, DistributionGroup::Sensor]
+                );
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+
+        let args = CliArgs::try_parse_from([
+            "app",
+            "distribution",
+            "DE",
+            "--parameter",
+            "NO2",
+            "--days",
+            "90",
+            "--group-by",
+            "sensor",
+        ])
+        .unwrap();
+        match args.mode {
+            Some(RunMode::Distribution(distribution)) => {
+                let distribution = distribution.distribution();
+                assert_eq!(distribution.parameter, "no2");
+                assert_eq!(distribution.days, 90);
+                assert_eq!(distribution.groups, [DistributionGroup::Sensor]);
+            },
+            other => panic!("unexpected mode {:?}", other),
+        }
+        assert!(CliArgs::try_parse_from(["app", "distribution"]).is_err());
+        assert!(
+            CliArgs::try_parse_from(["app", "distribution", "NL", "--group-by", "country"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_compare() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 14).unwrap();
+        let day = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap();
+        let compare = |args: &[&str]| match CliArgs::try_parse_from(args).unwrap().mode {
+            Some(RunMode::Compare(compare)) => compare.compare(today),
+            other => panic!("unexpected mode {:?}", other),
+        };
+
+        let args = compare(&["app", "compare", "nl"]);
+        assert_eq!(args.country, "NL");
+        assert_eq!(args.current, DateRange::new(day("2024-03-08"), today));
+        assert_eq!(
+            args.baseline,
+            DateRange::new(day("2024-03-01"), day("2024-03-07"))
+        );
+
+        let args = compare(&["app", "compare", "NL", "--days", "3", "--year-over-year"]);
+        assert_eq!(
+            args.baseline,
+            DateRange::new(day("2023-03-12"), day("2023-03-14"))
+        );
+
+        let args = compare(&[
+            "app",
+            "compare",
+            "DE",
+            "--locality",
+            "Berlin",
+            "--current",
+            "2024-02-01..2024-02-29",
+            "--baseline",
+            "2024-01-01..2024-01-31",
+        ]);
+        assert_eq!(args.locality.as_deref(), Some("Berlin"));
+        assert_eq!(
+            args.current,
+            DateRange::new(day("2024-02-01"), day("2024-02-29"))
+        );
+        assert_eq!(
+            args.baseline,
+            DateRange::new(day("2024-01-01"), day("2024-01-31"))
+        );
+
+        for invalid in [
+            vec!["app", "compare"],
+            vec!["app", "compare", "NL", "--current", "2024-02-01"],
+            vec![
+                "app",
+                "compare",
+                "NL",
+                "--current",
+                "2024-02-01..2024-02-30",
+            ],
+            vec![
+                "app",
+                "compare",
+                "NL",
+                "--days",
+                "7",
+                "--current",
+                "2024-02-01..2024-02-07",
+            ],
+            vec![
+                "app",
+                "compare",
+                "NL",
+                "--year-over-year",
+                "--baseline",
+                "2024-01-01..2024-01-07",
+            ],
+        ] {
+            assert!(CliArgs::try_parse_from(invalid).is_err());
+        }
+    }
+}
@@ -0,0 +1,291 @@
+//! Renders simple Unicode charts (line charts, horizontal bar charts and histograms)
+//! directly in the terminal.
+//!
+//! All functions are pure and return a `String`, so any command that produces a series
+//! of values can print a chart next to (or instead of) its `comfy_table` output.
+
+use chrono::{DateTime, Utc};
+
+/// Characters used to draw fractional bar ends, in eighths of a full block.
+const BAR_EIGHTHS: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+
+/// Width reserved for the y-axis labels of a line chart.
+const Y_LABEL_WIDTH: usize = 9;
+
+/// Renders a line chart of a time series.
+///
+/// Points are spread evenly across `width` columns (one column per point when the series
+/// is shorter than the width, otherwise points are averaged into buckets) and scaled to
+/// `height` rows. Consecutive points are joined with vertical strokes so trends are easy to follow.
+///
+/// Returns a short notice instead of a chart if the series is empty.
+pub fn line_chart(points: &[(DateTime<Utc>, f64)], width: usize, height: usize) -> String {
+    let points: Vec<(DateTime<Utc>, f64)> = points
+        .iter()
+        .copied()
+        .filter(|(_, v)| v.is_finite())
+        .collect();
+    if points.is_empty() {
+        return "(no data to chart)\n".to_string();
+    }
+    let width = width.max(2);
+    let height = height.max(2);
+
+    // Bucket the values so that the chart never exceeds the requested width.
+    let columns = points.len().min(width);
+    let values: Vec<f64> = (0..columns)
+        .map(|col| {
+            let start = col * points.len() / columns;
+            let end = ((col + 1) * points.len() / columns).max(start + 1);
+            let bucket = &points[start..end];
+            bucket.iter().map(|(_, v)| v).sum::<f64>() / bucket.len() as f64
+        })
+        .collect();
+
+    let (min, max) = min_max(&values);
+    let span = if (max - min).abs() < f64::EPSILON {
+        1.0
+    } else {
+        max - min
+    };
+    // Map each value to a row index where 0 is the bottom row.
+    let rows: Vec<usize> = values
+        .iter()
+        .map(|v| (((v - min) / span) * (height - 1) as f64).round() as usize)
+        .collect();
+
+    let mut grid = vec![vec![' '; columns]; height];
+    for (col, &row) in rows.iter().enumerate() {
+        if col > 0 {
+            // Join with the previous point using a vertical stroke.
+            let prev = rows[col - 1];
+            let (low, high) = (prev.min(row), prev.max(row));
+            for cell in grid.iter_mut().take(high).skip(low + 1) {
+                cell[col] = '│';
+            }
+        }
+        grid[row][col] = '●';
+    }
+
+    let mut out = String::new();
+    for (i, line) in grid.iter().enumerate().rev() {
+        let label = if i == height - 1 {
+            format!("{:>w$.2}", max, w = Y_LABEL_WIDTH - 2)
+        } else if i == 0 {
+            format!("{:>w$.2}", min, w = Y_LABEL_WIDTH - 2)
+        } else {
+            " ".repeat(Y_LABEL_WIDTH - 2)
+        };
+        out.push_str(&label);
+        out.push_str(" ┤");
+        out.extend(line.iter());
+        out.push('\n');
+    }
+    out.push_str(&" ".repeat(Y_LABEL_WIDTH - 1));
+    out.push('└');
+    out.push_str(&"─".repeat(columns));
+    out.push('\n');
+
+    // X-axis labels: first and last date of the series.
+    let first = points[0].0.format("%Y-%m-%d").to_string();
+    let last = points[points.len() - 1].0.format("%Y-%m-%d").to_string();
+    out.push_str(&" ".repeat(Y_LABEL_WIDTH));
+    if points.len() > 1 && columns > first.len() + last.len() {
+        out.push_str(&first);
+        out.push_str(&" ".repeat(columns - first.len() - last.len()));
+        out.push_str(&last);
+    } else {
+        out.push_str(&first);
+    }
+    out.push('\n');
+    out
+}
+
+/// Renders a horizontal bar chart, one labelled bar per item.
+///
+/// Bars are scaled relative to the largest value so that it spans `width` characters.
+/// Negative and non-finite values are drawn as empty bars.
+pub fn bar_chart(items: &[(String, f64)], width: usize) -> String {
+    if items.is_empty() {
+        return "(no data to chart)\n".to_string();
+    }
+    let width = width.max(1);
+    let label_width = items
+        .iter()
+        .map(|(l, _)| l.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max = items
+        .iter()
+        .map(|(_, v)| *v)
+        .filter(|v| v.is_finite())
+        .fold(0.0_f64, f64::max);
+
+    let mut out = String::new();
+    for (label, value) in items {
+        let bar = if max > 0.0 && value.is_finite() && *value > 0.0 {
+            render_bar(value / max * width as f64)
+        } else {
+            String::new()
+        };
+        let padding = width.saturating_sub(bar.chars().count());
+        out.push_str(&format!(
+            "{:<lw$} │{}{} {:.2}\n",
+            label,
+            bar,
+            " ".repeat(padding),
+            value,
+            lw = label_width
+        ));
+    }
+    out
+}
+
+/// Renders a histogram of `values` split into `bins` equally sized buckets.
+///
+/// Each bucket is shown as a bar labelled with its value range and the number of
+/// values it contains.
+pub fn histogram(values: &[f64], bins: usize, width: usize) -> String {
+    let counts = histogram_counts(values, bins);
+    if counts.is_empty() {
+        return "(no data to chart)\n".to_string();
+    }
+    let width = width.max(1);
+    let max_count = counts.iter().map(|(_, _, c)| *c).max().unwrap_or(0);
+    let labels: Vec<String> = counts
+        .iter()
+        .map(|(low, high, _)| format!("{:.1} – {:.1}", low, high))
+        .collect();
+    let label_width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+
+    let mut out = String::new();
+    for (label, (_, _, count)) in labels.iter().zip(counts.iter()) {
+        let bar = if max_count > 0 {
+            render_bar(*count as f64 / max_count as f64 * width as f64)
+        } else {
+            String::new()
+        };
+        let padding = width.saturating_sub(bar.chars().count());
+        out.push_str(&format!(
+            "{:>lw$} │{}{} {}\n",
+            label,
+            bar,
+            " ".repeat(padding),
+            count,
+            lw = label_width
+        ));
+    }
+    out
+}
+
+/// Splits `values` into `bins` equally sized buckets, returning `(low, high, count)` per bucket.
+///
+/// Non-finite values are ignored. Returns an empty Vec if there are no finite values.
+fn histogram_counts(values: &[f64], bins: usize) -> Vec<(f64, f64, usize)> {
+    let values: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let bins = bins.max(1);
+    let (min, max) = min_max(&values);
+    let bin_width = if (max - min).abs() < f64::EPSILON {
+        1.0
+    } else {
+        (max - min) / bins as f64
+    };
+
+    let mut counts = vec![0usize; bins];
+    for v in &values {
+        let idx = (((v - min) / bin_width) as usize).min(bins - 1);
+        counts[idx] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let low = min + i as f64 * bin_width;
+            (low, low + bin_width, c)
+        })
+        .collect()
+}
+
+/// Builds a bar of the given length (in characters) using full and fractional blocks.
+fn render_bar(length: f64) -> String {
+    let eighths = (length.max(0.0) * 8.0).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    bar.push_str(BAR_EIGHTHS[eighths % 8]);
+    bar
+}
+
+/// Returns the minimum and maximum of a non-empty slice.
+fn min_max(values: &[f64]) -> (f64, f64) {
+    values
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+            (lo.min(v), hi.max(v))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn series(values: &[f64]) -> Vec<(DateTime<Utc>, f64)> {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (start + Duration::days(i as i64), *v))
+            .collect()
+    }
+
+    #[test]
+    fn test_line_chart_has_requested_height_and_axis_labels() {
+        let chart = line_chart(&series(&[1.0, 5.0, 3.0, 10.0]), 40, 5);
+        let lines: Vec<&str> = chart.lines().collect();
+        // 5 plot rows, the x-axis line and the date labels line
+        assert_eq!(lines.len(), 7);
+        assert!(lines[0].trim_start().starts_with("10.00"));
+        assert!(lines[4].trim_start().starts_with("1.00"));
+        assert!(lines[6].contains("2024-01-01"));
+        assert_eq!(chart.matches('●').count(), 4);
+    }
+
+    #[test]
+    fn test_line_chart_buckets_long_series_to_width() {
+        let values: Vec<f64> = (0..100).map(|v| v as f64).collect();
+        let chart = line_chart(&series(&values), 20, 4);
+        assert_eq!(chart.matches('●').count(), 20);
+    }
+
+    #[test]
+    fn test_line_chart_empty_series() {
+        assert_eq!(line_chart(&[], 40, 5), "(no data to chart)\n");
+    }
+
+    #[test]
+    fn test_bar_chart_scales_to_largest_value() {
+        let items = vec![("PK".to_string(), 100.0), ("NL".to_string(), 50.0)];
+        let chart = bar_chart(&items, 10);
+        let lines: Vec<&str> = chart.lines().collect();
+        assert_eq!(lines[0].matches('█').count(), 10);
+        assert_eq!(lines[1].matches('█').count(), 5);
+        assert!(lines[1].ends_with("50.00"));
+    }
+
+    #[test]
+    fn test_histogram_counts_cover_all_values() {
+        let counts = histogram_counts(&[1.0, 2.0, 2.5, 9.0, 10.0], 3);
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts.iter().map(|(_, _, c)| c).sum::<usize>(), 5);
+        // The maximum value falls into the last bucket.
+        assert_eq!(counts[2].2, 2);
+    }
+
+    #[test]
+    fn test_render_bar_uses_fractional_blocks() {
+        assert_eq!(render_bar(2.5), "██▌");
+        assert_eq!(render_bar(0.0), "");
+    }
+}
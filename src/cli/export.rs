@@ -0,0 +1,115 @@
+//! Exports the stored tables as Parquet or Arrow IPC files, for the `export` run mode and the
+//! Export Tables menu entry.
+
+use super::{create_spinner, TableExportArgs};
+use crate::db::Database;
+use crate::error::Result;
+use crate::export::{
+    locations_to_batch, sensors_to_batch, write_measurements, write_table, ExportSummary,
+    ExportTable,
+};
+use colored::*;
+
+/// Exports the selected tables into `args.output_dir`: the `measurements` table partitioned by
+/// country and month, the `locations` and `sensors` tables as single files.
+///
+/// Measurements are streamed from the database and written in batches.
+///
+/// # Errors
+///
+/// Returns `AppError` if reading from the database or writing the files fails.
+pub async fn export_tables(db: &Database, args: &TableExportArgs) -> Result<()> {
+    let format = args.format.extension();
+    let pb = create_spinner(&format!("Exporting as {}...", format));
+    // Single-file tables, listed after the summary.
+    let mut tables = ExportSummary::default();
+    let mut exported = Vec::new();
+
+    if args.tables.contains(&ExportTable::Measurements) {
+        pb.set_message(format!("Exporting measurements as {}...", format));
+        let measurements =
+            write_measurements(db.stream_measurements(), &args.output_dir, args.format).await?;
+        exported.push(format!(
+            "{} measurements in {} partitions",
+            measurements.rows,
+            measurements.files.len()
+        ));
+    }
+    let with_locations = args.tables.contains(&ExportTable::Locations);
+    let with_sensors = args.tables.contains(&ExportTable::Sensors);
+    if with_locations || with_sensors {
+        pb.set_message(format!("Exporting locations and sensors as {}...", format));
+        let locations = db.get_locations(&[], None).await?;
+        if with_locations {
+            let batch = locations_to_batch(&locations)?;
+            tables.add(write_table(
+                &args.output_dir,
+                "locations",
+                &batch,
+                args.format,
+            )?);
+            exported.push(format!("{} locations", locations.len()));
+        }
+        if with_sensors {
+            let ids: Vec<i64> = locations.iter().map(|l| l.id).collect();
+            let sensors = db.get_sensors(&ids).await?;
+            let batch = sensors_to_batch(&sensors)?;
+            tables.add(write_table(
+                &args.output_dir,
+                "sensors",
+                &batch,
+                args.format,
+            )?);
+            exported.push(format!("{} sensors", sensors.len()));
+        }
+    }
+    pb.finish_and_clear();
+
+    println!(
+        "{} {} {} {}",
+        "Exported".green(),
+        exported.join(", ").bold().cyan(),
+        "to".green(),
+        args.output_dir.display()
+    );
+    for file in &tables.files {
+        println!("  {}", file.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::export::ColumnarFormat;
+    use sqlx::PgPool;
+
+    #[sqlx::test]
+    async fn test_export_tables_writes_selected_tables(pool: PgPool) {
+        let db = Database::from_pool(pool);
+        db.init_schema().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("tables-test-{}", std::process::id()));
+
+        let args = TableExportArgs {
+            format: ColumnarFormat::Parquet,
+            output_dir: dir.clone(),
+            tables: ExportTable::ALL.to_vec(),
+        };
+        export_tables(&db, &args).await.unwrap();
+        assert!(dir.join("locations.parquet").exists());
+        assert!(dir.join("sensors.parquet").exists());
+        // No measurements, so no partitions.
+        assert!(!dir.join("measurements").exists());
+
+        let args = TableExportArgs {
+            format: ColumnarFormat::Arrow,
+            output_dir: dir.clone(),
+            tables: vec![ExportTable::Sensors],
+        };
+        export_tables(&db, &args).await.unwrap();
+        assert!(dir.join("sensors.arrow").exists());
+        assert!(!dir.join("locations.arrow").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+//! Imports measurements from local CSV files, for the `import-file` run mode and the Import
+//! From File menu entry.
+
+use super::create_progress_bar;
+use crate::db::Database;
+use crate::error::{AppError, Result};
+use crate::import::{expand_measurement_files, ColumnMapping, DailyAggregator, FileImportOptions};
+use crate::metrics::metrics;
+use colored::*;
+use tracing::info;
+
+/// Arguments for the `ImportFile` command.
+#[derive(Debug, Clone)]
+pub struct FileImportArgs {
+    /// CSV or gzipped CSV files, directories whose CSV files are all imported, or glob patterns.
+    pub paths: Vec<String>,
+    pub mapping: ColumnMapping,
+    /// 2-letter country code for rows without a country column.
+    pub country: String,
+    /// Provider name stored with the measurements.
+    pub provider: String,
+    /// Data source name stored with the measurements.
+    pub source: String,
+    /// Number of observations expected per day (e.g. 24 for hourly data), if known.
+    pub expected_per_day: Option<i32>,
+}
+
+/// Imports measurements from the files, directories and glob patterns of `args`.
+///
+/// Each file is parsed with the column mapping of `args`. The observations of all files are
+/// aggregated together to daily values per sensor, so a day split across files (or reported
+/// in several units) yields one complete measurement, and inserted through
+/// `insert_measurements` in one batch. Parameter IDs are taken from the stored parameter
+/// catalogue. Returns the number of daily measurements inserted, excluding days that were
+/// already stored.
+///
+/// # Errors
+///
+/// Returns `AppError::Cli` for invalid arguments, `AppError::Import` or `AppError::Csv` if
+/// a file does not match the mapping, or `AppError::Db` if the insertion fails.
+pub async fn import_files(db: &Database, args: &FileImportArgs) -> Result<u64> {
+    validate_file_import_args(args)?;
+    let files = expand_measurement_files(&args.paths)?;
+    if files.is_empty() {
+        println!(
+            "{} {}",
+            "No CSV files found in".yellow(),
+            args.paths.join(", ")
+        );
+        return Ok(0);
+    }
+
+    db.init_schema().await?; // Idempotent schema initialization
+    let options = FileImportOptions {
+        source: args.source.clone(),
+        country: args.country.clone(),
+        provider_name: args.provider.clone(),
+        owner_name: args.provider.clone(),
+        expected_per_day: args.expected_per_day,
+        parameter_ids: db
+            .get_parameters()
+            .await?
+            .into_iter()
+            .map(|p| (p.name, p.id))
+            .collect(),
+    };
+
+    let pb = create_progress_bar(files.len() as u64);
+    let mut aggregator = DailyAggregator::new(args.mapping.period_end);
+    let mut skipped = 0;
+    for file in &files {
+        pb.set_message(file.display().to_string());
+        skipped += aggregator.read_file(file, &args.mapping)?.1;
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+
+    let measurements = aggregator.finish(&options);
+    let inserted = db.insert_measurements(&measurements).await?;
+    if inserted > 0 {
+        metrics().latest_values_changed();
+    }
+
+    println!(
+        "{} {} {}",
+        "Imported".green(),
+        format!("{} daily measurements", inserted).bold().cyan(),
+        format!("from {} files", files.len()).green()
+    );
+    let duplicates = measurements.len() as u64 - inserted;
+    if duplicates > 0 {
+        println!(
+            "{} {} daily measurements were already stored and kept unchanged.",
+            "Note:".yellow(),
+            duplicates
+        );
+    }
+    if skipped > 0 {
+        println!(
+            "{} {} rows with missing, invalid or negative values were skipped.",
+            "Warning:".yellow(),
+            skipped
+        );
+    }
+    info!(
+        "File import finished: {} measurements from {} files",
+        inserted,
+        files.len()
+    );
+    Ok(inserted)
+}
+
+/// Checks the country code, source name and expected daily count of an `ImportFile` command.
+pub(super) fn validate_file_import_args(args: &FileImportArgs) -> Result<()> {
+    if args.paths.is_empty() {
+        return Err(AppError::Cli("No files to import".to_string()));
+    }
+    if args.country.len() != 2 || !args.country.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(AppError::Cli(format!(
+            "Invalid country code '{}'. Must be 2 uppercase letters",
+            args.country
+        )));
+    }
+    if args.source.trim().is_empty() {
+        return Err(AppError::Cli("Source name must not be empty".to_string()));
+    }
+    if args.expected_per_day.is_some_and(|e| e <= 0) {
+        return Err(AppError::Cli(
+            "Expected observations per day must be positive".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::import::FILE_SOURCE;
+    use sqlx::PgPool;
+
+    #[sqlx::test]
+    async fn test_import_files_skips_stored_days(pool: PgPool) {
+        let dir = std::env::temp_dir().join(format!("file-import-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // One day of sensor 7, split across two files.
+        let header = "location_id,sensors_id,location,datetime,lat,lon,parameter,units,value\n";
+        std::fs::write(
+            dir.join("01.csv"),
+            format!(
+                "{}3,7,Utrecht,2024-03-05T01:00:00+01:00,52.1,5.1,pm25,µg/m³,8\n",
+                header
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("02.csv"),
+            format!(
+                "{}3,7,Utrecht,2024-03-05T02:00:00+01:00,52.1,5.1,pm25,µg/m³,12\n",
+                header
+            ),
+        )
+        .unwrap();
+
+        let db = Database::from_pool(pool.clone());
+        let args = FileImportArgs {
+            paths: vec![dir.display().to_string()],
+            mapping: ColumnMapping::default(),
+            country: "NL".to_string(),
+            provider: "OpenAQ archive".to_string(),
+            source: FILE_SOURCE.to_string(),
+            expected_per_day: Some(24),
+        };
+        assert_eq!(import_files(&db, &args).await.unwrap(), 1);
+        // The day is already stored.
+        assert_eq!(import_files(&db, &args).await.unwrap(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (count, source): (Option<i32>, String) =
+            sqlx::query_as("SELECT measurement_count, source FROM measurements")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(count, Some(2));
+        assert_eq!(source, FILE_SOURCE);
+    }
+}
@@ -0,0 +1,175 @@
+//! Prints the sensor health check of the `health` run mode and manages the inactive marks.
+
+use super::HealthArgs;
+use crate::db::Database;
+use crate::error::Result;
+use crate::models::SensorHealth;
+use chrono::{DateTime, Utc};
+use colored::*;
+use comfy_table::{presets::UTF8_FULL, Cell, Color, ContentArrangement, Table};
+
+/// Runs the health check: lists the silent and offline sensors and the localities whose
+/// coverage shrinks, and marks the listed sensors inactive with `--mark-inactive`. With
+/// `--reactivate`, only clears the inactive marks.
+///
+/// # Errors
+///
+/// Returns `AppError::Db` if a query or update fails.
+pub async fn check_health(db: &Database, args: &HealthArgs) -> Result<()> {
+    if args.reactivate {
+        let reactivated = db.reactivate_sensors().await?;
+        println!(
+            "{} {} sensor(s); the next imports fetch them again.",
+            "Reactivated".green(),
+            reactivated
+        );
+        return Ok(());
+    }
+
+    let thresholds = args.thresholds();
+    let now = Utc::now();
+    let activity = db.get_sensor_activity().await?;
+    let unhealthy: Vec<_> = activity
+        .iter()
+        .map(|sensor| (sensor, sensor.health(now, &thresholds)))
+        .filter(|(_, health)| *health != SensorHealth::Healthy)
+        .collect();
+
+    if unhealthy.is_empty() {
+        println!(
+            "{}",
+            format!("All {} sensors reported recently.", activity.len()).green()
+        );
+    } else {
+        println!(
+            "{}",
+            format!(
+                "{} of {} sensors are silent (no stored value in {} days) or offline (no OpenAQ data in {} days):",
+                unhealthy.len(),
+                activity.len(),
+                thresholds.stale_days,
+                thresholds.source_stale_days
+            )
+            .yellow()
+        );
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec![
+                Cell::new("Sensor").fg(Color::Green),
+                Cell::new("Parameter").fg(Color::Green),
+                Cell::new("Location").fg(Color::Green),
+                Cell::new("Locality").fg(Color::Green),
+                Cell::new("Country").fg(Color::Green),
+                Cell::new("Health").fg(Color::Green),
+                Cell::new("Last Stored").fg(Color::Green),
+                Cell::new("Last Reported (OpenAQ)").fg(Color::Green),
+                Cell::new("Inactive Since").fg(Color::Green),
+            ]);
+        for (sensor, health) in &unhealthy {
+            let health_color = match health {
+                SensorHealth::Offline => Color::Red,
+                _ => Color::Yellow,
+            };
+            table.add_row(vec![
+                Cell::new(sensor.sensor_id).fg(Color::Cyan),
+                Cell::new(&sensor.parameter_name),
+                Cell::new(format!(
+                    "{} ({})",
+                    sensor.location_name.as_deref().unwrap_or("-"),
+                    sensor.location_id
+                )),
+                Cell::new(sensor.locality.as_deref().unwrap_or("-")),
+                Cell::new(&sensor.country_code),
+                Cell::new(health.as_str()).fg(health_color),
+                Cell::new(day(sensor.last_stored)),
+                Cell::new(day(sensor.source_last)),
+                Cell::new(day(sensor.inactive_since)),
+            ]);
+        }
+        println!("{table}");
+    }
+
+    let coverage = db
+        .get_locality_coverage(now.date_naive(), thresholds.coverage_days)
+        .await?;
+    let shrinking: Vec<_> = coverage
+        .iter()
+        .filter(|locality| locality.is_shrinking(&thresholds))
+        .collect();
+    if shrinking.is_empty() {
+        println!(
+            "{}",
+            format!(
+                "No locality lost {}% or more of its sensor days over the last {} days.",
+                thresholds.shrink_percent, thresholds.coverage_days
+            )
+            .green()
+        );
+    } else {
+        println!(
+            "{}",
+            format!(
+                "Localities with shrinking coverage (last {} days vs. the {} days before):",
+                thresholds.coverage_days, thresholds.coverage_days
+            )
+            .yellow()
+        );
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec![
+                Cell::new("Locality").fg(Color::Green),
+                Cell::new("Country").fg(Color::Green),
+                Cell::new("Sensor Days Before").fg(Color::Green),
+                Cell::new("Sensor Days Now").fg(Color::Green),
+                Cell::new("Change").fg(Color::Green),
+                Cell::new("Sensors").fg(Color::Green),
+            ]);
+        for locality in &shrinking {
+            table.add_row(vec![
+                Cell::new(&locality.locality).fg(Color::Cyan),
+                Cell::new(&locality.country),
+                Cell::new(locality.previous_days),
+                Cell::new(locality.current_days),
+                Cell::new(
+                    locality
+                        .change_percent()
+                        .map_or_else(|| "-".to_string(), |c| format!("{:+.1}%", c)),
+                )
+                .fg(Color::Red),
+                Cell::new(format!(
+                    "{} → {}",
+                    locality.previous_sensors, locality.current_sensors
+                )),
+            ]);
+        }
+        println!("{table}");
+    }
+
+    if args.mark_inactive && !unhealthy.is_empty() {
+        let mut marked = 0;
+        for health in [SensorHealth::Silent, SensorHealth::Offline] {
+            let ids: Vec<i64> = unhealthy
+                .iter()
+                .filter(|(_, h)| *h == health)
+                .map(|(sensor, _)| sensor.sensor_id)
+                .collect();
+            if !ids.is_empty() {
+                marked += db.mark_sensors_inactive(&ids, health.as_str()).await?;
+            }
+        }
+        println!(
+            "{} {} sensor(s) inactive; the imports skip them until `health --reactivate`.",
+            "Marked".yellow(),
+            marked
+        );
+    }
+    Ok(())
+}
+
+fn day(time: Option<DateTime<Utc>>) -> String {
+    time.map_or_else(|| "-".to_string(), |t| t.format("%Y-%m-%d").to_string())
+}
@@ -0,0 +1,534 @@
+//! The import from a `DataSource`, shared by the menu, the dashboard and the daemon. Progress
+//! is reported through a `Progress`, so each front end can show it its own way.
+
+use super::{NoticeLevel, Progress, COUNTRIES};
+use crate::api::DataSource;
+use crate::db::Database;
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{Location, SensorBase};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use std::time::Duration as StdDuration;
+use tracing::{error, info, warn};
+
+/// Number of attempts to fetch the measurements of a sensor.
+pub const MAX_FETCH_ATTEMPTS: usize = 3;
+
+/// Delay between two attempts to fetch the measurements of a sensor.
+pub const RETRY_DELAY: StdDuration = StdDuration::from_secs(10);
+
+/// A sensor to import measurements for, with the location it belongs to.
+pub type SensorTarget = (Location, SensorBase);
+
+/// Returns the import window covering the `days` whole days (UTC) before `now`'s day.
+pub fn import_window(now: DateTime<Utc>, days: i64) -> (DateTime<Utc>, DateTime<Utc>) {
+    let midnight = |date: NaiveDate| {
+        date.and_time(NaiveTime::MIN)
+            .and_local_timezone(Utc)
+            .unwrap()
+    };
+    let today = now.date_naive();
+    (midnight(today - Duration::days(days)), midnight(today))
+}
+
+/// Imports the locations, sensors and the last `days` days of measurements of every country
+/// in `COUNTRIES` from `source`, returning the number of measurements fetched.
+///
+/// 1. Ensures the database schema exists.
+/// 2. Discovers the sensors of every country (see `discover_sensors`).
+/// 3. Fetches and stores their measurements (see `import_sensors`).
+///
+/// All stored rows are tagged with the name of the source. Failures of individual API calls or
+/// location/sensor insertions are logged and reported as notices, and the import continues
+/// with the other countries and sensors.
+///
+/// # Errors
+///
+/// Returns `AppError` if critical operations like schema initialization or the final
+/// measurement insertion transaction fail, or if every country or every sensor failed.
+pub async fn import_from_source(
+    db: &Database,
+    source: &dyn DataSource,
+    days: i64,
+    progress: &dyn Progress,
+) -> Result<usize> {
+    info!("Ensuring database schema exists before import...");
+    db.init_schema().await?; // Idempotent schema initialization
+
+    let targets = discover_sensors(db, source, &COUNTRIES, progress).await?;
+    import_sensors(db, source, &targets, days, progress).await
+}
+
+/// Refreshes the parameter catalogue, creates the measurement partitions of the coming months
+/// and stores the locations and sensors of `countries`, returning the sensors found.
+///
+/// Countries and locations that fail are logged, reported as notices and skipped, as is a
+/// failure to refresh the catalogue or to create the partitions (the insert creates missing
+/// ones as well).
+///
+/// # Errors
+///
+/// Returns `AppError::Import` if no sensors were found and at least one country failed, or
+/// `AppError::Db` if the parameter catalogue cannot be stored.
+pub async fn discover_sensors(
+    db: &Database,
+    source: &dyn DataSource,
+    countries: &[&str],
+    progress: &dyn Progress,
+) -> Result<Vec<SensorTarget>> {
+    // Refresh the parameter catalogue; the stored catalogue is kept if the API call fails.
+    match source.get_parameters().await {
+        Ok(parameters) if parameters.is_empty() => {},
+        Ok(parameters) => db.upsert_parameters(&parameters).await?,
+        Err(e) => {
+            warn!("Failed to refresh parameter catalogue: {}", e);
+            progress.notice(
+                NoticeLevel::Warning,
+                format!("Failed to refresh parameter catalogue: {}", e),
+            );
+        },
+    }
+    if let Err(e) = db.create_upcoming_partitions().await {
+        warn!("Failed to create upcoming measurement partitions: {}", e);
+    }
+
+    progress.stage(
+        "Fetching & saving locations/sensors...",
+        Some(countries.len() as u64),
+    );
+
+    let mut targets: Vec<SensorTarget> = Vec::new();
+    let mut failed_countries = 0;
+    for country_code in countries {
+        progress.message(format!("Processing {}...", country_code));
+        info!("Fetching locations for country: {}", country_code);
+
+        // Discover the locations of the country
+        let locations = match source.discover_locations(country_code).await {
+            Ok(locs) => locs,
+            Err(e) => {
+                error!(
+                    "Failed to fetch locations for {}: {}. Skipping.",
+                    country_code, e
+                );
+                progress.notice(
+                    NoticeLevel::Error,
+                    format!(
+                        "Failed to fetch locations for {}: {}. Skipping.",
+                        country_code, e
+                    ),
+                );
+                failed_countries += 1;
+                progress.advance();
+                continue;
+            },
+        };
+        info!("Fetched {} locations for {}", locations.len(), country_code);
+
+        if locations.is_empty() {
+            progress.notice(
+                NoticeLevel::Warning,
+                format!("No locations found for {}. Skipping.", country_code),
+            );
+            progress.advance();
+            continue;
+        }
+
+        // Save locations to DB
+        if let Err(e) = db.insert_locations(source.name(), &locations).await {
+            error!(
+                "Failed to insert locations for {}: {}. Skipping country's sensors.",
+                country_code, e
+            );
+            progress.notice(
+                NoticeLevel::Error,
+                format!(
+                    "Failed to save locations for {}: {}. Skipping sensors.",
+                    country_code, e
+                ),
+            );
+            failed_countries += 1;
+            progress.advance();
+            continue;
+        }
+
+        // List and save sensors, and collect them for measurement fetching
+        for loc in locations {
+            let sensors = match source.list_sensors(&loc).await {
+                Ok(sensors) => sensors,
+                Err(e) => {
+                    error!("Failed to list sensors for location {}: {}", loc.id, e);
+                    progress.notice(
+                        NoticeLevel::Warning,
+                        format!("Failed to list sensors for location {}: {}.", loc.id, e),
+                    );
+                    continue;
+                },
+            };
+            if let Err(e) = db
+                .insert_sensors(source.name(), loc.id as i64, &sensors)
+                .await
+            {
+                // Log error but continue processing other locations/sensors
+                error!("Failed to insert sensors for location {}: {}", loc.id, e);
+                progress.notice(
+                    NoticeLevel::Warning,
+                    format!("Failed to save sensors for location {}: {}.", loc.id, e),
+                );
+            } else {
+                // Add sensors to the list for fetching measurements later
+                targets.extend(sensors.into_iter().map(|s| (loc.clone(), s)));
+            }
+        }
+        progress.advance();
+    }
+    progress.finish("Finished fetching & saving locations/sensors.");
+
+    if targets.is_empty() && failed_countries > 0 {
+        return Err(AppError::Import(format!(
+            "discovery found no sensors ({} of {} countries failed)",
+            failed_countries,
+            countries.len()
+        )));
+    }
+    info!(
+        "Discovered {} sensors in {} countries",
+        targets.len(),
+        countries.len()
+    );
+    Ok(targets)
+}
+
+/// Fetches the daily measurements of the last `days` days (up to midnight UTC today) of every
+/// target and inserts them in a single transaction, returning the number of measurements
+/// fetched.
+///
+/// Sensors marked inactive by the health check are skipped. Failed requests are retried
+/// (`MAX_FETCH_ATTEMPTS` attempts, `RETRY_DELAY` apart) and sensors that still fail are
+/// skipped. Once `progress` is cancelled, no further sensors are fetched; the measurements
+/// fetched so far are still stored.
+///
+/// # Errors
+///
+/// Returns `AppError::Import` if every sensor failed, or `AppError::Db` if the insert fails.
+pub async fn import_sensors(
+    db: &Database,
+    source: &dyn DataSource,
+    all_targets: &[SensorTarget],
+    days: i64,
+    progress: &dyn Progress,
+) -> Result<usize> {
+    // Records the duration of the import when dropped, including early returns
+    let _import_timer = metrics().import_duration.start_timer();
+    let (start_date, end_date) = import_window(Utc::now(), days);
+    info!("Importing data from {} to {}", start_date, end_date);
+
+    // Skip the sensors marked inactive by the health check.
+    let inactive = db.get_inactive_sensor_ids().await?;
+    let targets: Vec<&SensorTarget> = all_targets
+        .iter()
+        .filter(|(_, sensor)| !inactive.contains(&(sensor.id as i64)))
+        .collect();
+    let skipped = all_targets.len() - targets.len();
+    if skipped > 0 {
+        progress.notice(
+            NoticeLevel::Info,
+            format!(
+                "Skipping {} inactive sensors (see `health --reactivate`).",
+                skipped
+            ),
+        );
+    }
+
+    if targets.is_empty() {
+        progress.notice(
+            NoticeLevel::Info,
+            "No sensors found to fetch measurements for.".to_string(),
+        );
+        info!("Data import process finished: No sensors found.");
+        return Ok(0);
+    }
+
+    progress.stage("Fetching measurements...", Some(targets.len() as u64));
+    let mut all_db_measurements = Vec::new();
+    let mut failed = 0;
+
+    for (location_context, sensor) in targets.iter().copied() {
+        if progress.cancelled() {
+            warn!("Import cancelled; storing the measurements fetched so far");
+            break;
+        }
+        progress.message(format!("Sensor {}...", sensor.id));
+        info!("Fetching measurements for sensor ID: {}", sensor.id);
+        let mut fetched = None; // Option to hold fetched measurements
+
+        for attempt in 0..MAX_FETCH_ATTEMPTS {
+            match source
+                .fetch_measurements(location_context, sensor, start_date, end_date)
+                .await
+            {
+                Ok(m) => {
+                    fetched = Some(m);
+                    break; // Success, exit retry loop
+                },
+                Err(e) => {
+                    error!(
+                        "Attempt {}/{} failed to fetch measurements for sensor {}: {}",
+                        attempt + 1,
+                        MAX_FETCH_ATTEMPTS,
+                        sensor.id,
+                        e
+                    );
+                    if attempt + 1 < MAX_FETCH_ATTEMPTS {
+                        metrics().import_retries.inc();
+                        progress.notice(
+                            NoticeLevel::Warning,
+                            format!("Retrying sensor {} after {:?}...", sensor.id, RETRY_DELAY),
+                        );
+                        tokio::time::sleep(RETRY_DELAY).await;
+                    } else {
+                        progress.notice(
+                            NoticeLevel::Error,
+                            format!(
+                                "Failed to fetch measurements for sensor {} after {} attempts: {}. Skipping.",
+                                sensor.id, MAX_FETCH_ATTEMPTS, e
+                            ),
+                        );
+                    }
+                },
+            }
+        }
+
+        // Process measurements if fetched successfully
+        match fetched {
+            Some(fetched_measurements) => {
+                info!(
+                    "Fetched {} measurements for sensor {}",
+                    fetched_measurements.len(),
+                    sensor.id
+                );
+                all_db_measurements.extend(fetched_measurements);
+            },
+            None => failed += 1,
+        }
+        progress.advance();
+    }
+    progress.finish("Finished fetching measurements.");
+
+    if failed > 0 && failed == targets.len() {
+        return Err(AppError::Import(format!(
+            "fetching failed for all {} sensors",
+            failed
+        )));
+    }
+
+    if all_db_measurements.is_empty() {
+        progress.notice(
+            NoticeLevel::Info,
+            "No measurements fetched successfully to insert.".to_string(),
+        );
+        info!("Data import process finished: No measurements fetched.");
+        return Ok(0);
+    }
+
+    progress.notice(
+        NoticeLevel::Info,
+        format!(
+            "Inserting {} total measurements...",
+            all_db_measurements.len()
+        ),
+    );
+    progress.stage("Inserting data into database...", None);
+    db.insert_measurements(&all_db_measurements).await?;
+    metrics().latest_values_changed();
+    progress.finish("Data insertion completed successfully!");
+    info!(
+        "Inserted {} total measurements ({} sensors failed).",
+        all_db_measurements.len(),
+        failed
+    );
+    info!("Data import process finished.");
+    Ok(all_db_measurements.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_import_window() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 10, 14, 30, 0).unwrap();
+        let (from, to) = import_window(now, 2);
+        assert_eq!(from, Utc.with_ymd_and_hms(2024, 3, 8, 0, 0, 0).unwrap());
+        assert_eq!(to, Utc.with_ymd_and_hms(2024, 3, 10, 0, 0, 0).unwrap());
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod integration_tests {
+    use super::*;
+    use crate::cli::ProgressEvent;
+    use crate::error::AppError;
+    use crate::models::{normalization, DbMeasurement};
+    use async_trait::async_trait;
+    use chrono::DateTime;
+    use sqlx::PgPool;
+    use std::sync::Mutex;
+
+    /// Collects the reported events.
+    #[derive(Default)]
+    struct RecordingProgress {
+        events: Mutex<Vec<ProgressEvent>>,
+    }
+
+    impl Progress for RecordingProgress {
+        fn report(&self, event: ProgressEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    /// A source with one location and sensor in the Netherlands, failing for other countries.
+    struct DutchSource;
+
+    #[async_trait]
+    impl DataSource for DutchSource {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        async fn discover_locations(&self, country: &str) -> crate::error::Result<Vec<Location>> {
+            if country != "NL" {
+                return Err(AppError::Cli(format!("no stations in {}", country)));
+            }
+            Ok(vec![serde_json::from_value(serde_json::json!({
+                "id": 1,
+                "name": "Utrecht",
+                "locality": "Utrecht",
+                "timezone": "Europe/Amsterdam",
+                "country": { "id": null, "code": "NL", "name": "Netherlands" },
+                "owner": { "id": 1, "name": "RIVM" },
+                "provider": { "id": 1, "name": "RIVM" },
+                "isMobile": false,
+                "isMonitor": true,
+                "instruments": [],
+                "sensors": [{
+                    "id": 10,
+                    "name": "pm25 µg/m³",
+                    "parameter": { "id": 2, "name": "pm25", "units": "µg/m³", "displayName": "PM2.5" }
+                }],
+                "coordinates": { "latitude": 52.1, "longitude": 5.1 },
+                "bounds": [],
+                "distance": null,
+                "datetimeFirst": null,
+                "datetimeLast": null
+            }))?])
+        }
+
+        async fn list_sensors(&self, location: &Location) -> crate::error::Result<Vec<SensorBase>> {
+            Ok(location.sensors.clone())
+        }
+
+        async fn fetch_measurements(
+            &self,
+            location: &Location,
+            sensor: &SensorBase,
+            date_from: DateTime<Utc>,
+            _date_to: DateTime<Utc>,
+        ) -> crate::error::Result<Vec<DbMeasurement>> {
+            let (unit_normalized, unit_factor) = normalization("pm25", "µg/m³");
+            Ok(vec![DbMeasurement {
+                id: None,
+                location_id: location.id as i64,
+                sensor_id: sensor.id as i64,
+                sensor_name: "pm25 µg/m³".to_string(),
+                location_name: "Utrecht".to_string(),
+                parameter_id: 2,
+                parameter_name: "pm25".to_string(),
+                parameter_display_name: None,
+                value_avg: None,
+                value_min: None,
+                value_max: None,
+                value_q02: None,
+                value_q25: None,
+                value_median: None,
+                value_q75: None,
+                value_q98: None,
+                value_sd: None,
+                measurement_count: Some(24),
+                expected_count: Some(24),
+                percent_complete: Some(100.0),
+                percent_coverage: Some(100.0),
+                unit: "µg/m³".to_string(),
+                unit_normalized: unit_normalized.to_string(),
+                unit_factor,
+                value_normalized: None,
+                date_utc: date_from,
+                date_local: date_from.to_rfc3339(),
+                country: "NL".to_string(),
+                city: Some("Utrecht".to_string()),
+                latitude: Some(52.1),
+                longitude: Some(5.1),
+                is_mobile: false,
+                is_monitor: true,
+                owner_name: "RIVM".to_string(),
+                provider_name: "RIVM".to_string(),
+                source: self.name().to_string(),
+            }])
+        }
+    }
+
+    /// Tests that the import reports its stages, steps and skipped countries.
+    #[sqlx::test]
+    async fn test_import_reports_progress(pool: PgPool) {
+        let db = Database::from_pool(pool);
+        let progress = RecordingProgress::default();
+        let imported = import_from_source(&db, &DutchSource, 2, &progress)
+            .await
+            .unwrap();
+        assert_eq!(imported, 1);
+
+        let events = progress.events.into_inner().unwrap();
+        let stages: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                ProgressEvent::Stage { name, len } => Some((name.as_str(), *len)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            stages,
+            [
+                (
+                    "Fetching & saving locations/sensors...",
+                    Some(COUNTRIES.len() as u64)
+                ),
+                ("Fetching measurements...", Some(1)),
+                ("Inserting data into database...", None),
+            ]
+        );
+        let advanced: u64 = events
+            .iter()
+            .map(|e| match e {
+                ProgressEvent::Advance(n) => *n,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(advanced, COUNTRIES.len() as u64 + 1);
+        let errors = events
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    ProgressEvent::Notice {
+                        level: NoticeLevel::Error,
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert_eq!(errors, COUNTRIES.len() - 1);
+        assert!(db.has_data_imported().await.unwrap());
+    }
+}
@@ -0,0 +1,421 @@
+//! Prints the location and sensor inventory of the `locations` and `sensors` run modes.
+
+use super::InventoryCommand;
+use crate::analysis::metadata_changes;
+use crate::db::Database;
+use crate::error::{AppError, Result};
+use crate::models::{InventoryFilter, LocationSummary, MetadataChange, SensorSummary};
+use chrono::{DateTime, NaiveDate, Utc};
+use colored::*;
+use comfy_table::{presets::UTF8_FULL, Cell, Color, ContentArrangement, Table};
+
+/// Runs a `locations` subcommand.
+///
+/// # Errors
+///
+/// Returns `AppError::Cli` if the location to show does not exist, or `AppError::Db` if a
+/// query fails.
+pub async fn browse_locations(db: &Database, command: InventoryCommand) -> Result<()> {
+    match command {
+        InventoryCommand::List(args) => {
+            let locations = db.get_location_inventory(&args.filter()).await?;
+            if locations.is_empty() {
+                println!("{}", "No locations match the filters.".yellow());
+                return Ok(());
+            }
+            println!("{}", location_table(&locations));
+            println!("{} location(s)", locations.len());
+        },
+        InventoryCommand::Show { id } => {
+            let filter = InventoryFilter {
+                location_id: Some(id),
+                ..Default::default()
+            };
+            let location = db
+                .get_location_inventory(&filter)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| AppError::Cli(format!("Location {} not found", id)))?;
+            let sensors = db.get_sensor_inventory(&filter).await?;
+
+            println!(
+                "{}",
+                format!("Location {} ({})", location.id, label(&location.name)).bold()
+            );
+            let mut details = details_table();
+            for (field, value) in [
+                ("Locality", label(&location.locality)),
+                ("Country", location.country_code.clone()),
+                ("Provider", label(&location.provider_name)),
+                ("Owner", label(&location.owner_name)),
+                ("Kind", kind(location.is_monitor).to_string()),
+                (
+                    "Mobile",
+                    if location.is_mobile { "yes" } else { "no" }.to_string(),
+                ),
+                (
+                    "Coordinates",
+                    match (location.latitude, location.longitude) {
+                        (Some(lat), Some(lon)) => format!("{:.5}, {:.5}", lat, lon),
+                        _ => "-".to_string(),
+                    },
+                ),
+                ("First seen (OpenAQ)", timestamp(location.datetime_first)),
+                ("Last seen (OpenAQ)", timestamp(location.datetime_last)),
+                ("First stored day", date(location.first_stored)),
+                ("Last stored day", date(location.last_stored)),
+                ("Stored days", location.stored_days.to_string()),
+                ("Coverage", percent(location.coverage_percent())),
+            ] {
+                details.add_row(vec![Cell::new(field).fg(Color::Cyan), Cell::new(value)]);
+            }
+            println!("{details}");
+            if sensors.is_empty() {
+                println!("{}", "The location has no stored sensors.".yellow());
+            } else {
+                println!("{}", sensor_table(&sensors));
+            }
+        },
+        InventoryCommand::History { id } => {
+            let versions = db.get_location_versions(id).await?;
+            let Some(current) = versions.last() else {
+                return Err(AppError::Cli(format!("Location {} not found", id)));
+            };
+            let sensors = db.get_sensor_versions(Some(id), None).await?;
+
+            println!(
+                "{}",
+                format!("History of location {} ({})", id, label(&current.name)).bold()
+            );
+            let mut table = Table::new();
+            table
+                .load_preset(UTF8_FULL)
+                .set_content_arrangement(ContentArrangement::Dynamic)
+                .set_header(vec![
+                    Cell::new("Valid From").fg(Color::Green),
+                    Cell::new("Valid To").fg(Color::Green),
+                    Cell::new("Name").fg(Color::Green),
+                    Cell::new("Locality").fg(Color::Green),
+                    Cell::new("Provider").fg(Color::Green),
+                    Cell::new("Owner").fg(Color::Green),
+                    Cell::new("Kind").fg(Color::Green),
+                    Cell::new("Coordinates").fg(Color::Green),
+                    Cell::new("Last Reported (OpenAQ)").fg(Color::Green),
+                ]);
+            for version in &versions {
+                table.add_row(vec![
+                    Cell::new(timestamp(Some(version.valid_from))),
+                    Cell::new(
+                        version
+                            .valid_to
+                            .map_or_else(|| "current".to_string(), |t| timestamp(Some(t))),
+                    ),
+                    Cell::new(label(&version.name)),
+                    Cell::new(label(&version.locality)),
+                    Cell::new(label(&version.provider_name)),
+                    Cell::new(label(&version.owner_name)),
+                    Cell::new(kind(version.is_monitor)),
+                    Cell::new(match (version.latitude, version.longitude) {
+                        (Some(lat), Some(lon)) => format!("{:.5}, {:.5}", lat, lon),
+                        _ => "-".to_string(),
+                    }),
+                    Cell::new(timestamp(version.datetime_last)),
+                ]);
+            }
+            println!("{table}");
+            println!("{}", change_table(&metadata_changes(&versions, &sensors)));
+
+            // A location that lost all of its sensors no longer measures anything.
+            if let Some(removed) = sensors
+                .iter()
+                .map(|s| s.valid_to)
+                .collect::<Option<Vec<_>>>()
+                .and_then(|ends| ends.into_iter().max())
+            {
+                println!(
+                    "{} {}",
+                    "All sensors of the location were removed; decommissioned since".yellow(),
+                    timestamp(Some(removed))
+                );
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Runs a `sensors` subcommand.
+///
+/// # Errors
+///
+/// Returns `AppError::Cli` if the sensor to show does not exist, or `AppError::Db` if a query
+/// fails.
+pub async fn browse_sensors(db: &Database, command: InventoryCommand) -> Result<()> {
+    match command {
+        InventoryCommand::List(args) => {
+            let sensors = db.get_sensor_inventory(&args.filter()).await?;
+            if sensors.is_empty() {
+                println!("{}", "No sensors match the filters.".yellow());
+                return Ok(());
+            }
+            println!("{}", sensor_table(&sensors));
+            println!("{} sensor(s)", sensors.len());
+        },
+        InventoryCommand::Show { id } => {
+            let filter = InventoryFilter {
+                sensor_id: Some(id),
+                ..Default::default()
+            };
+            let sensor = db
+                .get_sensor_inventory(&filter)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| AppError::Cli(format!("Sensor {} not found", id)))?;
+            let months = db.get_sensor_monthly_coverage(id).await?;
+
+            println!(
+                "{}",
+                format!("Sensor {} ({})", sensor.id, sensor.parameter_name).bold()
+            );
+            let mut details = details_table();
+            for (field, value) in [
+                (
+                    "Location",
+                    format!("{} ({})", sensor.location_id, label(&sensor.location_name)),
+                ),
+                ("Locality", label(&sensor.locality)),
+                ("Country", sensor.country_code.clone()),
+                ("Units", sensor.units.clone()),
+                ("Provider", label(&sensor.provider_name)),
+                ("Owner", label(&sensor.owner_name)),
+                ("Kind", kind(sensor.is_monitor).to_string()),
+                (
+                    "Status",
+                    sensor.inactive_since.map_or_else(
+                        || "active".to_string(),
+                        |t| format!("inactive since {}", timestamp(Some(t))),
+                    ),
+                ),
+                ("First stored day", date(sensor.first_stored)),
+                ("Last stored day", date(sensor.last_stored)),
+                ("Stored days", sensor.stored_days.to_string()),
+                ("Coverage", percent(sensor.coverage_percent())),
+            ] {
+                details.add_row(vec![Cell::new(field).fg(Color::Cyan), Cell::new(value)]);
+            }
+            println!("{details}");
+
+            if months.is_empty() {
+                println!("{}", "No measurements of this sensor are stored.".yellow());
+                return Ok(());
+            }
+            let mut table = Table::new();
+            table
+                .load_preset(UTF8_FULL)
+                .set_content_arrangement(ContentArrangement::Dynamic)
+                .set_header(vec![
+                    Cell::new("Month").fg(Color::Green),
+                    Cell::new("Stored Days").fg(Color::Green),
+                    Cell::new("Coverage").fg(Color::Green),
+                ]);
+            for month in &months {
+                table.add_row(vec![
+                    Cell::new(month.month.format("%Y-%m")),
+                    Cell::new(format!("{} / {}", month.stored_days, month.month_days)),
+                    Cell::new(percent(Some(
+                        month.stored_days as f64 / month.month_days as f64 * 100.0,
+                    ))),
+                ]);
+            }
+            println!("{table}");
+        },
+        InventoryCommand::History { id } => {
+            let versions = db.get_sensor_versions(None, Some(id)).await?;
+            if versions.is_empty() {
+                return Err(AppError::Cli(format!("Sensor {} not found", id)));
+            }
+
+            println!("{}", format!("History of sensor {}", id).bold());
+            let mut table = Table::new();
+            table
+                .load_preset(UTF8_FULL)
+                .set_content_arrangement(ContentArrangement::Dynamic)
+                .set_header(vec![
+                    Cell::new("Valid From").fg(Color::Green),
+                    Cell::new("Valid To").fg(Color::Green),
+                    Cell::new("Location").fg(Color::Green),
+                    Cell::new("Name").fg(Color::Green),
+                    Cell::new("Parameter").fg(Color::Green),
+                    Cell::new("Units").fg(Color::Green),
+                ]);
+            for version in &versions {
+                table.add_row(vec![
+                    Cell::new(timestamp(Some(version.valid_from))),
+                    Cell::new(
+                        version
+                            .valid_to
+                            .map_or_else(|| "current".to_string(), |t| timestamp(Some(t))),
+                    ),
+                    Cell::new(version.location_id),
+                    Cell::new(&version.name),
+                    Cell::new(&version.parameter_name),
+                    Cell::new(&version.units),
+                ]);
+            }
+            println!("{table}");
+            println!("{}", change_table(&metadata_changes(&[], &versions)));
+        },
+    }
+    Ok(())
+}
+
+/// A table of the metadata changes, oldest first.
+fn change_table(changes: &[MetadataChange]) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("When").fg(Color::Green),
+            Cell::new("Sensor").fg(Color::Green),
+            Cell::new("Change").fg(Color::Green),
+        ]);
+    for change in changes {
+        table.add_row(vec![
+            Cell::new(timestamp(Some(change.at))),
+            Cell::new(
+                change
+                    .sensor_id
+                    .map_or_else(|| "-".to_string(), |id| id.to_string()),
+            ),
+            Cell::new(&change.description),
+        ]);
+    }
+    table
+}
+
+fn location_table(locations: &[LocationSummary]) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("ID").fg(Color::Green),
+            Cell::new("Name").fg(Color::Green),
+            Cell::new("Locality").fg(Color::Green),
+            Cell::new("Country").fg(Color::Green),
+            Cell::new("Provider").fg(Color::Green),
+            Cell::new("Kind").fg(Color::Green),
+            Cell::new("Parameters").fg(Color::Green),
+            Cell::new("Seen (OpenAQ)").fg(Color::Green),
+            Cell::new("Stored").fg(Color::Green),
+            Cell::new("Days").fg(Color::Green),
+            Cell::new("Coverage").fg(Color::Green),
+        ]);
+    for location in locations {
+        table.add_row(vec![
+            Cell::new(location.id).fg(Color::Cyan),
+            Cell::new(label(&location.name)),
+            Cell::new(label(&location.locality)),
+            Cell::new(&location.country_code),
+            Cell::new(label(&location.provider_name)),
+            Cell::new(kind(location.is_monitor)),
+            Cell::new(location.parameters.join(", ")),
+            Cell::new(span(
+                location.datetime_first.map(|t| t.date_naive()),
+                location.datetime_last.map(|t| t.date_naive()),
+            )),
+            Cell::new(span(location.first_stored, location.last_stored)),
+            Cell::new(location.stored_days),
+            Cell::new(percent(location.coverage_percent())),
+        ]);
+    }
+    table
+}
+
+fn sensor_table(sensors: &[SensorSummary]) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("ID").fg(Color::Green),
+            Cell::new("Parameter").fg(Color::Green),
+            Cell::new("Units").fg(Color::Green),
+            Cell::new("Location").fg(Color::Green),
+            Cell::new("Locality").fg(Color::Green),
+            Cell::new("Country").fg(Color::Green),
+            Cell::new("Kind").fg(Color::Green),
+            Cell::new("Stored").fg(Color::Green),
+            Cell::new("Days").fg(Color::Green),
+            Cell::new("Coverage").fg(Color::Green),
+        ]);
+    for sensor in sensors {
+        table.add_row(vec![
+            Cell::new(sensor.id).fg(Color::Cyan),
+            Cell::new(&sensor.parameter_name),
+            Cell::new(&sensor.units),
+            Cell::new(format!(
+                "{} ({})",
+                label(&sensor.location_name),
+                sensor.location_id
+            )),
+            Cell::new(label(&sensor.locality)),
+            Cell::new(&sensor.country_code),
+            Cell::new(kind(sensor.is_monitor)),
+            Cell::new(span(sensor.first_stored, sensor.last_stored)),
+            Cell::new(sensor.stored_days),
+            Cell::new(percent(sensor.coverage_percent())),
+        ]);
+    }
+    table
+}
+
+/// A two-column table of the fields of one entry.
+fn details_table() -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("Field").fg(Color::Green),
+            Cell::new("Value").fg(Color::Green),
+        ]);
+    table
+}
+
+fn label(value: &Option<String>) -> String {
+    value.clone().unwrap_or_else(|| "-".to_string())
+}
+
+fn kind(is_monitor: bool) -> &'static str {
+    if is_monitor {
+        "monitor"
+    } else {
+        "low-cost"
+    }
+}
+
+fn date(day: Option<NaiveDate>) -> String {
+    day.map_or_else(|| "-".to_string(), |d| d.to_string())
+}
+
+fn timestamp(time: Option<DateTime<Utc>>) -> String {
+    time.map_or_else(
+        || "-".to_string(),
+        |t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+    )
+}
+
+/// Formats a first and last day as `first to last`, or `-` without data.
+fn span(first: Option<NaiveDate>, last: Option<NaiveDate>) -> String {
+    match (first, last) {
+        (Some(first), Some(last)) => format!("{} to {}", first, last),
+        _ => "-".to_string(),
+    }
+}
+
+fn percent(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |p| format!("{:.1}%", p))
+}
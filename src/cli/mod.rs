@@ -1,8 +1,28 @@
 //! Handles Command Line Interface (CLI) related functionalities.
 //!
-//! Includes defining commands, parsing arguments (though currently minimal),
-//! handling user interaction (prompts, menus), and managing application state relevant to the UI.
+//! Includes defining commands, parsing the command-line arguments that select the run mode,
+//! handling user interaction (prompts, menus), managing application state relevant to the UI,
+//! rendering terminal charts, importing CSV files, exporting the tables, printing the location and sensor inventory and the sensor health
+//! check, pruning by the retention policies, and the API import with its progress reporting.
 
+mod args;
+mod charts;
 mod commands;
+mod export;
+mod file_import;
+mod health;
+mod import;
+mod inventory;
+mod progress;
+mod prune;
 
+pub use args::*;
+pub use charts::*;
 pub use commands::*;
+pub use export::*;
+pub use file_import::*;
+pub use health::*;
+pub use import::*;
+pub use inventory::*;
+pub use progress::*;
+pub use prune::*;
@@ -0,0 +1,196 @@
+//! Reports the progress of long-running operations (imports) independently of how it is shown:
+//! as `indicatif` progress bars in the interactive menu, in the progress pane of the
+//! dashboard (see `tui`), or in the log of the daemon (see `daemon`).
+
+use colored::*;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::Mutex;
+use std::time::Duration as StdDuration;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Severity of a progress notice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoticeLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A step of a long-running operation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// A new stage of `len` steps starts (`None` for a stage of unknown length).
+    Stage { name: String, len: Option<u64> },
+    /// Describes the step in progress.
+    Message(String),
+    /// `n` steps of the current stage are done.
+    Advance(u64),
+    /// Something worth reporting that does not interrupt the stage.
+    Notice { level: NoticeLevel, message: String },
+    /// The current stage is done.
+    Finished(String),
+}
+
+/// Receives the progress of a long-running operation.
+pub trait Progress: Send + Sync {
+    fn report(&self, event: ProgressEvent);
+
+    /// Starts a stage of `len` steps (`None` if unknown).
+    fn stage(&self, name: &str, len: Option<u64>) {
+        self.report(ProgressEvent::Stage {
+            name: name.to_string(),
+            len,
+        });
+    }
+
+    fn message(&self, message: String) {
+        self.report(ProgressEvent::Message(message));
+    }
+
+    fn advance(&self) {
+        self.report(ProgressEvent::Advance(1));
+    }
+
+    fn notice(&self, level: NoticeLevel, message: String) {
+        self.report(ProgressEvent::Notice { level, message });
+    }
+
+    fn finish(&self, message: &str) {
+        self.report(ProgressEvent::Finished(message.to_string()));
+    }
+
+    /// Returns `true` once the operation should stop early (e.g. on shutdown). Checked between
+    /// steps, so the step in progress is completed.
+    fn cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// Forwards the events to a channel, e.g. to the dashboard's event loop. Events sent after the
+/// receiver is dropped are discarded.
+impl Progress for UnboundedSender<ProgressEvent> {
+    fn report(&self, event: ProgressEvent) {
+        let _ = self.send(event);
+    }
+}
+
+/// Shows the progress as `indicatif` bars: a bar per stage of known length and a spinner
+/// otherwise. Notices are printed above the bar.
+#[derive(Default)]
+pub struct BarProgress {
+    bar: Mutex<Option<ProgressBar>>,
+}
+
+impl BarProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Creates a standard spinner ProgressBar.
+pub fn create_spinner(msg: &str) -> ProgressBar {
+    let pb = ProgressBar::new_spinner();
+    pb.enable_steady_tick(StdDuration::from_millis(120));
+    pb.set_style(
+        ProgressStyle::with_template("{spinner:.blue} {msg}")
+            .unwrap() // Assume template is valid
+            .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
+    );
+    pb.set_message(msg.to_string());
+    pb
+}
+
+/// Creates a standard progress bar.
+pub fn create_progress_bar(len: u64) -> ProgressBar {
+    let pb = ProgressBar::new(len);
+    pb.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) {msg}",
+        )
+        .unwrap() // Assume template is valid
+        .progress_chars("#>-"),
+    );
+    pb
+}
+
+impl Progress for BarProgress {
+    fn report(&self, event: ProgressEvent) {
+        let mut bar = self.bar.lock().unwrap_or_else(|e| e.into_inner());
+        match event {
+            ProgressEvent::Stage { name, len } => {
+                if let Some(previous) = bar.take() {
+                    previous.finish_and_clear();
+                }
+                let pb = match len {
+                    Some(len) => {
+                        let pb = create_progress_bar(len);
+                        pb.set_message(name);
+                        pb
+                    },
+                    None => create_spinner(&name),
+                };
+                *bar = Some(pb);
+            },
+            ProgressEvent::Message(message) => {
+                if let Some(pb) = bar.as_ref() {
+                    pb.set_message(message);
+                }
+            },
+            ProgressEvent::Advance(n) => {
+                if let Some(pb) = bar.as_ref() {
+                    pb.inc(n);
+                }
+            },
+            ProgressEvent::Notice { level, message } => {
+                let line = match level {
+                    NoticeLevel::Info => message.yellow().to_string(),
+                    NoticeLevel::Warning => format!("{} {}", "Warning:".yellow(), message),
+                    NoticeLevel::Error => format!("{} {}", "Error:".red(), message),
+                };
+                match bar.as_ref() {
+                    Some(pb) => pb.println(line),
+                    None => println!("{}", line),
+                }
+            },
+            ProgressEvent::Finished(message) => {
+                if let Some(pb) = bar.take() {
+                    pb.finish_with_message(message);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_progress() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        tx.stage("Fetching", Some(2));
+        tx.advance();
+        tx.notice(NoticeLevel::Warning, "slow".to_string());
+        tx.finish("Done");
+        let events: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(
+            events,
+            [
+                ProgressEvent::Stage {
+                    name: "Fetching".to_string(),
+                    len: Some(2)
+                },
+                ProgressEvent::Advance(1),
+                ProgressEvent::Notice {
+                    level: NoticeLevel::Warning,
+                    message: "slow".to_string()
+                },
+                ProgressEvent::Finished("Done".to_string()),
+            ]
+        );
+
+        // Reporting to a closed channel is not an error.
+        drop(rx);
+        tx.advance();
+    }
+}
@@ -0,0 +1,133 @@
+//! Applies the retention policies in the `prune` run mode: reports the measurements beyond
+//! their retention and archives and deletes them.
+
+use super::{create_spinner, PruneArgs};
+use crate::db::Database;
+use crate::error::Result;
+use crate::export::{write_measurements, ColumnarFormat};
+use crate::models::{PruneCount, RetentionPolicy};
+use chrono::Utc;
+use colored::*;
+use comfy_table::{presets::UTF8_FULL, Cell, Color, ContentArrangement, Table};
+
+/// Runs the `prune` mode: loads the retention policy, prints the measurements beyond their
+/// retention per country and parameter and, unless `--dry-run` is given, writes them to
+/// `--archive-dir` (if given) and deletes them.
+///
+/// # Errors
+///
+/// Returns an `AppError` if the policies file is invalid, a query fails, or archiving fails;
+/// nothing is deleted if the archive could not be written.
+pub async fn prune(db: &Database, args: &PruneArgs) -> Result<()> {
+    let mut policy = RetentionPolicy::from_file_or_env(args.policies.as_deref())?;
+    if let Some(keep_days) = args.keep_days {
+        policy.keep_days = keep_days;
+    }
+    print_policy(&policy);
+
+    let Some(max_id) = db.get_max_measurement_id().await? else {
+        println!("{}", "No measurements stored; nothing to prune.".green());
+        return Ok(());
+    };
+    let now = Utc::now();
+    let cutoffs = policy.cutoffs(now.date_naive());
+    let counts = db.count_prunable_measurements(&cutoffs, max_id).await?;
+    if counts.is_empty() {
+        println!(
+            "{}",
+            "No measurements are beyond their retention; nothing to prune.".green()
+        );
+        return Ok(());
+    }
+    let total = print_counts(&counts);
+
+    if args.dry_run {
+        println!(
+            "{} {} measurement(s) would be pruned; run without --dry-run to delete them.",
+            "Dry run:".yellow(),
+            total
+        );
+        return Ok(());
+    }
+
+    if let Some(archive_dir) = &args.archive_dir {
+        let dir = archive_dir.join(format!("pruned-{}", now.format("%Y%m%dT%H%M%S")));
+        let pb = create_spinner("Archiving the measurements to prune...");
+        let archived = write_measurements(
+            db.stream_prunable_measurements(&cutoffs, max_id),
+            &dir,
+            ColumnarFormat::Parquet,
+        )
+        .await;
+        pb.finish_and_clear();
+        let archived = archived?;
+        println!(
+            "{} {} measurements in {} Parquet files below {}",
+            "Archived".green(),
+            archived.rows,
+            archived.files.len(),
+            dir.display()
+        );
+    }
+
+    let pb = create_spinner("Deleting the measurements beyond their retention...");
+    let deleted = db.delete_prunable_measurements(&cutoffs, max_id).await;
+    pb.finish_and_clear();
+    println!("{} {} measurement(s).", "Pruned".green(), deleted?);
+    Ok(())
+}
+
+/// Prints the retention of the default and of every rule.
+fn print_policy(policy: &RetentionPolicy) {
+    println!(
+        "{}",
+        format!(
+            "Keeping {} days of daily measurements by default.",
+            policy.keep_days
+        )
+        .cyan()
+    );
+    for rule in &policy.rules {
+        println!(
+            "  {} {} in {}: {} days",
+            "Rule".cyan(),
+            rule.parameter.as_deref().unwrap_or("all parameters"),
+            rule.country.as_deref().unwrap_or("all countries"),
+            rule.keep_days
+        );
+    }
+}
+
+/// Prints the prunable measurements per country and parameter, returning their total.
+fn print_counts(counts: &[PruneCount]) -> i64 {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("Country").fg(Color::Green),
+            Cell::new("Parameter").fg(Color::Green),
+            Cell::new("Rows").fg(Color::Green),
+            Cell::new("First Day").fg(Color::Green),
+            Cell::new("Last Day").fg(Color::Green),
+        ]);
+    for count in counts {
+        table.add_row(vec![
+            Cell::new(&count.country).fg(Color::Cyan),
+            Cell::new(&count.parameter_name),
+            Cell::new(count.rows),
+            Cell::new(count.first_day.format("%Y-%m-%d")),
+            Cell::new(count.last_day.format("%Y-%m-%d")),
+        ]);
+    }
+    let total = counts.iter().map(|count| count.rows).sum();
+    table.add_row(vec![
+        Cell::new("Total").fg(Color::Yellow),
+        Cell::new(""),
+        Cell::new(total).fg(Color::Yellow),
+        Cell::new(""),
+        Cell::new(""),
+    ]);
+    println!("{table}");
+    total
+}
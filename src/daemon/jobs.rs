@@ -0,0 +1,64 @@
+//! The progress sink of the daemon jobs. The jobs themselves are the steps of the interactive
+//! import (see `cli::discover_sensors` and `cli::import_sensors`): discovering the locations and
+//! sensors of the predefined countries, and importing the recent daily measurements of the
+//! discovered sensors.
+//!
+//! Unlike the interactive import, the jobs only log their progress.
+
+use crate::cli::{NoticeLevel, Progress, ProgressEvent};
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+/// Logs the stages of a job and cancels it once `shutdown` turns `true`.
+///
+/// Notices repeat what the import already logs at their level, so they are only logged at
+/// debug level; per-sensor messages and steps are not logged.
+pub struct LogProgress {
+    shutdown: watch::Receiver<bool>,
+}
+
+impl LogProgress {
+    pub fn new(shutdown: watch::Receiver<bool>) -> Self {
+        Self { shutdown }
+    }
+}
+
+impl Progress for LogProgress {
+    fn report(&self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::Stage {
+                name,
+                len: Some(len),
+            } => info!("{} ({} steps)", name, len),
+            ProgressEvent::Stage { name, len: None } => info!("{}", name),
+            ProgressEvent::Finished(message) => info!("{}", message),
+            ProgressEvent::Notice { level, message } => {
+                let level = match level {
+                    NoticeLevel::Info => "info",
+                    NoticeLevel::Warning => "warning",
+                    NoticeLevel::Error => "error",
+                };
+                debug!("Import notice ({}): {}", level, message);
+            },
+            ProgressEvent::Message(_) | ProgressEvent::Advance(_) => {},
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_progress_follows_shutdown() {
+        let (tx, rx) = watch::channel(false);
+        let progress = LogProgress::new(rx);
+        assert!(!progress.cancelled());
+        tx.send(true).unwrap();
+        assert!(progress.cancelled());
+    }
+}
@@ -0,0 +1,296 @@
+//! Long-running daemon importing data on a cron-like schedule.
+//!
+//! Started with `fizyr-assessment daemon`. Two jobs run on their own schedules: location
+//! discovery and the incremental import of the discovered sensors, both shared with the
+//! interactive import and logging their progress (see `jobs`). Every run takes a
+//! Postgres advisory lock first, so instances sharing a database never import simultaneously,
+//! and is recorded in the `job_runs` table (shown by `fizyr-assessment status`). The alert
+//! rules (see `alerts`) are evaluated after every successful import. SIGTERM or Ctrl+C stops
+//! the daemon after the running job.
+
+mod jobs;
+
+pub use jobs::*;
+
+use crate::alerts::{evaluate_after_import, AlertEngine};
+use crate::api::DataSource;
+use crate::cli::{discover_sensors, import_sensors, SensorTarget, COUNTRIES};
+use crate::db::Database;
+use crate::error::Result;
+use crate::models::{JobRun, JobRunStatus, MeasurementFilter};
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use std::fmt;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+/// Key of the advisory lock held while a job runs.
+pub const IMPORT_LOCK_KEY: i64 = 0x0A1B_0001;
+
+/// Schedules and settings of the daemon.
+#[derive(Debug)]
+pub struct DaemonConfig {
+    /// When to import the recent measurements of the discovered sensors.
+    pub import_schedule: Schedule,
+    /// When to discover the locations and sensors of the predefined countries.
+    pub discovery_schedule: Schedule,
+    /// Number of past days fetched by every import.
+    pub days: i64,
+    /// Alert rules evaluated after every successful import, if configured.
+    pub alerts: Option<AlertEngine>,
+    /// Data quality filter applied when evaluating the alert rules.
+    pub filter: MeasurementFilter,
+}
+
+/// A scheduled job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Job {
+    Discovery,
+    Import,
+}
+
+impl Job {
+    /// Returns the name stored in `job_runs.job`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Job::Discovery => "discovery",
+            Job::Import => "import",
+        }
+    }
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The upcoming run time of each job.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timetable {
+    discovery: Option<DateTime<Utc>>,
+    import: Option<DateTime<Utc>>,
+}
+
+impl Timetable {
+    /// Schedules the first run of each job after `now`.
+    pub fn new(config: &DaemonConfig, now: DateTime<Utc>) -> Self {
+        Self {
+            discovery: config.discovery_schedule.after(&now).next(),
+            import: config.import_schedule.after(&now).next(),
+        }
+    }
+
+    /// Returns the job due first and when it is due. Discovery goes first when both jobs are
+    /// due at the same time, so the import uses the fresh sensors.
+    pub fn next(&self) -> Option<(Job, DateTime<Utc>)> {
+        match (self.discovery, self.import) {
+            (Some(d), Some(i)) if i < d => Some((Job::Import, i)),
+            (Some(d), _) => Some((Job::Discovery, d)),
+            (None, Some(i)) => Some((Job::Import, i)),
+            (None, None) => None,
+        }
+    }
+
+    /// Schedules the next run of `job` after `now`, the end of its previous run. Runs missed
+    /// while another job was running are not caught up, except for a run of the other job
+    /// that fell due meanwhile, which stays due.
+    pub fn advance(&mut self, config: &DaemonConfig, job: Job, now: DateTime<Utc>) {
+        match job {
+            Job::Discovery => self.discovery = config.discovery_schedule.after(&now).next(),
+            Job::Import => self.import = config.import_schedule.after(&now).next(),
+        }
+    }
+}
+
+/// Returns a receiver that turns `true` once SIGTERM or Ctrl+C is received.
+pub fn shutdown_signal() -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(false);
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        info!("Shutdown requested; stopping after the running job");
+        let _ = tx.send(true);
+    });
+    rx
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => {
+            tokio::select! {
+                _ = term.recv() => {},
+                _ = tokio::signal::ctrl_c() => {},
+            }
+        },
+        Err(e) => {
+            warn!("Failed to listen for SIGTERM: {}", e);
+            tokio::signal::ctrl_c().await.ok();
+        },
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    tokio::signal::ctrl_c().await.ok();
+}
+
+/// Runs the jobs on their schedules until `shutdown` turns `true`.
+///
+/// Discovery runs once at startup so the first import has sensors to fetch.
+///
+/// # Errors
+///
+/// Job failures are recorded in `job_runs` and logged rather than returned; this function
+/// currently always returns `Ok`.
+pub async fn run(
+    db: &Database,
+    source: &dyn DataSource,
+    config: &DaemonConfig,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    info!(
+        "Daemon started (import: '{}', discovery: '{}', {} days per import)",
+        config.import_schedule, config.discovery_schedule, config.days
+    );
+    let mut targets = Vec::new();
+    run_job(db, source, config, Job::Discovery, &mut targets, &shutdown).await;
+
+    let mut timetable = Timetable::new(config, Utc::now());
+    while !*shutdown.borrow() {
+        let Some((job, due)) = timetable.next() else {
+            warn!("No upcoming runs in the schedules; stopping");
+            break;
+        };
+        info!("Next job: {} at {}", job, due);
+        let wait = (due - Utc::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {},
+            _ = shutdown.changed() => break,
+        }
+        run_job(db, source, config, job, &mut targets, &shutdown).await;
+        timetable.advance(config, job, Utc::now());
+    }
+    info!("Daemon stopped");
+    Ok(())
+}
+
+/// Runs one job under the advisory lock and records the run.
+///
+/// An import without discovered sensors (e.g. because another instance held the lock at
+/// startup) runs the discovery first.
+async fn run_job(
+    db: &Database,
+    source: &dyn DataSource,
+    config: &DaemonConfig,
+    job: Job,
+    targets: &mut Vec<SensorTarget>,
+    shutdown: &watch::Receiver<bool>,
+) {
+    let started_at = Utc::now();
+    info!("Starting {} job", job);
+    let (status, rows, error) = match db.try_advisory_lock(IMPORT_LOCK_KEY).await {
+        Ok(None) => {
+            info!(
+                "Skipping {} job: another instance holds the import lock",
+                job
+            );
+            (JobRunStatus::Skipped, 0, None)
+        },
+        Ok(Some(lock)) => {
+            let progress = LogProgress::new(shutdown.clone());
+            let result = match job {
+                Job::Discovery => discover_sensors(db, source, &COUNTRIES, &progress)
+                    .await
+                    .map(|found| {
+                        let count = found.len();
+                        *targets = found;
+                        count
+                    }),
+                Job::Import => {
+                    let discovered = if targets.is_empty() {
+                        discover_sensors(db, source, &COUNTRIES, &progress)
+                            .await
+                            .map(|found| *targets = found)
+                    } else {
+                        Ok(())
+                    };
+                    match discovered {
+                        Ok(()) => import_sensors(db, source, targets, config.days, &progress).await,
+                        Err(e) => Err(e),
+                    }
+                },
+            };
+            if job == Job::Import && result.is_ok() {
+                // Evaluated under the lock, so instances never announce an alert twice.
+                // Failures are logged and do not fail the import.
+                let _ = evaluate_after_import(config.alerts.as_ref(), db, &config.filter).await;
+            }
+            if let Err(e) = lock.release().await {
+                warn!("Failed to release the import lock: {}", e);
+            }
+            match result {
+                Ok(rows) => (JobRunStatus::Success, rows as i64, None),
+                Err(e) => {
+                    error!("{} job failed: {}", job, e);
+                    (JobRunStatus::Failed, 0, Some(e.to_string()))
+                },
+            }
+        },
+        Err(e) => {
+            error!("Failed to take the import lock for the {} job: {}", job, e);
+            (JobRunStatus::Failed, 0, Some(e.to_string()))
+        },
+    };
+
+    let run = JobRun {
+        job: job.as_str().to_string(),
+        started_at,
+        finished_at: Utc::now(),
+        status,
+        rows,
+        error,
+    };
+    info!("Finished {} job: {} ({} rows)", job, status.as_str(), rows);
+    if let Err(e) = db.record_job_run(&run).await {
+        error!("Failed to record the {} job run: {}", job, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::str::FromStr;
+
+    fn config(import: &str, discovery: &str) -> DaemonConfig {
+        DaemonConfig {
+            import_schedule: Schedule::from_str(import).unwrap(),
+            discovery_schedule: Schedule::from_str(discovery).unwrap(),
+            days: 2,
+            alerts: None,
+            filter: MeasurementFilter::default(),
+        }
+    }
+
+    #[test]
+    fn test_timetable() {
+        // Hourly import, daily discovery at 03:00.
+        let config = config("0 0 * * * *", "0 0 3 * * *");
+        let at = |h, m| Utc.with_ymd_and_hms(2024, 3, 10, h, m, 0).unwrap();
+
+        let mut timetable = Timetable::new(&config, at(1, 15));
+        assert_eq!(timetable.next(), Some((Job::Import, at(2, 0))));
+        timetable.advance(&config, Job::Import, at(2, 5));
+
+        // Both are due at 03:00: discovery first, then the import that fell due meanwhile.
+        assert_eq!(timetable.next(), Some((Job::Discovery, at(3, 0))));
+        timetable.advance(&config, Job::Discovery, at(3, 10));
+        assert_eq!(timetable.next(), Some((Job::Import, at(3, 0))));
+
+        // A long import skips the runs it overlapped.
+        timetable.advance(&config, Job::Import, at(5, 30));
+        assert_eq!(timetable.next(), Some((Job::Import, at(6, 0))));
+    }
+}
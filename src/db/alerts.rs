@@ -0,0 +1,298 @@
+//! Database operations of the alert rules: the daily values within a rule's scope and the
+//! state of the alerts in the `alerts` table.
+
+use super::{filter_conditions, Database};
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{Alert, AlertScope, MeasurementFilter, ScopeDailyValue};
+use chrono::{DateTime, Utc};
+use tracing::error;
+
+impl Database {
+    /// Loads the daily averages of `parameter` within `scope` on the latest `limit` days with
+    /// data, newest first.
+    ///
+    /// Values are normalised and the data quality `filter` is applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_scope_daily_values(
+        &self,
+        parameter: &str,
+        scope: &AlertScope,
+        limit: i64,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<ScopeDailyValue>> {
+        let _timer = metrics().query_timer("get_scope_daily_values");
+        let query = format!(
+            r#"
+            SELECT
+                date_trunc('day', date_utc) as day,
+                AVG(value_normalized::DOUBLE PRECISION) as value,
+                MAX(unit_normalized) as unit
+            FROM measurements
+            WHERE
+                parameter_name = $1
+                AND ($2::TEXT IS NULL OR country = $2)
+                AND ($3::TEXT IS NULL OR city = $3)
+                AND ($4::BIGINT IS NULL OR location_id = $4)
+                AND value_normalized IS NOT NULL
+                {conditions}
+            GROUP BY day
+            ORDER BY day DESC
+            LIMIT $5
+            "#,
+            conditions = filter_conditions(filter)
+        );
+        sqlx::query_as::<_, ScopeDailyValue>(&query)
+            .bind(parameter)
+            .bind(&scope.country)
+            .bind(&scope.locality)
+            .bind(scope.location_id)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!(
+                    "Failed to fetch daily {} values for {}: {}",
+                    parameter, scope, e
+                );
+                AppError::Db(e.into())
+            })
+    }
+
+    /// Loads the firing alert of `rule`, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_firing_alert(&self, rule: &str) -> Result<Option<Alert>> {
+        let _timer = metrics().query_timer("get_firing_alert");
+        sqlx::query_as::<_, Alert>("SELECT * FROM alerts WHERE rule = $1 AND status = 'firing'")
+            .bind(rule)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to fetch firing alert of rule {}: {}", rule, e);
+                AppError::Db(e.into())
+            })
+    }
+
+    /// Returns when `rule` last fired an alert, if ever.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_last_fired_at(&self, rule: &str) -> Result<Option<DateTime<Utc>>> {
+        let _timer = metrics().query_timer("get_last_fired_at");
+        sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+            "SELECT MAX(fired_at) FROM alerts WHERE rule = $1",
+        )
+        .bind(rule)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch last firing of rule {}: {}", rule, e);
+            AppError::Db(e.into())
+        })
+    }
+
+    /// Stores a new firing alert of `rule` and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the insert fails, e.g. because the rule already has a firing
+    /// alert.
+    pub async fn fire_alert(
+        &self,
+        rule: &str,
+        first_day: DateTime<Utc>,
+        last: &ScopeDailyValue,
+        threshold: f64,
+        fired_at: DateTime<Utc>,
+    ) -> Result<Alert> {
+        let _timer = metrics().query_timer("fire_alert");
+        sqlx::query_as::<_, Alert>(
+            r#"
+            INSERT INTO alerts (rule, status, fired_at, first_day, last_day, value, threshold, unit)
+            VALUES ($1, 'firing', $2, $3, $4, $5, $6, $7)
+            RETURNING *
+            "#,
+        )
+        .bind(rule)
+        .bind(fired_at)
+        .bind(first_day)
+        .bind(last.day)
+        .bind(last.value)
+        .bind(threshold)
+        .bind(&last.unit)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to store alert of rule {}: {}", rule, e);
+            AppError::Db(e.into())
+        })
+    }
+
+    /// Updates the latest day and value of the firing alert `id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the update fails.
+    pub async fn update_firing_alert(&self, id: i32, last: &ScopeDailyValue) -> Result<()> {
+        let _timer = metrics().query_timer("update_firing_alert");
+        sqlx::query("UPDATE alerts SET last_day = $2, value = $3 WHERE id = $1")
+            .bind(id)
+            .bind(last.day)
+            .bind(last.value)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to update alert {}: {}", id, e);
+                AppError::Db(e.into())
+            })?;
+        Ok(())
+    }
+
+    /// Marks the alert `id` as resolved by the day `last` and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the update fails.
+    pub async fn resolve_alert(
+        &self,
+        id: i32,
+        last: &ScopeDailyValue,
+        resolved_at: DateTime<Utc>,
+    ) -> Result<Alert> {
+        let _timer = metrics().query_timer("resolve_alert");
+        sqlx::query_as::<_, Alert>(
+            r#"
+            UPDATE alerts
+            SET status = 'resolved', resolved_at = $2, last_day = $3, value = $4
+            WHERE id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(id)
+        .bind(resolved_at)
+        .bind(last.day)
+        .bind(last.value)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to resolve alert {}: {}", id, e);
+            AppError::Db(e.into())
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::DbMeasurement;
+    use chrono::{Duration, Utc};
+
+    use sqlx::PgPool;
+
+    fn measurement(sensor_id: i64, city: &str, days_ago: i64, value: f64) -> DbMeasurement {
+        let date_utc = Utc::now() - Duration::days(days_ago);
+        DbMeasurement {
+            city: Some(city.to_string()),
+            ..DbMeasurement::test(sensor_id, "NL", "pm25", date_utc).with_value(value)
+        }
+    }
+
+    /// Tests the daily averages within a scope, newest first.
+    #[sqlx::test]
+    async fn test_scope_daily_values(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        db.insert_measurements(&[
+            measurement(1, "Utrecht", 1, 20.0),
+            measurement(2, "Utrecht", 1, 30.0),
+            measurement(1, "Utrecht", 2, 10.0),
+            measurement(3, "Amsterdam", 1, 90.0),
+        ])
+        .await
+        .unwrap();
+
+        let scope = AlertScope {
+            country: Some("NL".to_string()),
+            locality: Some("Utrecht".to_string()),
+            location_id: None,
+        };
+        let filter = MeasurementFilter::default();
+        let values = db
+            .get_scope_daily_values("pm25", &scope, 10, &filter)
+            .await
+            .unwrap();
+        assert_eq!(values.len(), 2);
+        assert!((values[0].value - 25.0).abs() < 1e-9);
+        assert!((values[1].value - 10.0).abs() < 1e-9);
+        assert!(values[0].day > values[1].day);
+
+        let limited = db
+            .get_scope_daily_values("pm25", &scope, 1, &filter)
+            .await
+            .unwrap();
+        assert_eq!(limited.len(), 1);
+
+        let location = AlertScope {
+            location_id: Some(3),
+            ..AlertScope::default()
+        };
+        let values = db
+            .get_scope_daily_values("pm25", &location, 10, &filter)
+            .await
+            .unwrap();
+        assert_eq!(values.len(), 1);
+        assert!((values[0].value - 90.0).abs() < 1e-9);
+    }
+
+    /// Tests firing, updating and resolving an alert.
+    #[sqlx::test]
+    async fn test_alert_lifecycle(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        let now = Utc::now();
+        let day = |days_ago: i64, value: f64| ScopeDailyValue {
+            day: now - Duration::days(days_ago),
+            value,
+            unit: Some("µg/m³".to_string()),
+        };
+
+        assert!(db.get_firing_alert("pm25").await.unwrap().is_none());
+        assert!(db.get_last_fired_at("pm25").await.unwrap().is_none());
+
+        let alert = db
+            .fire_alert("pm25", now - Duration::days(3), &day(1, 40.0), 25.0, now)
+            .await
+            .unwrap();
+        assert_eq!(alert.status, "firing");
+        assert_eq!(alert.value, Some(40.0));
+        // Only one alert per rule can fire at a time.
+        assert!(db
+            .fire_alert("pm25", now, &day(0, 40.0), 25.0, now)
+            .await
+            .is_err());
+
+        db.update_firing_alert(alert.id, &day(0, 45.0))
+            .await
+            .unwrap();
+        let firing = db.get_firing_alert("pm25").await.unwrap().unwrap();
+        assert_eq!(firing.value, Some(45.0));
+        assert!(db.get_last_fired_at("pm25").await.unwrap().is_some());
+
+        let resolved = db
+            .resolve_alert(alert.id, &day(0, 12.0), now)
+            .await
+            .unwrap();
+        assert_eq!(resolved.status, "resolved");
+        assert_eq!(resolved.value, Some(12.0));
+        assert!(resolved.resolved_at.is_some());
+        assert!(db.get_firing_alert("pm25").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,181 @@
+//! Database operations backing anomaly detection: loading daily sensor readings and
+//! storing the resulting flags in the `measurement_flags` table.
+
+use super::Database;
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{AnomalyFlag, SensorReading};
+use chrono::{DateTime, Utc};
+use tracing::{error, info};
+
+impl Database {
+    /// Loads all non-null normalised daily sensor averages recorded at or after `since`,
+    /// ordered by sensor and date, for anomaly detection.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_sensor_readings(&self, since: DateTime<Utc>) -> Result<Vec<SensorReading>> {
+        let _timer = metrics().query_timer("get_sensor_readings");
+        info!("Loading sensor readings since {} for anomaly scan", since);
+        let query = r#"
+        SELECT
+            id as measurement_id,
+            sensor_id,
+            parameter_name,
+            date_utc,
+            value_normalized::DOUBLE PRECISION as value,
+            latitude,
+            longitude
+        FROM measurements
+        WHERE date_utc >= $1 AND value_normalized IS NOT NULL
+        ORDER BY sensor_id, date_utc
+        "#;
+
+        sqlx::query_as::<_, SensorReading>(query)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to load sensor readings for anomaly scan: {}", e);
+                AppError::Db(e.into())
+            })
+    }
+
+    /// Replaces all anomaly flags for measurements dated at or after `since` with `flags`.
+    ///
+    /// Runs in a single transaction so that a re-scan of a period never leaves stale or
+    /// partial flags behind. Returns the number of flags stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the transaction or any statement fails.
+    pub async fn replace_anomaly_flags(
+        &self,
+        since: DateTime<Utc>,
+        flags: &[AnomalyFlag],
+    ) -> Result<usize> {
+        let _timer = metrics().query_timer("replace_anomaly_flags");
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            error!("Failed to begin transaction for anomaly flags: {}", e);
+            AppError::Db(e.into())
+        })?;
+
+        let removed = sqlx::query("DELETE FROM measurement_flags WHERE date_utc >= $1")
+            .bind(since)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to clear previous anomaly flags: {}", e);
+                AppError::Db(e.into())
+            })?
+            .rows_affected();
+
+        for f in flags {
+            sqlx::query(
+                r#"
+                INSERT INTO measurement_flags (measurement_id, sensor_id, date_utc, flag, score, detail)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (measurement_id, flag) DO NOTHING
+                "#,
+            )
+            .bind(f.measurement_id)
+            .bind(f.sensor_id)
+            .bind(f.date_utc)
+            .bind(f.kind.as_str())
+            .bind(f.score)
+            .bind(&f.detail)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!(
+                    "Failed to insert anomaly flag (measurement_id: {}, flag: {}): {}",
+                    f.measurement_id,
+                    f.kind.as_str(),
+                    e
+                );
+                AppError::Db(e.into())
+            })?;
+        }
+
+        tx.commit().await.map_err(|e| {
+            error!("Failed to commit anomaly flags: {}", e);
+            AppError::Db(e.into())
+        })?;
+
+        info!(
+            "Replaced {} previous anomaly flags with {} new flags",
+            removed,
+            flags.len()
+        );
+        Ok(flags.len())
+    }
+}
+
+// --- Integration Tests ---
+// Gated by the `integration-tests` feature flag like the tests in `postgres.rs`.
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::analysis::{detect_anomalies, AnomalyConfig};
+    use crate::models::{DbMeasurement, MeasurementFilter};
+    use chrono::Duration;
+
+    use sqlx::PgPool;
+
+    /// Creates a measurement for sensor 42 in NL, `days_ago` days before today (midnight UTC).
+    fn measurement(value: f64, days_ago: i64) -> DbMeasurement {
+        let date = (Utc::now().date_naive() - Duration::days(days_ago))
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_local_timezone(Utc)
+            .unwrap();
+        DbMeasurement::test(42, "NL", "pm25", date).with_value(value)
+    }
+
+    /// Tests that flags are stored, replaced on re-scan, and excluded by the analytic queries.
+    #[sqlx::test]
+    async fn test_flags_are_stored_and_excluded(pool: PgPool) -> Result<()> {
+        let db = Database { pool };
+        db.init_schema().await?;
+        let values = [10.0, 11.0, 9.0, 10.0, 300.0];
+        let rows: Vec<DbMeasurement> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| measurement(*v, (values.len() - i) as i64))
+            .collect();
+        db.insert_measurements(&rows).await?;
+
+        let since = Utc::now() - Duration::days(10);
+        let readings = db.get_sensor_readings(since).await?;
+        assert_eq!(readings.len(), 5);
+        let flags = detect_anomalies(&readings, &AnomalyConfig::default());
+        assert!(!flags.is_empty(), "the 300 spike should be flagged");
+        db.replace_anomaly_flags(since, &flags).await?;
+        // Re-scanning replaces the flags instead of duplicating them
+        let stored = db.replace_anomaly_flags(since, &flags).await?;
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM measurement_flags")
+            .fetch_one(&db.pool)
+            .await?;
+        assert_eq!(count as usize, stored);
+
+        let excluded = db
+            .get_daily_values("NL", "pm25", 30, &MeasurementFilter::default())
+            .await?;
+        assert_eq!(excluded.len(), 4, "the flagged spike should be excluded");
+        let included = db
+            .get_daily_values(
+                "NL",
+                "pm25",
+                30,
+                &MeasurementFilter {
+                    exclude_flagged: false,
+                    min_completeness: None,
+                },
+            )
+            .await?;
+        assert_eq!(included.len(), 5);
+        Ok(())
+    }
+}
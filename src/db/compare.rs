@@ -0,0 +1,182 @@
+//! Database operations of the period comparison: per-sensor sums of the daily values in two
+//! periods.
+
+use super::{filter_conditions, Database};
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{DateRange, MeasurementFilter, SensorPeriodStats};
+use tracing::{error, info, warn};
+
+impl Database {
+    /// Sums the normalised daily values of every sensor and parameter of a country (or one of
+    /// its localities) in the `current` and the `baseline` period.
+    ///
+    /// Rows are ordered by catalogue ID, parameter name and sensor ID. The periods must not
+    /// overlap.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::IncompatibleUnits` if a parameter has values in different units, or
+    /// `AppError::Db` if the query fails.
+    pub async fn get_sensor_period_stats(
+        &self,
+        country: &str,
+        locality: Option<&str>,
+        current: &DateRange,
+        baseline: &DateRange,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<SensorPeriodStats>> {
+        let _timer = metrics().query_timer("get_sensor_period_stats");
+        info!(
+            "Comparing {} with {} for {}{}",
+            current,
+            baseline,
+            country,
+            locality.map(|l| format!("/{}", l)).unwrap_or_default()
+        );
+        let query = format!(
+            r#"
+            SELECT
+                measurements.parameter_name as parameter,
+                parameters.display_name,
+                measurements.unit_normalized as unit,
+                measurements.sensor_id,
+                MAX(measurements.location_name) as location_name,
+                (measurements.date_utc >= $3 AND measurements.date_utc < $4) as in_current,
+                SUM(measurements.value_normalized::DOUBLE PRECISION) as total,
+                COUNT(*) as days
+            FROM measurements
+            LEFT JOIN parameters ON parameters.name = measurements.parameter_name
+            WHERE
+                measurements.country = $1
+                AND ($2::TEXT IS NULL OR measurements.city = $2)
+                AND (
+                    (measurements.date_utc >= $3 AND measurements.date_utc < $4)
+                    OR (measurements.date_utc >= $5 AND measurements.date_utc < $6)
+                )
+                AND measurements.value_normalized IS NOT NULL
+                {}
+            GROUP BY
+                measurements.parameter_name, parameters.id, parameters.display_name,
+                measurements.unit_normalized, measurements.sensor_id, in_current
+            ORDER BY
+                parameters.id NULLS LAST, measurements.parameter_name, measurements.sensor_id,
+                in_current
+            "#,
+            filter_conditions(filter)
+        );
+
+        let stats = sqlx::query_as::<_, SensorPeriodStats>(&query)
+            .bind(country)
+            .bind(locality)
+            .bind(current.start_utc())
+            .bind(current.end_utc())
+            .bind(baseline.start_utc())
+            .bind(baseline.end_utc())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to compare periods for {}: {}", country, e);
+                AppError::Db(e.into())
+            })?;
+
+        // Averages of values in different units are meaningless, like in the other queries.
+        let mut conflicts: Vec<(&str, Vec<&str>)> = Vec::new();
+        for row in &stats {
+            let unit = row.unit.as_deref().unwrap_or("unknown");
+            match conflicts.iter_mut().find(|(p, _)| *p == row.parameter) {
+                Some((_, units)) if !units.contains(&unit) => units.push(unit),
+                Some(_) => {},
+                None => conflicts.push((&row.parameter, vec![unit])),
+            }
+        }
+        conflicts.retain(|(_, units)| units.len() > 1);
+        if !conflicts.is_empty() {
+            let detail = conflicts
+                .iter()
+                .map(|(parameter, units)| format!("{} ({})", parameter, units.join(", ")))
+                .collect::<Vec<_>>()
+                .join("; ");
+            warn!("Refusing to compare mixed units: {}", detail);
+            return Err(AppError::IncompatibleUnits(detail));
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::DbMeasurement;
+    use chrono::{Duration, NaiveDate, NaiveTime};
+
+    use sqlx::PgPool;
+
+    fn measurement(
+        sensor_id: i64,
+        city: &str,
+        day: NaiveDate,
+        unit: &str,
+        value: f64,
+    ) -> DbMeasurement {
+        let date_utc = day.and_time(NaiveTime::MIN).and_utc() + Duration::hours(12);
+        DbMeasurement {
+            city: Some(city.to_string()),
+            ..DbMeasurement::test(sensor_id, "NL", "pm25", date_utc).with_value_in(value, unit)
+        }
+    }
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    /// Tests summing the values per sensor and period, restricted to a locality.
+    #[sqlx::test]
+    async fn test_sensor_period_stats(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        db.insert_measurements(&[
+            measurement(1, "Utrecht", day(1), "µg/m³", 10.0),
+            measurement(1, "Utrecht", day(2), "µg/m³", 20.0),
+            measurement(1, "Utrecht", day(8), "µg/m³", 30.0),
+            measurement(2, "Utrecht", day(9), "µg/m³", 5.0),
+            // Outside both periods.
+            measurement(1, "Utrecht", day(20), "µg/m³", 99.0),
+            measurement(3, "Amsterdam", day(8), "µg/m³", 50.0),
+        ])
+        .await
+        .unwrap();
+
+        let baseline = DateRange::new(day(1), day(7));
+        let current = baseline.shifted(7);
+        let filter = MeasurementFilter::default();
+        let stats = db
+            .get_sensor_period_stats("NL", Some("Utrecht"), &current, &baseline, &filter)
+            .await
+            .unwrap();
+        let rows: Vec<_> = stats
+            .iter()
+            .map(|s| (s.sensor_id, s.in_current, s.total, s.days))
+            .collect();
+        assert_eq!(
+            rows,
+            [(1, false, 30.0, 2), (1, true, 30.0, 1), (2, true, 5.0, 1)]
+        );
+
+        let country = db
+            .get_sensor_period_stats("NL", None, &current, &baseline, &filter)
+            .await
+            .unwrap();
+        assert_eq!(country.len(), 4);
+
+        // Mixed units cannot be compared.
+        db.insert_measurements(&[measurement(4, "Utrecht", day(10), "ppm", 1.0)])
+            .await
+            .unwrap();
+        let result = db
+            .get_sensor_period_stats("NL", None, &current, &baseline, &filter)
+            .await;
+        assert!(matches!(result, Err(AppError::IncompatibleUnits(_))));
+    }
+}
@@ -0,0 +1,160 @@
+//! Database operations summarising the stored daily distribution (quantiles) of a parameter
+//! per sensor and per locality.
+
+use super::{filter_conditions, Database};
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{DistributionSummary, MeasurementFilter};
+use clap::ValueEnum;
+use tracing::{error, info};
+
+/// Grouping used by `Database::get_distribution`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DistributionGroup {
+    /// One row per sensor, labelled with its location name and sensor ID.
+    Sensor,
+    /// One row per locality (city), falling back to the location name when no city is known.
+    Locality,
+}
+
+impl Database {
+    /// Summarises the daily distribution of a parameter for a country over the last `days` days,
+    /// grouped per sensor or per locality.
+    ///
+    /// Quantiles are converted into the normalised unit of the parameter. Rows are ordered by
+    /// median, highest first. Days without a stored median are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_distribution(
+        &self,
+        country: &str,
+        parameter: &str,
+        days: i64,
+        group: DistributionGroup,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<DistributionSummary>> {
+        let _timer = metrics().query_timer("get_distribution");
+        info!(
+            "Fetching {:?} distribution of {} for {} over the last {} days",
+            group, parameter, country, days
+        );
+        self.ensure_compatible_units(&[country.to_string()], &[parameter], days as i32, filter)
+            .await?;
+        let (label, locality, group_by) = match group {
+            DistributionGroup::Sensor => (
+                "location_name || ' #' || sensor_id",
+                "MAX(city)",
+                "sensor_id, location_name",
+            ),
+            DistributionGroup::Locality => (
+                "COALESCE(city, location_name)",
+                "NULL::TEXT",
+                "COALESCE(city, location_name)",
+            ),
+        };
+        let query = format!(
+            r#"
+        SELECT
+            {label} as label,
+            {locality} as locality,
+            COUNT(DISTINCT date_trunc('day', date_utc)) as days,
+            AVG(value_q02::DOUBLE PRECISION * unit_factor) as q02,
+            AVG(value_q25::DOUBLE PRECISION * unit_factor) as q25,
+            AVG(value_median::DOUBLE PRECISION * unit_factor) as median,
+            AVG(value_q75::DOUBLE PRECISION * unit_factor) as q75,
+            AVG(value_q98::DOUBLE PRECISION * unit_factor) as q98,
+            MAX(value_q98::DOUBLE PRECISION * unit_factor) as peak_q98,
+            AVG(value_normalized::DOUBLE PRECISION) as mean
+        FROM measurements
+        WHERE
+            country = $1
+            AND parameter_name = $2
+            AND value_median IS NOT NULL
+            AND date_utc > NOW() - make_interval(days => $3)
+            {conditions}
+        GROUP BY {group_by}
+        ORDER BY median DESC NULLS LAST, label
+        "#,
+            conditions = filter_conditions(filter)
+        );
+
+        sqlx::query_as::<_, DistributionSummary>(&query)
+            .bind(country)
+            .bind(parameter)
+            .bind(days as i32)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!(
+                    "Failed to fetch {} distribution for {}: {}",
+                    parameter, country, e
+                );
+                AppError::Db(e.into())
+            })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::DbMeasurement;
+    use chrono::{Duration, Utc};
+    use sqlx::types::Decimal;
+    use sqlx::PgPool;
+
+    fn measurement(sensor_id: i64, city: &str, days_ago: i64, median: i64) -> DbMeasurement {
+        let date_utc = Utc::now() - Duration::days(days_ago);
+        DbMeasurement {
+            value_min: Some(Decimal::from(median - 5)),
+            value_max: Some(Decimal::from(median + 20)),
+            value_q02: Some(Decimal::from(median - 4)),
+            value_q25: Some(Decimal::from(median - 2)),
+            value_median: Some(Decimal::from(median)),
+            value_q75: Some(Decimal::from(median + 2)),
+            value_q98: Some(Decimal::from(median + 10)),
+            value_sd: Some(Decimal::from(3)),
+            city: Some(city.to_string()),
+            ..DbMeasurement::test(sensor_id, "NL", "pm25", date_utc).with_value((median + 1) as f64)
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_get_distribution_per_sensor_and_locality(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        db.insert_measurements(&[
+            measurement(1, "Utrecht", 1, 10),
+            measurement(1, "Utrecht", 2, 20),
+            measurement(2, "Utrecht", 1, 30),
+            measurement(3, "Amsterdam", 1, 50),
+        ])
+        .await
+        .expect("Failed to insert measurements");
+        let filter = MeasurementFilter::default();
+
+        let sensors = db
+            .get_distribution("NL", "pm25", 30, DistributionGroup::Sensor, &filter)
+            .await
+            .expect("Sensor distribution failed");
+        assert_eq!(sensors.len(), 3);
+        assert_eq!(sensors[0].label, "Station 3 #3");
+        assert_eq!(sensors[0].locality.as_deref(), Some("Amsterdam"));
+        let sensor_1 = sensors.iter().find(|s| s.label == "Station 1 #1").unwrap();
+        assert_eq!(sensor_1.days, 2);
+        assert_eq!(sensor_1.median, Some(15.0));
+        assert_eq!(sensor_1.peak_q98, Some(30.0));
+
+        let localities = db
+            .get_distribution("NL", "pm25", 30, DistributionGroup::Locality, &filter)
+            .await
+            .expect("Locality distribution failed");
+        assert_eq!(localities.len(), 2);
+        assert_eq!(localities[0].label, "Amsterdam");
+        assert_eq!(localities[1].label, "Utrecht");
+        assert_eq!(localities[1].median, Some(20.0));
+        assert_eq!(localities[1].locality, None);
+    }
+}
@@ -0,0 +1,74 @@
+//! Database operations backing the columnar export: streaming the `measurements` table
+//! without loading it into memory.
+
+use super::Database;
+use crate::error::{AppError, Result};
+use crate::models::MeasurementRecord;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use tracing::error;
+
+impl Database {
+    /// Streams every row of the `measurements` table, ordered by country and date so that
+    /// each country/month partition is read contiguously.
+    ///
+    /// # Errors
+    ///
+    /// Each item is `AppError::Db` if reading the row fails.
+    pub fn stream_measurements(&self) -> BoxStream<'_, Result<MeasurementRecord>> {
+        sqlx::query_as::<_, MeasurementRecord>(
+            r#"
+            SELECT
+                id, location_id, sensor_id, location_name, parameter_id, parameter_name,
+                value_avg, value_min, value_max, value_q02, value_q25, value_median, value_q75,
+                value_q98, value_sd, measurement_count, expected_count, percent_complete,
+                percent_coverage, unit, unit_normalized, unit_factor, value_normalized, date_utc,
+                date_local, country, city, latitude, longitude, is_mobile, is_monitor,
+                owner_name, provider_name, source, created_at
+            FROM measurements
+            ORDER BY country, date_utc, sensor_id
+            "#,
+        )
+        .fetch(&self.pool)
+        .map_err(|e| {
+            error!("Failed to stream measurements: {}", e);
+            AppError::Db(e.into())
+        })
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::DbMeasurement;
+    use chrono::{Duration, Utc};
+    use num_traits::FromPrimitive;
+    use sqlx::types::Decimal;
+    use sqlx::PgPool;
+
+    fn measurement(sensor_id: i64, country: &str) -> DbMeasurement {
+        DbMeasurement::test(sensor_id, country, "pm25", Utc::now() - Duration::days(1))
+            .with_value(12.5)
+    }
+
+    /// Tests that all rows are streamed, ordered by country.
+    #[sqlx::test]
+    async fn test_stream_measurements(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        db.insert_measurements(&[measurement(1, "NL"), measurement(2, "DE")])
+            .await
+            .expect("Failed to insert measurements");
+
+        let records: Vec<MeasurementRecord> = db
+            .stream_measurements()
+            .try_collect()
+            .await
+            .expect("Streaming should succeed");
+        let countries: Vec<&str> = records.iter().map(|r| r.country.as_str()).collect();
+        assert_eq!(countries, vec!["DE", "NL"]);
+        assert_eq!(records[0].value_avg, Decimal::from_f64(12.5));
+    }
+}
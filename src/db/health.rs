@@ -0,0 +1,240 @@
+//! Database operations of the sensor health check: the latest data per sensor, the coverage
+//! per locality in two windows and the inactive marks skipped by the imports.
+
+use super::Database;
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{LocalityCoverage, SensorActivity};
+use chrono::{Duration, NaiveDate, NaiveTime};
+use tracing::{error, info};
+
+impl Database {
+    /// Loads the latest stored value and OpenAQ's latest measurement of every stored sensor,
+    /// ordered by country, locality, location and sensor.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_sensor_activity(&self) -> Result<Vec<SensorActivity>> {
+        let _timer = metrics().query_timer("get_sensor_activity");
+        sqlx::query_as::<_, SensorActivity>(
+            r#"
+            SELECT
+                s.id as sensor_id,
+                s.location_id,
+                l.name as location_name,
+                l.locality,
+                l.country_code,
+                s.parameter_name,
+                (SELECT MAX(m.date_utc) FROM measurements m WHERE m.sensor_id = s.id) as last_stored,
+                l.datetime_last as source_last,
+                s.inactive_since
+            FROM sensors s
+            JOIN locations l ON l.id = s.location_id
+            ORDER BY l.country_code, l.locality NULLS LAST, s.location_id, s.id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch sensor activity: {}", e);
+            AppError::Db(e.into())
+        })
+    }
+
+    /// Counts the stored sensor days and sensors of every locality in the `days` days before
+    /// `today` and in the `days` days before those, ordered by country and locality.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_locality_coverage(
+        &self,
+        today: NaiveDate,
+        days: i64,
+    ) -> Result<Vec<LocalityCoverage>> {
+        let _timer = metrics().query_timer("get_locality_coverage");
+        let day_start = |day: NaiveDate| day.and_time(NaiveTime::MIN).and_utc();
+        sqlx::query_as::<_, LocalityCoverage>(
+            r#"
+            SELECT
+                country,
+                city as locality,
+                COUNT(*) FILTER (WHERE date_utc < $2) as previous_days,
+                COUNT(*) FILTER (WHERE date_utc >= $2) as current_days,
+                COUNT(DISTINCT sensor_id) FILTER (WHERE date_utc < $2) as previous_sensors,
+                COUNT(DISTINCT sensor_id) FILTER (WHERE date_utc >= $2) as current_sensors
+            FROM measurements
+            WHERE city IS NOT NULL AND date_utc >= $1 AND date_utc < $3
+            GROUP BY country, city
+            ORDER BY country, city
+            "#,
+        )
+        .bind(day_start(today - Duration::days(2 * days)))
+        .bind(day_start(today - Duration::days(days)))
+        .bind(day_start(today))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch locality coverage: {}", e);
+            AppError::Db(e.into())
+        })
+    }
+
+    /// Marks the given sensors inactive with `reason`, returning the number of sensors that
+    /// were active until now.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the update fails.
+    pub async fn mark_sensors_inactive(&self, sensor_ids: &[i64], reason: &str) -> Result<u64> {
+        let _timer = metrics().query_timer("mark_sensors_inactive");
+        let marked = sqlx::query(
+            r#"
+            UPDATE sensors SET inactive_since = NOW(), inactive_reason = $2
+            WHERE id = ANY($1) AND inactive_since IS NULL
+            "#,
+        )
+        .bind(sensor_ids)
+        .bind(reason)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to mark sensors inactive: {}", e);
+            AppError::Db(e.into())
+        })?
+        .rows_affected();
+        info!("Marked {} sensors inactive ({})", marked, reason);
+        Ok(marked)
+    }
+
+    /// Clears every inactive mark, so the next imports fetch all sensors again. Returns the
+    /// number of reactivated sensors.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the update fails.
+    pub async fn reactivate_sensors(&self) -> Result<u64> {
+        let _timer = metrics().query_timer("reactivate_sensors");
+        let reactivated = sqlx::query(
+            r#"
+            UPDATE sensors SET inactive_since = NULL, inactive_reason = NULL
+            WHERE inactive_since IS NOT NULL
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to reactivate sensors: {}", e);
+            AppError::Db(e.into())
+        })?
+        .rows_affected();
+        info!("Reactivated {} sensors", reactivated);
+        Ok(reactivated)
+    }
+
+    /// Loads the IDs of the sensors marked inactive.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_inactive_sensor_ids(&self) -> Result<Vec<i64>> {
+        let _timer = metrics().query_timer("get_inactive_sensor_ids");
+        sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM sensors WHERE inactive_since IS NOT NULL ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch inactive sensors: {}", e);
+            AppError::Db(e.into())
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::DbMeasurement;
+    use chrono::Utc;
+
+    use sqlx::PgPool;
+
+    fn measurement(sensor_id: i64, city: &str, day: NaiveDate) -> DbMeasurement {
+        DbMeasurement {
+            city: Some(city.to_string()),
+            ..DbMeasurement::test(
+                sensor_id,
+                "NL",
+                "pm25",
+                day.and_time(NaiveTime::MIN).and_utc(),
+            )
+        }
+    }
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    /// Tests the latest data per sensor, the coverage windows and the inactive marks.
+    #[sqlx::test]
+    async fn test_sensor_health(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        sqlx::query(
+            r#"
+            INSERT INTO locations (id, name, locality, country_code, country_name, timezone, is_mobile, is_monitor, datetime_last)
+            VALUES (1, 'Station 1', 'Utrecht', 'NL', 'Netherlands', 'UTC', false, true, '2024-03-20T00:00:00Z')
+            "#,
+        )
+        .execute(&db.pool)
+        .await
+        .expect("Failed to insert location");
+        sqlx::query(
+            r#"
+            INSERT INTO sensors (id, location_id, name, parameter_id, parameter_name, units)
+            VALUES (11, 1, 'pm25', 2, 'pm25', 'µg/m³'), (12, 1, 'pm25', 2, 'pm25', 'µg/m³')
+            "#,
+        )
+        .execute(&db.pool)
+        .await
+        .expect("Failed to insert sensors");
+        // Both sensors reported in the first week, only sensor 11 in the second.
+        let mut measurements = Vec::new();
+        for d in 1..=7 {
+            measurements.push(measurement(11, "Utrecht", day(d)));
+            measurements.push(measurement(12, "Utrecht", day(d)));
+            measurements.push(measurement(11, "Utrecht", day(d + 7)));
+        }
+        db.insert_measurements(&measurements).await.unwrap();
+
+        let activity = db.get_sensor_activity().await.unwrap();
+        let rows: Vec<_> = activity
+            .iter()
+            .map(|a| (a.sensor_id, a.last_stored.map(|t| t.date_naive())))
+            .collect();
+        assert_eq!(rows, [(11, Some(day(14))), (12, Some(day(7)))]);
+        assert!(activity[0].source_last.is_some());
+
+        let coverage = db.get_locality_coverage(day(15), 7).await.unwrap();
+        assert_eq!(coverage.len(), 1);
+        assert_eq!(
+            (
+                coverage[0].previous_days,
+                coverage[0].current_days,
+                coverage[0].previous_sensors,
+                coverage[0].current_sensors
+            ),
+            (14, 7, 2, 1)
+        );
+
+        assert_eq!(db.mark_sensors_inactive(&[12], "silent").await.unwrap(), 1);
+        assert_eq!(db.mark_sensors_inactive(&[12], "silent").await.unwrap(), 0);
+        assert_eq!(db.get_inactive_sensor_ids().await.unwrap(), [12]);
+        let activity = db.get_sensor_activity().await.unwrap();
+        assert!(activity[1].inactive_since.unwrap() <= Utc::now());
+        assert_eq!(db.reactivate_sensors().await.unwrap(), 1);
+        assert!(db.get_inactive_sensor_ids().await.unwrap().is_empty());
+    }
+}
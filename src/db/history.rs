@@ -0,0 +1,400 @@
+//! Database operations of the versioned location and sensor metadata: recording a new version
+//! when the attributes of a stored location or sensor change, and reading the versions back.
+
+use super::Database;
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{LocationVersion, SensorVersion};
+use sqlx::{Postgres, Transaction};
+use tracing::{error, info};
+
+/// Attributes of `location_versions` that start a new version when they change.
+const LOCATION_ATTRIBUTES: &str = "name, locality, country_code, timezone, latitude, longitude, \
+     is_mobile, is_monitor, owner_name, provider_name";
+
+/// Attributes of `sensor_versions` that start a new version when they change.
+const SENSOR_ATTRIBUTES: &str = "location_id, name, parameter_id, parameter_name, units";
+
+/// Prefixes every attribute of `attributes` with `table`, e.g. `l.name, l.locality`.
+fn qualified(table: &str, attributes: &str) -> String {
+    attributes
+        .split(',')
+        .map(|attribute| format!("{}.{}", table, attribute.trim()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Brings the versions of the given locations in line with their rows in `locations`: closes
+/// the current version of every location whose attributes changed and opens a new one, valid
+/// from the start of the transaction. The current versions follow `datetime_last`.
+pub(super) async fn sync_location_versions(
+    tx: &mut Transaction<'_, Postgres>,
+    location_ids: &[i64],
+) -> Result<()> {
+    let statements = [
+        format!(
+            r#"
+            UPDATE location_versions v SET valid_to = NOW()
+            FROM locations l
+            WHERE
+                l.id = ANY($1) AND v.location_id = l.id AND v.valid_to IS NULL
+                AND ({}) IS DISTINCT FROM ({})
+            "#,
+            qualified("v", LOCATION_ATTRIBUTES),
+            qualified("l", LOCATION_ATTRIBUTES)
+        ),
+        format!(
+            r#"
+            INSERT INTO location_versions (location_id, {}, datetime_last, valid_from)
+            SELECT l.id, {}, l.datetime_last, NOW()
+            FROM locations l
+            WHERE
+                l.id = ANY($1)
+                AND NOT EXISTS (
+                    SELECT 1 FROM location_versions v
+                    WHERE v.location_id = l.id AND v.valid_to IS NULL
+                )
+            "#,
+            LOCATION_ATTRIBUTES,
+            qualified("l", LOCATION_ATTRIBUTES)
+        ),
+        r#"
+        UPDATE location_versions v SET datetime_last = l.datetime_last
+        FROM locations l
+        WHERE l.id = ANY($1) AND v.location_id = l.id AND v.valid_to IS NULL
+        "#
+        .to_string(),
+    ];
+    for statement in &statements {
+        sqlx::query(statement)
+            .bind(location_ids)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to record location versions: {}", e);
+                AppError::Db(e.into())
+            })?;
+    }
+    Ok(())
+}
+
+/// Brings the versions of the sensors of a location in line with their rows in `sensors`, like
+/// `sync_location_versions`. Sensors of the location missing from `sensor_ids` (the complete
+/// list reported by the data source) were removed, so their current version is closed.
+pub(super) async fn sync_sensor_versions(
+    tx: &mut Transaction<'_, Postgres>,
+    location_id: i64,
+    sensor_ids: &[i64],
+) -> Result<()> {
+    let statements = [
+        r#"
+        UPDATE sensor_versions SET valid_to = NOW()
+        WHERE location_id = $1 AND valid_to IS NULL AND NOT (sensor_id = ANY($2))
+        "#
+        .to_string(),
+        format!(
+            r#"
+            UPDATE sensor_versions v SET valid_to = NOW()
+            FROM sensors s
+            WHERE
+                s.location_id = $1 AND s.id = ANY($2) AND v.sensor_id = s.id
+                AND v.valid_to IS NULL
+                AND ({}) IS DISTINCT FROM ({})
+            "#,
+            qualified("v", SENSOR_ATTRIBUTES),
+            qualified("s", SENSOR_ATTRIBUTES)
+        ),
+        format!(
+            r#"
+            INSERT INTO sensor_versions (sensor_id, {}, valid_from)
+            SELECT s.id, {}, NOW()
+            FROM sensors s
+            WHERE
+                s.location_id = $1 AND s.id = ANY($2)
+                AND NOT EXISTS (
+                    SELECT 1 FROM sensor_versions v
+                    WHERE v.sensor_id = s.id AND v.valid_to IS NULL
+                )
+            "#,
+            SENSOR_ATTRIBUTES,
+            qualified("s", SENSOR_ATTRIBUTES)
+        ),
+    ];
+    for statement in &statements {
+        sqlx::query(statement)
+            .bind(location_id)
+            .bind(sensor_ids)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| {
+                error!(
+                    "Failed to record sensor versions of location {}: {}",
+                    location_id, e
+                );
+                AppError::Db(e.into())
+            })?;
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Opens a first version, valid from the time the row was stored, for every location and
+    /// sensor without any version yet (stored before the history was recorded).
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if a statement fails.
+    pub(super) async fn backfill_metadata_versions(&self) -> Result<()> {
+        let statements = [
+            format!(
+                r#"
+                INSERT INTO location_versions (location_id, {}, datetime_last, valid_from)
+                SELECT l.id, {}, l.datetime_last, l.created_at
+                FROM locations l
+                WHERE NOT EXISTS (SELECT 1 FROM location_versions v WHERE v.location_id = l.id)
+                "#,
+                LOCATION_ATTRIBUTES,
+                qualified("l", LOCATION_ATTRIBUTES)
+            ),
+            format!(
+                r#"
+                INSERT INTO sensor_versions (sensor_id, {}, valid_from)
+                SELECT s.id, {}, s.created_at
+                FROM sensors s
+                WHERE NOT EXISTS (SELECT 1 FROM sensor_versions v WHERE v.sensor_id = s.id)
+                "#,
+                SENSOR_ATTRIBUTES,
+                qualified("s", SENSOR_ATTRIBUTES)
+            ),
+        ];
+        let mut backfilled = 0;
+        for statement in &statements {
+            backfilled += sqlx::query(statement)
+                .execute(&self.pool)
+                .await
+                .map_err(|e| {
+                    error!("Failed to backfill metadata versions: {}", e);
+                    AppError::Db(e.into())
+                })?
+                .rows_affected();
+        }
+        if backfilled > 0 {
+            info!(
+                "Started the history of {} locations and sensors",
+                backfilled
+            );
+        }
+        Ok(())
+    }
+
+    /// Loads the versions of a location, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_location_versions(&self, location_id: i64) -> Result<Vec<LocationVersion>> {
+        let _timer = metrics().query_timer("get_location_versions");
+        sqlx::query_as::<_, LocationVersion>(&format!(
+            r#"
+            SELECT location_id, {}, datetime_last, valid_from, valid_to
+            FROM location_versions
+            WHERE location_id = $1
+            ORDER BY valid_from, id
+            "#,
+            LOCATION_ATTRIBUTES
+        ))
+        .bind(location_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!(
+                "Failed to fetch versions of location {}: {}",
+                location_id, e
+            );
+            AppError::Db(e.into())
+        })
+    }
+
+    /// Loads the versions of the sensors that belonged to a location (`location_id`) or of a
+    /// single sensor (`sensor_id`), ordered by sensor and oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_sensor_versions(
+        &self,
+        location_id: Option<i64>,
+        sensor_id: Option<i64>,
+    ) -> Result<Vec<SensorVersion>> {
+        let _timer = metrics().query_timer("get_sensor_versions");
+        sqlx::query_as::<_, SensorVersion>(
+            r#"
+            SELECT sensor_id, location_id, name, parameter_name, units, valid_from, valid_to
+            FROM sensor_versions
+            WHERE
+                ($1::BIGINT IS NULL OR location_id = $1)
+                AND ($2::BIGINT IS NULL OR sensor_id = $2)
+            ORDER BY sensor_id, valid_from, id
+            "#,
+        )
+        .bind(location_id)
+        .bind(sensor_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch sensor versions: {}", e);
+            AppError::Db(e.into())
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::{
+        Coordinates, CountryBase, DatetimeObject, EntityBase, Location, ParameterBase,
+        ProviderBase, SensorBase,
+    };
+    use chrono::{TimeZone, Utc};
+    use sqlx::PgPool;
+
+    fn location(provider: &str, latitude: f64, last_day: u32) -> Location {
+        let last = Utc.with_ymd_and_hms(2024, 3, last_day, 0, 0, 0).unwrap();
+        Location {
+            id: 1,
+            name: Some("Station".to_string()),
+            locality: Some("Utrecht".to_string()),
+            timezone: "Europe/Amsterdam".to_string(),
+            country: CountryBase {
+                id: Some(1),
+                code: "NL".to_string(),
+                name: "Netherlands".to_string(),
+            },
+            owner: EntityBase {
+                id: 1,
+                name: "Owner".to_string(),
+            },
+            provider: ProviderBase {
+                id: 1,
+                name: provider.to_string(),
+            },
+            is_mobile: false,
+            is_monitor: true,
+            instruments: Vec::new(),
+            sensors: Vec::new(),
+            coordinates: Coordinates {
+                latitude: Some(latitude),
+                longitude: Some(5.0),
+            },
+            bounds: Vec::new(),
+            distance: None,
+            datetime_first: None,
+            datetime_last: Some(DatetimeObject {
+                utc: last,
+                local: last.to_rfc3339(),
+            }),
+        }
+    }
+
+    fn sensor(id: i32, units: &str) -> SensorBase {
+        SensorBase {
+            id,
+            name: format!("pm25 {}", units),
+            parameter: ParameterBase {
+                id: 2,
+                name: "pm25".to_string(),
+                units: units.to_string(),
+                display_name: None,
+            },
+        }
+    }
+
+    /// Tests opening versions on changes only, following `datetime_last` and removing sensors.
+    #[sqlx::test]
+    async fn test_metadata_versions(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+
+        db.insert_locations("openaq", &[location("AirNow", 52.0, 1)])
+            .await
+            .unwrap();
+        db.insert_sensors("openaq", 1, &[sensor(11, "ppm"), sensor(12, "µg/m³")])
+            .await
+            .unwrap();
+        // A later import with only a newer last measurement keeps the version.
+        db.insert_locations("openaq", &[location("AirNow", 52.0, 2)])
+            .await
+            .unwrap();
+        let versions = db.get_location_versions(1).await.unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(
+            versions[0].datetime_last,
+            Some(Utc.with_ymd_and_hms(2024, 3, 2, 0, 0, 0).unwrap())
+        );
+
+        // The station moved and changed provider; sensor 11 changed units and 12 was removed.
+        db.insert_locations("openaq", &[location("EEA", 52.1, 3)])
+            .await
+            .unwrap();
+        db.insert_sensors("openaq", 1, &[sensor(11, "µg/m³")])
+            .await
+            .unwrap();
+        let versions = db.get_location_versions(1).await.unwrap();
+        let summary: Vec<_> = versions
+            .iter()
+            .map(|v| (v.provider_name.as_deref(), v.latitude, v.valid_to.is_some()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Some("AirNow"), Some(52.0), true),
+                (Some("EEA"), Some(52.1), false)
+            ]
+        );
+        assert_eq!(versions[0].valid_to, Some(versions[1].valid_from));
+        let stored: (Option<String>,) =
+            sqlx::query_as("SELECT provider_name FROM locations WHERE id = 1")
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(stored.0.as_deref(), Some("EEA"));
+
+        let sensors = db.get_sensor_versions(Some(1), None).await.unwrap();
+        let summary: Vec<_> = sensors
+            .iter()
+            .map(|v| (v.sensor_id, v.units.as_str(), v.valid_to.is_some()))
+            .collect();
+        assert_eq!(
+            summary,
+            [(11, "ppm", true), (11, "µg/m³", false), (12, "µg/m³", true)]
+        );
+        assert_eq!(
+            db.get_sensor_versions(None, Some(12)).await.unwrap().len(),
+            1
+        );
+
+        // Initialising the schema again does not reopen the removed sensor.
+        db.init_schema().await.unwrap();
+        assert_eq!(db.get_sensor_versions(None, None).await.unwrap().len(), 3);
+    }
+
+    /// Tests that an empty sensor list records every sensor of the location as removed.
+    #[sqlx::test]
+    async fn test_empty_sensor_list_closes_versions(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+
+        db.insert_locations("openaq", &[location("AirNow", 52.0, 1)])
+            .await
+            .unwrap();
+        db.insert_sensors("openaq", 1, &[sensor(11, "ppm"), sensor(12, "µg/m³")])
+            .await
+            .unwrap();
+        db.insert_sensors("openaq", 1, &[]).await.unwrap();
+
+        let sensors = db.get_sensor_versions(Some(1), None).await.unwrap();
+        assert_eq!(sensors.len(), 2);
+        assert!(sensors.iter().all(|v| v.valid_to.is_some()));
+    }
+}
@@ -0,0 +1,351 @@
+//! Database operations of the location and sensor inventory: the stored `locations` and
+//! `sensors` with the extent of their stored measurements.
+
+use super::Database;
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{InventoryFilter, LocationSummary, MonthlyCoverage, SensorSummary};
+use tracing::{error, info};
+
+/// Conditions on the joined `locations l` row shared by both listings, using the binds
+/// `$3` (country) to `$8` (monitor).
+const LOCATION_CONDITIONS: &str = r#"
+    ($3::TEXT IS NULL OR l.country_code = UPPER($3))
+    AND ($4::TEXT IS NULL OR LOWER(l.locality) = LOWER($4))
+    AND ($5::TEXT IS NULL OR l.provider_name ILIKE '%' || $5 || '%')
+    AND ($6::TEXT IS NULL OR l.owner_name ILIKE '%' || $6 || '%')
+    AND ($8::BOOLEAN IS NULL OR l.is_monitor = $8)
+"#;
+
+impl Database {
+    /// Loads the stored locations matching `filter` with their sensor count, parameters and
+    /// the days of their stored measurements, ordered by country, locality and ID.
+    ///
+    /// A location is active since a day if OpenAQ or our data has values from that day on.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_location_inventory(
+        &self,
+        filter: &InventoryFilter,
+    ) -> Result<Vec<LocationSummary>> {
+        let _timer = metrics().query_timer("get_location_inventory");
+        info!("Fetching location inventory: {:?}", filter);
+        let query = format!(
+            r#"
+            WITH stored AS (
+                SELECT
+                    location_id,
+                    MIN(date_utc AT TIME ZONE 'UTC')::DATE as first_stored,
+                    MAX(date_utc AT TIME ZONE 'UTC')::DATE as last_stored,
+                    COUNT(DISTINCT (date_utc AT TIME ZONE 'UTC')::DATE) as stored_days
+                FROM measurements
+                WHERE ($1::BIGINT IS NULL OR location_id = $1)
+                GROUP BY location_id
+            )
+            SELECT
+                l.id, l.name, l.locality, l.country_code, l.provider_name, l.owner_name,
+                l.is_monitor, l.is_mobile, l.latitude, l.longitude, l.datetime_first,
+                l.datetime_last,
+                (SELECT COUNT(*) FROM sensors s WHERE s.location_id = l.id) as sensors,
+                ARRAY(
+                    SELECT DISTINCT s.parameter_name FROM sensors s
+                    WHERE s.location_id = l.id ORDER BY s.parameter_name
+                ) as parameters,
+                stored.first_stored,
+                stored.last_stored,
+                COALESCE(stored.stored_days, 0) as stored_days
+            FROM locations l
+            LEFT JOIN stored ON stored.location_id = l.id
+            WHERE
+                ($1::BIGINT IS NULL OR l.id = $1)
+                AND ($2::BIGINT IS NULL OR EXISTS (
+                    SELECT 1 FROM sensors s WHERE s.location_id = l.id AND s.id = $2
+                ))
+                AND ($7::TEXT IS NULL OR EXISTS (
+                    SELECT 1 FROM sensors s
+                    WHERE s.location_id = l.id AND LOWER(s.parameter_name) = LOWER($7)
+                ))
+                AND ($9::DATE IS NULL
+                    OR (l.datetime_last AT TIME ZONE 'UTC')::DATE >= $9
+                    OR stored.last_stored >= $9)
+                AND {}
+            ORDER BY l.country_code, l.locality NULLS LAST, l.id
+            "#,
+            LOCATION_CONDITIONS
+        );
+
+        sqlx::query_as::<_, LocationSummary>(&query)
+            .bind(filter.location_id)
+            .bind(filter.sensor_id)
+            .bind(&filter.country)
+            .bind(&filter.locality)
+            .bind(&filter.provider)
+            .bind(&filter.owner)
+            .bind(&filter.parameter)
+            .bind(filter.monitor)
+            .bind(filter.active_since)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to fetch location inventory: {}", e);
+                AppError::Db(e.into())
+            })
+    }
+
+    /// Loads the stored sensors matching `filter` with their location and the days of their
+    /// stored measurements, ordered by country, locality, location and parameter.
+    ///
+    /// OpenAQ only reports when a location was last seen, so a sensor is active since a day if
+    /// our data has values from that day on.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_sensor_inventory(
+        &self,
+        filter: &InventoryFilter,
+    ) -> Result<Vec<SensorSummary>> {
+        let _timer = metrics().query_timer("get_sensor_inventory");
+        info!("Fetching sensor inventory: {:?}", filter);
+        let query = format!(
+            r#"
+            WITH stored AS (
+                SELECT
+                    sensor_id,
+                    MIN(date_utc AT TIME ZONE 'UTC')::DATE as first_stored,
+                    MAX(date_utc AT TIME ZONE 'UTC')::DATE as last_stored,
+                    COUNT(DISTINCT (date_utc AT TIME ZONE 'UTC')::DATE) as stored_days
+                FROM measurements
+                WHERE
+                    ($1::BIGINT IS NULL OR location_id = $1)
+                    AND ($2::BIGINT IS NULL OR sensor_id = $2)
+                GROUP BY sensor_id
+            )
+            SELECT
+                s.id, s.location_id, l.name as location_name, l.locality, l.country_code,
+                s.parameter_name, s.units, l.is_monitor, l.provider_name, l.owner_name,
+                stored.first_stored,
+                stored.last_stored,
+                COALESCE(stored.stored_days, 0) as stored_days,
+                s.inactive_since
+            FROM sensors s
+            JOIN locations l ON l.id = s.location_id
+            LEFT JOIN stored ON stored.sensor_id = s.id
+            WHERE
+                ($1::BIGINT IS NULL OR s.location_id = $1)
+                AND ($2::BIGINT IS NULL OR s.id = $2)
+                AND ($7::TEXT IS NULL OR LOWER(s.parameter_name) = LOWER($7))
+                AND ($9::DATE IS NULL OR stored.last_stored >= $9)
+                AND {}
+            ORDER BY l.country_code, l.locality NULLS LAST, s.location_id, s.parameter_name, s.id
+            "#,
+            LOCATION_CONDITIONS
+        );
+
+        sqlx::query_as::<_, SensorSummary>(&query)
+            .bind(filter.location_id)
+            .bind(filter.sensor_id)
+            .bind(&filter.country)
+            .bind(&filter.locality)
+            .bind(&filter.provider)
+            .bind(&filter.owner)
+            .bind(&filter.parameter)
+            .bind(filter.monitor)
+            .bind(filter.active_since)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to fetch sensor inventory: {}", e);
+                AppError::Db(e.into())
+            })
+    }
+
+    /// Counts the stored days of a sensor per calendar month, oldest month first. Months
+    /// without values are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_sensor_monthly_coverage(
+        &self,
+        sensor_id: i64,
+    ) -> Result<Vec<MonthlyCoverage>> {
+        let _timer = metrics().query_timer("get_sensor_monthly_coverage");
+        sqlx::query_as::<_, MonthlyCoverage>(
+            r#"
+            SELECT
+                month,
+                stored_days,
+                EXTRACT(DAY FROM month + INTERVAL '1 month' - INTERVAL '1 day')::BIGINT
+                    as month_days
+            FROM (
+                SELECT
+                    date_trunc('month', date_utc AT TIME ZONE 'UTC')::DATE as month,
+                    COUNT(DISTINCT (date_utc AT TIME ZONE 'UTC')::DATE) as stored_days
+                FROM measurements
+                WHERE sensor_id = $1
+                GROUP BY month
+            ) months
+            ORDER BY month
+            "#,
+        )
+        .bind(sensor_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!(
+                "Failed to fetch monthly coverage of sensor {}: {}",
+                sensor_id, e
+            );
+            AppError::Db(e.into())
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::DbMeasurement;
+    use chrono::{Duration, NaiveDate, NaiveTime};
+
+    use sqlx::PgPool;
+
+    fn day(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn measurement(sensor_id: i64, location_id: i64, day: NaiveDate) -> DbMeasurement {
+        let date_utc = day.and_time(NaiveTime::MIN).and_utc() + Duration::hours(12);
+        DbMeasurement {
+            location_id,
+            location_name: format!("Station {}", location_id),
+            ..DbMeasurement::test(sensor_id, "NL", "pm25", date_utc)
+        }
+    }
+
+    /// Tests the filters, the stored days per location and sensor and the monthly coverage.
+    #[sqlx::test]
+    async fn test_inventory(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        for (id, locality, provider, is_monitor) in [
+            (1_i64, "Utrecht", "AirNow", true),
+            (2, "Utrecht", "PurpleAir", false),
+            (3, "Amsterdam", "AirNow", true),
+        ] {
+            sqlx::query(
+                r#"
+                INSERT INTO locations (id, name, locality, country_code, country_name, timezone, is_mobile, is_monitor, provider_name, datetime_last)
+                VALUES ($1, $2, $3, 'NL', 'Netherlands', 'UTC', false, $4, $5, '2024-01-01T00:00:00Z')
+                "#,
+            )
+            .bind(id)
+            .bind(format!("Station {}", id))
+            .bind(locality)
+            .bind(is_monitor)
+            .bind(provider)
+            .execute(&db.pool)
+            .await
+            .expect("Failed to insert location");
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO sensors (id, location_id, name, parameter_id, parameter_name, units)
+            VALUES
+                (11, 1, 'pm25 µg/m³', 2, 'pm25', 'µg/m³'),
+                (12, 1, 'no2 µg/m³', 7, 'no2', 'µg/m³'),
+                (21, 2, 'pm25 µg/m³', 2, 'pm25', 'µg/m³'),
+                (31, 3, 'no2 µg/m³', 7, 'no2', 'µg/m³')
+            "#,
+        )
+        .execute(&db.pool)
+        .await
+        .expect("Failed to insert sensors");
+        db.insert_measurements(&[
+            measurement(11, 1, day(2, 28)),
+            measurement(11, 1, day(3, 1)),
+            measurement(11, 1, day(3, 4)),
+            measurement(12, 1, day(3, 4)),
+            measurement(21, 2, day(3, 2)),
+        ])
+        .await
+        .expect("Failed to insert measurements");
+
+        let all = db
+            .get_location_inventory(&InventoryFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(all.iter().map(|l| l.id).collect::<Vec<_>>(), [3, 1, 2]);
+        let utrecht = &all[1];
+        assert_eq!(utrecht.sensors, 2);
+        assert_eq!(utrecht.parameters, ["no2", "pm25"]);
+        assert_eq!(utrecht.first_stored, Some(day(2, 28)));
+        assert_eq!(utrecht.last_stored, Some(day(3, 4)));
+        // Both sensors reported on 4 March, which counts as one day.
+        assert_eq!(utrecht.stored_days, 3);
+        assert_eq!(all[0].stored_days, 0);
+
+        let filter = InventoryFilter {
+            country: Some("nl".to_string()),
+            locality: Some("utrecht".to_string()),
+            provider: Some("air".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(db.get_location_inventory(&filter).await.unwrap().len(), 2);
+        let low_cost = InventoryFilter {
+            monitor: Some(false),
+            ..Default::default()
+        };
+        let ids: Vec<_> = db
+            .get_location_inventory(&low_cost)
+            .await
+            .unwrap()
+            .iter()
+            .map(|l| l.id)
+            .collect();
+        assert_eq!(ids, [2]);
+        let no2 = InventoryFilter {
+            parameter: Some("NO2".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(db.get_location_inventory(&no2).await.unwrap().len(), 2);
+        let active = InventoryFilter {
+            active_since: Some(day(3, 3)),
+            ..Default::default()
+        };
+        let ids: Vec<_> = db
+            .get_location_inventory(&active)
+            .await
+            .unwrap()
+            .iter()
+            .map(|l| l.id)
+            .collect();
+        assert_eq!(ids, [1]);
+
+        let sensors = db
+            .get_sensor_inventory(&InventoryFilter {
+                location_id: Some(1),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let rows: Vec<_> = sensors.iter().map(|s| (s.id, s.stored_days)).collect();
+        assert_eq!(rows, [(12, 1), (11, 3)]);
+        assert_eq!(sensors[1].location_name.as_deref(), Some("Station 1"));
+        let active_sensors = db.get_sensor_inventory(&active).await.unwrap();
+        assert_eq!(
+            active_sensors.iter().map(|s| s.id).collect::<Vec<_>>(),
+            [12, 11]
+        );
+
+        let months = db.get_sensor_monthly_coverage(11).await.unwrap();
+        let months: Vec<_> = months
+            .iter()
+            .map(|m| (m.month, m.stored_days, m.month_days))
+            .collect();
+        assert_eq!(months, [(day(2, 1), 1, 29), (day(3, 1), 2, 31)]);
+    }
+}
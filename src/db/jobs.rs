@@ -0,0 +1,222 @@
+//! Database operations of the scheduled daemon: a Postgres advisory lock preventing overlapping
+//! imports across instances, and the `job_runs` table recording every run.
+
+use super::Database;
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{JobRun, JobStatus};
+use sqlx::pool::PoolConnection;
+use sqlx::Postgres;
+use tracing::{debug, error, warn};
+
+/// A held session-level advisory lock on a dedicated pool connection.
+///
+/// `release` unlocks it and returns the connection to the pool. Dropping the lock without
+/// releasing it (e.g. when a job panics) closes the connection instead, so the lock ends with
+/// the session and never returns to the pool held.
+pub struct AdvisoryLock {
+    key: i64,
+    conn: Option<PoolConnection<Postgres>>,
+}
+
+impl AdvisoryLock {
+    /// Releases the lock.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the unlock fails; the connection is then closed, which
+    /// releases the lock as well.
+    pub async fn release(mut self) -> Result<()> {
+        debug!("Releasing advisory lock {}", self.key);
+        let mut conn = self
+            .conn
+            .take()
+            .expect("the connection is held until released");
+        let unlocked = sqlx::query_scalar::<_, bool>("SELECT pg_advisory_unlock($1)")
+            .bind(self.key)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|e| {
+                error!("Failed to release advisory lock {}: {}", self.key, e);
+                drop(conn.detach());
+                AppError::Db(e.into())
+            })?;
+        if !unlocked {
+            warn!("Advisory lock {} was not held on release", self.key);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AdvisoryLock {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            warn!(
+                "Advisory lock {} dropped without release; closing its connection",
+                self.key
+            );
+            drop(conn.detach());
+        }
+    }
+}
+
+impl Database {
+    /// Tries to take the advisory lock `key` without waiting, on a connection of its own
+    /// that is kept out of the pool until the lock is released.
+    ///
+    /// Returns `None` if another session holds the lock.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if no connection can be acquired or the query fails.
+    pub async fn try_advisory_lock(&self, key: i64) -> Result<Option<AdvisoryLock>> {
+        let _timer = metrics().query_timer("try_advisory_lock");
+        let mut conn = self.pool.acquire().await.map_err(|e| {
+            error!(
+                "Failed to acquire a connection for advisory lock {}: {}",
+                key, e
+            );
+            AppError::Db(e.into())
+        })?;
+        let locked = sqlx::query_scalar::<_, bool>("SELECT pg_try_advisory_lock($1)")
+            .bind(key)
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|e| {
+                error!("Failed to take advisory lock {}: {}", key, e);
+                AppError::Db(e.into())
+            })?;
+        debug!("Advisory lock {} taken: {}", key, locked);
+        Ok(locked.then(|| AdvisoryLock {
+            key,
+            conn: Some(conn),
+        }))
+    }
+
+    /// Stores a job run in the `job_runs` table.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the insert fails.
+    pub async fn record_job_run(&self, run: &JobRun) -> Result<()> {
+        let _timer = metrics().query_timer("record_job_run");
+        sqlx::query(
+            r#"
+            INSERT INTO job_runs (job, started_at, finished_at, status, rows, error)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(&run.job)
+        .bind(run.started_at)
+        .bind(run.finished_at)
+        .bind(run.status.as_str())
+        .bind(run.rows)
+        .bind(&run.error)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to record {} job run: {}", run.job, e);
+            AppError::Db(e.into())
+        })?;
+        Ok(())
+    }
+
+    /// Loads the latest run and the latest successful run of every job, ordered by job name.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_job_status(&self) -> Result<Vec<JobStatus>> {
+        let _timer = metrics().query_timer("get_job_status");
+        sqlx::query_as::<_, JobStatus>(
+            r#"
+            SELECT
+                last.job,
+                last.started_at as last_started_at,
+                last.status as last_status,
+                last.error as last_error,
+                success.finished_at as last_success_at,
+                success.rows as last_success_rows
+            FROM (
+                SELECT DISTINCT ON (job) job, started_at, status, error
+                FROM job_runs
+                ORDER BY job, started_at DESC, id DESC
+            ) last
+            LEFT JOIN LATERAL (
+                SELECT finished_at, rows
+                FROM job_runs
+                WHERE job = last.job AND status = 'success'
+                ORDER BY started_at DESC, id DESC
+                LIMIT 1
+            ) success ON TRUE
+            ORDER BY last.job
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch job status: {}", e);
+            AppError::Db(e.into())
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::JobRunStatus;
+    use chrono::{Duration, Utc};
+    use sqlx::PgPool;
+
+    /// Tests that the lock is exclusive until released.
+    #[sqlx::test]
+    async fn test_advisory_lock(pool: PgPool) {
+        let db = Database { pool };
+        let first = db.try_advisory_lock(42).await.unwrap();
+        assert!(first.is_some());
+        assert!(db.try_advisory_lock(42).await.unwrap().is_none());
+        assert!(db.try_advisory_lock(43).await.unwrap().is_some());
+
+        first.unwrap().release().await.unwrap();
+        let again = db.try_advisory_lock(42).await.unwrap();
+        assert!(again.is_some());
+
+        again.unwrap().release().await.unwrap();
+        assert!(db.try_advisory_lock(42).await.unwrap().is_some());
+    }
+
+    /// Tests that the status reports the latest run and the latest successful run per job.
+    #[sqlx::test]
+    async fn test_job_status(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        let now = Utc::now();
+        let run = |job: &str, hours_ago: i64, status: JobRunStatus, rows: i64| JobRun {
+            job: job.to_string(),
+            started_at: now - Duration::hours(hours_ago),
+            finished_at: now - Duration::hours(hours_ago) + Duration::minutes(1),
+            status,
+            rows,
+            error: (status == JobRunStatus::Failed).then(|| "boom".to_string()),
+        };
+        for r in [
+            run("import", 3, JobRunStatus::Success, 10),
+            run("import", 2, JobRunStatus::Success, 20),
+            run("import", 1, JobRunStatus::Failed, 0),
+            run("discovery", 5, JobRunStatus::Skipped, 0),
+        ] {
+            db.record_job_run(&r).await.unwrap();
+        }
+
+        let status = db.get_job_status().await.unwrap();
+        assert_eq!(status.len(), 2);
+        assert_eq!(status[0].job, "discovery");
+        assert_eq!(status[0].last_status, "skipped");
+        assert_eq!(status[0].last_success_at, None);
+        assert_eq!(status[1].job, "import");
+        assert_eq!(status[1].last_status, "failed");
+        assert_eq!(status[1].last_error.as_deref(), Some("boom"));
+        assert_eq!(status[1].last_success_rows, Some(20));
+    }
+}
@@ -0,0 +1,279 @@
+//! Database operations reading the stored `locations` and `sensors` together with the recent
+//! values per location and parameter, used by the location export.
+
+use super::{filter_conditions, Database};
+use crate::analysis::BoundingBox;
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{LocationParameterValues, MeasurementFilter, StoredLocation, StoredSensor};
+use tracing::{error, info};
+
+impl Database {
+    /// Loads the stored locations, optionally restricted to `countries` (empty for all) and to
+    /// locations inside `bbox`. Locations without coordinates are excluded when a bounding box
+    /// is given.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_locations(
+        &self,
+        countries: &[String],
+        bbox: Option<&BoundingBox>,
+    ) -> Result<Vec<StoredLocation>> {
+        let _timer = metrics().query_timer("get_locations");
+        info!("Fetching stored locations (countries: {:?})", countries);
+        sqlx::query_as::<_, StoredLocation>(
+            r#"
+            SELECT
+                id, name, locality, country_code, country_name, timezone, latitude, longitude,
+                is_mobile, is_monitor, owner_name, provider_name, source, datetime_first,
+                datetime_last
+            FROM locations
+            WHERE
+                (cardinality($1::TEXT[]) = 0 OR country_code = ANY($1))
+                AND ($2::DOUBLE PRECISION IS NULL OR (
+                    longitude BETWEEN $2 AND $4 AND latitude BETWEEN $3 AND $5
+                ))
+            ORDER BY country_code, id
+            "#,
+        )
+        .bind(countries)
+        .bind(bbox.map(|b| b.min_lon))
+        .bind(bbox.map(|b| b.min_lat))
+        .bind(bbox.map(|b| b.max_lon))
+        .bind(bbox.map(|b| b.max_lat))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch locations: {}", e);
+            AppError::Db(e.into())
+        })
+    }
+
+    /// Loads one page of the stored locations, optionally restricted to `countries` (empty for
+    /// all), in the order of `get_locations`, together with the number of matching locations.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if a query fails.
+    pub async fn get_locations_page(
+        &self,
+        countries: &[String],
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<StoredLocation>, i64)> {
+        let _timer = metrics().query_timer("get_locations_page");
+        info!(
+            "Fetching stored locations (countries: {:?}, limit {}, offset {})",
+            countries, limit, offset
+        );
+        let total: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM locations WHERE cardinality($1::TEXT[]) = 0 OR country_code = ANY($1)",
+        )
+        .bind(countries)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to count locations: {}", e);
+            AppError::Db(e.into())
+        })?;
+        let locations = sqlx::query_as::<_, StoredLocation>(
+            r#"
+            SELECT
+                id, name, locality, country_code, country_name, timezone, latitude, longitude,
+                is_mobile, is_monitor, owner_name, provider_name, source, datetime_first,
+                datetime_last
+            FROM locations
+            WHERE cardinality($1::TEXT[]) = 0 OR country_code = ANY($1)
+            ORDER BY country_code, id
+            LIMIT $2 OFFSET $3
+            "#,
+        )
+        .bind(countries)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch locations: {}", e);
+            AppError::Db(e.into())
+        })?;
+        Ok((locations, total))
+    }
+
+    /// Loads the sensors of the given locations, ordered by location and parameter.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_sensors(&self, location_ids: &[i64]) -> Result<Vec<StoredSensor>> {
+        let _timer = metrics().query_timer("get_sensors");
+        sqlx::query_as::<_, StoredSensor>(
+            r#"
+            SELECT id, location_id, name, parameter_name, units, source
+            FROM sensors
+            WHERE location_id = ANY($1)
+            ORDER BY location_id, parameter_name, id
+            "#,
+        )
+        .bind(location_ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch sensors: {}", e);
+            AppError::Db(e.into())
+        })
+    }
+
+    /// Loads, per location and parameter, the latest daily value and the mean daily value over
+    /// the last `days` days.
+    ///
+    /// The latest value is the average over the location's sensors on its most recent day.
+    /// Values are normalised and the data quality `filter` is applied. A parameter reported in
+    /// several units yields one row per unit.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_location_parameter_values(
+        &self,
+        location_ids: &[i64],
+        days: i64,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<LocationParameterValues>> {
+        let _timer = metrics().query_timer("get_location_parameter_values");
+        let query = format!(
+            r#"
+        WITH recent AS (
+            SELECT location_id, parameter_name, unit_normalized, date_utc, value_normalized::DOUBLE PRECISION as value
+            FROM measurements
+            WHERE
+                location_id = ANY($1)
+                AND value_normalized IS NOT NULL
+                AND date_utc > NOW() - make_interval(days => $2)
+                {conditions}
+        ),
+        latest AS (
+            SELECT location_id, parameter_name, unit_normalized, MAX(date_utc) as latest_date
+            FROM recent
+            GROUP BY location_id, parameter_name, unit_normalized
+        )
+        SELECT
+            r.location_id,
+            r.parameter_name as parameter,
+            MAX(parameters.display_name) as display_name,
+            r.unit_normalized as unit,
+            AVG(r.value) FILTER (WHERE date_trunc('day', r.date_utc) = date_trunc('day', l.latest_date)) as latest,
+            l.latest_date,
+            AVG(r.value) as average,
+            COUNT(*) as days
+        FROM recent r
+        JOIN latest l USING (location_id, parameter_name, unit_normalized)
+        LEFT JOIN parameters ON parameters.name = r.parameter_name
+        GROUP BY r.location_id, r.parameter_name, r.unit_normalized, l.latest_date
+        ORDER BY r.location_id, MIN(parameters.id) NULLS LAST, r.parameter_name
+        "#,
+            conditions = filter_conditions(filter)
+        );
+
+        sqlx::query_as::<_, LocationParameterValues>(&query)
+            .bind(location_ids)
+            .bind(days as i32)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to fetch values per location: {}", e);
+                AppError::Db(e.into())
+            })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::DbMeasurement;
+    use chrono::{Duration, Utc};
+
+    use sqlx::PgPool;
+
+    fn measurement(sensor_id: i64, location_id: i64, days_ago: i64, value: f64) -> DbMeasurement {
+        let date_utc = Utc::now() - Duration::days(days_ago);
+        DbMeasurement {
+            location_id,
+            location_name: format!("Station {}", location_id),
+            ..DbMeasurement::test(sensor_id, "NL", "pm25", date_utc).with_value(value)
+        }
+    }
+
+    /// Tests the country and bounding box filters, sensor lookup and the latest/average values.
+    #[sqlx::test]
+    async fn test_locations_with_sensors_and_values(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        for (id, country, longitude) in [(1_i64, "NL", 5.0), (2, "NL", 6.5), (3, "DE", 10.0)] {
+            sqlx::query(
+                r#"
+                INSERT INTO locations (id, name, country_code, country_name, timezone, latitude, longitude, is_mobile, is_monitor)
+                VALUES ($1, $2, $3, $3, 'UTC', 52.0, $4, false, true)
+                "#,
+            )
+            .bind(id)
+            .bind(format!("Station {}", id))
+            .bind(country)
+            .bind(longitude)
+            .execute(&db.pool)
+            .await
+            .expect("Failed to insert location");
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO sensors (id, location_id, name, parameter_id, parameter_name, units)
+            VALUES (11, 1, 'pm25 µg/m³', 2, 'pm25', 'µg/m³'), (12, 1, 'pm25 µg/m³', 2, 'pm25', 'µg/m³')
+            "#,
+        )
+        .execute(&db.pool)
+        .await
+        .expect("Failed to insert sensors");
+        db.insert_measurements(&[
+            measurement(11, 1, 1, 10.0),
+            measurement(12, 1, 1, 20.0),
+            measurement(11, 1, 3, 30.0),
+        ])
+        .await
+        .expect("Failed to insert measurements");
+
+        let all = db.get_locations(&[], None).await.unwrap();
+        assert_eq!(all.len(), 3);
+        let nl = db.get_locations(&["NL".to_string()], None).await.unwrap();
+        assert_eq!(nl.len(), 2);
+        let bbox = BoundingBox::parse("4,51,6,53").unwrap();
+        let boxed = db.get_locations(&[], Some(&bbox)).await.unwrap();
+        assert_eq!(boxed.iter().map(|l| l.id).collect::<Vec<_>>(), vec![1]);
+
+        let (page, total) = db.get_locations_page(&[], 2, 1).await.unwrap();
+        assert_eq!(total, 3);
+        // Ordered by country and ID: DE 3, NL 1, NL 2.
+        assert_eq!(page.iter().map(|l| l.id).collect::<Vec<_>>(), vec![1, 2]);
+        let (page, total) = db
+            .get_locations_page(&["NL".to_string()], 10, 5)
+            .await
+            .unwrap();
+        assert_eq!(total, 2);
+        assert!(page.is_empty());
+
+        let sensors = db.get_sensors(&[1, 2]).await.unwrap();
+        assert_eq!(sensors.len(), 2);
+
+        let values = db
+            .get_location_parameter_values(&[1, 2], 7, &MeasurementFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(values.len(), 1);
+        // Latest day averages both sensors; the period average covers all three days.
+        assert_eq!(values[0].latest, Some(15.0));
+        assert_eq!(values[0].average, Some(20.0));
+        assert_eq!(values[0].days, 3);
+    }
+}
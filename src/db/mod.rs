@@ -1,7 +1,45 @@
 //! Provides database interaction functionalities.
 //!
-//! Currently, this module focuses on PostgreSQL interactions via the `postgres` submodule.
+//! Currently, this module focuses on PostgreSQL interactions via the `postgres` submodule,
+//! with feature-specific queries split into further submodules:
+//! - `alerts`: Daily values within the scope of an alert rule and the state of the alerts.
+//! - `anomalies`: Storage of anomaly detection results.
+//! - `compare`: Per-sensor sums of the daily values in two compared periods.
+//! - `distribution`: Per-sensor and per-locality quantile summaries.
+//! - `export`: Streaming reads of whole tables for the columnar export.
+//! - `health`: Latest data per sensor, coverage per locality and inactive sensor marks.
+//! - `history`: Versions of the location and sensor metadata (slowly changing dimension).
+//! - `inventory`: Stored locations and sensors with the days of their stored measurements.
+//! - `jobs`: Advisory locking and run records of the scheduled daemon jobs.
+//! - `locations`: Stored locations and sensors with recent values per parameter.
+//! - `overview`: Per-country counts and time spans of the stored data.
+//! - `parameters`: The parameter catalogue.
+//! - `partitions`: Monthly partitions of the `measurements` table and the migration into them.
+//! - `query`: The general long-format measurement query builder.
+//! - `report`: Guideline exceedances shown in the HTML report.
+//! - `retention`: Counting, streaming and deleting the measurements beyond their retention.
+//! - `spatial`: Location coordinates with recent values for nearest-location lookups.
+//! - `units`: Unit normalisation back-fill and mixed-unit checks.
 
+mod alerts;
+mod anomalies;
+mod compare;
+mod distribution;
+mod export;
+mod health;
+mod history;
+mod inventory;
+mod jobs;
+mod locations;
+mod overview;
+mod parameters;
+mod partitions;
 mod postgres;
+mod query;
+mod report;
+mod retention;
+mod spatial;
+mod units;
 
+pub use distribution::*;
 pub use postgres::*;
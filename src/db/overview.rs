@@ -0,0 +1,133 @@
+//! Database operations summarising the stored data per country.
+
+use super::Database;
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::CountryOverview;
+use tracing::{error, info};
+
+impl Database {
+    /// Counts the stored locations, sensors and daily measurements per country, together with
+    /// the first and last measurement day.
+    ///
+    /// Countries are included if they have stored locations or measurements, ordered by code.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_country_overview(&self) -> Result<Vec<CountryOverview>> {
+        let _timer = metrics().query_timer("get_country_overview");
+        info!("Fetching country overview");
+        // SQL Query Explanation:
+        // 1. CTE `stations`: Counts the locations and their sensors per country code.
+        // 2. CTE `days`: Counts the measurements and their time span per country.
+        // 3. Main Query: Combines both with a full outer join, so countries with only locations
+        //    or only measurements are still listed.
+        sqlx::query_as::<_, CountryOverview>(
+            r#"
+            WITH stations AS (
+                SELECT
+                    l.country_code as code,
+                    MAX(l.country_name) as name,
+                    COUNT(DISTINCT l.id) as locations,
+                    COUNT(s.id) as sensors
+                FROM locations l
+                LEFT JOIN sensors s ON s.location_id = l.id
+                GROUP BY l.country_code
+            ),
+            days AS (
+                SELECT
+                    country as code,
+                    COUNT(*) as measurements,
+                    MIN(date_utc) as first_day,
+                    MAX(date_utc) as last_day
+                FROM measurements
+                GROUP BY country
+            )
+            SELECT
+                COALESCE(stations.code, days.code) as code,
+                stations.name,
+                COALESCE(stations.locations, 0) as locations,
+                COALESCE(stations.sensors, 0) as sensors,
+                COALESCE(days.measurements, 0) as measurements,
+                days.first_day,
+                days.last_day
+            FROM stations
+            FULL OUTER JOIN days ON days.code = stations.code
+            ORDER BY code
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch country overview: {}", e);
+            AppError::Db(e.into())
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use sqlx::PgPool;
+
+    /// Tests that countries with only locations or only measurements are both listed.
+    #[sqlx::test]
+    async fn test_country_overview(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        sqlx::query(
+            r#"
+            INSERT INTO locations (id, name, country_code, country_name, timezone, is_mobile, is_monitor)
+            VALUES (1, 'A', 'NL', 'Netherlands', 'UTC', false, true),
+                   (2, 'B', 'NL', 'Netherlands', 'UTC', false, true)
+            "#,
+        )
+        .execute(&db.pool)
+        .await
+        .expect("Failed to insert locations");
+        sqlx::query(
+            r#"
+            INSERT INTO sensors (id, location_id, name, parameter_id, parameter_name, units)
+            VALUES (11, 1, 'pm25 µg/m³', 2, 'pm25', 'µg/m³'), (12, 1, 'pm10 µg/m³', 1, 'pm10', 'µg/m³')
+            "#,
+        )
+        .execute(&db.pool)
+        .await
+        .expect("Failed to insert sensors");
+        let january = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+        db.create_measurement_partitions(january(1), january(3))
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO measurements (location_id, sensor_id, location_name, parameter_id,
+                parameter_name, unit, date_utc, date_local, country)
+            VALUES (3, 31, 'C', 2, 'pm25', 'µg/m³', '2024-01-01T00:00:00Z', '2024-01-01', 'DE'),
+                   (3, 31, 'C', 2, 'pm25', 'µg/m³', '2024-01-03T00:00:00Z', '2024-01-03', 'DE')
+            "#,
+        )
+        .execute(&db.pool)
+        .await
+        .expect("Failed to insert measurements");
+
+        let overview = db.get_country_overview().await.unwrap();
+        assert_eq!(overview.len(), 2);
+        assert_eq!(overview[0].code, "DE");
+        assert_eq!(overview[0].name, None);
+        assert_eq!(overview[0].locations, 0);
+        assert_eq!(overview[0].measurements, 2);
+        assert_eq!(
+            overview[0].first_day.map(|d| d.to_rfc3339()),
+            Some("2024-01-01T00:00:00+00:00".to_string())
+        );
+        assert_eq!(overview[1].code, "NL");
+        assert_eq!(overview[1].name.as_deref(), Some("Netherlands"));
+        assert_eq!(overview[1].locations, 2);
+        assert_eq!(overview[1].sensors, 2);
+        assert_eq!(overview[1].measurements, 0);
+        assert_eq!(overview[1].last_day, None);
+    }
+}
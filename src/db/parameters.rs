@@ -0,0 +1,158 @@
+//! Database operations for the parameter catalogue stored in the `parameters` table.
+
+use super::Database;
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::Parameter;
+use tracing::{error, info};
+
+impl Database {
+    /// Inserts or updates the given catalogue entries, keyed by OpenAQ parameter ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the transaction or any statement fails.
+    pub async fn upsert_parameters(&self, parameters: &[Parameter]) -> Result<()> {
+        let _timer = metrics().query_timer("upsert_parameters");
+        if parameters.is_empty() {
+            return Ok(());
+        }
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            error!("Failed to begin transaction for parameters: {}", e);
+            AppError::Db(e.into())
+        })?;
+
+        for p in parameters {
+            sqlx::query(
+                r#"
+                INSERT INTO parameters (id, name, units, display_name, description)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (id) DO UPDATE SET
+                    name = EXCLUDED.name,
+                    units = EXCLUDED.units,
+                    display_name = EXCLUDED.display_name,
+                    description = EXCLUDED.description,
+                    updated_at = NOW()
+                "#,
+            )
+            .bind(p.id)
+            .bind(&p.name)
+            .bind(&p.units)
+            .bind(&p.display_name)
+            .bind(&p.description)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to upsert parameter {} ({}): {}", p.id, p.name, e);
+                AppError::Db(e.into())
+            })?;
+        }
+
+        tx.commit().await.map_err(|e| {
+            error!("Failed to commit parameters transaction: {}", e);
+            AppError::Db(e.into())
+        })?;
+        info!("Stored {} catalogue parameters", parameters.len());
+        Ok(())
+    }
+
+    /// Returns the stored parameter catalogue, ordered by ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_parameters(&self) -> Result<Vec<Parameter>> {
+        let _timer = metrics().query_timer("get_parameters");
+        sqlx::query_as::<_, Parameter>(
+            "SELECT id, name, units, display_name, description FROM parameters ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to load parameter catalogue: {}", e);
+            AppError::Db(e.into())
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::{DbMeasurement, MeasurementFilter};
+    use chrono::{Duration, Utc};
+
+    use sqlx::PgPool;
+
+    fn parameter(id: i32, name: &str, units: &str, display_name: &str) -> Parameter {
+        Parameter {
+            id,
+            name: name.to_string(),
+            units: units.to_string(),
+            display_name: Some(display_name.to_string()),
+            description: None,
+        }
+    }
+
+    fn measurement(sensor_id: i64, parameter: &str, unit: &str, value: f64) -> DbMeasurement {
+        DbMeasurement::test(sensor_id, "NL", parameter, Utc::now() - Duration::days(1))
+            .with_value_in(value, unit)
+    }
+
+    /// Tests that the catalogue is upserted and that averages and latest values include
+    /// every measured parameter, labelled and ordered by the catalogue.
+    #[sqlx::test]
+    async fn test_catalogue_drives_dynamic_parameters(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        db.upsert_parameters(&[
+            parameter(19, "pm1", "µg/m³", "PM1.0"),
+            parameter(2, "pm25", "µg/m³", "PM2.5"),
+        ])
+        .await
+        .expect("Failed to store catalogue");
+        // Upserting again updates existing rows and adds new ones
+        db.upsert_parameters(&[
+            parameter(19, "pm1", "µg/m³", "PM1"),
+            parameter(98, "relativehumidity", "%", "RH"),
+        ])
+        .await
+        .expect("Failed to store catalogue");
+        assert_eq!(
+            db.get_parameters()
+                .await
+                .unwrap()
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["pm25", "pm1", "relativehumidity"]
+        );
+
+        db.insert_measurements(&[
+            measurement(1, "pm1", "µg/m³", 4.0),
+            measurement(2, "pm25", "µg/m³", 9.0),
+            measurement(3, "relativehumidity", "%", 70.0),
+            measurement(4, "bc", "µg/m³", 1.5), // Not in the catalogue
+        ])
+        .await
+        .expect("Failed to insert measurements");
+        let filter = MeasurementFilter::default();
+
+        let average = db.get_average_air_quality("NL", &filter).await.unwrap();
+        let labels: Vec<String> = average.averages.iter().map(|a| a.label()).collect();
+        assert_eq!(labels, vec!["PM2.5", "PM1", "RH", "BC"]);
+        assert_eq!(average.averages[2].unit.as_deref(), Some("%"));
+
+        let latest = db
+            .get_latest_measurements_by_locality("NL", &filter)
+            .await
+            .unwrap();
+        assert_eq!(latest.len(), 1);
+        let parameters: Vec<&str> = latest[0]
+            .values
+            .iter()
+            .map(|v| v.parameter.as_str())
+            .collect();
+        assert_eq!(parameters, vec!["pm25", "pm1", "relativehumidity", "bc"]);
+    }
+}
@@ -0,0 +1,586 @@
+//! Monthly range partitions of the `measurements` table: creating the partitions of the months
+//! about to be stored, dropping the ones past their retention, and migrating a table created
+//! before it was partitioned.
+
+use super::{Database, CREATE_MEASUREMENTS_TABLE};
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use chrono::{DateTime, Datelike, Months, NaiveDate, NaiveTime, Utc};
+use sqlx::{Postgres, Transaction};
+use std::collections::HashSet;
+use tracing::{error, info, warn};
+
+/// Months after the current one whose partitions are created ahead of time.
+pub const PARTITION_MONTHS_AHEAD: u32 = 3;
+
+/// Advisory lock serialising the creation of partitions across connections and instances.
+const PARTITION_LOCK_KEY: i64 = 0x0A1B_0002;
+
+/// Columns copied when migrating an unpartitioned `measurements` table.
+const MEASUREMENT_COLUMNS: &str = "id, location_id, sensor_id, location_name, parameter_id, \
+     parameter_name, value_avg, value_min, value_max, value_q02, value_q25, value_median, \
+     value_q75, value_q98, value_sd, measurement_count, expected_count, percent_complete, \
+     percent_coverage, unit, unit_normalized, unit_factor, value_normalized, date_utc, \
+     date_local, country, city, latitude, longitude, is_mobile, is_monitor, owner_name, \
+     provider_name, source, created_at";
+
+/// Returns the first day of every month from `first`'s through `last`'s.
+fn months(first: NaiveDate, last: NaiveDate) -> Vec<NaiveDate> {
+    let mut month = first.with_day(1).expect("every month has a first day");
+    let mut months = Vec::new();
+    while month <= last {
+        months.push(month);
+        month = month + Months::new(1);
+    }
+    months
+}
+
+/// Returns the name of the partition holding `month`, e.g. `measurements_y2024m03`.
+fn partition_name(month: NaiveDate) -> String {
+    format!("measurements_y{}m{:02}", month.year(), month.month())
+}
+
+/// Returns the month of a partition named by `partition_name`, `None` for other names.
+fn partition_month(name: &str) -> Option<NaiveDate> {
+    let (year, month) = name.strip_prefix("measurements_y")?.split_once('m')?;
+    NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, 1)
+}
+
+/// Loads the names of the existing partitions of `measurements`.
+async fn existing_partitions<'e, E>(executor: E) -> Result<HashSet<String>>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let names = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT c.relname::text
+        FROM pg_inherits i
+        JOIN pg_class c ON c.oid = i.inhrelid
+        WHERE i.inhparent = to_regclass('measurements')
+        "#,
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| {
+        error!("Failed to list the measurement partitions: {}", e);
+        AppError::Db(e.into())
+    })?;
+    Ok(names.into_iter().collect())
+}
+
+/// Creates the missing partitions of the months from `first`'s through `last`'s, returning the
+/// number created. Holds the partition lock until the transaction ends.
+async fn create_partitions(
+    tx: &mut Transaction<'_, Postgres>,
+    first: NaiveDate,
+    last: NaiveDate,
+) -> Result<usize> {
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(PARTITION_LOCK_KEY)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to take the partition lock: {}", e);
+            AppError::Db(e.into())
+        })?;
+    let existing = existing_partitions(&mut **tx).await?;
+    let mut created = 0;
+    for month in months(first, last) {
+        let name = partition_name(month);
+        if existing.contains(&name) {
+            continue;
+        }
+        let start = month.and_time(NaiveTime::MIN).and_utc();
+        let end = (month + Months::new(1)).and_time(NaiveTime::MIN).and_utc();
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {} PARTITION OF measurements FOR VALUES FROM ('{}') TO ('{}')",
+            name,
+            start.to_rfc3339(),
+            end.to_rfc3339()
+        ))
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to create measurement partition {}: {}", name, e);
+            AppError::Db(e.into())
+        })?;
+        info!("Created measurement partition {}", name);
+        created += 1;
+    }
+    Ok(created)
+}
+
+impl Database {
+    /// Creates the missing monthly partitions of `measurements` for the days from `first`
+    /// through `last`, returning the number created. Without missing partitions, only the
+    /// catalogue is read.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if a partition cannot be created.
+    pub async fn create_measurement_partitions(
+        &self,
+        first: NaiveDate,
+        last: NaiveDate,
+    ) -> Result<usize> {
+        let _timer = metrics().query_timer("create_measurement_partitions");
+        let existing = existing_partitions(&self.pool).await?;
+        if months(first, last)
+            .into_iter()
+            .all(|month| existing.contains(&partition_name(month)))
+        {
+            return Ok(0);
+        }
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            error!(
+                "Failed to begin transaction for measurement partitions: {}",
+                e
+            );
+            AppError::Db(e.into())
+        })?;
+        let created = create_partitions(&mut tx, first, last).await?;
+        tx.commit().await.map_err(|e| {
+            error!("Failed to commit measurement partitions: {}", e);
+            AppError::Db(e.into())
+        })?;
+        Ok(created)
+    }
+
+    /// Drops the monthly partitions of `measurements` whose month ends on or before `before`,
+    /// with the quality flags of their measurements, returning the number of measurements
+    /// dropped. A partition holding a measurement with an ID above `max_id` (stored after
+    /// the prune run started) is kept, so nothing is dropped unarchived.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if a statement fails; the partitions are then left untouched.
+    pub async fn drop_measurement_partitions(
+        &self,
+        before: DateTime<Utc>,
+        max_id: i32,
+    ) -> Result<u64> {
+        let _timer = metrics().query_timer("drop_measurement_partitions");
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            error!("Failed to begin transaction for dropping partitions: {}", e);
+            AppError::Db(e.into())
+        })?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(PARTITION_LOCK_KEY)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to take the partition lock: {}", e);
+                AppError::Db(e.into())
+            })?;
+        let mut expired: Vec<(NaiveDate, String)> = existing_partitions(&mut *tx)
+            .await?
+            .into_iter()
+            .filter_map(|name| Some((partition_month(&name)?, name)))
+            .filter(|(month, _)| {
+                (*month + Months::new(1)).and_time(NaiveTime::MIN).and_utc() <= before
+            })
+            .collect();
+        expired.sort();
+
+        let mut dropped = 0;
+        for (month, name) in expired {
+            let start = month.and_time(NaiveTime::MIN).and_utc();
+            let end = (month + Months::new(1)).and_time(NaiveTime::MIN).and_utc();
+            let fail = |e: sqlx::Error| {
+                error!("Failed to drop measurement partition {}: {}", name, e);
+                AppError::Db(e.into())
+            };
+            sqlx::query(&format!("LOCK TABLE {} IN ACCESS EXCLUSIVE MODE", name))
+                .execute(&mut *tx)
+                .await
+                .map_err(fail)?;
+            let (rows, last_id): (i64, Option<i32>) =
+                sqlx::query_as(&format!("SELECT COUNT(*), MAX(id) FROM {}", name))
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(fail)?;
+            if last_id.is_some_and(|id| id > max_id) {
+                warn!(
+                    "Keeping measurement partition {}: it holds rows stored after the prune started",
+                    name
+                );
+                continue;
+            }
+            sqlx::query("DELETE FROM measurement_flags WHERE date_utc >= $1 AND date_utc < $2")
+                .bind(start)
+                .bind(end)
+                .execute(&mut *tx)
+                .await
+                .map_err(fail)?;
+            sqlx::query(&format!(
+                "ALTER TABLE measurements DETACH PARTITION {}",
+                name
+            ))
+            .execute(&mut *tx)
+            .await
+            .map_err(fail)?;
+            sqlx::query(&format!("DROP TABLE {}", name))
+                .execute(&mut *tx)
+                .await
+                .map_err(fail)?;
+            info!("Dropped measurement partition {} ({} rows)", name, rows);
+            dropped += rows as u64;
+        }
+
+        tx.commit().await.map_err(|e| {
+            error!("Failed to commit dropping measurement partitions: {}", e);
+            AppError::Db(e.into())
+        })?;
+        Ok(dropped)
+    }
+
+    /// Creates the partitions of the current month and the `PARTITION_MONTHS_AHEAD` months
+    /// after it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if a partition cannot be created.
+    pub async fn create_upcoming_partitions(&self) -> Result<usize> {
+        let today = Utc::now().date_naive();
+        self.create_measurement_partitions(today, today + Months::new(PARTITION_MONTHS_AHEAD))
+            .await
+    }
+
+    /// Replaces an unpartitioned `measurements` table, as created before partitioning, by the
+    /// partitioned one: copies every row with its ID into the partitions of its month, moves
+    /// the foreign key of `measurement_flags` and drops the old table, all in one transaction.
+    /// Does nothing if the table is already partitioned.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if a statement fails; the old table is then left untouched.
+    pub(super) async fn partition_measurements(&self) -> Result<()> {
+        let _timer = metrics().query_timer("partition_measurements");
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            error!("Failed to begin transaction for partitioning: {}", e);
+            AppError::Db(e.into())
+        })?;
+        // Taken before looking at the table, so concurrent instances migrate it only once.
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(PARTITION_LOCK_KEY)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to take the partition lock: {}", e);
+                AppError::Db(e.into())
+            })?;
+        let kind = sqlx::query_scalar::<_, String>(
+            "SELECT relkind::text FROM pg_class WHERE oid = to_regclass('measurements')",
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to look up the measurements table: {}", e);
+            AppError::Db(e.into())
+        })?;
+        if kind.as_deref() != Some("r") {
+            return Ok(());
+        }
+        warn!("Migrating the measurements table to monthly partitions...");
+
+        let statements = [
+            "LOCK TABLE measurements IN ACCESS EXCLUSIVE MODE",
+            "ALTER TABLE measurements RENAME TO measurements_unpartitioned",
+            // Frees the names of the indexes and the ID sequence for the partitioned table.
+            r#"
+            DO $$
+            DECLARE
+                index_name TEXT;
+            BEGIN
+                FOR index_name IN
+                    SELECT c.relname FROM pg_index i JOIN pg_class c ON c.oid = i.indexrelid
+                    WHERE i.indrelid = 'measurements_unpartitioned'::regclass
+                LOOP
+                    EXECUTE format('ALTER INDEX %I RENAME TO %I', index_name, 'unpartitioned_' || index_name);
+                END LOOP;
+            END
+            $$
+            "#,
+            "ALTER SEQUENCE IF EXISTS measurements_id_seq RENAME TO measurements_unpartitioned_id_seq",
+            "ALTER TABLE IF EXISTS measurement_flags DROP CONSTRAINT IF EXISTS measurement_flags_measurement_id_fkey",
+            CREATE_MEASUREMENTS_TABLE,
+        ];
+        for statement in statements {
+            sqlx::query(statement)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    error!(
+                        "Failed to prepare the partitioned measurements table: {}",
+                        e
+                    );
+                    AppError::Db(e.into())
+                })?;
+        }
+
+        let (first, last) = sqlx::query_as::<_, (Option<NaiveDate>, Option<NaiveDate>)>(
+            r#"
+            SELECT MIN(date_utc AT TIME ZONE 'UTC')::date, MAX(date_utc AT TIME ZONE 'UTC')::date
+            FROM measurements_unpartitioned
+            "#,
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to read the date range of the measurements: {}", e);
+            AppError::Db(e.into())
+        })?;
+        if let (Some(first), Some(last)) = (first, last) {
+            create_partitions(&mut tx, first, last).await?;
+        }
+
+        let copied = sqlx::query(&format!(
+            "INSERT INTO measurements ({MEASUREMENT_COLUMNS}) SELECT {MEASUREMENT_COLUMNS} FROM measurements_unpartitioned"
+        ))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to copy the measurements into their partitions: {}", e);
+            AppError::Db(e.into())
+        })?
+        .rows_affected();
+
+        sqlx::query(
+            r#"
+            SELECT setval(pg_get_serial_sequence('measurements', 'id'), COALESCE(MAX(id), 0) + 1, false)
+            FROM measurements
+            "#,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to advance the measurement ID sequence: {}", e);
+            AppError::Db(e.into())
+        })?;
+
+        let flags =
+            sqlx::query_scalar::<_, bool>("SELECT to_regclass('measurement_flags') IS NOT NULL")
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(|e| {
+                    error!("Failed to look up the measurement_flags table: {}", e);
+                    AppError::Db(e.into())
+                })?;
+        if flags {
+            // Flags must carry the date of their measurement to reference its partition.
+            let statements = [
+                r#"
+                DELETE FROM measurement_flags f
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM measurements m WHERE m.id = f.measurement_id AND m.date_utc = f.date_utc
+                )
+                "#,
+                r#"
+                ALTER TABLE measurement_flags ADD FOREIGN KEY (measurement_id, date_utc)
+                    REFERENCES measurements(id, date_utc) ON DELETE CASCADE
+                "#,
+            ];
+            for statement in statements {
+                sqlx::query(statement)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| {
+                        error!("Failed to move the foreign key of the flags: {}", e);
+                        AppError::Db(e.into())
+                    })?;
+            }
+        }
+
+        sqlx::query("DROP TABLE measurements_unpartitioned")
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to drop the unpartitioned measurements table: {}", e);
+                AppError::Db(e.into())
+            })?;
+
+        tx.commit().await.map_err(|e| {
+            error!("Failed to commit partitioning of the measurements: {}", e);
+            AppError::Db(e.into())
+        })?;
+        info!("Moved {} measurements into monthly partitions", copied);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::DbMeasurement;
+
+    use sqlx::PgPool;
+
+    fn measurement(sensor_id: i64, day: NaiveDate) -> DbMeasurement {
+        DbMeasurement::test(
+            sensor_id,
+            "NL",
+            "pm25",
+            day.and_time(NaiveTime::MIN).and_utc(),
+        )
+    }
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// Returns the partition of every stored measurement, ordered by ID.
+    async fn stored_partitions(db: &Database) -> Vec<(i32, String)> {
+        sqlx::query_as("SELECT id, tableoid::regclass::text FROM measurements ORDER BY id")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_months() {
+        assert_eq!(
+            months(day(2023, 11, 20), day(2024, 2, 1)),
+            [
+                day(2023, 11, 1),
+                day(2023, 12, 1),
+                day(2024, 1, 1),
+                day(2024, 2, 1)
+            ]
+        );
+        assert!(months(day(2024, 2, 1), day(2024, 1, 31)).is_empty());
+        assert_eq!(partition_name(day(2024, 3, 1)), "measurements_y2024m03");
+        assert_eq!(
+            partition_month("measurements_y2024m03"),
+            Some(day(2024, 3, 1))
+        );
+        assert_eq!(partition_month("measurements_unpartitioned"), None);
+    }
+
+    /// Tests that the partitions of the coming months exist and inserts create the ones of
+    /// older months.
+    #[sqlx::test]
+    async fn test_measurement_partitions(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        let today = Utc::now().date_naive();
+        let existing = existing_partitions(&db.pool).await.unwrap();
+        assert_eq!(existing.len(), PARTITION_MONTHS_AHEAD as usize + 1);
+        assert!(existing.contains(&partition_name(today)));
+        assert_eq!(db.create_upcoming_partitions().await.unwrap(), 0);
+
+        db.insert_measurements(&[
+            measurement(11, day(2020, 1, 31)),
+            measurement(11, day(2020, 3, 1)),
+        ])
+        .await
+        .unwrap();
+        let partitions: Vec<String> = stored_partitions(&db)
+            .await
+            .into_iter()
+            .map(|(_, partition)| partition)
+            .collect();
+        assert_eq!(
+            partitions,
+            ["measurements_y2020m01", "measurements_y2020m03"]
+        );
+        assert!(existing_partitions(&db.pool)
+            .await
+            .unwrap()
+            .contains("measurements_y2020m02"));
+    }
+
+    /// Tests the migration of a table created before partitioning, with an anomaly flag.
+    #[sqlx::test]
+    async fn test_partition_measurements(pool: PgPool) {
+        let db = Database { pool };
+        for statement in [
+            r#"
+            CREATE TABLE measurements (
+                id SERIAL PRIMARY KEY,
+                location_id BIGINT NOT NULL,
+                sensor_id BIGINT NOT NULL,
+                location_name TEXT NOT NULL,
+                parameter_id INT NOT NULL,
+                parameter_name TEXT NOT NULL,
+                value_avg NUMERIC,
+                value_min NUMERIC,
+                value_max NUMERIC,
+                measurement_count INT,
+                unit TEXT NOT NULL,
+                date_utc TIMESTAMPTZ NOT NULL,
+                date_local TEXT NOT NULL,
+                country TEXT NOT NULL,
+                city TEXT,
+                latitude DOUBLE PRECISION,
+                longitude DOUBLE PRECISION,
+                is_mobile BOOLEAN NOT NULL DEFAULT FALSE,
+                is_monitor BOOLEAN NOT NULL DEFAULT FALSE,
+                owner_name TEXT,
+                provider_name TEXT,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                UNIQUE (sensor_id, date_utc)
+            )
+            "#,
+            "CREATE INDEX idx_measurements_country ON measurements(country)",
+            r#"
+            CREATE TABLE measurement_flags (
+                id SERIAL PRIMARY KEY,
+                measurement_id INT NOT NULL REFERENCES measurements(id) ON DELETE CASCADE,
+                sensor_id BIGINT NOT NULL,
+                date_utc TIMESTAMPTZ NOT NULL,
+                flag TEXT NOT NULL,
+                score DOUBLE PRECISION,
+                detail TEXT,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                UNIQUE (measurement_id, flag)
+            )
+            "#,
+            r#"
+            INSERT INTO measurements (location_id, sensor_id, location_name, parameter_id,
+                parameter_name, value_avg, unit, date_utc, date_local, country)
+            VALUES (1, 11, 'Station 1', 2, 'pm25', 10, 'µg/m³', '2023-12-31T00:00:00Z', '2023-12-31', 'NL'),
+                   (1, 11, 'Station 1', 2, 'pm25', 90, 'µg/m³', '2024-01-01T00:00:00Z', '2024-01-01', 'NL')
+            "#,
+            r#"
+            INSERT INTO measurement_flags (measurement_id, sensor_id, date_utc, flag)
+            VALUES (2, 11, '2024-01-01T00:00:00Z', 'spike')
+            "#,
+        ] {
+            sqlx::query(statement).execute(&db.pool).await.unwrap();
+        }
+
+        db.init_schema().await.expect("Failed to migrate schema");
+        db.init_schema()
+            .await
+            .expect("Failed to init migrated schema");
+        assert_eq!(
+            stored_partitions(&db).await,
+            [
+                (1, "measurements_y2023m12".to_string()),
+                (2, "measurements_y2024m01".to_string())
+            ]
+        );
+        let unpartitioned: Option<String> =
+            sqlx::query_scalar("SELECT to_regclass('measurements_unpartitioned')::text")
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(unpartitioned, None);
+
+        // New rows continue the IDs, and flags still follow their measurement.
+        db.insert_measurements(&[measurement(11, day(2024, 1, 2))])
+            .await
+            .unwrap();
+        assert_eq!(stored_partitions(&db).await[2].0, 3);
+        sqlx::query("DELETE FROM measurements WHERE id = 2")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let flags: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM measurement_flags")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(flags, 0);
+    }
+}
@@ -4,21 +4,81 @@
 //! inserting air quality measurements, and executing various analytical queries.
 //! Also contains integration tests for database operations (requires the `integration-tests` feature).
 
+use super::history::{sync_location_versions, sync_sensor_versions};
+use super::units::{exclude_unit_conflicts, unit_conflict_binds};
 use crate::error::{AppError, Result};
+use crate::metrics::metrics;
 use crate::models::{
     CityLatestMeasurements,
     CountryAirQuality,
+    DailyAverage,
     DbMeasurement,
+    MeasurementFilter,
+    ParameterValue,
     PollutionRanking, // Removed unused Measurement
 };
+use chrono::{DateTime, Utc};
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres, Row};
+use std::collections::HashMap;
 use tracing::{debug, error, info};
 
+/// Number of past days covered by the pollution ranking unless another window is requested.
+pub const DEFAULT_RANKING_DAYS: i64 = 7;
+/// Number of past days covered by the country averages unless another window is requested.
+pub const DEFAULT_AVERAGE_DAYS: i64 = 5;
+
+/// Creates the `measurements` table, range partitioned by month on `date_utc` (see
+/// `partitions`).
+pub(super) const CREATE_MEASUREMENTS_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS measurements (
+        id SERIAL,
+        location_id BIGINT NOT NULL,
+        sensor_id BIGINT NOT NULL, -- Made explicitly NOT NULL to match struct/usage
+        location_name TEXT NOT NULL, -- Renamed from location
+        parameter_id INT NOT NULL,
+        parameter_name TEXT NOT NULL, -- Renamed from parameter
+        value_avg NUMERIC, -- Using NUMERIC for precise storage, now NULLABLE
+        value_min NUMERIC, -- Minimum value during the period
+        value_max NUMERIC, -- Maximum value during the period
+        value_q02 NUMERIC, -- 2nd percentile during the period
+        value_q25 NUMERIC, -- 25th percentile during the period
+        value_median NUMERIC, -- Median during the period
+        value_q75 NUMERIC, -- 75th percentile during the period
+        value_q98 NUMERIC, -- 98th percentile during the period
+        value_sd NUMERIC, -- Standard deviation during the period
+        measurement_count INT, -- Number of observations during the period
+        expected_count INT, -- Number of observations expected during the period
+        percent_complete DOUBLE PRECISION, -- observed / expected, in percent
+        percent_coverage DOUBLE PRECISION, -- Share of the period covered by observations, in percent
+
+        unit TEXT NOT NULL,
+        unit_normalized TEXT, -- Common unit per parameter, see models::units
+        unit_factor DOUBLE PRECISION, -- Factor converting `unit` into `unit_normalized`
+        value_normalized NUMERIC, -- value_avg expressed in unit_normalized
+        date_utc TIMESTAMPTZ NOT NULL,
+        date_local TEXT NOT NULL, -- Storing local time as text as provided by API
+        country TEXT NOT NULL,
+        city TEXT,
+        latitude DOUBLE PRECISION,
+        longitude DOUBLE PRECISION,
+        is_mobile BOOLEAN NOT NULL DEFAULT FALSE,
+        is_monitor BOOLEAN NOT NULL DEFAULT FALSE,
+        owner_name TEXT,
+        provider_name TEXT,
+        source TEXT NOT NULL DEFAULT 'openaq', -- Data source that provided the row
+        created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(), -- Timestamp of insertion
+        PRIMARY KEY (id, date_utc), -- Unique keys of a partitioned table include the partition key
+        UNIQUE (sensor_id, date_utc) -- Prevent duplicate readings for the same sensor at the same time
+    ) PARTITION BY RANGE (date_utc)
+"#;
+
 /// Represents the database connection pool and provides methods for database operations.
 ///
-/// Holds a `sqlx::Pool` for efficient connection management.
+/// Holds a `sqlx::Pool` for efficient connection management. Cloning is cheap and shares
+/// the pool.
+#[derive(Clone)]
 pub struct Database {
-    pool: Pool<Postgres>,
+    pub(super) pool: Pool<Postgres>,
 }
 
 impl Database {
@@ -47,6 +107,12 @@ impl Database {
         Ok(Self { pool })
     }
 
+    /// Wraps an existing connection pool.
+    #[cfg(all(test, feature = "integration-tests"))]
+    pub fn from_pool(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
     /// Initializes the database schema by creating the `locations`, `sensors`, and `measurements` tables and necessary indexes.
     ///
     /// Uses `CREATE TABLE IF NOT EXISTS` and `CREATE INDEX IF NOT EXISTS` to be idempotent,
@@ -76,6 +142,7 @@ impl Database {
                     is_monitor BOOLEAN NOT NULL,
                     owner_name TEXT,
                     provider_name TEXT,
+                    source TEXT NOT NULL DEFAULT 'openaq', -- Data source that provided the row
                     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
                 )
                 "#,
@@ -98,6 +165,7 @@ impl Database {
                     parameter_name TEXT NOT NULL,
                     units TEXT NOT NULL,
                     display_name TEXT,
+                    source TEXT NOT NULL DEFAULT 'openaq', -- Data source that provided the row
                     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
                 )
                 "#,
@@ -109,99 +177,268 @@ impl Database {
             AppError::Db(e.into())
         })?;
 
-        // Create the main table for storing daily aggregated air quality measurements.
+        // Create the parameter catalogue table (filled from the OpenAQ /v3/parameters endpoint)
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS measurements (
-                id SERIAL PRIMARY KEY,
-                location_id BIGINT NOT NULL,
-                sensor_id BIGINT NOT NULL, -- Made explicitly NOT NULL to match struct/usage
-                location_name TEXT NOT NULL, -- Renamed from location
-                parameter_id INT NOT NULL,
-                parameter_name TEXT NOT NULL, -- Renamed from parameter
-                value_avg NUMERIC, -- Using NUMERIC for precise storage, now NULLABLE
-                value_min NUMERIC, -- Minimum value during the period
-                value_max NUMERIC, -- Maximum value during the period
-                measurement_count INT, -- Number of observations during the period
+                CREATE TABLE IF NOT EXISTS parameters (
+                    id INT PRIMARY KEY, -- OpenAQ parameter ID
+                    name TEXT NOT NULL, -- e.g. 'pm25', matches measurements.parameter_name
+                    units TEXT NOT NULL,
+                    display_name TEXT,
+                    description TEXT,
+                    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+                )
+                "#,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to create parameters table: {}", e);
+            AppError::Db(e.into())
+        })?;
 
-                unit TEXT NOT NULL,
+        // Create the main table for storing daily aggregated air quality measurements, split
+        // into monthly partitions.
+        sqlx::query(CREATE_MEASUREMENTS_TABLE)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to create measurements table: {}", e);
+                AppError::Db(e.into())
+            })?;
+
+        // Add coverage and distribution columns to tables created before they were introduced.
+        sqlx::query(
+            r#"
+            ALTER TABLE measurements
+                ADD COLUMN IF NOT EXISTS expected_count INT,
+                ADD COLUMN IF NOT EXISTS percent_complete DOUBLE PRECISION,
+                ADD COLUMN IF NOT EXISTS percent_coverage DOUBLE PRECISION,
+                ADD COLUMN IF NOT EXISTS value_q02 NUMERIC,
+                ADD COLUMN IF NOT EXISTS value_q25 NUMERIC,
+                ADD COLUMN IF NOT EXISTS value_median NUMERIC,
+                ADD COLUMN IF NOT EXISTS value_q75 NUMERIC,
+                ADD COLUMN IF NOT EXISTS value_q98 NUMERIC,
+                ADD COLUMN IF NOT EXISTS value_sd NUMERIC,
+                ADD COLUMN IF NOT EXISTS unit_normalized TEXT,
+                ADD COLUMN IF NOT EXISTS unit_factor DOUBLE PRECISION,
+                ADD COLUMN IF NOT EXISTS value_normalized NUMERIC,
+                ADD COLUMN IF NOT EXISTS source TEXT NOT NULL DEFAULT 'openaq'
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to add new columns to measurements table: {}", e);
+            AppError::Db(e.into())
+        })?;
+
+        // Tag locations and sensors stored before data sources were introduced as OpenAQ data.
+        for table in ["locations", "sensors"] {
+            sqlx::query(&format!(
+                "ALTER TABLE {} ADD COLUMN IF NOT EXISTS source TEXT NOT NULL DEFAULT 'openaq'",
+                table
+            ))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to add source column to {} table: {}", table, e);
+                AppError::Db(e.into())
+            })?;
+        }
+
+        // Sensors marked inactive by the health check are skipped by the imports.
+        sqlx::query(
+            r#"
+            ALTER TABLE sensors
+                ADD COLUMN IF NOT EXISTS inactive_since TIMESTAMPTZ, -- NULL while active
+                ADD COLUMN IF NOT EXISTS inactive_reason TEXT
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to add inactive columns to sensors table: {}", e);
+            AppError::Db(e.into())
+        })?;
+
+        // Move the rows of a table created before partitioning into monthly partitions, and
+        // create the partitions of the coming months.
+        self.partition_measurements().await?;
+        self.create_upcoming_partitions().await?;
+
+        // Create indexes matching the analytic queries, which filter by country, parameter and
+        // date. Lookups by sensor use the (sensor_id, date_utc) unique key. Indexes created on
+        // the partitioned table are created on every partition.
+        for index in [
+            "CREATE INDEX IF NOT EXISTS idx_measurements_country_parameter_date ON measurements(country, parameter_name, date_utc)",
+            "CREATE INDEX IF NOT EXISTS idx_measurements_parameter_date ON measurements(parameter_name, date_utc)",
+            "CREATE INDEX IF NOT EXISTS idx_measurements_date_utc ON measurements(date_utc)",
+        ] {
+            sqlx::query(index).execute(&self.pool).await.map_err(|e| {
+                error!("Failed to create index on measurements: {}", e);
+                AppError::Db(e.into())
+            })?;
+        }
+
+        // Create the table storing anomaly flags produced by the anomaly detector.
+        // A measurement can carry several flags, but only one per detector.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS measurement_flags (
+                id SERIAL PRIMARY KEY,
+                measurement_id INT NOT NULL,
+                sensor_id BIGINT NOT NULL,
                 date_utc TIMESTAMPTZ NOT NULL,
-                date_local TEXT NOT NULL, -- Storing local time as text as provided by API
-                country TEXT NOT NULL,
-                city TEXT,
-                latitude DOUBLE PRECISION,
-                longitude DOUBLE PRECISION,
-                is_mobile BOOLEAN NOT NULL DEFAULT FALSE,
-                is_monitor BOOLEAN NOT NULL DEFAULT FALSE,
-                owner_name TEXT,
-                provider_name TEXT,
-                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(), -- Timestamp of insertion
-                UNIQUE (sensor_id, date_utc) -- Prevent duplicate readings for the same sensor at the same time
+                flag TEXT NOT NULL, -- Detector identifier (e.g., 'spike', 'flat_line')
+                score DOUBLE PRECISION,
+                detail TEXT,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                UNIQUE (measurement_id, flag),
+                FOREIGN KEY (measurement_id, date_utc) REFERENCES measurements(id, date_utc) ON DELETE CASCADE
             )
             "#,
         )
         .execute(&self.pool)
         .await
         .map_err(|e| {
-            error!("Failed to create measurements table: {}", e);
+            error!("Failed to create measurement_flags table: {}", e);
             AppError::Db(e.into())
         })?;
 
-        // Create indexes to speed up common query patterns.
-        // Index on country for filtering by country.
+        // Index on date_utc so flags of a re-scanned period can be replaced quickly.
         sqlx::query(
-            r#"CREATE INDEX IF NOT EXISTS idx_measurements_country ON measurements(country)"#,
+            r#"CREATE INDEX IF NOT EXISTS idx_measurement_flags_date_utc ON measurement_flags(date_utc)"#,
         )
         .execute(&self.pool)
         .await
         .map_err(|e| {
-            error!("Failed to create country index: {}", e);
+            error!("Failed to create measurement_flags date index: {}", e);
             AppError::Db(e.into())
         })?;
 
-        // Index on sensor_id for joining or filtering by sensor.
+        // Create the table recording the runs of the scheduled daemon jobs.
         sqlx::query(
-            r#"CREATE INDEX IF NOT EXISTS idx_measurements_sensor_id ON measurements(sensor_id)"#,
+            r#"
+            CREATE TABLE IF NOT EXISTS job_runs (
+                id SERIAL PRIMARY KEY,
+                job TEXT NOT NULL, -- Job name (e.g., 'import', 'discovery')
+                started_at TIMESTAMPTZ NOT NULL,
+                finished_at TIMESTAMPTZ NOT NULL,
+                status TEXT NOT NULL, -- 'success', 'failed' or 'skipped'
+                rows BIGINT NOT NULL DEFAULT 0, -- Rows fetched or discovered by the run
+                error TEXT
+            )
+            "#,
         )
         .execute(&self.pool)
         .await
         .map_err(|e| {
-            error!("Failed to create sensor_id index: {}", e);
+            error!("Failed to create job_runs table: {}", e);
             AppError::Db(e.into())
         })?;
 
-        // Index on parameter_id for potential filtering/joining on parameter ID.
+        // Create the table tracking the alerts raised by the alert rules. At most one alert
+        // per rule is firing at a time.
         sqlx::query(
-            r#"CREATE INDEX IF NOT EXISTS idx_measurements_parameter_id ON measurements(parameter_id)"#,
+            r#"
+            CREATE TABLE IF NOT EXISTS alerts (
+                id SERIAL PRIMARY KEY,
+                rule TEXT NOT NULL, -- Name of the rule in the rules file
+                status TEXT NOT NULL, -- 'firing' or 'resolved'
+                fired_at TIMESTAMPTZ NOT NULL,
+                resolved_at TIMESTAMPTZ,
+                first_day TIMESTAMPTZ NOT NULL, -- First day of the breach that fired the alert
+                last_day TIMESTAMPTZ NOT NULL, -- Latest day evaluated while firing or at resolution
+                value DOUBLE PRECISION, -- Daily value of last_day
+                threshold DOUBLE PRECISION NOT NULL,
+                unit TEXT
+            )
+            "#,
         )
         .execute(&self.pool)
         .await
         .map_err(|e| {
-            error!("Failed to create parameter_id index: {}", e);
+            error!("Failed to create alerts table: {}", e);
             AppError::Db(e.into())
         })?;
-
-        // Index on parameter_name for filtering by pollutant type. (Changed from parameter)
         sqlx::query(
-            r#"CREATE INDEX IF NOT EXISTS idx_measurements_parameter_name ON measurements(parameter_name)"#,
+            r#"CREATE UNIQUE INDEX IF NOT EXISTS idx_alerts_firing_rule ON alerts(rule) WHERE status = 'firing'"#,
         )
         .execute(&self.pool)
         .await
         .map_err(|e| {
-            error!("Failed to create parameter index: {}", e);
+            error!("Failed to create index on alerts: {}", e);
             AppError::Db(e.into())
         })?;
 
-        // Index on date_utc for time-based filtering and ordering.
+        // Create the versioned metadata of the locations and sensors (slowly changing
+        // dimension): a row per period in which the attributes did not change, with
+        // `valid_to` NULL for the current version.
         sqlx::query(
-            r#"CREATE INDEX IF NOT EXISTS idx_measurements_date_utc ON measurements(date_utc)"#,
+            r#"
+            CREATE TABLE IF NOT EXISTS location_versions (
+                id BIGSERIAL PRIMARY KEY,
+                location_id BIGINT NOT NULL REFERENCES locations(id) ON DELETE CASCADE,
+                name TEXT,
+                locality TEXT,
+                country_code TEXT NOT NULL,
+                timezone TEXT NOT NULL,
+                latitude DOUBLE PRECISION,
+                longitude DOUBLE PRECISION,
+                is_mobile BOOLEAN NOT NULL,
+                is_monitor BOOLEAN NOT NULL,
+                owner_name TEXT,
+                provider_name TEXT,
+                datetime_last TIMESTAMPTZ, -- Kept up to date while the version is current
+                valid_from TIMESTAMPTZ NOT NULL,
+                valid_to TIMESTAMPTZ
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to create location_versions table: {}", e);
+            AppError::Db(e.into())
+        })?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sensor_versions (
+                id BIGSERIAL PRIMARY KEY,
+                sensor_id BIGINT NOT NULL REFERENCES sensors(id) ON DELETE CASCADE,
+                location_id BIGINT NOT NULL,
+                name TEXT NOT NULL,
+                parameter_id INT NOT NULL,
+                parameter_name TEXT NOT NULL,
+                units TEXT NOT NULL,
+                valid_from TIMESTAMPTZ NOT NULL,
+                valid_to TIMESTAMPTZ -- Closed without a successor when the sensor was removed
+            )
+            "#,
         )
         .execute(&self.pool)
         .await
         .map_err(|e| {
-            error!("Failed to create date index: {}", e);
+            error!("Failed to create sensor_versions table: {}", e);
             AppError::Db(e.into())
         })?;
+        for index in [
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_location_versions_current ON location_versions(location_id) WHERE valid_to IS NULL",
+            "CREATE INDEX IF NOT EXISTS idx_location_versions_location_id ON location_versions(location_id, valid_from)",
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_sensor_versions_current ON sensor_versions(sensor_id) WHERE valid_to IS NULL",
+            "CREATE INDEX IF NOT EXISTS idx_sensor_versions_location_id ON sensor_versions(location_id)",
+        ] {
+            sqlx::query(index).execute(&self.pool).await.map_err(|e| {
+                error!("Failed to create index on the metadata versions: {}", e);
+                AppError::Db(e.into())
+            })?;
+        }
+        // Start the history of locations and sensors stored before it was recorded.
+        self.backfill_metadata_versions().await?;
+
+        // Fill in the normalised values of rows stored before unit normalisation existed.
+        self.normalize_stored_units().await?;
 
         info!("Database schema initialized successfully");
         Ok(())
@@ -212,6 +449,8 @@ impl Database {
     /// Executes insertions within a single database transaction for atomicity.
     /// Uses `ON CONFLICT (sensor_id, date_utc) DO NOTHING` to silently ignore potential duplicate entries
     /// based on the unique constraint. Assumes input `db_measurements` are already converted.
+    /// Creates the partitions of the months of the batch that do not exist yet.
+    /// Returns the number of rows inserted, which excludes the ignored duplicates.
     ///
     /// # Arguments
     ///
@@ -221,10 +460,11 @@ impl Database {
     ///
     /// Returns `AppError::Db` if the transaction fails to begin, commit, or if any
     /// individual insertion query fails.
-    pub async fn insert_measurements(&self, db_measurements: &[DbMeasurement]) -> Result<()> {
+    pub async fn insert_measurements(&self, db_measurements: &[DbMeasurement]) -> Result<u64> {
+        let _timer = metrics().query_timer("insert_measurements");
         if db_measurements.is_empty() {
             debug!("No measurements provided for insertion.");
-            return Ok(());
+            return Ok(0);
         }
 
         info!(
@@ -234,6 +474,13 @@ impl Database {
 
         // Conversion step is removed, assuming input is already Vec<DbMeasurement>
 
+        // Rows can only be stored in the partition of their month; create missing ones first,
+        // outside the transaction so the insert never waits on the partition lock.
+        let days = db_measurements.iter().map(|m| m.date_utc.date_naive());
+        if let (Some(first), Some(last)) = (days.clone().min(), days.max()) {
+            self.create_measurement_partitions(first, last).await?;
+        }
+
         // Use a transaction to ensure all measurements are inserted or none are.
         let mut tx = self.pool.begin().await.map_err(|e| {
             error!("Failed to begin database transaction: {}", e);
@@ -241,13 +488,14 @@ impl Database {
         })?;
 
         // Iterate and execute INSERT query for each measurement.
+        let mut inserted = 0;
         for m in db_measurements {
             // Using `ON CONFLICT (sensor_id, date_utc) DO NOTHING` to handle duplicates based on the unique constraint.
-            sqlx::query(
+            inserted += sqlx::query(
                 r#"
                 INSERT INTO measurements
-                (location_id, sensor_id, location_name, parameter_id, parameter_name, value_avg, value_min, value_max, measurement_count, unit, date_utc, date_local, country, city, latitude, longitude, is_mobile, is_monitor, owner_name, provider_name)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
+                (location_id, sensor_id, location_name, parameter_id, parameter_name, value_avg, value_min, value_max, measurement_count, unit, date_utc, date_local, country, city, latitude, longitude, is_mobile, is_monitor, owner_name, provider_name, expected_count, percent_complete, percent_coverage, value_q02, value_q25, value_median, value_q75, value_q98, value_sd, unit_normalized, unit_factor, value_normalized, source)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32, $33)
                 ON CONFLICT (sensor_id, date_utc) DO NOTHING
                 "#,
             )
@@ -271,6 +519,19 @@ impl Database {
             .bind(m.is_monitor)          // $18
             .bind(&m.owner_name)         // $19
             .bind(&m.provider_name)      // $20
+            .bind(m.expected_count)      // $21
+            .bind(m.percent_complete)    // $22
+            .bind(m.percent_coverage)    // $23
+            .bind(m.value_q02)           // $24
+            .bind(m.value_q25)           // $25
+            .bind(m.value_median)        // $26
+            .bind(m.value_q75)           // $27
+            .bind(m.value_q98)           // $28
+            .bind(m.value_sd)            // $29
+            .bind(&m.unit_normalized)    // $30
+            .bind(m.unit_factor)         // $31
+            .bind(m.value_normalized)    // $32
+            .bind(&m.source)             // $33
             .execute(&mut *tx) // Execute within the transaction
             .await
             .map_err(|e| {
@@ -278,7 +539,8 @@ impl Database {
                 error!("SQLx error during measurement insert (sensor_id: {:?}, date_utc: {}): {:?}", m.sensor_id, m.date_utc, e);
                 error!("Failed to insert measurement record (sensor_id: {:?}, date_utc: {}): {}", m.sensor_id, m.date_utc, e);
                 AppError::Db(e.into())
-            })?;
+            })?
+            .rows_affected();
         } // End of for loop
 
         // Commit the transaction if all insertions were successful.
@@ -286,17 +548,25 @@ impl Database {
             error!("Failed to commit database transaction: {}", e);
             AppError::Db(e.into())
         })?;
+        metrics().record_rows("measurements", db_measurements.len(), inserted);
 
         info!(
-            "Successfully processed {} measurements for insertion (duplicates ignored).",
-            db_measurements.len()
+            "Successfully processed {} measurements for insertion ({} new, duplicates ignored).",
+            db_measurements.len(),
+            inserted
         );
-        Ok(())
+        Ok(inserted)
     } // End of function
 
-    /// Inserts a batch of `Location` records into the database.
-    /// Uses `ON CONFLICT DO NOTHING` to ignore duplicates based on the primary key `id`.
-    pub async fn insert_locations(&self, locations: &[crate::models::Location]) -> Result<()> {
+    /// Inserts or updates a batch of `Location` records provided by the data source `source`.
+    /// Known locations take the latest attributes, keeping the earliest `datetime_first` and the
+    /// latest `datetime_last`; a changed location gets a new version in `location_versions`.
+    pub async fn insert_locations(
+        &self,
+        source: &str,
+        locations: &[crate::models::Location],
+    ) -> Result<()> {
+        let _timer = metrics().query_timer("insert_locations");
         if locations.is_empty() {
             debug!("No locations provided for insertion.");
             return Ok(());
@@ -308,13 +578,29 @@ impl Database {
             AppError::Db(e.into())
         })?;
 
+        let mut inserted = 0;
         for loc in locations {
-            sqlx::query(
+            let (is_new,): (bool,) = sqlx::query_as(
                 r#"
                 INSERT INTO locations
-                (id, name, locality, country_code, country_name, timezone, latitude, longitude, datetime_first, datetime_last, is_mobile, is_monitor, owner_name, provider_name)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
-                ON CONFLICT (id) DO NOTHING
+                (id, name, locality, country_code, country_name, timezone, latitude, longitude, datetime_first, datetime_last, is_mobile, is_monitor, owner_name, provider_name, source)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+                ON CONFLICT (id) DO UPDATE SET
+                    name = EXCLUDED.name,
+                    locality = EXCLUDED.locality,
+                    country_code = EXCLUDED.country_code,
+                    country_name = EXCLUDED.country_name,
+                    timezone = EXCLUDED.timezone,
+                    latitude = EXCLUDED.latitude,
+                    longitude = EXCLUDED.longitude,
+                    datetime_first = LEAST(locations.datetime_first, EXCLUDED.datetime_first),
+                    datetime_last = GREATEST(locations.datetime_last, EXCLUDED.datetime_last),
+                    is_mobile = EXCLUDED.is_mobile,
+                    is_monitor = EXCLUDED.is_monitor,
+                    owner_name = EXCLUDED.owner_name,
+                    provider_name = EXCLUDED.provider_name,
+                    source = EXCLUDED.source
+                RETURNING (xmax = 0) as inserted -- false for updated rows
                 "#,
             )
             .bind(loc.id as i64) // Cast id to i64 for BIGINT column
@@ -331,18 +617,23 @@ impl Database {
             .bind(loc.is_monitor)
             .bind(&loc.owner.name)
             .bind(&loc.provider.name)
-            .execute(&mut *tx)
+            .bind(source)
+            .fetch_one(&mut *tx)
             .await
             .map_err(|e| {
                 error!("Failed to insert location record (id: {}): {}", loc.id, e);
                 AppError::Db(e.into())
             })?;
+            inserted += u64::from(is_new);
         }
+        let ids: Vec<i64> = locations.iter().map(|loc| loc.id as i64).collect();
+        sync_location_versions(&mut tx, &ids).await?;
 
         tx.commit().await.map_err(|e| {
             error!("Failed to commit transaction for locations: {}", e);
             AppError::Db(e.into())
         })?;
+        metrics().record_rows("locations", locations.len(), inserted);
 
         info!(
             "Successfully processed {} locations for insertion.",
@@ -351,19 +642,22 @@ impl Database {
         Ok(())
     }
 
-    /// Inserts a batch of `SensorBase` records associated with a location ID into the database.
-    /// Uses `ON CONFLICT DO NOTHING` to ignore duplicates based on the primary key `id`.
+    /// Inserts or updates the complete list of `SensorBase` records of a location provided by the
+    /// data source `source`. Changed sensors get a new version in `sensor_versions`, and sensors
+    /// of the location missing from the list are recorded as removed, so an empty list closes
+    /// the versions of all sensors of the location.
     pub async fn insert_sensors(
         &self,
+        source: &str,
         location_id: i64,
         sensors: &[crate::models::SensorBase],
     ) -> Result<()> {
+        let _timer = metrics().query_timer("insert_sensors");
         if sensors.is_empty() {
             debug!(
-                "No sensors provided for insertion for location {}.",
+                "No sensors provided for location {}; recording its sensors as removed.",
                 location_id
             );
-            return Ok(());
         }
         // Consider reducing log verbosity if this becomes too noisy
         // info!("Inserting {} sensors for location {}...", sensors.len(), location_id);
@@ -377,13 +671,22 @@ impl Database {
             AppError::Db(e.into())
         })?;
 
+        let mut inserted = 0;
         for sensor in sensors {
-            sqlx::query(
+            let (is_new,): (bool,) = sqlx::query_as(
                 r#"
                 INSERT INTO sensors
-                (id, location_id, name, parameter_id, parameter_name, units, display_name)
-                VALUES ($1, $2, $3, $4, $5, $6, $7)
-                ON CONFLICT (id) DO NOTHING
+                (id, location_id, name, parameter_id, parameter_name, units, display_name, source)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ON CONFLICT (id) DO UPDATE SET
+                    location_id = EXCLUDED.location_id,
+                    name = EXCLUDED.name,
+                    parameter_id = EXCLUDED.parameter_id,
+                    parameter_name = EXCLUDED.parameter_name,
+                    units = EXCLUDED.units,
+                    display_name = EXCLUDED.display_name,
+                    source = EXCLUDED.source
+                RETURNING (xmax = 0) as inserted -- false for updated rows
                 "#,
             )
             .bind(sensor.id as i64) // Cast id to i64 for BIGINT column
@@ -393,7 +696,8 @@ impl Database {
             .bind(&sensor.parameter.name)
             .bind(&sensor.parameter.units)
             .bind(&sensor.parameter.display_name)
-            .execute(&mut *tx)
+            .bind(source)
+            .fetch_one(&mut *tx)
             .await
             .map_err(|e| {
                 error!(
@@ -402,7 +706,10 @@ impl Database {
                 );
                 AppError::Db(e.into())
             })?;
+            inserted += u64::from(is_new);
         }
+        let ids: Vec<i64> = sensors.iter().map(|sensor| sensor.id as i64).collect();
+        sync_sensor_versions(&mut tx, location_id, &ids).await?;
 
         tx.commit().await.map_err(|e| {
             error!(
@@ -411,6 +718,7 @@ impl Database {
             );
             AppError::Db(e.into())
         })?;
+        metrics().record_rows("sensors", sensors.len(), inserted);
 
         // info!("Successfully processed {} sensors for location {}.", sensors.len(), location_id);
         Ok(())
@@ -419,7 +727,7 @@ impl Database {
     /// Finds the most polluted country among a given list based on recent PM2.5 and PM10 data.
     ///
     /// Calculates a pollution index: `(avg_pm25 * 1.5) + avg_pm10` using data from the last 7 days.
-    /// Returns the country with the highest index.
+    /// Returns the country with the highest index (the first entry of `get_pollution_ranking`).
     ///
     /// # Arguments
     ///
@@ -429,7 +737,12 @@ impl Database {
     ///
     /// Returns `AppError::Db` if the query fails. Returns a default `PollutionRanking` with index 0
     /// if no relevant data is found for any of the specified countries in the last 7 days.
-    pub async fn get_most_polluted_country(&self, countries: &[&str]) -> Result<PollutionRanking> {
+    pub async fn get_most_polluted_country(
+        &self,
+        countries: &[&str],
+        filter: &MeasurementFilter,
+    ) -> Result<PollutionRanking> {
+        let _timer = metrics().query_timer("get_most_polluted_country");
         if countries.is_empty() {
             // Handle case where no countries are provided, perhaps return an error or default.
             // For now, returning a default for "Unknown". Consider a specific error.
@@ -438,30 +751,98 @@ impl Database {
         }
         info!("Finding the most polluted country among: {:?}", countries);
 
-        // Join country codes into a comma-separated string suitable for SQL IN clause.
-        // Note: This approach is generally safe for known country codes but be wary of SQL injection
-        // if `countries` could come from untrusted input without sanitization. Binding is safer.
-        let countries_list = countries.join("','");
+        match self
+            .get_pollution_ranking(countries, filter)
+            .await?
+            .into_iter()
+            .next()
+        {
+            Some(ranking) => {
+                info!(
+                    "Most polluted country determined: {} with index: {}",
+                    ranking.country, ranking.pollution_index
+                );
+                Ok(ranking)
+            },
+            None => {
+                // If no data found for any country in the list within the time frame.
+                let default_country = countries.first().map_or("Unknown", |c| *c);
+                error!(
+                    "No recent pollution data (PM2.5/PM10) found for the specified countries: {:?}",
+                    countries
+                );
+                // Return a default ranking for the first country in the list (or "Unknown").
+                Ok(PollutionRanking::new(default_country))
+            },
+        }
+    }
+
+    /// Ranks the given countries by their pollution index over the last 7 days.
+    ///
+    /// Uses the same weighted index as `get_most_polluted_country`: `(avg_pm25 * 1.5) + avg_pm10`.
+    /// Countries without any recent PM2.5/PM10 data are omitted from the result.
+    ///
+    /// # Arguments
+    ///
+    /// * `countries` - A slice of country codes (e.g., "NL", "DE") to rank.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_pollution_ranking(
+        &self,
+        countries: &[&str],
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<PollutionRanking>> {
+        self.get_pollution_ranking_over(countries, DEFAULT_RANKING_DAYS, filter)
+            .await
+    }
+
+    /// Ranks the given countries by their pollution index over the last `days` days.
+    ///
+    /// PM2.5 or PM10 values of a country that cannot be normalised to a single unit are left
+    /// out of its index and listed in `unit_conflicts`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_pollution_ranking_over(
+        &self,
+        countries: &[&str],
+        days: i64,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<PollutionRanking>> {
+        let _timer = metrics().query_timer("get_pollution_ranking_over");
+        info!(
+            "Ranking countries by pollution index over {} days: {:?}",
+            days, countries
+        );
+        let countries: Vec<String> = countries.iter().map(|c| c.to_string()).collect();
+        let conflicts = self
+            .find_unit_conflicts(&countries, &["pm25", "pm10"], days as i32, filter)
+            .await?;
+        let (conflict_countries, conflict_parameters) = unit_conflict_binds(&conflicts);
 
         // SQL Query Explanation:
         // 1. CTE `latest_data`: Calculates the average value for PM2.5 and PM10 for each country
-        //    within the last 7 days.
+        //    within the last `days` days.
         // 2. Main Query: Groups by country, calculates the weighted pollution index,
-        //    extracts the specific PM2.5 and PM10 averages using MAX(CASE...), orders by the index descending,
-        //    and takes the top result.
+        //    extracts the specific PM2.5 and PM10 averages using MAX(CASE...) and orders by the index descending.
         let query = format!(
             r#"
             WITH latest_data AS (
                 SELECT
                     country,
-                    parameter_name, -- Use new column name
-                    AVG(value_avg::DOUBLE PRECISION) as avg_value -- Cast NUMERIC to float for calculation
+                    parameter_name,
+                    AVG(value_normalized::DOUBLE PRECISION) as avg_value -- Cast NUMERIC to float for calculation
                 FROM measurements
                 WHERE
-                    country IN ('{}') -- Injecting the list here (less safe than binding)
-                    AND parameter_name IN ('pm25', 'pm10') -- Use new column name
-                    AND date_utc > NOW() - INTERVAL '7 days'
-                GROUP BY country, parameter_name -- Use new column name
+                    country = ANY($1)
+                    AND parameter_name IN ('pm25', 'pm10')
+                    AND date_utc > NOW() - make_interval(days => $2)
+                    {}
+                    {}
+                GROUP BY country, parameter_name
             )
             SELECT
                 country,
@@ -473,51 +854,54 @@ impl Database {
                 MAX(CASE WHEN parameter_name = 'pm10' THEN avg_value ELSE NULL END)::DOUBLE PRECISION as pm10_avg
             FROM latest_data
             GROUP BY country
-            ORDER BY pollution_index DESC
-            LIMIT 1
+            ORDER BY pollution_index DESC, country
             "#,
-            countries_list // Use the joined list for formatting
+            filter_conditions(filter),
+            exclude_unit_conflicts(3)
         );
 
-        // Execute the formatted query, mapping the result to a tuple.
-        let result = sqlx::query_as::<_, (String, f64, Option<f64>, Option<f64>)>(&query) // Use the formatted query string
-            // No .bind() needed here as parameters are formatted into the string
-            .fetch_optional(&self.pool) // Use fetch_optional as there might be no data
+        let rows = sqlx::query_as::<_, (String, f64, Option<f64>, Option<f64>)>(&query)
+            .bind(&countries)
+            .bind(days as i32)
+            .bind(&conflict_countries)
+            .bind(&conflict_parameters)
+            .fetch_all(&self.pool)
             .await
             .map_err(|e| {
-                error!("Failed to query most polluted country: {}", e);
+                error!("Failed to query pollution ranking: {}", e);
                 AppError::Db(e.into())
             })?;
 
-        match result {
-            Some((country, pollution_index, pm25_avg, pm10_avg)) => {
-                info!(
-                    "Most polluted country determined: {} with index: {}",
-                    country, pollution_index
-                );
-                Ok(PollutionRanking {
+        let excluded = self
+            .count_excluded_days(&countries, &["pm25", "pm10"], days as i32, filter)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(country, pollution_index, pm25_avg, pm10_avg)| {
+                let excluded_days = excluded.get(&country).copied().unwrap_or(0);
+                let unit_conflicts = conflicts
+                    .iter()
+                    .filter(|c| c.country == country)
+                    .cloned()
+                    .collect();
+                PollutionRanking {
                     country,
                     pollution_index,
                     pm25_avg,
                     pm10_avg,
-                })
-            },
-            None => {
-                // If no data found for any country in the list within the time frame.
-                let default_country = countries.first().map_or("Unknown", |c| *c);
-                error!(
-                    "No recent pollution data (PM2.5/PM10) found for the specified countries: {:?}",
-                    countries
-                );
-                // Return a default ranking for the first country in the list (or "Unknown").
-                Ok(PollutionRanking::new(default_country))
-            },
-        }
+                    excluded_days,
+                    unit_conflicts,
+                }
+            })
+            .collect())
     }
 
     /// Calculates the 5-day average air quality for a specific country.
     ///
-    /// Averages values for PM2.5, PM10, O3, NO2, SO2, and CO from the last 5 days.
+    /// Averages the normalised values of every parameter measured in the country during the
+    /// last 5 days. Parameters are ordered by their catalogue ID; parameters missing from the
+    /// catalogue are listed last, by name.
     ///
     /// # Arguments
     ///
@@ -525,101 +909,184 @@ impl Database {
     ///
     /// # Errors
     ///
-    /// Returns `AppError::Db` if the query fails. Returns default `CountryAirQuality`
-    /// with zero counts and None averages if no data is found for the country in the last 5 days.
-    pub async fn get_average_air_quality(&self, country: &str) -> Result<CountryAirQuality> {
-        info!("Calculating 5-day average air quality for {}", country);
+    /// Returns `AppError::Db` if the query fails. Returns a `CountryAirQuality` with a zero
+    /// count and no averages if no data is found for the country in the last 5 days.
+    pub async fn get_average_air_quality(
+        &self,
+        country: &str,
+        filter: &MeasurementFilter,
+    ) -> Result<CountryAirQuality> {
+        self.get_average_air_quality_over(country, DEFAULT_AVERAGE_DAYS, filter)
+            .await
+    }
+
+    /// Calculates the average air quality for a specific country over the last `days` days.
+    ///
+    /// Parameters whose values cannot be normalised to a single unit are left out of the
+    /// averages and listed in `unit_conflicts`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_average_air_quality_over(
+        &self,
+        country: &str,
+        days: i64,
+        filter: &MeasurementFilter,
+    ) -> Result<CountryAirQuality> {
+        let _timer = metrics().query_timer("get_average_air_quality_over");
+        info!(
+            "Calculating {}-day average air quality for {}",
+            days, country
+        );
+        let unit_conflicts = self
+            .find_unit_conflicts(&[country.to_string()], &[], days as i32, filter)
+            .await?;
+        let (conflict_countries, conflict_parameters) = unit_conflict_binds(&unit_conflicts);
 
         // SQL Query Explanation:
-        // Uses conditional aggregation (AVG(CASE...)) to calculate the average for each
-        // parameter separately within a single query, filtered by country and the last 5 days.
-        // COUNT(*) gets the total number of measurements included in the averages.
-        let query = r#"
+        // Groups the measurements of the last `days` days per parameter and joins the parameter
+        // catalogue for display names. COUNT(*) gets the number of measurements per parameter.
+        let query = format!(
+            r#"
         SELECT
-            country,
-            AVG(CASE WHEN parameter_name = 'pm25' THEN value_avg::DOUBLE PRECISION ELSE NULL END) as avg_pm25,
-            AVG(CASE WHEN parameter_name = 'pm10' THEN value_avg::DOUBLE PRECISION ELSE NULL END) as avg_pm10,
-            AVG(CASE WHEN parameter_name = 'o3' THEN value_avg::DOUBLE PRECISION ELSE NULL END) as avg_o3,
-            AVG(CASE WHEN parameter_name = 'no2' THEN value_avg::DOUBLE PRECISION ELSE NULL END) as avg_no2,
-            AVG(CASE WHEN parameter_name = 'so2' THEN value_avg::DOUBLE PRECISION ELSE NULL END) as avg_so2,
-            AVG(CASE WHEN parameter_name = 'co' THEN value_avg::DOUBLE PRECISION ELSE NULL END) as avg_co,
+            measurements.parameter_name,
+            parameters.display_name,
+            MAX(measurements.unit_normalized) as unit,
+            AVG(measurements.value_normalized::DOUBLE PRECISION) as value,
             COUNT(*) as measurement_count
         FROM measurements
+        LEFT JOIN parameters ON parameters.name = measurements.parameter_name
         WHERE
-            country = $1 -- Use binding for country parameter
-            AND date_utc > NOW() - INTERVAL '5 days' -- Hardcoded 5-day interval
-        GROUP BY country
-        "#;
+            measurements.country = $1 -- Use binding for country parameter
+            AND measurements.date_utc > NOW() - make_interval(days => $2)
+            {}
+            {}
+        GROUP BY measurements.parameter_name, parameters.id, parameters.display_name
+        ORDER BY parameters.id NULLS LAST, measurements.parameter_name
+        "#,
+            filter_conditions(filter),
+            exclude_unit_conflicts(3)
+        );
 
         // Execute the query, binding the country parameter.
-        let result = sqlx::query_as::<
+        let rows = sqlx::query_as::<
             _,
             (
-                String,      // country
-                Option<f64>, // avg_pm25
-                Option<f64>, // avg_pm10
-                Option<f64>, // avg_o3
-                Option<f64>, // avg_no2
-                Option<f64>, // avg_so2
-                Option<f64>, // avg_co
-                i64,         // measurement_count
+                String,         // parameter_name
+                Option<String>, // display_name
+                Option<String>, // unit
+                Option<f64>,    // value
+                i64,            // measurement_count
             ),
-        >(query)
+        >(&query)
         .bind(country)
-        .fetch_optional(&self.pool) // Use fetch_optional as there might be no data
+        .bind(days as i32)
+        .bind(&conflict_countries)
+        .bind(&conflict_parameters)
+        .fetch_all(&self.pool)
         .await
         .map_err(|e| {
             error!("Failed to query average air quality for {}: {}", country, e);
             AppError::Db(e.into())
         })?;
 
-        match result {
-            Some((
-                country_name, // Renamed to avoid conflict with input `country`
-                avg_pm25,
-                avg_pm10,
-                avg_o3,
-                avg_no2,
-                avg_so2,
-                avg_co,
+        let excluded_days = self
+            .count_excluded_days(&[country.to_string()], &[], days as i32, filter)
+            .await?
+            .get(country)
+            .copied()
+            .unwrap_or(0);
+
+        let measurement_count = rows.iter().map(|row| row.4).sum();
+        if rows.is_empty() {
+            info!("No recent air quality data found for {}", country);
+        } else {
+            info!(
+                "Found {}-day average air quality data for {} ({} measurements, {} parameters)",
+                days,
+                country,
                 measurement_count,
-            )) => {
-                info!(
-                    "Found 5-day average air quality data for {} ({} measurements)",
-                    country_name, measurement_count
-                );
-                Ok(CountryAirQuality {
-                    country: country_name,
-                    avg_pm25,
-                    avg_pm10,
-                    avg_o3,
-                    avg_no2,
-                    avg_so2,
-                    avg_co,
-                    measurement_count,
-                })
-            },
-            None => {
-                // If no measurements found for the country in the last 5 days.
-                info!("No recent air quality data found for {}", country);
-                Ok(CountryAirQuality {
-                    country: country.to_string(),
-                    avg_pm25: None,
-                    avg_pm10: None,
-                    avg_o3: None,
-                    avg_no2: None,
-                    avg_so2: None,
-                    avg_co: None,
-                    measurement_count: 0,
+                rows.len()
+            );
+        }
+        Ok(CountryAirQuality {
+            country: country.to_string(),
+            averages: rows
+                .into_iter()
+                .map(|(parameter, display_name, unit, value, _)| ParameterValue {
+                    parameter,
+                    display_name,
+                    unit,
+                    value,
                 })
-            },
+                .collect(),
+            measurement_count,
+            excluded_days,
+            unit_conflicts,
+        })
+    }
+
+    /// Counts, per country, the sensor days within the last `days` days that are excluded
+    /// by the given `MeasurementFilter` (flagged rows and days below the completeness threshold).
+    ///
+    /// # Arguments
+    ///
+    /// * `countries` - Country codes to count for.
+    /// * `parameters` - Parameter names to restrict the count to; an empty slice counts all parameters.
+    /// * `days` - Size of the time window in days, matching the window of the calling query.
+    /// * `filter` - The data quality filter applied by the calling query.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn count_excluded_days(
+        &self,
+        countries: &[String],
+        parameters: &[&str],
+        days: i32,
+        filter: &MeasurementFilter,
+    ) -> Result<HashMap<String, i64>> {
+        let _timer = metrics().query_timer("count_excluded_days");
+        let conditions = filter_conditions(filter);
+        if conditions.is_empty() {
+            return Ok(HashMap::new());
         }
+        let parameters: Vec<String> = parameters.iter().map(|p| p.to_string()).collect();
+        // Rows matching the base criteria but failing at least one of the filter conditions.
+        let query = format!(
+            r#"
+            SELECT country, COUNT(*) as excluded
+            FROM measurements
+            WHERE
+                country = ANY($1)
+                AND (cardinality($2::TEXT[]) = 0 OR parameter_name = ANY($2))
+                AND date_utc > NOW() - make_interval(days => $3)
+                AND NOT (TRUE {})
+            GROUP BY country
+            "#,
+            conditions
+        );
+
+        let rows = sqlx::query_as::<_, (String, i64)>(&query)
+            .bind(countries)
+            .bind(&parameters)
+            .bind(days)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to count excluded measurement days: {}", e);
+                AppError::Db(e.into())
+            })?;
+        debug!("Excluded measurement days per country: {:?}", rows);
+        Ok(rows.into_iter().collect())
     }
 
     /// Gets the latest measurement for each parameter, grouped by locality (using the `city` column), for a specific country.
     ///
     /// Uses `DISTINCT ON (city, parameter_name)` to efficiently find the latest record per locality/parameter combination,
-    /// then pivots the data using conditional aggregation (`MAX(CASE...)`) to structure the result into `CityLatestMeasurements`.
+    /// then groups the rows per locality into `CityLatestMeasurements`. Every measured parameter is included,
+    /// ordered by its catalogue ID.
     ///
     /// # Arguments
     ///
@@ -629,66 +1096,282 @@ impl Database {
     ///
     /// Returns `AppError::Db` if the query fails. Returns an empty Vec if no data is found.
     pub async fn get_latest_measurements_by_locality(
-        // Renamed function
         &self,
         country: &str,
+        filter: &MeasurementFilter,
     ) -> Result<Vec<CityLatestMeasurements>> {
-        // Keep return type for now
+        let _timer = metrics().query_timer("get_latest_measurements_by_locality");
         info!("Fetching latest measurements by city for {}", country);
+        self.latest_measurements_by_locality(country, filter, None)
+            .await
+    }
+
+    /// Gets one page of `get_latest_measurements_by_locality`: the localities are ordered by
+    /// name and `limit` of them are returned from `offset` on, together with the number of
+    /// localities with measurements in the country.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if a query fails.
+    pub async fn get_latest_measurements_by_locality_page(
+        &self,
+        country: &str,
+        filter: &MeasurementFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<CityLatestMeasurements>, i64)> {
+        let _timer = metrics().query_timer("get_latest_measurements_by_locality_page");
+        info!(
+            "Fetching latest measurements by city for {} (limit {}, offset {})",
+            country, limit, offset
+        );
+        let query = format!(
+            r#"
+            SELECT COUNT(DISTINCT city)
+            FROM measurements
+            WHERE country = $1 AND city IS NOT NULL
+                {}
+            "#,
+            filter_conditions(filter)
+        );
+        let total: i64 = sqlx::query_scalar(&query)
+            .bind(country)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to count localities of {}: {}", country, e);
+                AppError::Db(e.into())
+            })?;
+        let localities = self
+            .latest_measurements_by_locality(country, filter, Some((limit, offset)))
+            .await?;
+        Ok((localities, total))
+    }
+
+    /// Runs the query of `get_latest_measurements_by_locality`, restricted to the `(limit,
+    /// offset)` page of the localities ordered by name if `page` is given.
+    async fn latest_measurements_by_locality(
+        &self,
+        country: &str,
+        filter: &MeasurementFilter,
+        page: Option<(i64, i64)>,
+    ) -> Result<Vec<CityLatestMeasurements>> {
+        let conditions = filter_conditions(filter);
+        // The page of localities, ordered like the result rows.
+        let page_condition = match page {
+            Some(_) => format!(
+                "AND city IN (
+                    SELECT DISTINCT city FROM measurements
+                    WHERE country = $1 AND city IS NOT NULL {conditions}
+                    ORDER BY city LIMIT $2 OFFSET $3
+                )"
+            ),
+            None => String::new(),
+        };
 
         // SQL Query Explanation:
-        // 1. CTE `latest_city_param`: Uses `DISTINCT ON (city, parameter_name)` ordered by `date_utc DESC`
+        // 1. CTE `latest_locality_param`: Uses `DISTINCT ON (city, parameter_name)` ordered by `date_utc DESC`
         //    to select only the single latest row for each unique combination of city and parameter
         //    within the specified country.
-        // 2. Main Query: Groups the results from the CTE by city. Uses `MAX(CASE...)` to pivot
-        //    the parameter values into separate columns (pm25, pm10, etc.). `MAX(date_utc)` finds the
-        //    most recent update timestamp among all parameters for that city.
-        let query = r#"
+        // 2. Main Query: Joins the parameter catalogue for display names and orders the rows per
+        //    city in catalogue order, so they can be grouped per city without re-sorting.
+        let query = format!(
+            r#"
         -- Fetch latest measurements grouped by city/locality (using the 'city' column populated from 'locality')
         WITH latest_locality_param AS (
-            SELECT DISTINCT ON (city, parameter_name) -- Still group by 'city' column
-                city, -- Select 'city' column
+            SELECT DISTINCT ON (city, parameter_name)
+                city,
                 parameter_name,
-                value_avg,
+                unit_normalized,
+                value_normalized,
                 date_utc
             FROM measurements
             WHERE country = $1 AND city IS NOT NULL -- Filter by country, ignore null cities
-            ORDER BY city, parameter_name, date_utc DESC -- Order by city
+                {conditions}
+                {page_condition}
+            ORDER BY city, parameter_name, date_utc DESC
         )
         SELECT
-            city, -- Select 'city' column (which represents locality)
-            -- Pivot parameter values into columns
-            MAX(CASE WHEN parameter_name = 'pm25' THEN value_avg ELSE NULL END) as pm25,
-            MAX(CASE WHEN parameter_name = 'pm10' THEN value_avg ELSE NULL END) as pm10,
-            MAX(CASE WHEN parameter_name = 'o3' THEN value_avg ELSE NULL END) as o3,
-            MAX(CASE WHEN parameter_name = 'no2' THEN value_avg ELSE NULL END) as no2,
-            MAX(CASE WHEN parameter_name = 'so2' THEN value_avg ELSE NULL END) as so2,
-            MAX(CASE WHEN parameter_name = 'co' THEN value_avg ELSE NULL END) as co,
-            -- Find the overall latest update time for the city/locality across all parameters
-            MAX(date_utc) as last_updated
-        FROM latest_locality_param
-        GROUP BY city -- Group by 'city' column
-        ORDER BY city -- Order results alphabetically by city/locality name
-        "#;
-
-        let results = sqlx::query_as::<_, CityLatestMeasurements>(query)
+            latest.city,
+            latest.parameter_name,
+            parameters.display_name,
+            latest.unit_normalized,
+            latest.value_normalized::DOUBLE PRECISION,
+            latest.date_utc
+        FROM latest_locality_param latest
+        LEFT JOIN parameters ON parameters.name = latest.parameter_name
+        ORDER BY latest.city, parameters.id NULLS LAST, latest.parameter_name
+        "#
+        );
+
+        let mut rows = sqlx::query_as::<
+            _,
+            (
+                String,         // city
+                String,         // parameter_name
+                Option<String>, // display_name
+                Option<String>, // unit_normalized
+                Option<f64>,    // value
+                DateTime<Utc>,  // date_utc
+            ),
+        >(&query)
+        .bind(country);
+        if let Some((limit, offset)) = page {
+            rows = rows.bind(limit).bind(offset);
+        }
+        let rows = rows.fetch_all(&self.pool).await.map_err(|e| {
+            error!(
+                "Failed to fetch latest measurements by city for {}: {}",
+                country, e
+            );
+            AppError::Db(e.into())
+        })?;
+
+        // Rows are ordered by city, so consecutive rows belong to the same locality.
+        let mut results: Vec<CityLatestMeasurements> = Vec::new();
+        for (city, parameter, display_name, unit, value, date_utc) in rows {
+            let value = ParameterValue {
+                parameter,
+                display_name,
+                unit,
+                value,
+            };
+            match results.last_mut() {
+                Some(current) if current.locality == city => {
+                    current.last_updated = current.last_updated.max(date_utc);
+                    current.values.push(value);
+                },
+                _ => results.push(CityLatestMeasurements {
+                    locality: city,
+                    values: vec![value],
+                    last_updated: date_utc,
+                }),
+            }
+        }
+
+        info!(
+            "Retrieved latest measurements for {} cities in {}",
+            results.len(),
+            country
+        );
+        Ok(results)
+    }
+
+    /// Gets the daily average of a parameter across all sensors of a country over the last `days` days.
+    ///
+    /// Each returned point is the mean of the daily sensor averages for that day, ordered by date.
+    ///
+    /// # Arguments
+    ///
+    /// * `country` - The 2-letter country code.
+    /// * `parameter` - The parameter name (e.g., "pm25").
+    /// * `days` - How many days back from now to include.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails. Returns an empty Vec if no data is found.
+    pub async fn get_daily_average_series(
+        &self,
+        country: &str,
+        parameter: &str,
+        days: i64,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<DailyAverage>> {
+        let _timer = metrics().query_timer("get_daily_average_series");
+        info!(
+            "Fetching daily {} series for {} over the last {} days",
+            parameter, country, days
+        );
+        self.ensure_compatible_units(&[country.to_string()], &[parameter], days as i32, filter)
+            .await?;
+        let query = format!(
+            r#"
+        SELECT
+            date_trunc('day', date_utc) as day,
+            AVG(value_normalized::DOUBLE PRECISION) as value,
+            COUNT(DISTINCT sensor_id) as sensor_count
+        FROM measurements
+        WHERE
+            country = $1
+            AND parameter_name = $2
+            AND value_normalized IS NOT NULL
+            AND date_utc > NOW() - make_interval(days => $3)
+            {}
+        GROUP BY day
+        ORDER BY day
+        "#,
+            filter_conditions(filter)
+        );
+
+        let rows = sqlx::query_as::<_, (DateTime<Utc>, f64, i64)>(&query)
             .bind(country)
-            .fetch_all(&self.pool) // Fetch all resulting rows
+            .bind(parameter)
+            .bind(days as i32)
+            .fetch_all(&self.pool)
             .await
             .map_err(|e| {
                 error!(
-                    "Failed to fetch latest measurements by city for {}: {}",
-                    country, e
+                    "Failed to fetch daily {} series for {}: {}",
+                    parameter, country, e
                 );
                 AppError::Db(e.into())
             })?;
 
-        info!(
-            "Retrieved latest measurements for {} cities in {}",
-            results.len(),
-            country
+        Ok(rows
+            .into_iter()
+            .map(|(day, value, sensor_count)| DailyAverage {
+                day,
+                value,
+                sensor_count,
+            })
+            .collect())
+    }
+
+    /// Gets every stored daily sensor average of a parameter for a country over the last `days` days.
+    ///
+    /// Unlike `get_daily_average_series`, values are not aggregated across sensors, which makes
+    /// the result suitable for distribution charts (histograms).
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_daily_values(
+        &self,
+        country: &str,
+        parameter: &str,
+        days: i64,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<f64>> {
+        let _timer = metrics().query_timer("get_daily_values");
+        self.ensure_compatible_units(&[country.to_string()], &[parameter], days as i32, filter)
+            .await?;
+        let query = format!(
+            r#"
+        SELECT value_normalized::DOUBLE PRECISION
+        FROM measurements
+        WHERE
+            country = $1
+            AND parameter_name = $2
+            AND value_normalized IS NOT NULL
+            AND date_utc > NOW() - make_interval(days => $3)
+            {}
+        "#,
+            filter_conditions(filter)
         );
-        Ok(results)
+
+        sqlx::query_scalar::<_, f64>(&query)
+            .bind(country)
+            .bind(parameter)
+            .bind(days as i32)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!(
+                    "Failed to fetch daily {} values for {}: {}",
+                    parameter, country, e
+                );
+                AppError::Db(e.into())
+            })
     }
 
     /// Checks if the `measurements` table exists in the database schema.
@@ -699,6 +1382,7 @@ impl Database {
     ///
     /// Returns `AppError::Db` if the query to `information_schema.tables` fails.
     pub async fn is_schema_initialized(&self) -> Result<bool> {
+        let _timer = metrics().query_timer("is_schema_initialized");
         debug!("Checking if database schema is initialized...");
         let query = "SELECT EXISTS (SELECT FROM information_schema.tables WHERE table_schema = 'public' AND table_name = 'measurements')";
         let result = sqlx::query(query)
@@ -724,6 +1408,7 @@ impl Database {
     ///
     /// Returns `AppError::Db` if any underlying database query fails.
     pub async fn has_data_imported(&self) -> Result<bool> {
+        let _timer = metrics().query_timer("has_data_imported");
         debug!("Checking if data has been imported...");
         // Ensure schema exists before checking for data.
         if !self.is_schema_initialized().await? {
@@ -745,6 +1430,25 @@ impl Database {
     }
 }
 
+/// Builds the additional `WHERE` conditions (each prefixed with `AND`) that apply a
+/// `MeasurementFilter` to queries reading from the `measurements` table.
+pub(super) fn filter_conditions(filter: &MeasurementFilter) -> String {
+    let mut conditions = String::new();
+    if filter.exclude_flagged {
+        conditions.push_str(
+            " AND NOT EXISTS (SELECT 1 FROM measurement_flags f WHERE f.measurement_id = measurements.id)",
+        );
+    }
+    if let Some(min) = filter.min_completeness.filter(|m| m.is_finite()) {
+        // Days without coverage information are kept rather than silently dropped.
+        conditions.push_str(&format!(
+            " AND (percent_complete IS NULL OR percent_complete >= {})",
+            min
+        ));
+    }
+    conditions
+}
+
 // --- Integration Tests ---
 // These tests interact with a real PostgreSQL database.
 // They are gated by the `integration-tests` feature flag.
@@ -755,7 +1459,7 @@ impl Database {
 mod tests {
     use super::*; // Import items from parent module (Database, etc.)
                   // Import DbMeasurement instead of Measurement and Dates
-    use crate::models::DbMeasurement;
+    use crate::models::{parameter_value, DbMeasurement};
     use chrono::{Duration, Utc};
     use num_traits::FromPrimitive; // Required for Decimal::from_f64
     use rand::Rng; // For generating random IDs
@@ -772,46 +1476,24 @@ mod tests {
         count: Option<i32>,
         days_ago: i64,
     ) -> DbMeasurement {
-        let timestamp = Utc::now() - Duration::days(days_ago);
         let mut rng = rand::thread_rng();
         let location_id: i64 = rng.gen_range(1000..10000);
         let sensor_id: i64 = location_id * 10 + rng.gen_range(0..10);
-        let parameter_id: i32 = match parameter_name {
-            "pm25" => 1,
-            "pm10" => 2,
-            "no2" => 3,
-            "o3" => 4,
-            "so2" => 5,
-            "co" => 6,
-            _ => 0,
-        };
         let to_decimal_opt =
             |val: Option<f64>| -> Option<Decimal> { val.and_then(Decimal::from_f64) };
-
+        let timestamp = Utc::now() - Duration::days(days_ago);
         DbMeasurement {
-            id: None,
             location_id,
-            sensor_id,                                    // Now i64
-            sensor_name: format!("Sensor {}", sensor_id), // Added
             location_name: format!("Test Location {}", country),
-            parameter_id,
-            parameter_name: parameter_name.to_string(),
-            parameter_display_name: Some(parameter_name.to_uppercase()), // Added
-            value_avg: Some(Decimal::from_f64(avg_value).unwrap_or(Decimal::ZERO)), // Wrap in Some()
-            value_min: to_decimal_opt(min_value),                                   // Use helper
-            value_max: to_decimal_opt(max_value),                                   // Use helper
-            measurement_count: count,                                               // Use parameter
-            unit: "µg/m³".to_string(),
-            date_utc: timestamp,
-            date_local: timestamp.to_rfc3339(),
-            country: country.to_string(),
+            parameter_display_name: Some(parameter_name.to_uppercase()),
+            value_min: to_decimal_opt(min_value),
+            value_max: to_decimal_opt(max_value),
+            value_median: Decimal::from_f64(avg_value),
+            measurement_count: count,
+            percent_complete: count.map(|c| c as f64 / 24.0 * 100.0),
             city: Some(format!("Test City {}", country)),
-            latitude: Some(52.0),
-            longitude: Some(5.0),
-            is_mobile: false,
-            is_monitor: true,
-            owner_name: "Test Owner".to_string(),
-            provider_name: "Test Provider".to_string(),
+            ..DbMeasurement::test(sensor_id, country, parameter_name, timestamp)
+                .with_value(avg_value)
         }
     }
 
@@ -865,11 +1547,9 @@ mod tests {
 
         // Verify indexes exist using pg_indexes
         let indexes = [
-            "idx_measurements_country",
-            "idx_measurements_parameter_name", // Updated index name
+            "idx_measurements_country_parameter_date",
+            "idx_measurements_parameter_date",
             "idx_measurements_date_utc",
-            "idx_measurements_sensor_id", // Added check for sensor_id index
-            "idx_measurements_parameter_id", // Added check for parameter_id index
         ];
         for index_name in indexes {
             let index_exists = sqlx::query_scalar::<_, bool>(
@@ -888,17 +1568,16 @@ mod tests {
         Ok(())
     }
 
-    /// Tests the `insert_measurements` function correctly inserts data.
-
     /// Tests the `get_most_polluted_country` function logic.
     #[sqlx::test]
     async fn test_get_most_polluted_country(pool: PgPool) -> Result<()> {
         info!("Running integration test: test_get_most_polluted_country");
         insert_test_data(&pool).await?; // Insert standard test data
         let db = Database { pool };
+        let filter = MeasurementFilter::default();
 
         let countries = ["NL", "DE", "FR", "GR", "ES", "PK"];
-        let result = db.get_most_polluted_country(&countries).await?;
+        let result = db.get_most_polluted_country(&countries, &filter).await?;
 
         // Expected calculation based on test data (pm25*1.5 + pm10):
         // PK: (50 * 1.5) + 80 = 75 + 80 = 155
@@ -932,7 +1611,7 @@ mod tests {
 
         // Test case with no recent data (only FR has old data)
         // The query now uses parameter_name, but the logic remains the same.
-        let result_fr = db.get_most_polluted_country(&["FR"]).await?;
+        let result_fr = db.get_most_polluted_country(&["FR"], &filter).await?;
         assert_eq!(
             result_fr.country, "FR",
             "Country should default to FR when no data"
@@ -955,39 +1634,85 @@ mod tests {
     // Note: The underlying query was already updated in a previous step to use parameter_name.
     // This diff mainly verifies the assertions remain correct.
 
+    /// Tests that `get_pollution_ranking` orders countries by descending pollution index.
+    #[sqlx::test]
+    async fn test_get_pollution_ranking(pool: PgPool) -> Result<()> {
+        insert_test_data(&pool).await?;
+        let db = Database { pool };
+        let filter = MeasurementFilter::default();
+
+        let countries = ["NL", "DE", "FR", "GR", "ES", "PK"];
+        let ranking = db.get_pollution_ranking(&countries, &filter).await?;
+
+        // FR only has data older than 7 days and is therefore omitted.
+        let order: Vec<&str> = ranking.iter().map(|r| r.country.as_str()).collect();
+        assert_eq!(order, vec!["PK", "DE", "NL", "GR", "ES"]);
+        assert!((ranking[1].pollution_index - 55.0).abs() < 1e-6);
+        Ok(())
+    }
+
+    /// Tests that `get_daily_average_series` averages sensors per day and respects the window.
+    #[sqlx::test]
+    async fn test_get_daily_average_series(pool: PgPool) -> Result<()> {
+        insert_test_data(&pool).await?;
+        let db = Database { pool };
+        let filter = MeasurementFilter::default();
+        let extra = vec![
+            create_test_db_measurement("NL", "pm25", 25.0, None, None, None, 1),
+            create_test_db_measurement("NL", "pm25", 9.0, None, None, None, 3),
+            create_test_db_measurement("NL", "pm25", 99.0, None, None, None, 40),
+        ];
+        db.insert_measurements(&extra).await?;
+
+        let series = db
+            .get_daily_average_series("NL", "pm25", 30, &filter)
+            .await?;
+        assert_eq!(series.len(), 2, "Only days within the window are returned");
+        assert!((series[0].value - 9.0).abs() < 1e-6);
+        assert!(
+            (series[1].value - 20.0).abs() < 1e-6,
+            "15 and 25 average to 20"
+        );
+        assert_eq!(series[1].sensor_count, 2);
+
+        let values = db.get_daily_values("NL", "pm25", 30, &filter).await?;
+        assert_eq!(values.len(), 3);
+        Ok(())
+    }
+
     /// Tests the `get_average_air_quality` function logic over a 5-day period.
     #[sqlx::test]
     async fn test_get_average_air_quality(pool: PgPool) -> Result<()> {
         info!("Running integration test: test_get_average_air_quality");
         insert_test_data(&pool).await?;
         let db = Database { pool };
+        let filter = MeasurementFilter::default();
 
         // Test for NL (should have 3 recent measurements: pm25, pm10, no2)
-        let result_nl = db.get_average_air_quality("NL").await?;
+        let result_nl = db.get_average_air_quality("NL", &filter).await?;
         assert_eq!(result_nl.country, "NL");
         assert_eq!(
             result_nl.measurement_count, 3,
             "NL should have 3 measurements in last 5 days"
         );
-        assert!(result_nl.avg_pm25.is_some());
-        assert!((result_nl.avg_pm25.unwrap() - 15.0).abs() < 1e-6);
-        assert!(result_nl.avg_pm10.is_some());
-        assert!((result_nl.avg_pm10.unwrap() - 25.0).abs() < 1e-6);
-        assert!(result_nl.avg_no2.is_some());
-        assert!((result_nl.avg_no2.unwrap() - 30.0).abs() < 1e-6);
-        assert!(result_nl.avg_o3.is_none(), "NL should have no O3 data"); // No O3 data inserted
+        let average = |parameter: &str| parameter_value(&result_nl.averages, parameter);
+        assert_eq!(result_nl.averages.len(), 3);
+        assert!((average("pm25").unwrap() - 15.0).abs() < 1e-6);
+        assert!((average("pm10").unwrap() - 25.0).abs() < 1e-6);
+        assert!((average("no2").unwrap() - 30.0).abs() < 1e-6);
+        assert!(average("o3").is_none(), "NL should have no O3 data"); // No O3 data inserted
 
         // Test for FR (only old data exists, > 5 days ago)
-        let result_fr = db.get_average_air_quality("FR").await?;
+        let result_fr = db.get_average_air_quality("FR", &filter).await?;
         assert_eq!(result_fr.country, "FR");
         assert_eq!(
             result_fr.measurement_count, 0,
             "FR should have 0 measurements in last 5 days"
         );
-        assert!(result_fr.avg_pm25.is_none());
+        assert!(result_fr.averages.is_empty());
 
         // Test for a country with no data at all
-        let result_xx = db.get_average_air_quality("XX").await?; // Assuming XX has no data
+        let result_xx = db.get_average_air_quality("XX", &filter).await?; // Assuming XX has no data
         assert_eq!(result_xx.country, "XX");
         assert_eq!(
             result_xx.measurement_count, 0,
@@ -999,6 +1724,35 @@ mod tests {
     // Note: The underlying query was already updated in a previous step to use parameter_name.
     // This diff mainly verifies the assertions remain correct.
 
+    /// Tests that incomplete days are excluded from averages and reported as excluded.
+    #[sqlx::test]
+    async fn test_min_completeness_excludes_incomplete_days(pool: PgPool) -> Result<()> {
+        insert_test_data(&pool).await?;
+        let db = Database { pool };
+        // 6 of 24 hours observed (25% complete) with an extreme value
+        let incomplete =
+            create_test_db_measurement("NL", "pm25", 500.0, Some(1.0), Some(900.0), Some(6), 1);
+        db.insert_measurements(&[incomplete]).await?;
+
+        let filter = MeasurementFilter::default();
+        let result_nl = db.get_average_air_quality("NL", &filter).await?;
+        assert_eq!(result_nl.measurement_count, 3);
+        assert!((parameter_value(&result_nl.averages, "pm25").unwrap() - 15.0).abs() < 1e-6);
+        assert_eq!(result_nl.excluded_days, 1);
+
+        let ranking = db.get_pollution_ranking(&["NL"], &filter).await?;
+        assert_eq!(ranking[0].excluded_days, 1);
+
+        let unfiltered = MeasurementFilter {
+            exclude_flagged: false,
+            min_completeness: None,
+        };
+        let result_all = db.get_average_air_quality("NL", &unfiltered).await?;
+        assert_eq!(result_all.measurement_count, 4);
+        assert_eq!(result_all.excluded_days, 0);
+        Ok(())
+    }
+
     /// Tests the `get_latest_measurements_by_city` function logic.
     #[sqlx::test]
     async fn test_get_latest_measurements_by_city(pool: PgPool) -> Result<()> {
@@ -1007,6 +1761,7 @@ mod tests {
 
         // Add slightly older data for NL to test DISTINCT ON logic
         let db = Database { pool };
+        let filter = MeasurementFilter::default();
         // Use the new helper function
         let older_nl_pm25 =
             create_test_db_measurement("NL", "pm25", 5.0, Some(4.0), Some(6.0), Some(20), 2); // Older PM2.5 value
@@ -1015,7 +1770,9 @@ mod tests {
         db.insert_measurements(&[older_nl_pm25, older_nl_o3])
             .await?;
 
-        let results_nl = db.get_latest_measurements_by_locality("NL").await?; // Use renamed function
+        let results_nl = db
+            .get_latest_measurements_by_locality("NL", &filter)
+            .await?; // Use renamed function
 
         assert_eq!(
             results_nl.len(),
@@ -1026,32 +1783,18 @@ mod tests {
         assert_eq!(nl_locality_data.locality, "Test City NL"); // Use renamed field 'locality'
 
         // Check latest values (should pick the most recent ones from insert_test_data or the added O3)
-        assert!(nl_locality_data.pm25.is_some()); // Use renamed variable
+        let latest = |parameter: &str| parameter_value(&nl_locality_data.values, parameter);
         assert_eq!(
-            nl_locality_data.pm25.unwrap(), // Use renamed variable
-            Decimal::from_f64(15.0).unwrap(),
+            latest("pm25"),
+            Some(15.0),
             "Latest NL PM2.5 mismatch (should be 15.0, not 5.0)"
         );
-        assert!(nl_locality_data.pm10.is_some()); // Use renamed variable
-        assert_eq!(
-            nl_locality_data.pm10.unwrap(), // Use renamed variable
-            Decimal::from_f64(25.0).unwrap(),
-            "Latest NL PM10 mismatch"
-        );
-        assert!(nl_locality_data.no2.is_some()); // Use renamed variable
-        assert_eq!(
-            nl_locality_data.no2.unwrap(), // Use renamed variable
-            Decimal::from_f64(30.0).unwrap(),
-            "Latest NL NO2 mismatch"
-        );
-        assert!(nl_locality_data.o3.is_some()); // Use renamed variable
-        assert_eq!(
-            nl_locality_data.o3.unwrap(), // Use renamed variable
-            Decimal::from_f64(40.0).unwrap(),
-            "Latest NL O3 mismatch"
-        ); // Check the added O3
-        assert!(nl_locality_data.so2.is_none(), "NL SO2 should be None"); // Use renamed variable
-        assert!(nl_locality_data.co.is_none(), "NL CO should be None"); // Use renamed variable
+        assert_eq!(latest("pm10"), Some(25.0), "Latest NL PM10 mismatch");
+        assert_eq!(latest("no2"), Some(30.0), "Latest NL NO2 mismatch");
+        assert_eq!(latest("o3"), Some(40.0), "Latest NL O3 mismatch"); // Check the added O3
+        assert!(latest("so2").is_none(), "NL SO2 should be None");
+        assert!(latest("co").is_none(), "NL CO should be None");
+        assert_eq!(nl_locality_data.values.len(), 4);
 
         // Check last_updated timestamp (should be the timestamp of the most recent measurement overall for the city/locality)
         let one_day_ago = Utc::now() - Duration::days(1);
@@ -1073,6 +1816,40 @@ mod tests {
     // Note: The underlying query was already updated in a previous step to use parameter_name.
     // This diff mainly verifies the assertions remain correct and updates test data creation.
 
+    /// Tests that `get_latest_measurements_by_locality_page` pages over localities, not rows.
+    #[sqlx::test]
+    async fn test_get_latest_measurements_by_locality_page(pool: PgPool) -> Result<()> {
+        let db = Database { pool };
+        db.init_schema().await?;
+        let mut measurements = Vec::new();
+        for (sensor_id, city) in [(1, "Amsterdam"), (2, "Rotterdam"), (3, "Utrecht")] {
+            for parameter in ["pm25", "pm10"] {
+                measurements.push(DbMeasurement {
+                    sensor_id,
+                    city: Some(city.to_string()),
+                    ..create_test_db_measurement("NL", parameter, 10.0, None, None, Some(24), 1)
+                });
+            }
+        }
+        db.insert_measurements(&measurements).await?;
+
+        let filter = MeasurementFilter::default();
+        let (page, total) = db
+            .get_latest_measurements_by_locality_page("NL", &filter, 2, 1)
+            .await?;
+        assert_eq!(total, 3);
+        let localities: Vec<&str> = page.iter().map(|l| l.locality.as_str()).collect();
+        assert_eq!(localities, ["Rotterdam", "Utrecht"]);
+        assert!(page.iter().all(|l| l.values.len() == 2));
+
+        let (page, total) = db
+            .get_latest_measurements_by_locality_page("NL", &filter, 2, 3)
+            .await?;
+        assert_eq!(total, 3);
+        assert!(page.is_empty());
+        Ok(())
+    }
+
     /// Tests the `is_schema_initialized` helper function state changes.
     #[sqlx::test]
     async fn test_is_schema_initialized(pool: PgPool) -> Result<()> {
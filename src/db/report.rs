@@ -0,0 +1,148 @@
+//! Database operations of the HTML report: exceedances of the daily guideline levels.
+
+use super::{filter_conditions, Database};
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{Guideline, GuidelineExceedance, MeasurementFilter};
+use tracing::{error, info};
+
+impl Database {
+    /// Counts, per country and guideline parameter, the daily sensor averages of the last
+    /// `days` days above the guideline level.
+    ///
+    /// Only values normalised to µg/m³ are compared, so values in units without a conversion
+    /// are ignored. Countries or parameters without data are omitted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_guideline_exceedances(
+        &self,
+        countries: &[&str],
+        guidelines: &[Guideline],
+        days: i64,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<GuidelineExceedance>> {
+        let _timer = metrics().query_timer("get_guideline_exceedances");
+        info!(
+            "Counting guideline exceedances over {} days: {:?}",
+            days, countries
+        );
+        let countries: Vec<String> = countries.iter().map(|c| c.to_string()).collect();
+        let parameters: Vec<&str> = guidelines.iter().map(|g| g.parameter).collect();
+        let limits: Vec<f64> = guidelines.iter().map(|g| g.limit).collect();
+        let query = format!(
+            r#"
+            SELECT
+                measurements.country,
+                measurements.parameter_name as parameter,
+                guidelines.level as limit,
+                COUNT(*) FILTER (
+                    WHERE measurements.value_normalized::DOUBLE PRECISION > guidelines.level
+                ) as exceedance_days,
+                COUNT(*) as sensor_days,
+                MAX(measurements.value_normalized::DOUBLE PRECISION) as max_value
+            FROM measurements
+            JOIN UNNEST($2::TEXT[], $3::DOUBLE PRECISION[]) AS guidelines(parameter, level)
+                ON guidelines.parameter = measurements.parameter_name
+            WHERE
+                measurements.country = ANY($1)
+                AND measurements.unit_normalized = 'µg/m³'
+                AND measurements.value_normalized IS NOT NULL
+                AND measurements.date_utc > NOW() - make_interval(days => $4)
+                {}
+            GROUP BY measurements.country, measurements.parameter_name, guidelines.level
+            ORDER BY measurements.country, array_position($2::TEXT[], measurements.parameter_name)
+            "#,
+            filter_conditions(filter)
+        );
+
+        sqlx::query_as::<_, GuidelineExceedance>(&query)
+            .bind(&countries)
+            .bind(&parameters)
+            .bind(&limits)
+            .bind(days as i32)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to count guideline exceedances: {}", e);
+                AppError::Db(e.into())
+            })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::{DbMeasurement, WHO_DAILY_GUIDELINES};
+    use chrono::{Duration, Utc};
+
+    use sqlx::PgPool;
+
+    fn measurement(
+        sensor_id: i64,
+        country: &str,
+        parameter: &str,
+        unit: &str,
+        days_ago: i64,
+        value: f64,
+    ) -> DbMeasurement {
+        DbMeasurement::test(
+            sensor_id,
+            country,
+            parameter,
+            Utc::now() - Duration::days(days_ago),
+        )
+        .with_value_in(value, unit)
+    }
+
+    /// Tests counting the sensor days above the guideline levels per country and parameter.
+    #[sqlx::test]
+    async fn test_guideline_exceedances(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        db.insert_measurements(&[
+            measurement(1, "NL", "pm25", "µg/m³", 1, 20.0),
+            measurement(1, "NL", "pm25", "µg/m³", 2, 10.0),
+            measurement(2, "NL", "pm25", "µg/m³", 1, 40.0),
+            measurement(3, "NL", "pm10", "µg/m³", 1, 30.0),
+            // No guideline for ozone.
+            measurement(4, "NL", "o3", "µg/m³", 1, 300.0),
+            // Outside the window.
+            measurement(1, "NL", "pm25", "µg/m³", 20, 99.0),
+            measurement(5, "DE", "pm25", "µg/m³", 1, 16.0),
+        ])
+        .await
+        .unwrap();
+
+        let exceedances = db
+            .get_guideline_exceedances(
+                &["NL", "DE"],
+                &WHO_DAILY_GUIDELINES,
+                7,
+                &MeasurementFilter::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(exceedances.len(), 3);
+
+        let de = &exceedances[0];
+        assert_eq!((de.country.as_str(), de.parameter.as_str()), ("DE", "pm25"));
+        assert_eq!((de.exceedance_days, de.sensor_days), (1, 1));
+
+        let pm25 = &exceedances[1];
+        assert_eq!(
+            (pm25.country.as_str(), pm25.parameter.as_str()),
+            ("NL", "pm25")
+        );
+        assert_eq!(pm25.limit, 15.0);
+        assert_eq!((pm25.exceedance_days, pm25.sensor_days), (2, 3));
+        assert!((pm25.max_value - 40.0).abs() < 1e-9);
+
+        let pm10 = &exceedances[2];
+        assert_eq!(pm10.parameter, "pm10");
+        assert_eq!((pm10.exceedance_days, pm10.sensor_days), (0, 1));
+        assert_eq!(pm10.exceedance_percent(), 0.0);
+    }
+}
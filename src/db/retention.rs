@@ -0,0 +1,311 @@
+//! Database operations of the `prune` run mode: counting, streaming and deleting the
+//! measurements beyond their retention.
+
+use super::Database;
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{MeasurementRecord, PruneCount, RetentionCutoffs};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use std::sync::LazyLock;
+use tracing::{error, info};
+
+/// Selects the measurements before the cutoff of the first matching retention rule, up to
+/// measurement ID `$4`. Binds the rules' parameters (`$1`), countries (`$2`) and cutoffs (`$3`).
+const PRUNABLE_CONDITIONS: &str = r#"
+    m.id <= $4
+    AND m.date_utc < (SELECT MAX(c) FROM UNNEST($3::timestamptz[]) c)
+    AND m.date_utc < (
+        SELECT r.cutoff
+        FROM UNNEST($1::text[], $2::text[], $3::timestamptz[])
+            WITH ORDINALITY AS r(parameter, country, cutoff, position)
+        WHERE (r.parameter IS NULL OR r.parameter = m.parameter_name)
+          AND (r.country IS NULL OR r.country = m.country)
+        ORDER BY r.position
+        LIMIT 1
+    )
+"#;
+
+/// Selects the prunable measurements for archiving, in the partition order of the export.
+static PRUNABLE_SELECT: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        r#"
+        SELECT
+            id, location_id, sensor_id, location_name, parameter_id, parameter_name,
+            value_avg, value_min, value_max, value_q02, value_q25, value_median, value_q75,
+            value_q98, value_sd, measurement_count, expected_count, percent_complete,
+            percent_coverage, unit, unit_normalized, unit_factor, value_normalized, date_utc,
+            date_local, country, city, latitude, longitude, is_mobile, is_monitor,
+            owner_name, provider_name, source, created_at
+        FROM measurements m
+        WHERE {PRUNABLE_CONDITIONS}
+        ORDER BY country, date_utc, sensor_id
+        "#
+    )
+});
+
+impl Database {
+    /// Returns the highest measurement ID, `None` if nothing is stored. Pruning is limited to
+    /// the rows up to this ID, so rows stored while archiving are never deleted unarchived.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_max_measurement_id(&self) -> Result<Option<i32>> {
+        let _timer = metrics().query_timer("get_max_measurement_id");
+        sqlx::query_scalar::<_, Option<i32>>("SELECT MAX(id) FROM measurements")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to fetch the highest measurement ID: {}", e);
+                AppError::Db(e.into())
+            })
+    }
+
+    /// Counts the measurements beyond their retention per country and parameter.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn count_prunable_measurements(
+        &self,
+        cutoffs: &RetentionCutoffs,
+        max_id: i32,
+    ) -> Result<Vec<PruneCount>> {
+        let _timer = metrics().query_timer("count_prunable_measurements");
+        let sql = format!(
+            r#"
+            SELECT
+                m.country,
+                m.parameter_name,
+                COUNT(*) as rows,
+                MIN(m.date_utc) as first_day,
+                MAX(m.date_utc) as last_day
+            FROM measurements m
+            WHERE {PRUNABLE_CONDITIONS}
+            GROUP BY m.country, m.parameter_name
+            ORDER BY m.country, m.parameter_name
+            "#
+        );
+        sqlx::query_as::<_, PruneCount>(&sql)
+            .bind(&cutoffs.parameters)
+            .bind(&cutoffs.countries)
+            .bind(&cutoffs.cutoffs)
+            .bind(max_id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to count prunable measurements: {}", e);
+                AppError::Db(e.into())
+            })
+    }
+
+    /// Streams the measurements beyond their retention, ordered by country and date like
+    /// `stream_measurements`, for archiving.
+    ///
+    /// # Errors
+    ///
+    /// Each item is `AppError::Db` if reading the row fails.
+    pub fn stream_prunable_measurements<'a>(
+        &'a self,
+        cutoffs: &'a RetentionCutoffs,
+        max_id: i32,
+    ) -> BoxStream<'a, Result<MeasurementRecord>> {
+        sqlx::query_as::<_, MeasurementRecord>(&PRUNABLE_SELECT)
+            .bind(&cutoffs.parameters)
+            .bind(&cutoffs.countries)
+            .bind(&cutoffs.cutoffs)
+            .bind(max_id)
+            .fetch(&self.pool)
+            .map_err(|e| {
+                error!("Failed to stream prunable measurements: {}", e);
+                AppError::Db(e.into())
+            })
+            .boxed()
+    }
+
+    /// Deletes the measurements beyond their retention, with their quality flags, returning
+    /// the number of deleted measurements.
+    ///
+    /// The monthly partitions that end before every cutoff are dropped whole (see
+    /// `drop_measurement_partitions`); the remaining rows are deleted one by one.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if dropping a partition or the delete fails.
+    pub async fn delete_prunable_measurements(
+        &self,
+        cutoffs: &RetentionCutoffs,
+        max_id: i32,
+    ) -> Result<u64> {
+        let dropped = match cutoffs.cutoffs.iter().min() {
+            Some(&before) => self.drop_measurement_partitions(before, max_id).await?,
+            None => 0,
+        };
+        let _timer = metrics().query_timer("delete_prunable_measurements");
+        let sql = format!("DELETE FROM measurements m WHERE {PRUNABLE_CONDITIONS}");
+        let deleted = sqlx::query(&sql)
+            .bind(&cutoffs.parameters)
+            .bind(&cutoffs.countries)
+            .bind(&cutoffs.cutoffs)
+            .bind(max_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to delete prunable measurements: {}", e);
+                AppError::Db(e.into())
+            })?
+            .rows_affected();
+        info!(
+            "Pruned {} measurements ({} in dropped partitions)",
+            dropped + deleted,
+            dropped
+        );
+        Ok(dropped + deleted)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::{DbMeasurement, RetentionPolicy, RetentionRule};
+    use chrono::{Duration, Utc};
+
+    use sqlx::PgPool;
+
+    fn measurement(sensor_id: i64, country: &str, parameter: &str, days_ago: i64) -> DbMeasurement {
+        DbMeasurement::test(
+            sensor_id,
+            country,
+            parameter,
+            Utc::now() - Duration::days(days_ago),
+        )
+    }
+
+    /// Tests that the first matching rule decides the retention, and the count, stream and
+    /// delete of the measurements beyond it.
+    #[sqlx::test]
+    async fn test_prune_measurements(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        assert_eq!(db.get_max_measurement_id().await.unwrap(), None);
+        db.insert_measurements(&[
+            measurement(1, "NL", "pm25", 400),
+            measurement(2, "NL", "o3", 400),
+            measurement(3, "DE", "pm25", 400),
+            measurement(4, "DE", "pm25", 500),
+            measurement(5, "NL", "o3", 5),
+        ])
+        .await
+        .unwrap();
+        let policy = RetentionPolicy {
+            keep_days: 30,
+            rules: vec![RetentionRule {
+                parameter: Some("pm25".to_string()),
+                country: Some("NL".to_string()),
+                keep_days: 1000,
+            }],
+        };
+        let cutoffs = policy.cutoffs(Utc::now().date_naive());
+        let max_id = db.get_max_measurement_id().await.unwrap().unwrap();
+
+        let counts = db
+            .count_prunable_measurements(&cutoffs, max_id)
+            .await
+            .unwrap();
+        let rows: Vec<_> = counts
+            .iter()
+            .map(|c| (c.country.as_str(), c.parameter_name.as_str(), c.rows))
+            .collect();
+        assert_eq!(rows, [("DE", "pm25", 2), ("NL", "o3", 1)]);
+        assert!(counts[0].first_day < counts[0].last_day);
+
+        let streamed: Vec<_> = db
+            .stream_prunable_measurements(&cutoffs, max_id)
+            .map(|record| record.unwrap().sensor_id)
+            .collect()
+            .await;
+        assert_eq!(streamed, [4, 3, 2]);
+
+        // Rows stored after the highest ID was taken are left alone.
+        db.insert_measurements(&[measurement(6, "DE", "pm25", 600)])
+            .await
+            .unwrap();
+        assert_eq!(
+            db.delete_prunable_measurements(&cutoffs, max_id)
+                .await
+                .unwrap(),
+            3
+        );
+        let remaining: Vec<i64> =
+            sqlx::query_scalar("SELECT sensor_id FROM measurements ORDER BY sensor_id")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(remaining, [1, 5, 6]);
+    }
+
+    /// Tests that the partitions past every cutoff are dropped with their flags, except one
+    /// holding rows stored after the highest ID was taken.
+    #[sqlx::test]
+    async fn test_prune_drops_expired_partitions(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        db.insert_measurements(&[
+            measurement(1, "NL", "pm25", 400),
+            measurement(2, "NL", "pm25", 700),
+            measurement(3, "NL", "pm25", 5),
+        ])
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO measurement_flags (measurement_id, sensor_id, date_utc, flag)
+            SELECT id, sensor_id, date_utc, 'spike' FROM measurements WHERE sensor_id = 1
+            "#,
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        let cutoffs = RetentionPolicy {
+            keep_days: 100,
+            rules: Vec::new(),
+        }
+        .cutoffs(Utc::now().date_naive());
+        let max_id = db.get_max_measurement_id().await.unwrap().unwrap();
+        db.insert_measurements(&[measurement(4, "NL", "pm25", 700)])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.delete_prunable_measurements(&cutoffs, max_id)
+                .await
+                .unwrap(),
+            2
+        );
+        let remaining: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT sensor_id, tableoid::regclass::text FROM measurements ORDER BY sensor_id",
+        )
+        .fetch_all(&db.pool)
+        .await
+        .unwrap();
+        let old_month = (Utc::now() - Duration::days(700)).format("measurements_y%Ym%m");
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(remaining[0].0, 3);
+        assert_eq!(remaining[1], (4, old_month.to_string()));
+        let partition: Option<String> = sqlx::query_scalar(&format!(
+            "SELECT to_regclass('{}')::text",
+            (Utc::now() - Duration::days(400)).format("measurements_y%Ym%m")
+        ))
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!(partition, None);
+        let flags: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM measurement_flags")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(flags, 0);
+    }
+}
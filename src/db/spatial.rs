@@ -0,0 +1,209 @@
+//! Database operations backing spatial lookups and interpolation: loading the stored location
+//! coordinates together with recent or daily values of a parameter.
+
+use super::{filter_conditions, Database};
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{LocationValues, MeasurementFilter, PointValue};
+use chrono::NaiveDate;
+use tracing::{error, info};
+
+impl Database {
+    /// Loads every location with known coordinates, together with the latest daily average and
+    /// the mean daily average of `parameter` over the last `days` days.
+    ///
+    /// Locations without recent values of the parameter are included with empty values.
+    /// Values are normalised and the data quality `filter` is applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_location_values(
+        &self,
+        parameter: &str,
+        days: i64,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<LocationValues>> {
+        let _timer = metrics().query_timer("get_location_values");
+        info!(
+            "Fetching location values of {} over the last {} days",
+            parameter, days
+        );
+        let query = format!(
+            r#"
+        WITH recent AS (
+            SELECT location_id, date_utc, unit_normalized, value_normalized::DOUBLE PRECISION as value
+            FROM measurements
+            WHERE
+                parameter_name = $1
+                AND value_normalized IS NOT NULL
+                AND date_utc > NOW() - make_interval(days => $2)
+                {conditions}
+        ),
+        latest AS (
+            SELECT DISTINCT ON (location_id) location_id, date_utc, value
+            FROM recent
+            ORDER BY location_id, date_utc DESC
+        ),
+        summary AS (
+            SELECT location_id, MIN(unit_normalized) as unit, AVG(value) as average, COUNT(*) as days
+            FROM recent
+            GROUP BY location_id
+        )
+        SELECT
+            l.id as location_id,
+            l.name,
+            l.locality,
+            l.country_code as country,
+            l.latitude,
+            l.longitude,
+            summary.unit,
+            latest.value as latest_value,
+            latest.date_utc as latest_date,
+            summary.average as recent_average,
+            COALESCE(summary.days, 0) as days
+        FROM locations l
+        LEFT JOIN latest ON latest.location_id = l.id
+        LEFT JOIN summary ON summary.location_id = l.id
+        WHERE l.latitude IS NOT NULL AND l.longitude IS NOT NULL
+        ORDER BY l.id
+        "#,
+            conditions = filter_conditions(filter)
+        );
+
+        sqlx::query_as::<_, LocationValues>(&query)
+            .bind(parameter)
+            .bind(days as i32)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to fetch location values of {}: {}", parameter, e);
+                AppError::Db(e.into())
+            })
+    }
+
+    /// Loads the daily value of `parameter` at every location with coordinates on `day` (UTC),
+    /// averaging the location's sensors.
+    ///
+    /// Values are normalised and the data quality `filter` is applied. A location reporting in
+    /// several units yields one row per unit.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn get_daily_point_values(
+        &self,
+        parameter: &str,
+        day: NaiveDate,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<PointValue>> {
+        let _timer = metrics().query_timer("get_daily_point_values");
+        info!("Fetching point values of {} on {}", parameter, day);
+        let query = format!(
+            r#"
+        SELECT
+            location_id,
+            AVG(latitude) as latitude,
+            AVG(longitude) as longitude,
+            unit_normalized as unit,
+            AVG(value_normalized::DOUBLE PRECISION) as value
+        FROM measurements
+        WHERE
+            parameter_name = $1
+            AND (date_utc AT TIME ZONE 'UTC')::DATE = $2
+            AND value_normalized IS NOT NULL
+            AND latitude IS NOT NULL
+            AND longitude IS NOT NULL
+            {conditions}
+        GROUP BY location_id, unit_normalized
+        ORDER BY location_id
+        "#,
+            conditions = filter_conditions(filter)
+        );
+
+        sqlx::query_as::<_, PointValue>(&query)
+            .bind(parameter)
+            .bind(day)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!(
+                    "Failed to fetch point values of {} on {}: {}",
+                    parameter, day, e
+                );
+                AppError::Db(e.into())
+            })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::DbMeasurement;
+    use chrono::{Duration, Utc};
+
+    use sqlx::PgPool;
+
+    fn measurement(location_id: i64, days_ago: i64, value: f64) -> DbMeasurement {
+        let date_utc = Utc::now() - Duration::days(days_ago);
+        DbMeasurement {
+            location_id,
+            location_name: format!("Station {}", location_id),
+            ..DbMeasurement::test(location_id * 10, "NL", "pm25", date_utc).with_value(value)
+        }
+    }
+
+    /// Tests that locations are returned with their latest and average values, including
+    /// locations without recent values but excluding locations without coordinates, and that
+    /// daily point values use the coordinates stored with the measurements.
+    #[sqlx::test]
+    async fn test_get_location_values(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        for (id, latitude) in [(1_i64, Some(52.0)), (2, Some(52.1)), (3, None)] {
+            sqlx::query(
+                r#"
+                INSERT INTO locations (id, name, country_code, country_name, timezone, latitude, longitude, is_mobile, is_monitor)
+                VALUES ($1, $2, 'NL', 'Netherlands', 'Europe/Amsterdam', $3, 5.0, false, true)
+                "#,
+            )
+            .bind(id)
+            .bind(format!("Station {}", id))
+            .bind(latitude)
+            .execute(&db.pool)
+            .await
+            .expect("Failed to insert location");
+        }
+        db.insert_measurements(&[
+            measurement(1, 1, 10.0),
+            measurement(1, 2, 20.0),
+            measurement(3, 1, 50.0),
+        ])
+        .await
+        .expect("Failed to insert measurements");
+
+        let values = db
+            .get_location_values("pm25", 7, &MeasurementFilter::default())
+            .await
+            .expect("Query should succeed");
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0].latest_value, Some(10.0));
+        assert_eq!(values[0].recent_average, Some(15.0));
+        assert_eq!(values[0].days, 2);
+        assert_eq!(values[0].unit.as_deref(), Some("µg/m³"));
+        assert_eq!(values[1].location_id, 2);
+        assert_eq!(values[1].latest_value, None);
+        assert_eq!(values[1].days, 0);
+
+        let yesterday = (Utc::now() - Duration::days(1)).date_naive();
+        let points = db
+            .get_daily_point_values("pm25", yesterday, &MeasurementFilter::default())
+            .await
+            .expect("Query should succeed");
+        let ids: Vec<i64> = points.iter().map(|p| p.location_id).collect();
+        assert_eq!(ids, vec![1, 3]);
+        assert_eq!(points[0].value, 10.0);
+        assert_eq!(points[0].latitude, 52.0);
+    }
+}
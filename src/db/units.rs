@@ -0,0 +1,265 @@
+//! Database operations backing unit normalisation: back-filling the normalised value of
+//! stored measurements and guarding the analytic queries against mixed units.
+
+use super::{filter_conditions, Database};
+use crate::error::{AppError, Result};
+use crate::metrics::metrics;
+use crate::models::{describe_unit_conflicts, normalization, MeasurementFilter, UnitConflict};
+use tracing::{error, info, warn};
+
+impl Database {
+    /// Fills `unit_normalized`, `unit_factor` and `value_normalized` for rows stored before
+    /// unit normalisation was introduced.
+    ///
+    /// Conversions are linear, so each distinct `(parameter_name, unit)` pair is updated with a
+    /// single statement. Returns the number of updated rows.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if a query fails.
+    pub async fn normalize_stored_units(&self) -> Result<u64> {
+        let _timer = metrics().query_timer("normalize_stored_units");
+        let pairs = sqlx::query_as::<_, (String, String)>(
+            r#"
+            SELECT DISTINCT parameter_name, unit
+            FROM measurements
+            WHERE unit_normalized IS NULL
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("Failed to load units to normalise: {}", e);
+            AppError::Db(e.into())
+        })?;
+
+        let mut updated = 0;
+        for (parameter, unit) in pairs {
+            let (unit_normalized, factor) = normalization(&parameter, &unit);
+            let result = sqlx::query(
+                r#"
+                UPDATE measurements
+                SET unit_normalized = $3,
+                    unit_factor = $4,
+                    value_normalized = value_avg * $4::NUMERIC
+                WHERE parameter_name = $1 AND unit = $2 AND unit_normalized IS NULL
+                "#,
+            )
+            .bind(&parameter)
+            .bind(&unit)
+            .bind(unit_normalized.as_str())
+            .bind(factor)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!(
+                    "Failed to normalise {} values in {}: {}",
+                    parameter, unit, e
+                );
+                AppError::Db(e.into())
+            })?;
+            updated += result.rows_affected();
+        }
+        if updated > 0 {
+            info!("Normalised units of {} stored measurements", updated);
+        }
+        Ok(updated)
+    }
+
+    /// Finds the parameters whose values could not be normalised to a common unit, per
+    /// country, within the given countries and time window. Rows dropped by `filter` are
+    /// ignored, matching what the calling query aggregates.
+    ///
+    /// # Arguments
+    ///
+    /// * `countries` - Country codes the calling query aggregates over.
+    /// * `parameters` - Parameter names to check; an empty slice checks all parameters.
+    /// * `days` - Size of the time window in days, matching the window of the calling query.
+    /// * `filter` - The data quality filter of the calling query.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Db` if the query fails.
+    pub async fn find_unit_conflicts(
+        &self,
+        countries: &[String],
+        parameters: &[&str],
+        days: i32,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<UnitConflict>> {
+        let _timer = metrics().query_timer("find_unit_conflicts");
+        let parameters: Vec<String> = parameters.iter().map(|p| p.to_string()).collect();
+        let query = format!(
+            r#"
+            SELECT
+                country,
+                parameter_name as parameter,
+                array_agg(DISTINCT COALESCE(unit_normalized, unit) ORDER BY COALESCE(unit_normalized, unit)) as units
+            FROM measurements
+            WHERE
+                country = ANY($1)
+                AND (cardinality($2::TEXT[]) = 0 OR parameter_name = ANY($2))
+                AND date_utc > NOW() - make_interval(days => $3)
+                {}
+            GROUP BY country, parameter_name
+            HAVING COUNT(DISTINCT COALESCE(unit_normalized, unit)) > 1
+            ORDER BY country, parameter_name
+            "#,
+            filter_conditions(filter)
+        );
+        let conflicts = sqlx::query_as::<_, UnitConflict>(&query)
+            .bind(countries)
+            .bind(&parameters)
+            .bind(days)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to check units for aggregation: {}", e);
+                AppError::Db(e.into())
+            })?;
+        if !conflicts.is_empty() {
+            warn!(
+                "Leaving out mixed units: {}",
+                describe_unit_conflicts(&conflicts)
+            );
+        }
+        Ok(conflicts)
+    }
+
+    /// Refuses to aggregate values of a parameter that could not be normalised to a common
+    /// unit. Used by the queries of a single parameter, which have nothing left to return
+    /// without it; queries over several parameters leave out the conflicting ones instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::IncompatibleUnits` listing the offending parameters and units,
+    /// or `AppError::Db` if the query fails.
+    pub async fn ensure_compatible_units(
+        &self,
+        countries: &[String],
+        parameters: &[&str],
+        days: i32,
+        filter: &MeasurementFilter,
+    ) -> Result<()> {
+        let conflicts = self
+            .find_unit_conflicts(countries, parameters, days, filter)
+            .await?;
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+        Err(AppError::IncompatibleUnits(describe_unit_conflicts(
+            &conflicts,
+        )))
+    }
+}
+
+/// `AND` condition leaving the conflicting parameters of each country out of a query on the
+/// `measurements` table, binding their countries and parameters as parameters `$first` and
+/// `$first + 1`.
+pub(super) fn exclude_unit_conflicts(first: usize) -> String {
+    format!(
+        " AND (measurements.country, measurements.parameter_name) NOT IN \
+         (SELECT * FROM UNNEST(${}::TEXT[], ${}::TEXT[]))",
+        first,
+        first + 1
+    )
+}
+
+/// Splits conflicts into the country and parameter arrays bound by `exclude_unit_conflicts`.
+pub(super) fn unit_conflict_binds(conflicts: &[UnitConflict]) -> (Vec<String>, Vec<String>) {
+    conflicts
+        .iter()
+        .map(|c| (c.country.clone(), c.parameter.clone()))
+        .unzip()
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use crate::models::{parameter_value, DbMeasurement, MeasurementFilter};
+    use chrono::{Duration, Utc};
+    use num_traits::ToPrimitive;
+    use sqlx::types::Decimal;
+    use sqlx::PgPool;
+
+    fn measurement(sensor_id: i64, unit: &str, value: f64) -> DbMeasurement {
+        DbMeasurement::test(sensor_id, "NL", "co", Utc::now() - Duration::days(1))
+            .with_value_in(value, unit)
+    }
+
+    /// Tests that ppm and µg/m³ CO values are averaged in µg/m³, that legacy rows are
+    /// back-filled, and that unconvertible units are left out after the quality filter.
+    #[sqlx::test]
+    async fn test_units_are_normalised_before_aggregation(pool: PgPool) {
+        let db = Database { pool };
+        db.init_schema().await.expect("Failed to init schema");
+        let filter = MeasurementFilter::default();
+        let countries = vec!["NL".to_string()];
+
+        db.insert_measurements(&[measurement(1, "ppm", 1.0), measurement(2, "µg/m³", 854.4)])
+            .await
+            .expect("Failed to insert measurements");
+        db.ensure_compatible_units(&countries, &["co"], 5, &filter)
+            .await
+            .expect("ppm and µg/m³ should be compatible after normalisation");
+        let average = db
+            .get_average_air_quality("NL", &filter)
+            .await
+            .expect("Average should succeed");
+        // (1145.6 + 854.4) / 2
+        assert!((parameter_value(&average.averages, "co").unwrap() - 1000.0).abs() < 0.1);
+
+        // A legacy row without normalised columns is back-filled by init_schema.
+        sqlx::query(
+            "UPDATE measurements SET unit_normalized = NULL, unit_factor = NULL, value_normalized = NULL WHERE sensor_id = 1",
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        db.init_schema().await.expect("Failed to re-init schema");
+        let (value,): (Decimal,) =
+            sqlx::query_as("SELECT value_normalized FROM measurements WHERE sensor_id = 1")
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+        assert!((value.to_f64().unwrap() - 1145.6).abs() < 0.1);
+
+        // An unknown unit cannot be converted: CO is left out of the average and reported,
+        // and the CO-only queries are refused.
+        db.insert_measurements(&[measurement(3, "mol/l", 0.01)])
+            .await
+            .expect("Failed to insert measurement");
+        let average = db
+            .get_average_air_quality("NL", &filter)
+            .await
+            .expect("Average should leave out the mixed units");
+        assert_eq!(parameter_value(&average.averages, "co"), None);
+        assert_eq!(average.measurement_count, 0);
+        assert_eq!(average.unit_conflicts.len(), 1);
+        assert_eq!(average.unit_conflicts[0].parameter, "co");
+        assert!(average.unit_conflicts[0]
+            .units
+            .contains(&"mol/l".to_string()));
+        match db.get_daily_values("NL", "co", 5, &filter).await {
+            Err(AppError::IncompatibleUnits(detail)) => assert!(detail.contains("mol/l")),
+            other => panic!("Expected IncompatibleUnits, got {:?}", other),
+        }
+
+        // The check only looks at the rows the quality filter keeps.
+        sqlx::query("UPDATE measurements SET percent_complete = 10 WHERE sensor_id = 3")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let complete = MeasurementFilter {
+            min_completeness: Some(75.0),
+            ..MeasurementFilter::default()
+        };
+        db.ensure_compatible_units(&countries, &["co"], 5, &complete)
+            .await
+            .expect("The incomplete mol/l day should be filtered out");
+        let average = db.get_average_air_quality("NL", &complete).await.unwrap();
+        assert!(average.unit_conflicts.is_empty());
+        assert!(parameter_value(&average.averages, "co").is_some());
+    }
+}
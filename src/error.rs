@@ -38,6 +38,43 @@ pub enum AppError {
     #[error("Dialoguer Error: {0}")]
     Dialoguer(Arc<dialoguer::Error>),
 
+    /// Values of the same parameter in units that cannot be converted into each other
+    /// were about to be aggregated.
+    #[error("Incompatible Units: {0}")]
+    IncompatibleUnits(String),
+
+    /// Error reading a CSV file (`csv`).
+    #[error("CSV Error: {0}")]
+    Csv(Arc<csv::Error>),
+
+    /// A file to import does not match its column mapping.
+    #[error("Import Error: {0}")]
+    Import(String),
+
+    /// Error building Arrow record batches or writing Arrow IPC files (`arrow`).
+    #[error("Arrow Error: {0}")]
+    Arrow(Arc<arrow::error::ArrowError>),
+
+    /// Error writing Parquet files (`parquet`).
+    #[error("Parquet Error: {0}")]
+    Parquet(Arc<parquet::errors::ParquetError>),
+
+    /// An invalid alert rules file, or a notifier that failed to deliver an alert.
+    #[error("Alert Error: {0}")]
+    Alert(String),
+
+    /// An invalid retention policies file.
+    #[error("Retention Error: {0}")]
+    Retention(String),
+
+    /// Error loading or rendering an HTML report template (`minijinja`).
+    #[error("Report Template Error: {0}")]
+    Report(Arc<minijinja::Error>),
+
+    /// Error encoding the Prometheus metrics (`prometheus`).
+    #[error("Metrics Error: {0}")]
+    Metrics(Arc<prometheus::Error>),
+
     /// Error related to progress bar style templating (`indicatif`).
     #[error("Progress Style Template Error: {0}")]
     Template(Arc<indicatif::style::TemplateError>),
@@ -81,6 +118,36 @@ impl From<indicatif::style::TemplateError> for AppError {
     }
 }
 
+impl From<csv::Error> for AppError {
+    fn from(err: csv::Error) -> Self {
+        AppError::Csv(Arc::new(err))
+    }
+}
+
+impl From<arrow::error::ArrowError> for AppError {
+    fn from(err: arrow::error::ArrowError) -> Self {
+        AppError::Arrow(Arc::new(err))
+    }
+}
+
+impl From<parquet::errors::ParquetError> for AppError {
+    fn from(err: parquet::errors::ParquetError) -> Self {
+        AppError::Parquet(Arc::new(err))
+    }
+}
+
+impl From<minijinja::Error> for AppError {
+    fn from(err: minijinja::Error) -> Self {
+        AppError::Report(Arc::new(err))
+    }
+}
+
+impl From<prometheus::Error> for AppError {
+    fn from(err: prometheus::Error) -> Self {
+        AppError::Metrics(Arc::new(err))
+    }
+}
+
 impl From<serde_json::Error> for AppError {
     fn from(err: serde_json::Error) -> Self {
         AppError::JsonParse(Arc::new(err))
@@ -0,0 +1,585 @@
+//! Exports the `measurements`, `locations` and `sensors` tables as Parquet or Arrow IPC files.
+//!
+//! Column types follow the database: timestamps become UTC microsecond timestamps, `NUMERIC`
+//! columns become `Decimal128(38, 9)` and booleans stay booleans. Measurements are written in
+//! batches of `BATCH_SIZE` rows into Hive-style partitions
+//! (`measurements/country=NL/month=2024-03/part-0.parquet`), so the table is never held in
+//! memory as a whole. Locations and sensors are small and written as single files.
+
+use crate::error::{AppError, Result};
+use crate::models::{MeasurementRecord, StoredLocation, StoredSensor};
+use arrow::array::{
+    ArrayRef, BooleanArray, Decimal128Array, Float64Array, Int32Array, Int64Array, StringArray,
+    TimestampMicrosecondArray,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use futures::{Stream, StreamExt};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use sqlx::types::Decimal;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, info};
+
+/// Number of rows per record batch.
+pub const BATCH_SIZE: usize = 8192;
+
+/// Precision of the decimal columns.
+const DECIMAL_PRECISION: u8 = 38;
+/// Scale (digits after the decimal point) of the decimal columns.
+const DECIMAL_SCALE: i8 = 9;
+
+/// File format of the columnar export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColumnarFormat {
+    /// Apache Parquet, Snappy compressed.
+    Parquet,
+    /// Arrow IPC file format (Feather v2).
+    Arrow,
+}
+
+impl ColumnarFormat {
+    /// Returns the file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ColumnarFormat::Parquet => "parquet",
+            ColumnarFormat::Arrow => "arrow",
+        }
+    }
+}
+
+/// A database table written by the columnar export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportTable {
+    Measurements,
+    Locations,
+    Sensors,
+}
+
+impl ExportTable {
+    /// Every exported table, in export order.
+    pub const ALL: [ExportTable; 3] = [
+        ExportTable::Measurements,
+        ExportTable::Locations,
+        ExportTable::Sensors,
+    ];
+}
+
+/// Files and rows written by a columnar export.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportSummary {
+    pub files: Vec<PathBuf>,
+    pub rows: usize,
+}
+
+impl ExportSummary {
+    /// Adds the files and rows of `other`.
+    pub fn add(&mut self, other: ExportSummary) {
+        self.files.extend(other.files);
+        self.rows += other.rows;
+    }
+}
+
+/// A writer for one output file in either format.
+enum TableWriter {
+    Parquet(ArrowWriter<File>),
+    Arrow(FileWriter<File>),
+}
+
+impl TableWriter {
+    /// Creates the file (and missing parent directories) and writes the header.
+    fn create(path: &Path, schema: SchemaRef, format: ColumnarFormat) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = File::create(path)?;
+        Ok(match format {
+            ColumnarFormat::Parquet => {
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                TableWriter::Parquet(ArrowWriter::try_new(file, schema, Some(props))?)
+            },
+            ColumnarFormat::Arrow => TableWriter::Arrow(FileWriter::try_new(file, &schema)?),
+        })
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        match self {
+            TableWriter::Parquet(w) => w.write(batch)?,
+            TableWriter::Arrow(w) => w.write(batch)?,
+        }
+        Ok(())
+    }
+
+    /// Writes the footer and closes the file.
+    fn close(self) -> Result<()> {
+        match self {
+            TableWriter::Parquet(w) => {
+                w.close()?;
+            },
+            TableWriter::Arrow(mut w) => w.finish()?,
+        }
+        Ok(())
+    }
+}
+
+fn timestamp_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+}
+
+fn decimal_type() -> DataType {
+    DataType::Decimal128(DECIMAL_PRECISION, DECIMAL_SCALE)
+}
+
+/// Arrow schema of the `measurements` table.
+pub fn measurement_schema() -> SchemaRef {
+    let decimal = |name: &str| Field::new(name, decimal_type(), true);
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int32, false),
+        Field::new("location_id", DataType::Int64, false),
+        Field::new("sensor_id", DataType::Int64, false),
+        Field::new("location_name", DataType::Utf8, false),
+        Field::new("parameter_id", DataType::Int32, false),
+        Field::new("parameter_name", DataType::Utf8, false),
+        decimal("value_avg"),
+        decimal("value_min"),
+        decimal("value_max"),
+        decimal("value_q02"),
+        decimal("value_q25"),
+        decimal("value_median"),
+        decimal("value_q75"),
+        decimal("value_q98"),
+        decimal("value_sd"),
+        Field::new("measurement_count", DataType::Int32, true),
+        Field::new("expected_count", DataType::Int32, true),
+        Field::new("percent_complete", DataType::Float64, true),
+        Field::new("percent_coverage", DataType::Float64, true),
+        Field::new("unit", DataType::Utf8, false),
+        Field::new("unit_normalized", DataType::Utf8, true),
+        Field::new("unit_factor", DataType::Float64, true),
+        decimal("value_normalized"),
+        Field::new("date_utc", timestamp_type(), false),
+        Field::new("date_local", DataType::Utf8, false),
+        Field::new("country", DataType::Utf8, false),
+        Field::new("city", DataType::Utf8, true),
+        Field::new("latitude", DataType::Float64, true),
+        Field::new("longitude", DataType::Float64, true),
+        Field::new("is_mobile", DataType::Boolean, false),
+        Field::new("is_monitor", DataType::Boolean, false),
+        Field::new("owner_name", DataType::Utf8, true),
+        Field::new("provider_name", DataType::Utf8, true),
+        Field::new("source", DataType::Utf8, false),
+        Field::new("created_at", timestamp_type(), false),
+    ]))
+}
+
+/// Arrow schema of the `locations` table.
+pub fn location_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, true),
+        Field::new("locality", DataType::Utf8, true),
+        Field::new("country_code", DataType::Utf8, false),
+        Field::new("country_name", DataType::Utf8, false),
+        Field::new("timezone", DataType::Utf8, false),
+        Field::new("latitude", DataType::Float64, true),
+        Field::new("longitude", DataType::Float64, true),
+        Field::new("is_mobile", DataType::Boolean, false),
+        Field::new("is_monitor", DataType::Boolean, false),
+        Field::new("owner_name", DataType::Utf8, true),
+        Field::new("provider_name", DataType::Utf8, true),
+        Field::new("source", DataType::Utf8, false),
+        Field::new("datetime_first", timestamp_type(), true),
+        Field::new("datetime_last", timestamp_type(), true),
+    ]))
+}
+
+/// Arrow schema of the `sensors` table.
+pub fn sensor_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("location_id", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("parameter_name", DataType::Utf8, false),
+        Field::new("units", DataType::Utf8, false),
+        Field::new("source", DataType::Utf8, false),
+    ]))
+}
+
+/// Converts a decimal into the unscaled `i128` value of a `Decimal128(_, DECIMAL_SCALE)`.
+fn decimal_value(d: &Decimal) -> i128 {
+    let mut d = *d;
+    d.rescale(DECIMAL_SCALE as u32);
+    d.mantissa()
+}
+
+fn decimal_column<T>(rows: &[T], f: impl Fn(&T) -> Option<Decimal>) -> Result<ArrayRef> {
+    let array = rows
+        .iter()
+        .map(|r| f(r).as_ref().map(decimal_value))
+        .collect::<Decimal128Array>()
+        .with_precision_and_scale(DECIMAL_PRECISION, DECIMAL_SCALE)?;
+    Ok(Arc::new(array))
+}
+
+fn timestamp_column<T>(rows: &[T], f: impl Fn(&T) -> Option<DateTime<Utc>>) -> ArrayRef {
+    Arc::new(
+        rows.iter()
+            .map(|r| f(r).map(|t| t.timestamp_micros()))
+            .collect::<TimestampMicrosecondArray>()
+            .with_timezone("UTC"),
+    )
+}
+
+fn string_column<'a, T: 'a>(rows: &'a [T], f: impl Fn(&'a T) -> Option<&'a str>) -> ArrayRef {
+    Arc::new(rows.iter().map(f).collect::<StringArray>())
+}
+
+/// Converts measurement rows into a record batch with `measurement_schema`.
+pub fn measurements_to_batch(rows: &[MeasurementRecord]) -> Result<RecordBatch> {
+    let int32 = |f: fn(&MeasurementRecord) -> Option<i32>| -> ArrayRef {
+        Arc::new(rows.iter().map(f).collect::<Int32Array>())
+    };
+    let int64 = |f: fn(&MeasurementRecord) -> i64| -> ArrayRef {
+        Arc::new(rows.iter().map(f).collect::<Int64Array>())
+    };
+    let float = |f: fn(&MeasurementRecord) -> Option<f64>| -> ArrayRef {
+        Arc::new(rows.iter().map(f).collect::<Float64Array>())
+    };
+    let boolean = |f: fn(&MeasurementRecord) -> bool| -> ArrayRef {
+        Arc::new(rows.iter().map(|r| Some(f(r))).collect::<BooleanArray>())
+    };
+    let columns: Vec<ArrayRef> = vec![
+        int32(|r| Some(r.id)),
+        int64(|r| r.location_id),
+        int64(|r| r.sensor_id),
+        string_column(rows, |r| Some(r.location_name.as_str())),
+        int32(|r| Some(r.parameter_id)),
+        string_column(rows, |r| Some(r.parameter_name.as_str())),
+        decimal_column(rows, |r| r.value_avg)?,
+        decimal_column(rows, |r| r.value_min)?,
+        decimal_column(rows, |r| r.value_max)?,
+        decimal_column(rows, |r| r.value_q02)?,
+        decimal_column(rows, |r| r.value_q25)?,
+        decimal_column(rows, |r| r.value_median)?,
+        decimal_column(rows, |r| r.value_q75)?,
+        decimal_column(rows, |r| r.value_q98)?,
+        decimal_column(rows, |r| r.value_sd)?,
+        int32(|r| r.measurement_count),
+        int32(|r| r.expected_count),
+        float(|r| r.percent_complete),
+        float(|r| r.percent_coverage),
+        string_column(rows, |r| Some(r.unit.as_str())),
+        string_column(rows, |r| r.unit_normalized.as_deref()),
+        float(|r| r.unit_factor),
+        decimal_column(rows, |r| r.value_normalized)?,
+        timestamp_column(rows, |r| Some(r.date_utc)),
+        string_column(rows, |r| Some(r.date_local.as_str())),
+        string_column(rows, |r| Some(r.country.as_str())),
+        string_column(rows, |r| r.city.as_deref()),
+        float(|r| r.latitude),
+        float(|r| r.longitude),
+        boolean(|r| r.is_mobile),
+        boolean(|r| r.is_monitor),
+        string_column(rows, |r| r.owner_name.as_deref()),
+        string_column(rows, |r| r.provider_name.as_deref()),
+        string_column(rows, |r| Some(r.source.as_str())),
+        timestamp_column(rows, |r| Some(r.created_at)),
+    ];
+    Ok(RecordBatch::try_new(measurement_schema(), columns)?)
+}
+
+/// Converts location rows into a record batch with `location_schema`.
+pub fn locations_to_batch(rows: &[StoredLocation]) -> Result<RecordBatch> {
+    let float = |f: fn(&StoredLocation) -> Option<f64>| -> ArrayRef {
+        Arc::new(rows.iter().map(f).collect::<Float64Array>())
+    };
+    let boolean = |f: fn(&StoredLocation) -> bool| -> ArrayRef {
+        Arc::new(rows.iter().map(|r| Some(f(r))).collect::<BooleanArray>())
+    };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(rows.iter().map(|r| Some(r.id)).collect::<Int64Array>()),
+        string_column(rows, |r| r.name.as_deref()),
+        string_column(rows, |r| r.locality.as_deref()),
+        string_column(rows, |r| Some(r.country_code.as_str())),
+        string_column(rows, |r| Some(r.country_name.as_str())),
+        string_column(rows, |r| Some(r.timezone.as_str())),
+        float(|r| r.latitude),
+        float(|r| r.longitude),
+        boolean(|r| r.is_mobile),
+        boolean(|r| r.is_monitor),
+        string_column(rows, |r| r.owner_name.as_deref()),
+        string_column(rows, |r| r.provider_name.as_deref()),
+        string_column(rows, |r| Some(r.source.as_str())),
+        timestamp_column(rows, |r| r.datetime_first),
+        timestamp_column(rows, |r| r.datetime_last),
+    ];
+    Ok(RecordBatch::try_new(location_schema(), columns)?)
+}
+
+/// Converts sensor rows into a record batch with `sensor_schema`.
+pub fn sensors_to_batch(rows: &[StoredSensor]) -> Result<RecordBatch> {
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(rows.iter().map(|r| Some(r.id)).collect::<Int64Array>()),
+        Arc::new(
+            rows.iter()
+                .map(|r| Some(r.location_id))
+                .collect::<Int64Array>(),
+        ),
+        string_column(rows, |r| Some(r.name.as_str())),
+        string_column(rows, |r| Some(r.parameter_name.as_str())),
+        string_column(rows, |r| Some(r.units.as_str())),
+        string_column(rows, |r| Some(r.source.as_str())),
+    ];
+    Ok(RecordBatch::try_new(sensor_schema(), columns)?)
+}
+
+/// Writes a single record batch as `<dir>/<name>.<ext>`.
+pub fn write_table(
+    dir: &Path,
+    name: &str,
+    batch: &RecordBatch,
+    format: ColumnarFormat,
+) -> Result<ExportSummary> {
+    let path = dir.join(format!("{}.{}", name, format.extension()));
+    let mut writer = TableWriter::create(&path, batch.schema(), format)?;
+    writer.write(batch)?;
+    writer.close()?;
+    info!("Wrote {} rows to {}", batch.num_rows(), path.display());
+    Ok(ExportSummary {
+        files: vec![path],
+        rows: batch.num_rows(),
+    })
+}
+
+/// Returns the partition directory of a measurement below `<dir>/measurements`.
+fn partition_dir(dir: &Path, record: &MeasurementRecord) -> PathBuf {
+    dir.join("measurements")
+        .join(format!("country={}", record.country))
+        .join(format!("month={}", record.date_utc.format("%Y-%m")))
+}
+
+/// Writes a stream of measurements, ordered by country and date, into one file per
+/// country/month partition below `<dir>/measurements`.
+///
+/// # Errors
+///
+/// Returns the first error of the stream, or `AppError::Cli` if the stream is not ordered by
+/// partition, or an I/O, Arrow or Parquet error if writing fails.
+pub async fn write_measurements<S>(
+    mut records: S,
+    dir: &Path,
+    format: ColumnarFormat,
+) -> Result<ExportSummary>
+where
+    S: Stream<Item = Result<MeasurementRecord>> + Unpin,
+{
+    let mut summary = ExportSummary::default();
+    let mut finished: Vec<PathBuf> = Vec::new();
+    let mut current: Option<(PathBuf, PathBuf, TableWriter)> = None;
+    let mut buffer: Vec<MeasurementRecord> = Vec::with_capacity(BATCH_SIZE);
+
+    while let Some(record) = records.next().await {
+        let record = record?;
+        let partition = partition_dir(dir, &record);
+        if current.as_ref().map(|(p, _, _)| p) != Some(&partition) {
+            if let Some((_, path, mut writer)) = current.take() {
+                flush(&mut writer, &mut buffer)?;
+                writer.close()?;
+                summary.files.push(path);
+            }
+            if finished.contains(&partition) {
+                return Err(AppError::Cli(format!(
+                    "Measurements are not ordered by partition ({} seen twice)",
+                    partition.display()
+                )));
+            }
+            finished.push(partition.clone());
+            let path = partition.join(format!("part-0.{}", format.extension()));
+            debug!("Starting partition file {}", path.display());
+            let writer = TableWriter::create(&path, measurement_schema(), format)?;
+            current = Some((partition, path, writer));
+        }
+        buffer.push(record);
+        summary.rows += 1;
+        if buffer.len() >= BATCH_SIZE {
+            if let Some((_, _, writer)) = current.as_mut() {
+                flush(writer, &mut buffer)?;
+            }
+        }
+    }
+    if let Some((_, path, mut writer)) = current.take() {
+        flush(&mut writer, &mut buffer)?;
+        writer.close()?;
+        summary.files.push(path);
+    }
+    info!(
+        "Wrote {} measurements into {} partitions",
+        summary.rows,
+        summary.files.len()
+    );
+    Ok(summary)
+}
+
+/// Writes the buffered measurements as one record batch and clears the buffer.
+fn flush(writer: &mut TableWriter, buffer: &mut Vec<MeasurementRecord>) -> Result<()> {
+    if !buffer.is_empty() {
+        writer.write(&measurements_to_batch(buffer)?)?;
+        buffer.clear();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Array;
+    use chrono::TimeZone;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::str::FromStr;
+
+    fn record(id: i32, country: &str, month: u32) -> MeasurementRecord {
+        let date_utc = Utc.with_ymd_and_hms(2024, month, 5, 0, 0, 0).unwrap();
+        MeasurementRecord {
+            id,
+            location_id: 1,
+            sensor_id: 10,
+            location_name: "Station 1".to_string(),
+            parameter_id: 2,
+            parameter_name: "pm25".to_string(),
+            value_avg: Some(Decimal::from_str("12.345").unwrap()),
+            value_min: None,
+            value_max: None,
+            value_q02: None,
+            value_q25: None,
+            value_median: None,
+            value_q75: None,
+            value_q98: None,
+            value_sd: None,
+            measurement_count: Some(24),
+            expected_count: None,
+            percent_complete: Some(100.0),
+            percent_coverage: None,
+            unit: "µg/m³".to_string(),
+            unit_normalized: Some("µg/m³".to_string()),
+            unit_factor: Some(1.0),
+            value_normalized: Some(Decimal::from_str("12.345").unwrap()),
+            date_utc,
+            date_local: date_utc.to_rfc3339(),
+            country: country.to_string(),
+            city: None,
+            latitude: Some(52.0),
+            longitude: Some(5.0),
+            is_mobile: false,
+            is_monitor: true,
+            owner_name: None,
+            provider_name: Some("EEA".to_string()),
+            source: "openaq".to_string(),
+            created_at: date_utc,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_measurements_to_batch_types() {
+        let batch = measurements_to_batch(&[record(1, "NL", 3)]).unwrap();
+        assert_eq!(batch.num_columns(), measurement_schema().fields().len());
+        let avg = batch
+            .column_by_name("value_avg")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Decimal128Array>()
+            .unwrap();
+        assert_eq!(avg.value_as_string(0), "12.345000000");
+        assert!(batch.column_by_name("value_min").unwrap().is_null(0));
+        let date = batch
+            .column_by_name("date_utc")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap();
+        assert_eq!(date.value(0), 1_709_596_800_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_write_measurements_partitions_by_country_and_month() {
+        let dir = temp_dir("columnar-test");
+        let records = vec![
+            Ok(record(1, "DE", 3)),
+            Ok(record(2, "DE", 3)),
+            Ok(record(3, "DE", 4)),
+            Ok(record(4, "NL", 3)),
+        ];
+        let summary = write_measurements(
+            futures::stream::iter(records),
+            &dir,
+            ColumnarFormat::Parquet,
+        )
+        .await
+        .unwrap();
+        assert_eq!(summary.rows, 4);
+        assert_eq!(summary.files.len(), 3);
+        let first = dir.join("measurements/country=DE/month=2024-03/part-0.parquet");
+        assert_eq!(summary.files[0], first);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&first).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+        assert_eq!(batches[0].schema(), measurement_schema());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_measurements_requires_partition_order() {
+        let dir = temp_dir("columnar-order-test");
+        let records = vec![
+            Ok(record(1, "DE", 3)),
+            Ok(record(2, "NL", 3)),
+            Ok(record(3, "DE", 3)),
+        ];
+        let result =
+            write_measurements(futures::stream::iter(records), &dir, ColumnarFormat::Arrow).await;
+        assert!(matches!(result, Err(AppError::Cli(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_table_arrow_ipc() {
+        let dir = temp_dir("columnar-ipc-test");
+        let sensors = vec![StoredSensor {
+            id: 10,
+            location_id: 1,
+            name: "pm25 µg/m³".to_string(),
+            parameter_name: "pm25".to_string(),
+            units: "µg/m³".to_string(),
+            source: "openaq".to_string(),
+        }];
+        let summary = write_table(
+            &dir,
+            "sensors",
+            &sensors_to_batch(&sensors).unwrap(),
+            ColumnarFormat::Arrow,
+        )
+        .unwrap();
+        let reader =
+            arrow::ipc::reader::FileReader::try_new(File::open(&summary.files[0]).unwrap(), None)
+                .unwrap();
+        assert_eq!(reader.schema(), sensor_schema());
+        let rows: usize = reader.map(|b| b.unwrap().num_rows()).sum();
+        assert_eq!(rows, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+//! Exports interpolated grids as ESRI ASCII grids (`.asc`) and GeoJSON polygons (`.geojson`).
+//!
+//! Both formats use WGS84 longitude/latitude coordinates. Cells without an estimate are written
+//! as `NODATA_VALUE` in the ASCII grid and omitted from the GeoJSON.
+
+use super::write_json;
+use crate::analysis::Grid;
+use crate::error::Result;
+use serde_json::{json, Map, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Value written for cells without an estimate in ESRI ASCII grids.
+pub const NODATA_VALUE: f64 = -9999.0;
+
+/// Writes a grid in the ESRI ASCII grid format.
+pub fn write_esri_ascii<W: Write>(grid: &Grid, mut out: W) -> std::io::Result<()> {
+    writeln!(out, "ncols {}", grid.ncols)?;
+    writeln!(out, "nrows {}", grid.nrows)?;
+    writeln!(out, "xllcorner {}", grid.xll_corner)?;
+    writeln!(out, "yllcorner {}", grid.yll_corner)?;
+    writeln!(out, "cellsize {}", grid.cell_size)?;
+    writeln!(out, "NODATA_value {}", NODATA_VALUE)?;
+    for row in 0..grid.nrows {
+        let line: Vec<String> = (0..grid.ncols)
+            .map(|col| format!("{:.4}", grid.value(row, col).unwrap_or(NODATA_VALUE)))
+            .collect();
+        writeln!(out, "{}", line.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Converts a grid into a GeoJSON `FeatureCollection` with one polygon per cell that has a
+/// value. `metadata` (parameter, unit, day, method, ...) is attached as a foreign member.
+pub fn grid_to_geojson(grid: &Grid, metadata: Map<String, Value>) -> Value {
+    let mut features = Vec::new();
+    for row in 0..grid.nrows {
+        for col in 0..grid.ncols {
+            let Some(value) = grid.value(row, col) else {
+                continue;
+            };
+            let (min_lon, min_lat, max_lon, max_lat) = grid.cell_bounds(row, col);
+            features.push(json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[
+                        [min_lon, min_lat],
+                        [max_lon, min_lat],
+                        [max_lon, max_lat],
+                        [min_lon, max_lat],
+                        [min_lon, min_lat]
+                    ]]
+                },
+                "properties": { "row": row, "col": col, "value": value }
+            }));
+        }
+    }
+    json!({
+        "type": "FeatureCollection",
+        "metadata": metadata,
+        "features": features
+    })
+}
+
+/// Writes a grid next to `base_path` as `<base>.asc` and `<base>.geojson` and returns both paths.
+/// Missing parent directories are created.
+///
+/// # Errors
+///
+/// Returns `AppError::Io` if a file cannot be written, or `AppError::JsonParse` if the
+/// GeoJSON cannot be serialised.
+pub fn write_grid_files(
+    grid: &Grid,
+    base_path: &Path,
+    metadata: Map<String, Value>,
+) -> Result<(PathBuf, PathBuf)> {
+    let asc_path = base_path.with_extension("asc");
+    let geojson_path = base_path.with_extension("geojson");
+
+    // Creates the parent directories, so it goes first.
+    write_json(&geojson_path, &grid_to_geojson(grid, metadata))?;
+
+    let mut asc = BufWriter::new(File::create(&asc_path)?);
+    write_esri_ascii(grid, &mut asc)?;
+    asc.flush()?;
+
+    info!(
+        "Wrote {}x{} grid to {} and {}",
+        grid.ncols,
+        grid.nrows,
+        asc_path.display(),
+        geojson_path.display()
+    );
+    Ok((asc_path, geojson_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> Grid {
+        Grid {
+            xll_corner: 4.0,
+            yll_corner: 50.0,
+            cell_size: 0.5,
+            ncols: 2,
+            nrows: 1,
+            values: vec![Some(12.5), None],
+        }
+    }
+
+    #[test]
+    fn test_write_esri_ascii() {
+        let mut out = Vec::new();
+        write_esri_ascii(&grid(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text,
+            "ncols 2\nnrows 1\nxllcorner 4\nyllcorner 50\ncellsize 0.5\nNODATA_value -9999\n12.5000 -9999.0000\n"
+        );
+    }
+
+    #[test]
+    fn test_grid_to_geojson_skips_empty_cells() {
+        let mut metadata = Map::new();
+        metadata.insert("parameter".to_string(), json!("pm25"));
+        let geojson = grid_to_geojson(&grid(), metadata);
+        assert_eq!(geojson["metadata"]["parameter"], "pm25");
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["properties"]["value"], 12.5);
+        assert_eq!(
+            features[0]["geometry"]["coordinates"][0][2],
+            json!([4.5, 50.5])
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Exports stored locations as a GeoJSON `FeatureCollection` of points.
+//!
+//! Each feature carries the location's metadata, its sensors, the parameters it measures and
+//! the latest and averaged recent values per parameter. Locations without coordinates are kept
+//! with a `null` geometry.
+
+use super::write_json;
+use crate::error::Result;
+use crate::models::{LocationParameterValues, StoredLocation, StoredSensor};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::info;
+
+/// Builds the GeoJSON `FeatureCollection` of the given locations. `metadata` (filters,
+/// period, ...) is attached as a foreign member.
+pub fn locations_to_geojson(
+    locations: &[StoredLocation],
+    sensors: &[StoredSensor],
+    values: &[LocationParameterValues],
+    metadata: Map<String, Value>,
+) -> Value {
+    let mut sensors_by_location: HashMap<i64, Vec<&StoredSensor>> = HashMap::new();
+    for s in sensors {
+        sensors_by_location
+            .entry(s.location_id)
+            .or_default()
+            .push(s);
+    }
+    let mut values_by_location: HashMap<i64, Vec<&LocationParameterValues>> = HashMap::new();
+    for v in values {
+        values_by_location.entry(v.location_id).or_default().push(v);
+    }
+
+    let features: Vec<Value> = locations
+        .iter()
+        .map(|l| {
+            let sensors = sensors_by_location.remove(&l.id).unwrap_or_default();
+            let values = values_by_location.remove(&l.id).unwrap_or_default();
+            let mut parameters: Vec<&str> =
+                sensors.iter().map(|s| s.parameter_name.as_str()).collect();
+            parameters.dedup();
+            let geometry = match (l.longitude, l.latitude) {
+                (Some(lon), Some(lat)) => json!({ "type": "Point", "coordinates": [lon, lat] }),
+                _ => Value::Null,
+            };
+            json!({
+                "type": "Feature",
+                "id": l.id,
+                "geometry": geometry,
+                "properties": {
+                    "name": l.name,
+                    "locality": l.locality,
+                    "country": l.country_code,
+                    "country_name": l.country_name,
+                    "timezone": l.timezone,
+                    "provider": l.provider_name,
+                    "owner": l.owner_name,
+                    "source": l.source,
+                    "is_monitor": l.is_monitor,
+                    "is_mobile": l.is_mobile,
+                    "first_seen": l.datetime_first,
+                    "last_seen": l.datetime_last,
+                    "parameters": parameters,
+                    "sensors": sensors.iter().map(|s| json!({
+                        "id": s.id,
+                        "name": s.name,
+                        "parameter": s.parameter_name,
+                        "unit": s.units,
+                    })).collect::<Vec<_>>(),
+                    "values": values.iter().map(|v| json!({
+                        "parameter": v.parameter,
+                        "display_name": v.display_name,
+                        "unit": v.unit,
+                        "latest": v.latest,
+                        "latest_date": v.latest_date.format("%Y-%m-%d").to_string(),
+                        "average": v.average,
+                        "days": v.days,
+                    })).collect::<Vec<_>>(),
+                }
+            })
+        })
+        .collect();
+
+    json!({
+        "type": "FeatureCollection",
+        "metadata": metadata,
+        "features": features
+    })
+}
+
+/// Writes the locations as GeoJSON to `path`. Missing parent directories are created.
+///
+/// # Errors
+///
+/// Returns `AppError::Io` if the file cannot be written, or `AppError::JsonParse` if the
+/// GeoJSON cannot be serialised.
+pub fn write_locations_geojson(
+    path: &Path,
+    locations: &[StoredLocation],
+    sensors: &[StoredSensor],
+    values: &[LocationParameterValues],
+    metadata: Map<String, Value>,
+) -> Result<()> {
+    write_json(
+        path,
+        &locations_to_geojson(locations, sensors, values, metadata),
+    )?;
+    info!("Wrote {} locations to {}", locations.len(), path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn location(id: i64, latitude: Option<f64>) -> StoredLocation {
+        StoredLocation {
+            id,
+            name: Some(format!("Station {}", id)),
+            locality: Some("Utrecht".to_string()),
+            country_code: "NL".to_string(),
+            country_name: "Netherlands".to_string(),
+            timezone: "Europe/Amsterdam".to_string(),
+            latitude,
+            longitude: latitude.map(|_| 5.1),
+            is_mobile: false,
+            is_monitor: true,
+            owner_name: Some("RIVM".to_string()),
+            provider_name: Some("EEA".to_string()),
+            source: "openaq".to_string(),
+            datetime_first: None,
+            datetime_last: Some(Utc.with_ymd_and_hms(2024, 3, 5, 12, 0, 0).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_locations_to_geojson() {
+        let sensors = vec![
+            StoredSensor {
+                id: 11,
+                location_id: 1,
+                name: "pm25 µg/m³".to_string(),
+                parameter_name: "pm25".to_string(),
+                units: "µg/m³".to_string(),
+                source: "openaq".to_string(),
+            },
+            StoredSensor {
+                id: 12,
+                location_id: 1,
+                name: "no2 ppb".to_string(),
+                parameter_name: "no2".to_string(),
+                units: "ppb".to_string(),
+                source: "openaq".to_string(),
+            },
+        ];
+        let values = vec![LocationParameterValues {
+            location_id: 1,
+            parameter: "pm25".to_string(),
+            display_name: Some("PM2.5".to_string()),
+            unit: Some("µg/m³".to_string()),
+            latest: Some(12.0),
+            latest_date: Utc.with_ymd_and_hms(2024, 3, 5, 0, 0, 0).unwrap(),
+            average: Some(10.0),
+            days: 7,
+        }];
+        let geojson = locations_to_geojson(
+            &[location(1, Some(52.1)), location(2, None)],
+            &sensors,
+            &values,
+            Map::new(),
+        );
+
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0]["geometry"]["coordinates"], json!([5.1, 52.1]));
+        let properties = &features[0]["properties"];
+        assert_eq!(properties["parameters"], json!(["pm25", "no2"]));
+        assert_eq!(properties["sensors"].as_array().unwrap().len(), 2);
+        assert_eq!(properties["values"][0]["latest"], 12.0);
+        assert_eq!(properties["values"][0]["latest_date"], "2024-03-05");
+        assert_eq!(properties["last_seen"], "2024-03-05T12:00:00Z");
+        assert!(features[1]["geometry"].is_null());
+        assert_eq!(features[1]["properties"]["values"], json!([]));
+    }
+}
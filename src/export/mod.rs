@@ -0,0 +1,31 @@
+//! Writes analysis results to files in formats used by external tools.
+//!
+//! Includes:
+//! - `columnar`: The measurements, locations and sensors tables as Parquet or Arrow IPC files.
+//! - `grid`: Interpolated grids as ESRI ASCII grids and GeoJSON polygons.
+//! - `locations`: Stored locations with their sensors and recent values as GeoJSON points.
+
+mod columnar;
+mod grid;
+mod locations;
+
+pub use columnar::*;
+pub use grid::*;
+pub use locations::*;
+
+use crate::error::Result;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Serialises `value` as JSON into the file at `path`, creating missing parent directories.
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut out, value)?;
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,779 @@
+//! Parses measurement files (CSV, optionally gzip compressed) into daily `DbMeasurement` rows.
+//!
+//! Columns are located by header name through a `ColumnMapping`. The default mapping matches
+//! the OpenAQ archive files (`location_id,sensors_id,location,datetime,lat,lon,parameter,units,value`),
+//! which hold hourly observations per location and day. Observations are aggregated per sensor,
+//! parameter, unit and local day (the day in the timestamp's own UTC offset), matching the daily
+//! aggregates returned by the OpenAQ API.
+
+use crate::error::{AppError, Result};
+use crate::models::{conversion_factor, normalization, DbMeasurement, Unit};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use csv::StringRecord;
+use flate2::read::GzDecoder;
+use num_traits::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Decimal;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// Default data source name of file imports, stored in the `source` column.
+pub const FILE_SOURCE: &str = "file";
+
+/// Maps the columns of a measurement file to the fields of a measurement, by header name.
+///
+/// Optional columns may be absent from the file. Identifiers that are not numeric (e.g.
+/// station codes) are mapped to stable negative IDs, which never collide with OpenAQ IDs.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ColumnMapping {
+    pub location_id: String,
+    /// Sensor ID column; without it, one sensor per location and parameter is assumed.
+    pub sensor_id: Option<String>,
+    pub location_name: Option<String>,
+    pub datetime: String,
+    pub latitude: Option<String>,
+    pub longitude: Option<String>,
+    pub parameter: String,
+    pub unit: String,
+    pub value: String,
+    /// Country code column; without it, `FileImportOptions::country` is used.
+    pub country: Option<String>,
+    pub city: Option<String>,
+    /// `chrono` format of the datetime column, if it is not RFC 3339 / ISO 8601. Formats
+    /// without an offset are read as UTC.
+    pub datetime_format: Option<String>,
+    /// Whether timestamps mark the end of the observation period (as in the OpenAQ archive,
+    /// where `01:00` covers `00:00-01:00`); if so, midnight belongs to the previous day.
+    pub period_end: bool,
+    /// Field delimiter.
+    pub delimiter: char,
+}
+
+impl Default for ColumnMapping {
+    /// The layout of the OpenAQ archive files.
+    fn default() -> Self {
+        Self {
+            location_id: "location_id".to_string(),
+            sensor_id: Some("sensors_id".to_string()),
+            location_name: Some("location".to_string()),
+            datetime: "datetime".to_string(),
+            latitude: Some("lat".to_string()),
+            longitude: Some("lon".to_string()),
+            parameter: "parameter".to_string(),
+            unit: "units".to_string(),
+            value: "value".to_string(),
+            country: None,
+            city: None,
+            datetime_format: None,
+            period_end: true,
+            delimiter: ',',
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Reads a mapping from a JSON file. Missing keys keep their OpenAQ archive defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Io` if the file cannot be read or `AppError::JsonParse` if it is not
+    /// a valid mapping.
+    pub fn from_json_file(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+}
+
+/// Values applied to every measurement of a file import.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileImportOptions {
+    /// Data source name stored with the measurements (e.g. `FILE_SOURCE`).
+    pub source: String,
+    /// Country code used when the mapping has no country column.
+    pub country: String,
+    pub provider_name: String,
+    pub owner_name: String,
+    /// Number of observations expected per day (e.g. 24 for hourly data), used to compute
+    /// the completeness of each day.
+    pub expected_per_day: Option<i32>,
+    /// Parameter IDs by name, taken from the parameter catalogue (0 for unknown parameters).
+    pub parameter_ids: HashMap<String, i32>,
+}
+
+/// One observation read from a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub location_id: i64,
+    pub sensor_id: i64,
+    pub location_name: String,
+    pub timestamp: DateTime<FixedOffset>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub parameter: String,
+    pub unit: String,
+    pub value: f64,
+    pub country: Option<String>,
+    pub city: Option<String>,
+}
+
+/// Column indices resolved from the header of a file.
+struct Columns {
+    location_id: usize,
+    sensor_id: Option<usize>,
+    location_name: Option<usize>,
+    datetime: usize,
+    latitude: Option<usize>,
+    longitude: Option<usize>,
+    parameter: usize,
+    unit: usize,
+    value: usize,
+    country: Option<usize>,
+    city: Option<usize>,
+}
+
+impl Columns {
+    fn resolve(headers: &StringRecord, mapping: &ColumnMapping) -> Result<Self> {
+        let find = |name: &str| headers.iter().position(|h| h.trim() == name);
+        let required = |name: &str| {
+            find(name).ok_or_else(|| {
+                AppError::Import(format!(
+                    "Column '{}' not found (columns: {})",
+                    name,
+                    headers.iter().collect::<Vec<_>>().join(", ")
+                ))
+            })
+        };
+        let optional = |name: &Option<String>| name.as_deref().and_then(find);
+        Ok(Self {
+            location_id: required(&mapping.location_id)?,
+            sensor_id: optional(&mapping.sensor_id),
+            location_name: optional(&mapping.location_name),
+            datetime: required(&mapping.datetime)?,
+            latitude: optional(&mapping.latitude),
+            longitude: optional(&mapping.longitude),
+            parameter: required(&mapping.parameter)?,
+            unit: required(&mapping.unit)?,
+            value: required(&mapping.value)?,
+            country: optional(&mapping.country),
+            city: optional(&mapping.city),
+        })
+    }
+}
+
+/// Returns a stable negative ID for a non-numeric identifier (FNV-1a hash).
+fn synthetic_id(key: &str) -> i64 {
+    let hash = key.bytes().fold(0xcbf29ce484222325_u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    -((hash >> 1) as i64 % 1_000_000_000_000) - 1
+}
+
+/// Parses an identifier, falling back to a synthetic ID for non-numeric values.
+fn parse_id(value: &str) -> i64 {
+    value
+        .trim()
+        .parse()
+        .unwrap_or_else(|_| synthetic_id(value.trim()))
+}
+
+/// Parses a timestamp as RFC 3339, with a custom format, or as a naive UTC date/time.
+pub fn parse_timestamp(value: &str, format: Option<&str>) -> Option<DateTime<FixedOffset>> {
+    let value = value.trim();
+    let utc = |naive: NaiveDateTime| Utc.from_utc_datetime(&naive).fixed_offset();
+    if let Some(format) = format {
+        return DateTime::parse_from_str(value, format)
+            .ok()
+            .or_else(|| NaiveDateTime::parse_from_str(value, format).ok().map(utc))
+            .or_else(|| {
+                NaiveDate::parse_from_str(value, format)
+                    .ok()
+                    .and_then(|d| d.and_hms_opt(0, 0, 0))
+                    .map(utc)
+            });
+    }
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .or_else(|| DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%:z").ok())
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+                .ok()
+                .map(utc)
+        })
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .map(utc)
+        })
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(utc)
+        })
+}
+
+/// Parses one CSV record into an observation.
+fn parse_record(
+    record: &StringRecord,
+    columns: &Columns,
+    mapping: &ColumnMapping,
+) -> std::result::Result<Observation, String> {
+    let field = |i: usize| record.get(i).map(str::trim).unwrap_or("");
+    let optional = |i: Option<usize>| i.map(field).filter(|v| !v.is_empty()).map(str::to_string);
+    let float = |i: Option<usize>| optional(i).and_then(|v| v.parse::<f64>().ok());
+
+    let location = field(columns.location_id);
+    if location.is_empty() {
+        return Err("missing location ID".to_string());
+    }
+    let location_id = parse_id(location);
+    let parameter = field(columns.parameter).to_lowercase();
+    if parameter.is_empty() {
+        return Err("missing parameter".to_string());
+    }
+    let sensor_id = match optional(columns.sensor_id) {
+        Some(sensor) => parse_id(&sensor),
+        None => synthetic_id(&format!("{}/{}", location, parameter)),
+    };
+    let timestamp = parse_timestamp(field(columns.datetime), mapping.datetime_format.as_deref())
+        .ok_or_else(|| format!("invalid datetime '{}'", field(columns.datetime)))?;
+    let value: f64 = field(columns.value)
+        .parse()
+        .map_err(|_| format!("invalid value '{}'", field(columns.value)))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("invalid value {}", value));
+    }
+
+    Ok(Observation {
+        location_id,
+        sensor_id,
+        location_name: optional(columns.location_name)
+            .unwrap_or_else(|| format!("Location {}", location)),
+        timestamp,
+        latitude: float(columns.latitude),
+        longitude: float(columns.longitude),
+        parameter,
+        unit: field(columns.unit).to_string(),
+        value,
+        country: optional(columns.country).map(|c| c.to_uppercase()),
+        city: optional(columns.city),
+    })
+}
+
+/// Observations of one sensor on one local day, in the unit of the first observation.
+#[derive(Debug)]
+struct DayGroup {
+    first: Observation,
+    /// Local midnight starting the day.
+    day_start: DateTime<FixedOffset>,
+    values: Vec<f64>,
+}
+
+/// Aggregates observations into daily groups, one per sensor and local day (the key of the
+/// stored daily measurements). Observations of several files can be added before finishing,
+/// so a day split across files is aggregated as a whole.
+#[derive(Debug, Default)]
+pub struct DailyAggregator {
+    groups: BTreeMap<(i64, NaiveDate), DayGroup>,
+    period_end: bool,
+}
+
+impl DailyAggregator {
+    /// Creates an aggregator. With `period_end`, a timestamp at local midnight is attributed
+    /// to the previous day.
+    pub fn new(period_end: bool) -> Self {
+        Self {
+            groups: BTreeMap::new(),
+            period_end,
+        }
+    }
+
+    /// Adds an observation to its day. A value reported in another unit than the earlier
+    /// values of the day is converted to their unit.
+    ///
+    /// # Errors
+    ///
+    /// Returns the reason if the observation cannot join its day: its parameter differs from
+    /// the earlier values of the sensor, or its unit cannot be converted to theirs.
+    pub fn add(&mut self, observation: Observation) -> std::result::Result<(), String> {
+        let instant = if self.period_end {
+            observation.timestamp - Duration::seconds(1)
+        } else {
+            observation.timestamp
+        };
+        let day = instant.date_naive();
+        let group = match self.groups.entry((observation.sensor_id, day)) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let offset = *observation.timestamp.offset();
+                let day_start = offset
+                    .from_local_datetime(&day.and_hms_opt(0, 0, 0).unwrap_or_default())
+                    .single()
+                    .unwrap_or(observation.timestamp);
+                let value = observation.value;
+                entry
+                    .insert(DayGroup {
+                        first: observation,
+                        day_start,
+                        values: Vec::new(),
+                    })
+                    .values
+                    .push(value);
+                return Ok(());
+            },
+        };
+        let first = &group.first;
+        if observation.parameter != first.parameter {
+            return Err(format!(
+                "sensor {} reports {} and {}",
+                observation.sensor_id, first.parameter, observation.parameter
+            ));
+        }
+        let factor = conversion_factor(
+            &first.parameter,
+            &Unit::parse(&observation.unit),
+            &Unit::parse(&first.unit),
+        )
+        .ok_or_else(|| {
+            format!(
+                "{} in {} cannot be converted to {}",
+                observation.parameter, observation.unit, first.unit
+            )
+        })?;
+        group.values.push(observation.value * factor);
+        Ok(())
+    }
+
+    /// Reads CSV data and adds its observations, returning the number of observations added
+    /// and of rows skipped.
+    ///
+    /// Rows with missing or invalid fields, negative values, or values that cannot join their
+    /// day (see `add`) are skipped and counted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Import` if a mapped column is missing, or `AppError::Csv` if the data
+    /// is not valid CSV.
+    pub fn read<R: Read>(&mut self, reader: R, mapping: &ColumnMapping) -> Result<(usize, usize)> {
+        let mut csv = csv::ReaderBuilder::new()
+            .delimiter(mapping.delimiter as u8)
+            .flexible(true)
+            .from_reader(reader);
+        let columns = Columns::resolve(csv.headers()?, mapping)?;
+
+        let (mut observations, mut skipped) = (0, 0);
+        for (line, record) in csv.records().enumerate() {
+            match parse_record(&record?, &columns, mapping).and_then(|o| self.add(o)) {
+                Ok(()) => observations += 1,
+                Err(reason) => {
+                    debug!("Skipping row {}: {}", line + 2, reason);
+                    skipped += 1;
+                },
+            }
+        }
+        if skipped > 0 {
+            warn!("Skipped {} invalid rows", skipped);
+        }
+        Ok((observations, skipped))
+    }
+
+    /// Reads a CSV file (gzip compressed if its name ends in `.gz`) and adds its observations,
+    /// returning the number of observations added and of rows skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Io` if the file cannot be opened, or the errors of `read`.
+    pub fn read_file(&mut self, path: &Path, mapping: &ColumnMapping) -> Result<(usize, usize)> {
+        info!("Reading measurements from {}", path.display());
+        let file = BufReader::new(File::open(path)?);
+        let gzipped = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"));
+        let (observations, skipped) = if gzipped {
+            self.read(GzDecoder::new(file), mapping)?
+        } else {
+            self.read(file, mapping)?
+        };
+        info!("Read {} observations from {}", observations, path.display());
+        Ok((observations, skipped))
+    }
+
+    /// Converts the daily groups into measurements, ordered by sensor and day.
+    pub fn finish(self, options: &FileImportOptions) -> Vec<DbMeasurement> {
+        self.groups
+            .into_values()
+            .map(|group| daily_measurement(group, options))
+            .collect()
+    }
+}
+
+/// Returns the `q`-quantile (0-1) of sorted values, interpolating linearly between ranks.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let rank = q * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64)
+}
+
+/// Builds the daily measurement of a group of observations.
+fn daily_measurement(group: DayGroup, options: &FileImportOptions) -> DbMeasurement {
+    let DayGroup {
+        first,
+        day_start,
+        mut values,
+    } = group;
+    values.sort_by(|a, b| a.total_cmp(b));
+    let n = values.len();
+    let mean = values.iter().sum::<f64>() / n as f64;
+    let sd = (n > 1)
+        .then(|| (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt());
+    let decimal = |v: f64| Decimal::from_f64(v);
+    let q = |p: f64| decimal(quantile(&values, p));
+
+    let value_avg = decimal(mean);
+    let (unit_normalized, unit_factor) = normalization(&first.parameter, &first.unit);
+    // Identity conversions keep the exact stored value.
+    let value_normalized = if unit_factor == 1.0 {
+        value_avg
+    } else {
+        value_avg
+            .and_then(|v| v.to_f64())
+            .and_then(|v| Decimal::from_f64(v * unit_factor))
+    };
+    let percent = options
+        .expected_per_day
+        .filter(|e| *e > 0)
+        .map(|e| (n as f64 / e as f64 * 100.0).min(100.0));
+
+    DbMeasurement {
+        id: None,
+        location_id: first.location_id,
+        sensor_id: first.sensor_id,
+        sensor_name: format!("{} {}", first.parameter, first.unit),
+        location_name: first.location_name,
+        parameter_id: options
+            .parameter_ids
+            .get(&first.parameter)
+            .copied()
+            .unwrap_or(0),
+        parameter_name: first.parameter,
+        parameter_display_name: None,
+        value_avg,
+        value_min: decimal(values[0]),
+        value_max: decimal(values[n - 1]),
+        value_q02: q(0.02),
+        value_q25: q(0.25),
+        value_median: q(0.5),
+        value_q75: q(0.75),
+        value_q98: q(0.98),
+        value_sd: sd.and_then(decimal),
+        measurement_count: Some(n as i32),
+        expected_count: options.expected_per_day,
+        percent_complete: percent,
+        percent_coverage: percent,
+        unit: first.unit,
+        unit_normalized: unit_normalized.to_string(),
+        unit_factor,
+        value_normalized,
+        date_utc: day_start.with_timezone(&Utc),
+        date_local: day_start.to_rfc3339(),
+        country: first.country.unwrap_or_else(|| options.country.clone()),
+        city: first.city,
+        latitude: first.latitude,
+        longitude: first.longitude,
+        is_mobile: false,
+        is_monitor: false,
+        owner_name: options.owner_name.clone(),
+        provider_name: options.provider_name.clone(),
+        source: options.source.clone(),
+    }
+}
+
+/// Returns `path` itself if it is a file, or the CSV and gzipped CSV files directly inside it
+/// (sorted by name) if it is a directory.
+///
+/// # Errors
+///
+/// Returns `AppError::Io` if the path cannot be read.
+pub fn list_measurement_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        // Surface a missing file as an I/O error here rather than when it is opened.
+        std::fs::metadata(path)?;
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let file = entry?.path();
+        let name = file
+            .file_name()
+            .map(|n| n.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if file.is_file() && (name.ends_with(".csv") || name.ends_with(".csv.gz")) {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Expands the command-line arguments of a file import into the files to read: each argument
+/// is a file, a directory (see `list_measurement_files`) or a glob pattern such as
+/// `archive/2024-*.csv.gz`. Files matched more than once are only returned the first time.
+///
+/// # Errors
+///
+/// Returns `AppError::Import` for an invalid glob pattern, or `AppError::Io` if a path cannot
+/// be read.
+pub fn expand_measurement_files(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        let paths = if pattern.contains(['*', '?', '[']) {
+            let matches = glob::glob(pattern)
+                .map_err(|e| AppError::Import(format!("Invalid pattern '{}': {}", pattern, e)))?;
+            let mut paths = Vec::new();
+            for path in matches {
+                paths.push(path.map_err(|e| e.into_error())?);
+            }
+            if paths.is_empty() {
+                warn!("Pattern '{}' matched no files", pattern);
+            }
+            paths
+        } else {
+            vec![PathBuf::from(pattern)]
+        };
+        for path in paths {
+            for file in list_measurement_files(&path)? {
+                if !files.contains(&file) {
+                    files.push(file);
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const ARCHIVE: &str = "\
+location_id,sensors_id,location,datetime,lat,lon,parameter,units,value
+2178,3917,Del Norte,2022-01-01T01:00:00-07:00,35.1353,-106.5847,pm25,µg/m³,10.0
+2178,3917,Del Norte,2022-01-01T02:00:00-07:00,35.1353,-106.5847,pm25,µg/m³,20.0
+2178,3917,Del Norte,2022-01-02T00:00:00-07:00,35.1353,-106.5847,pm25,µg/m³,30.0
+2178,3918,Del Norte,2022-01-01T01:00:00-07:00,35.1353,-106.5847,o3,ppm,0.03
+2178,3918,Del Norte,2022-01-01T02:00:00-07:00,35.1353,-106.5847,o3,ppm,
+2178,3918,Del Norte,2022-01-01T03:00:00-07:00,35.1353,-106.5847,o3,ppm,-1
+";
+
+    /// Aggregates CSV data, returning the measurements and the observation and skipped counts.
+    fn aggregate(
+        data: &str,
+        mapping: &ColumnMapping,
+    ) -> Result<(Vec<DbMeasurement>, usize, usize)> {
+        let mut aggregator = DailyAggregator::new(mapping.period_end);
+        let (observations, skipped) = aggregator.read(data.as_bytes(), mapping)?;
+        Ok((aggregator.finish(&options()), observations, skipped))
+    }
+
+    fn options() -> FileImportOptions {
+        FileImportOptions {
+            source: FILE_SOURCE.to_string(),
+            country: "US".to_string(),
+            provider_name: "OpenAQ archive".to_string(),
+            owner_name: "Unknown".to_string(),
+            expected_per_day: Some(24),
+            parameter_ids: HashMap::from([("pm25".to_string(), 2)]),
+        }
+    }
+
+    #[test]
+    fn test_read_openaq_archive_aggregates_to_local_days() {
+        let (measurements, observations, skipped) =
+            aggregate(ARCHIVE, &ColumnMapping::default()).unwrap();
+        assert_eq!(observations, 4);
+        assert_eq!(skipped, 2);
+        assert_eq!(measurements.len(), 2);
+
+        // The midnight reading closes January 1st, so all three pm25 values share a day.
+        let pm25 = &measurements[0];
+        assert_eq!(pm25.sensor_id, 3917);
+        assert_eq!(pm25.parameter_id, 2);
+        assert_eq!(pm25.measurement_count, Some(3));
+        assert_eq!(pm25.value_avg, Decimal::from_f64(20.0));
+        assert_eq!(pm25.value_median, Decimal::from_f64(20.0));
+        assert_eq!(pm25.value_min, Decimal::from_f64(10.0));
+        assert_eq!(pm25.value_sd, Decimal::from_f64(10.0));
+        assert_eq!(pm25.percent_complete, Some(12.5));
+        assert_eq!(pm25.date_local, "2022-01-01T00:00:00-07:00");
+        assert_eq!(pm25.date_utc.to_rfc3339(), "2022-01-01T07:00:00+00:00");
+        assert_eq!(pm25.country, "US");
+        assert_eq!(pm25.source, FILE_SOURCE);
+
+        // ppm ozone is normalised to µg/m³.
+        let o3 = &measurements[1];
+        assert_eq!(o3.unit_normalized, "µg/m³");
+        assert!((o3.value_normalized.unwrap().to_f64().unwrap() - 58.9).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_custom_mapping_with_station_codes() {
+        let data = "station;time;pollutant;unit;conc;country\n\
+                    NL10418;2024-03-05 10:00:00;NO2;µg/m³;12.5\n\
+                    NL10418;2024-03-05 11:00:00;NO2;µg/m³;17.5\n";
+        let mapping = ColumnMapping {
+            location_id: "station".to_string(),
+            sensor_id: None,
+            location_name: None,
+            datetime: "time".to_string(),
+            latitude: None,
+            longitude: None,
+            parameter: "pollutant".to_string(),
+            unit: "unit".to_string(),
+            value: "conc".to_string(),
+            country: Some("country".to_string()),
+            period_end: false,
+            delimiter: ';',
+            ..ColumnMapping::default()
+        };
+        let (measurements, _, _) = aggregate(data, &mapping).unwrap();
+        assert_eq!(measurements.len(), 1);
+        let m = &measurements[0];
+        assert!(m.location_id < 0 && m.sensor_id < 0);
+        assert_eq!(m.location_id, parse_id("NL10418"));
+        assert_eq!(m.parameter_name, "no2");
+        assert_eq!(m.parameter_id, 0);
+        assert_eq!(m.value_avg, Decimal::from_f64(15.0));
+        assert_eq!(m.date_local, "2024-03-05T00:00:00+00:00");
+        // Missing country column values fall back to the import option.
+        assert_eq!(m.country, "US");
+    }
+
+    #[test]
+    fn test_missing_column_is_reported() {
+        let data = "location_id,datetime,parameter,units\n1,2024-03-05,pm25,µg/m³\n";
+        let result = aggregate(data, &ColumnMapping::default());
+        assert!(matches!(result, Err(AppError::Import(msg)) if msg.contains("'value'")));
+    }
+
+    #[test]
+    fn test_read_gzipped_file() {
+        let path = std::env::temp_dir().join(format!("archive-test-{}.csv.gz", std::process::id()));
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(ARCHIVE.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let mapping = ColumnMapping::default();
+        let mut aggregator = DailyAggregator::new(mapping.period_end);
+        let counts = aggregator.read_file(&path, &mapping).unwrap();
+        assert_eq!(counts, (4, 2));
+        assert_eq!(aggregator.finish(&options()).len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_day_split_across_files_is_aggregated_once() {
+        let header = "location_id,sensors_id,location,datetime,lat,lon,parameter,units,value\n";
+        let first = format!(
+            "{header}2178,3917,Del Norte,2022-01-01T10:00:00-07:00,35.1,-106.5,pm25,µg/m³,10.0\n"
+        );
+        let second = format!(
+            "{header}2178,3917,Del Norte,2022-01-01T22:00:00-07:00,35.1,-106.5,pm25,µg/m³,30.0\n"
+        );
+        let mapping = ColumnMapping::default();
+        let mut aggregator = DailyAggregator::new(mapping.period_end);
+        aggregator.read(first.as_bytes(), &mapping).unwrap();
+        aggregator.read(second.as_bytes(), &mapping).unwrap();
+
+        let measurements = aggregator.finish(&options());
+        assert_eq!(measurements.len(), 1);
+        assert_eq!(measurements[0].measurement_count, Some(2));
+        assert_eq!(measurements[0].value_avg, Decimal::from_f64(20.0));
+    }
+
+    #[test]
+    fn test_units_of_one_day_are_converted_to_the_first() {
+        let data = "\
+location_id,sensors_id,location,datetime,lat,lon,parameter,units,value
+2178,4000,Del Norte,2022-01-01T10:00:00+00:00,35.1,-106.5,no2,µg/m³,20.0
+2178,4000,Del Norte,2022-01-01T11:00:00+00:00,35.1,-106.5,no2,ppm,0.01
+2178,4000,Del Norte,2022-01-01T12:00:00+00:00,35.1,-106.5,no2,unknown,5.0
+2178,4000,Del Norte,2022-01-01T13:00:00+00:00,35.1,-106.5,pm25,µg/m³,5.0
+";
+        let (measurements, observations, skipped) =
+            aggregate(data, &ColumnMapping::default()).unwrap();
+        assert_eq!((observations, skipped), (2, 2));
+        assert_eq!(measurements.len(), 1);
+        let no2 = &measurements[0];
+        assert_eq!(no2.unit, "µg/m³");
+        assert_eq!(no2.measurement_count, Some(2));
+        let expected = (20.0
+            + 0.01 * conversion_factor("no2", &Unit::Ppm, &Unit::MicrogramsPerCubicMetre).unwrap())
+            / 2.0;
+        assert!((no2.value_avg.unwrap().to_f64().unwrap() - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_list_measurement_files_in_directory() {
+        let dir = std::env::temp_dir().join(format!("archive-dir-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["b.csv.gz", "a.CSV", "notes.txt"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        let files = list_measurement_files(&dir).unwrap();
+        assert_eq!(files, vec![dir.join("a.CSV"), dir.join("b.csv.gz")]);
+        assert!(list_measurement_files(&dir.join("missing.csv")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_expand_measurement_files() {
+        let dir = std::env::temp_dir().join(format!("archive-glob-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["2024-01.csv", "2024-02.csv.gz", "2023-12.csv"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        let patterns = [
+            dir.join("2024-*").display().to_string(),
+            dir.join("2024-01.csv").display().to_string(),
+            dir.join("2023-12.csv").display().to_string(),
+        ];
+        let files = expand_measurement_files(&patterns).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.join("2024-01.csv"),
+                dir.join("2024-02.csv.gz"),
+                dir.join("2023-12.csv")
+            ]
+        );
+        assert!(expand_measurement_files(&[dir.join("[").display().to_string()]).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_timestamp_formats() {
+        let expected = "2024-03-05T10:00:00+00:00";
+        for value in [
+            "2024-03-05T10:00:00Z",
+            "2024-03-05 10:00:00",
+            "2024-03-05T10:00:00",
+        ] {
+            assert_eq!(parse_timestamp(value, None).unwrap().to_rfc3339(), expected);
+        }
+        assert_eq!(
+            parse_timestamp("05/03/2024 10:00", Some("%d/%m/%Y %H:%M"))
+                .unwrap()
+                .to_rfc3339(),
+            expected
+        );
+        assert!(parse_timestamp("yesterday", None).is_none());
+    }
+}
@@ -0,0 +1,9 @@
+//! Imports measurements from sources other than the live OpenAQ API.
+//!
+//! Includes:
+//! - `file`: OpenAQ archive files and other station exports in CSV or gzipped CSV, aggregated
+//!   to daily `DbMeasurement` rows.
+
+mod file;
+
+pub use file::*;
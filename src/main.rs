@@ -1,18 +1,38 @@
 //! Main entry point and interactive loop for the Air Quality Analysis CLI.
 //!
 //! Initializes logging, application state (including API client and DB connection),
-//! and runs the main menu loop, dispatching user-selected commands.
+//! and runs the main menu loop, dispatching user-selected commands. With the `serve`
+//! subcommand, the analytic queries are served as a REST API instead; `daemon` imports
+//! data on a schedule, `status` shows its latest runs, `report` writes an HTML report,
+//! `dashboard` shows a full-screen terminal dashboard, `health` lists silent sensors and
+//! `locations` and `sensors` browse the stored inventory.
 
+mod alerts;
+mod analysis;
 mod api;
 mod cli;
+mod daemon;
 mod db;
 mod error;
+mod export;
+mod import;
+mod metrics;
 mod models;
+mod report;
+mod server;
+mod tui;
 
-use cli::{App, AppState, AverageArgs, Commands, MeasurementsByLocalityArgs}; // Renamed MeasurementsArgs
+use clap::Parser;
+use cli::{
+    App, AppState, AverageArgs, CliArgs, Commands, DistributionArgs, MeasurementsByLocalityArgs,
+    RunMode, TrendArgs,
+};
 use colored::*;
+use comfy_table::{presets::UTF8_FULL, Cell, Color, ContentArrangement, Table};
+use db::DistributionGroup;
 use dialoguer::{theme::ColorfulTheme, Select};
 use error::Result;
+use std::net::SocketAddr;
 use tracing::{error, info, Level};
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 
@@ -22,6 +42,8 @@ use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, Env
 /// to process user commands until exit.
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = CliArgs::parse();
+
     // Configure rolling file logging to `logs/app.log`
     let file_appender = tracing_appender::rolling::daily("logs", "app.log");
     let (non_blocking_appender, _guard) = tracing_appender::non_blocking(file_appender); // _guard ensures logs are flushed on exit
@@ -51,6 +73,72 @@ async fn main() -> Result<()> {
         .with(console_layer)
         .init();
 
+    match args.mode {
+        Some(RunMode::Serve { addr }) => return serve(addr).await,
+        Some(RunMode::Daemon(args)) => {
+            return run_daemon(*args).await;
+        },
+        Some(RunMode::Status) => return status().await,
+        Some(RunMode::Report {
+            output,
+            days,
+            template,
+            title,
+        }) => {
+            let options = report::ReportOptions {
+                output,
+                days,
+                template: template.or_else(report::template_from_env),
+                title,
+            };
+            return write_report(options).await;
+        },
+        Some(RunMode::Dashboard { import_days }) => return run_dashboard(import_days).await,
+        Some(RunMode::Health(args)) => {
+            let db = open_database().await?;
+            return cli::check_health(&db, &args).await;
+        },
+        Some(RunMode::Locations { command }) => {
+            let db = open_database().await?;
+            return cli::browse_locations(&db, command).await;
+        },
+        Some(RunMode::Sensors { command }) => {
+            let db = open_database().await?;
+            return cli::browse_sensors(&db, command).await;
+        },
+        Some(RunMode::Prune(args)) => {
+            let db = open_database().await?;
+            return cli::prune(&db, &args).await;
+        },
+        Some(RunMode::Export(args)) => {
+            let db = open_database().await?;
+            return cli::export_tables(&db, &args).await;
+        },
+        Some(RunMode::ImportFile(args)) => return import_file(&args).await,
+        Some(RunMode::Query(args)) => {
+            let db = open_database().await?;
+            let filter = cli::measurement_filter_from_env();
+            return cli::run_query(&db, &args.query(), &filter).await;
+        },
+        Some(RunMode::Compare(args)) => {
+            let db = open_database().await?;
+            let filter = cli::measurement_filter_from_env();
+            let args = args.compare(chrono::Utc::now().date_naive());
+            return cli::show_comparison(&db, &args, &filter).await;
+        },
+        Some(RunMode::Near(args)) => {
+            let db = open_database().await?;
+            let filter = cli::measurement_filter_from_env();
+            return cli::show_nearby(&db, &args.near(), &filter).await;
+        },
+        Some(RunMode::Distribution(args)) => {
+            let db = open_database().await?;
+            let filter = cli::measurement_filter_from_env();
+            return cli::show_distribution(&db, &args.distribution(), &filter).await;
+        },
+        None => {},
+    }
+
     info!("Initializing air quality analysis app...");
 
     // Initialize the main application struct, handling potential errors
@@ -71,6 +159,31 @@ async fn main() -> Result<()> {
         },
     };
 
+    // Serve the Prometheus metrics in the background if requested
+    if let Ok(metrics_addr) = std::env::var("METRICS_ADDR") {
+        match metrics_addr.parse::<SocketAddr>() {
+            Ok(addr) => {
+                let state = server::ApiState {
+                    db: app.database().clone(),
+                    filter: app.filter().clone(),
+                };
+                tokio::spawn(async move {
+                    if let Err(e) = server::serve_metrics(addr, state).await {
+                        error!("Metrics server stopped: {:?}", e);
+                    }
+                });
+            },
+            Err(e) => {
+                error!("Invalid METRICS_ADDR '{}': {}", metrics_addr, e);
+                println!(
+                    "{} Invalid METRICS_ADDR '{}', metrics are not served.",
+                    "Warning:".yellow(),
+                    metrics_addr
+                );
+            },
+        }
+    }
+
     // Display welcome message
     println!(
         "{}",
@@ -92,6 +205,7 @@ async fn main() -> Result<()> {
             AppState::DbInitialized => {
                 options.push("Re-initialize Database Schema");
                 options.push("Import Data");
+                options.push("Import File (CSV)");
             },
             AppState::DataImported => {
                 options.push("Re-initialize Database Schema");
@@ -99,6 +213,17 @@ async fn main() -> Result<()> {
                 options.push("Find Most Polluted Country");
                 options.push("Calculate Average Air Quality");
                 options.push("Get Measurements by Locality"); // Updated menu text
+                options.push("Show Trend Chart");
+                options.push("Detect Anomalies");
+                options.push("Show Distribution");
+                options.push("Run Query");
+                options.push("Find Nearest Locations");
+                options.push("Export Interpolated Grid");
+                options.push("Export Locations (GeoJSON)");
+                options.push("Export Tables (Parquet/Arrow)");
+                options.push("Import File (CSV)");
+                options.push("Generate HTML Report");
+                options.push("Compare Periods");
             },
         }
         options.push("Exit"); // Always add Exit option
@@ -129,7 +254,14 @@ async fn main() -> Result<()> {
                         None // Don't run a command if input fails
                     },
                 },
-                2 => None, // Exit
+                2 => match cli::prompt_file_import() {
+                    Ok(args) => Some(Commands::ImportFile(Box::new(args))),
+                    Err(e) => {
+                        println!("{} {}", "Failed to get input:".red(), e);
+                        None
+                    },
+                },
+                3 => None, // Exit
                 _ => unreachable!(),
             },
             AppState::DataImported => match selection {
@@ -164,7 +296,107 @@ async fn main() -> Result<()> {
                         },
                     }
                 },
-                5 => None, // Exit
+                5 => {
+                    // Prompt for country, parameter and period needed for the Trend command
+                    let parameters = app.parameters().await;
+                    let args = parameters.and_then(|parameters| {
+                        Ok(TrendArgs {
+                            country: cli::prompt_country()?,
+                            parameter: cli::prompt_parameter(&parameters)?,
+                            days: cli::prompt_period_days()?,
+                        })
+                    });
+                    match args {
+                        Ok(args) => Some(Commands::Trend(args)),
+                        Err(e) => {
+                            println!("{} {}", "Failed to get input:".red(), e);
+                            None
+                        },
+                    }
+                },
+                6 => match cli::prompt_period_days() {
+                    Ok(days) => Some(Commands::DetectAnomalies { days }),
+                    Err(e) => {
+                        println!("{} {}", "Failed to get input:".red(), e);
+                        None
+                    },
+                },
+                7 => {
+                    // Prompt for country, parameter and period needed for the Distribution command
+                    let parameters = app.parameters().await;
+                    let args = parameters.and_then(|parameters| {
+                        Ok(DistributionArgs {
+                            country: cli::prompt_country()?,
+                            parameter: cli::prompt_parameter(&parameters)?,
+                            days: cli::prompt_period_days()?,
+                            groups: vec![DistributionGroup::Locality, DistributionGroup::Sensor],
+                        })
+                    });
+                    match args {
+                        Ok(args) => Some(Commands::Distribution(args)),
+                        Err(e) => {
+                            println!("{} {}", "Failed to get input:".red(), e);
+                            None
+                        },
+                    }
+                },
+                8 => match cli::prompt_query() {
+                    Ok(query) => Some(Commands::Query(query)),
+                    Err(e) => {
+                        println!("{} {}", "Failed to get input:".red(), e);
+                        None
+                    },
+                },
+                9 => match app.parameters().await.and_then(|p| cli::prompt_near(&p)) {
+                    Ok(args) => Some(Commands::Near(args)),
+                    Err(e) => {
+                        println!("{} {}", "Failed to get input:".red(), e);
+                        None
+                    },
+                },
+                10 => match app.parameters().await.and_then(|p| cli::prompt_grid(&p)) {
+                    Ok(args) => Some(Commands::Grid(args)),
+                    Err(e) => {
+                        println!("{} {}", "Failed to get input:".red(), e);
+                        None
+                    },
+                },
+                11 => match cli::prompt_location_export() {
+                    Ok(args) => Some(Commands::ExportLocations(args)),
+                    Err(e) => {
+                        println!("{} {}", "Failed to get input:".red(), e);
+                        None
+                    },
+                },
+                12 => match cli::prompt_table_export() {
+                    Ok(args) => Some(Commands::ExportTables(args)),
+                    Err(e) => {
+                        println!("{} {}", "Failed to get input:".red(), e);
+                        None
+                    },
+                },
+                13 => match cli::prompt_file_import() {
+                    Ok(args) => Some(Commands::ImportFile(Box::new(args))),
+                    Err(e) => {
+                        println!("{} {}", "Failed to get input:".red(), e);
+                        None
+                    },
+                },
+                14 => match cli::prompt_report() {
+                    Ok(options) => Some(Commands::Report(options)),
+                    Err(e) => {
+                        println!("{} {}", "Failed to get input:".red(), e);
+                        None
+                    },
+                },
+                15 => match cli::prompt_compare() {
+                    Ok(args) => Some(Commands::Compare(args)),
+                    Err(e) => {
+                        println!("{} {}", "Failed to get input:".red(), e);
+                        None
+                    },
+                },
+                16 => None, // Exit
                 _ => unreachable!(),
             },
         };
@@ -192,3 +424,169 @@ async fn main() -> Result<()> {
 
     Ok(()) // Indicate successful application termination
 }
+
+/// Serves the analytic queries as a REST API on `addr` until Ctrl+C is pressed.
+///
+/// Only needs the database; the OpenAQ API key is not required.
+async fn serve(addr: SocketAddr) -> Result<()> {
+    dotenv::dotenv().ok();
+    let db = db::Database::new(&cli::database_url()).await?;
+    let state = server::ApiState {
+        db,
+        filter: cli::measurement_filter_from_env(),
+    };
+    println!(
+        "{} http://{} (OpenAPI document at /openapi.json, Ctrl+C to stop)",
+        "Serving the REST API on".cyan().bold(),
+        addr
+    );
+    server::serve(addr, state).await
+}
+
+/// Runs the scheduled imports until SIGTERM or Ctrl+C, optionally serving the Prometheus
+/// metrics on `args.metrics_addr`. The alert rules are evaluated after every import.
+async fn run_daemon(args: cli::DaemonArgs) -> Result<()> {
+    dotenv::dotenv().ok();
+    let config = daemon::DaemonConfig {
+        import_schedule: args.import_schedule,
+        discovery_schedule: args.discovery_schedule,
+        days: args.days,
+        alerts: alerts::AlertEngine::from_env()?,
+        filter: cli::measurement_filter_from_env(),
+    };
+    let api_key = std::env::var("OPENAQ_KEY").map_err(|e| {
+        error!("Required environment variable OPENAQ_KEY is not set.");
+        error::AppError::Env(e)
+    })?;
+    let db = db::Database::new(&cli::database_url()).await?;
+    db.init_schema().await?;
+    let client = api::OpenAQClient::new(api_key);
+
+    if let Some(addr) = args.metrics_addr {
+        let state = server::ApiState {
+            db: db.clone(),
+            filter: config.filter.clone(),
+        };
+        tokio::spawn(async move {
+            if let Err(e) = server::serve_metrics(addr, state).await {
+                error!("Metrics server stopped: {:?}", e);
+            }
+        });
+    }
+
+    println!(
+        "{} imports '{}', discovery '{}' (SIGTERM or Ctrl+C to stop, progress in logs/app.log)",
+        "Daemon running:".cyan().bold(),
+        config.import_schedule,
+        config.discovery_schedule
+    );
+    daemon::run(&db, &client, &config, daemon::shutdown_signal()).await
+}
+
+/// Writes an HTML report without the interactive menu, e.g. from a weekly cron job.
+///
+/// Only needs the database; the OpenAQ API key is not required.
+async fn write_report(options: report::ReportOptions) -> Result<()> {
+    dotenv::dotenv().ok();
+    let db = db::Database::new(&cli::database_url()).await?;
+    report::generate_report(&db, &cli::measurement_filter_from_env(), &options).await?;
+    println!(
+        "{} {}",
+        "Report written to".green(),
+        options.output.display().to_string().cyan()
+    );
+    Ok(())
+}
+
+/// Runs the full-screen dashboard until the user quits.
+///
+/// Only needs the database; without the OpenAQ API key the dashboard cannot import data.
+/// The alert rules are evaluated after every import started from the dashboard.
+async fn run_dashboard(import_days: i64) -> Result<()> {
+    dotenv::dotenv().ok();
+    let db = db::Database::new(&cli::database_url()).await?;
+    db.init_schema().await?;
+    let client = std::env::var("OPENAQ_KEY").ok().map(api::OpenAQClient::new);
+    let alerts = alerts::AlertEngine::from_env()?;
+    tui::run(
+        db,
+        client,
+        alerts,
+        cli::measurement_filter_from_env(),
+        import_days,
+    )
+    .await
+}
+
+/// Imports measurements from CSV files without the interactive menu, then evaluates the
+/// alert rules if anything was inserted.
+///
+/// Only needs the database; the OpenAQ API key is not required.
+async fn import_file(args: &cli::ImportFileArgs) -> Result<()> {
+    let args = args.file_import()?;
+    let db = open_database().await?;
+    if cli::import_files(&db, &args).await? > 0 {
+        let alerts = alerts::AlertEngine::from_env()?;
+        cli::report_alerts(alerts.as_ref(), &db, &cli::measurement_filter_from_env()).await;
+    }
+    Ok(())
+}
+
+/// Connects to the database of the `health`, `locations` and `sensors` run modes, creating
+/// missing tables and columns (e.g. of the metadata history) first.
+///
+/// Only needs the database; the OpenAQ API key is not required.
+async fn open_database() -> Result<db::Database> {
+    dotenv::dotenv().ok();
+    let db = db::Database::new(&cli::database_url()).await?;
+    db.init_schema().await?;
+    Ok(db)
+}
+
+/// Prints the latest and the latest successful run of each daemon job. The schema is
+/// initialized first, so a fresh database reports that no jobs have run yet.
+async fn status() -> Result<()> {
+    let db = open_database().await?;
+    let statuses = db.get_job_status().await?;
+    if statuses.is_empty() {
+        println!("{}", "The daemon has not run any jobs yet.".yellow());
+        return Ok(());
+    }
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(vec![
+            Cell::new("Job").fg(Color::Green),
+            Cell::new("Last Run").fg(Color::Green),
+            Cell::new("Status").fg(Color::Green),
+            Cell::new("Last Success").fg(Color::Green),
+            Cell::new("Rows").fg(Color::Green),
+            Cell::new("Error").fg(Color::Green),
+        ]);
+    for status in &statuses {
+        let status_color = match status.last_status.as_str() {
+            "success" => Color::Green,
+            "failed" => Color::Red,
+            _ => Color::Yellow,
+        };
+        table.add_row(vec![
+            Cell::new(&status.job).fg(Color::Cyan),
+            Cell::new(status.last_started_at.format("%Y-%m-%d %H:%M:%S UTC")),
+            Cell::new(&status.last_status).fg(status_color),
+            Cell::new(status.last_success_at.map_or_else(
+                || "never".to_string(),
+                |t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            )),
+            Cell::new(
+                status
+                    .last_success_rows
+                    .map_or_else(|| "-".to_string(), |r| r.to_string()),
+            ),
+            Cell::new(status.last_error.as_deref().unwrap_or("-")),
+        ]);
+    }
+    println!("{table}");
+    Ok(())
+}
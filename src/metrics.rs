@@ -0,0 +1,295 @@
+//! Prometheus metrics of the imports, the OpenAQ client, the database and the latest air quality.
+//!
+//! All metrics live in one process-wide registry (see `metrics()`), rendered in the Prometheus
+//! text format by `Metrics::encode` and served on `/metrics` (see `server`). The latest value
+//! gauges are refreshed by the metrics server in the background, after every import (see
+//! `Metrics::latest_values_changed`) and otherwise every `LATEST_VALUES_REFRESH`.
+
+use crate::error::Result;
+use crate::models::CityLatestMeasurements;
+use prometheus::{
+    histogram_opts, opts, Counter, GaugeVec, Histogram, HistogramTimer, HistogramVec, IntCounter,
+    IntCounterVec, Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Parameters exported as `air_quality_latest_value` gauges.
+pub const GAUGE_PARAMETERS: [&str; 2] = ["pm25", "pm10"];
+
+/// Longest time between two refreshes of the latest value gauges.
+pub const LATEST_VALUES_REFRESH: Duration = Duration::from_secs(300);
+
+/// The registered metrics.
+pub struct Metrics {
+    registry: Registry,
+    /// Duration of complete API imports.
+    pub import_duration: Histogram,
+    /// Measurement fetches retried during imports.
+    pub import_retries: IntCounter,
+    /// OpenAQ API requests by endpoint and HTTP status code (`error` for network failures).
+    pub api_requests: IntCounterVec,
+    /// Sleeps caused by a nearly exhausted OpenAQ rate limit.
+    pub rate_limit_sleeps: IntCounter,
+    /// Time spent in those sleeps.
+    pub rate_limit_sleep_seconds: Counter,
+    /// Rows written by table and outcome (`inserted` or `ignored` as duplicates).
+    pub rows: IntCounterVec,
+    /// Latency of database queries by query name.
+    pub db_query_duration: HistogramVec,
+    /// Latest normalised value per country, locality and parameter.
+    pub latest_value: GaugeVec,
+    /// Wakes the refresh of the latest value gauges once new measurements are stored.
+    latest_values_stale: Notify,
+}
+
+impl Metrics {
+    fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("air_quality".to_string()), None)?;
+        let metrics = Self {
+            import_duration: Histogram::with_opts(histogram_opts!(
+                "import_duration_seconds",
+                "Duration of complete API imports.",
+                vec![1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0]
+            ))?,
+            import_retries: IntCounter::with_opts(opts!(
+                "import_retries_total",
+                "Measurement fetches retried during imports."
+            ))?,
+            api_requests: IntCounterVec::new(
+                opts!(
+                    "api_requests_total",
+                    "OpenAQ API requests by endpoint and status code."
+                ),
+                &["endpoint", "status"],
+            )?,
+            rate_limit_sleeps: IntCounter::with_opts(opts!(
+                "rate_limit_sleeps_total",
+                "Sleeps caused by a nearly exhausted OpenAQ rate limit."
+            ))?,
+            rate_limit_sleep_seconds: Counter::with_opts(opts!(
+                "rate_limit_sleep_seconds_total",
+                "Time spent sleeping on the OpenAQ rate limit."
+            ))?,
+            rows: IntCounterVec::new(
+                opts!(
+                    "rows_total",
+                    "Rows written by table and outcome (inserted or ignored)."
+                ),
+                &["table", "outcome"],
+            )?,
+            db_query_duration: HistogramVec::new(
+                histogram_opts!(
+                    "db_query_duration_seconds",
+                    "Latency of database queries by query name.",
+                    vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+                ),
+                &["query"],
+            )?,
+            latest_value: GaugeVec::new(
+                opts!(
+                    "latest_value",
+                    "Latest normalised value per country, locality and parameter."
+                ),
+                &["country", "locality", "parameter", "unit"],
+            )?,
+            latest_values_stale: Notify::new(),
+            registry,
+        };
+        metrics
+            .registry
+            .register(Box::new(metrics.import_duration.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.import_retries.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.api_requests.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.rate_limit_sleeps.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.rate_limit_sleep_seconds.clone()))?;
+        metrics.registry.register(Box::new(metrics.rows.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.db_query_duration.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.latest_value.clone()))?;
+        Ok(metrics)
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Metrics` if encoding fails.
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = String::new();
+        TextEncoder::new().encode_utf8(&self.registry.gather(), &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Starts timing a database query; the latency is recorded when the timer is dropped.
+    pub fn query_timer(&self, query: &str) -> HistogramTimer {
+        self.db_query_duration
+            .with_label_values(&[query])
+            .start_timer()
+    }
+
+    /// Counts an OpenAQ API request by endpoint and status (`None` for network failures).
+    pub fn record_api_request(&self, endpoint: &str, status: Option<u16>) {
+        let status = status.map_or_else(|| "error".to_string(), |s| s.to_string());
+        self.api_requests
+            .with_label_values(&[endpoint, status.as_str()])
+            .inc();
+    }
+
+    /// Counts the outcome of writing `attempted` rows to `table`, of which `inserted` were new.
+    pub fn record_rows(&self, table: &str, attempted: usize, inserted: u64) {
+        let inserted = inserted.min(attempted as u64);
+        self.rows
+            .with_label_values(&[table, "inserted"])
+            .inc_by(inserted);
+        self.rows
+            .with_label_values(&[table, "ignored"])
+            .inc_by(attempted as u64 - inserted);
+    }
+
+    /// Signals that an import or daemon job stored new measurements, so the latest value
+    /// gauges are refreshed without waiting for `LATEST_VALUES_REFRESH`. Signals arriving while
+    /// no refresh is waiting are combined into one.
+    pub fn latest_values_changed(&self) {
+        self.latest_values_stale.notify_one();
+    }
+
+    /// Waits for the next `latest_values_changed` signal.
+    pub async fn latest_values_stale(&self) {
+        self.latest_values_stale.notified().await;
+    }
+
+    /// Replaces the latest value gauges with the PM2.5/PM10 values per locality of each
+    /// country, dropping the gauges of localities that are no longer reported.
+    pub fn set_latest_values(&self, countries: &[(&str, Vec<CityLatestMeasurements>)]) {
+        self.latest_value.reset();
+        for (country, localities) in countries {
+            for locality in localities {
+                for value in &locality.values {
+                    let Some(v) = value.value else {
+                        continue;
+                    };
+                    if !GAUGE_PARAMETERS.contains(&value.parameter.as_str()) {
+                        continue;
+                    }
+                    self.latest_value
+                        .with_label_values(&[
+                            country,
+                            locality.locality.as_str(),
+                            value.parameter.as_str(),
+                            value.unit.as_deref().unwrap_or(""),
+                        ])
+                        .set(v);
+                }
+            }
+        }
+    }
+}
+
+static METRICS: LazyLock<Metrics> =
+    LazyLock::new(|| Metrics::new().expect("metric definitions are valid"));
+
+/// Returns the process-wide metrics.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ParameterValue;
+    use chrono::Utc;
+
+    fn locality(name: &str, values: &[(&str, f64)]) -> CityLatestMeasurements {
+        CityLatestMeasurements {
+            locality: name.to_string(),
+            values: values
+                .iter()
+                .map(|(parameter, value)| ParameterValue {
+                    parameter: parameter.to_string(),
+                    display_name: None,
+                    unit: Some("µg/m³".to_string()),
+                    value: Some(*value),
+                })
+                .collect(),
+            last_updated: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_encode_metrics() {
+        let metrics = Metrics::new().unwrap();
+        metrics.record_api_request("locations", Some(200));
+        metrics.record_api_request("locations", None);
+        metrics.record_rows("measurements", 5, 3);
+        drop(metrics.query_timer("insert_measurements"));
+
+        let text = metrics.encode().unwrap();
+        assert!(
+            text.contains(r#"air_quality_api_requests_total{endpoint="locations",status="200"} 1"#)
+        );
+        assert!(text
+            .contains(r#"air_quality_api_requests_total{endpoint="locations",status="error"} 1"#));
+        assert!(
+            text.contains(r#"air_quality_rows_total{outcome="ignored",table="measurements"} 2"#)
+        );
+        assert!(
+            text.contains(r#"air_quality_rows_total{outcome="inserted",table="measurements"} 3"#)
+        );
+        assert!(text.contains(
+            r#"air_quality_db_query_duration_seconds_count{query="insert_measurements"} 1"#
+        ));
+    }
+
+    #[test]
+    fn test_latest_value_gauges_are_replaced() {
+        let metrics = Metrics::new().unwrap();
+        metrics.set_latest_values(&[
+            (
+                "NL",
+                vec![
+                    locality("Utrecht", &[("pm25", 12.5), ("no2", 30.0)]),
+                    locality("Amsterdam", &[("pm10", 20.0)]),
+                ],
+            ),
+            ("DE", vec![locality("Berlin", &[("pm25", 8.0)])]),
+        ]);
+        let text = metrics.encode().unwrap();
+        assert!(text.contains(r#"locality="Utrecht",parameter="pm25",unit="µg/m³"} 12.5"#));
+        assert!(text.contains("Berlin"));
+        assert!(!text.contains(r#"parameter="no2""#));
+
+        // A refresh without Utrecht drops its gauge.
+        metrics.set_latest_values(&[("NL", vec![locality("Amsterdam", &[("pm10", 21.0)])])]);
+        let text = metrics.encode().unwrap();
+        assert!(!text.contains("Utrecht"));
+        assert!(text.contains(r#"locality="Amsterdam",parameter="pm10",unit="µg/m³"} 21"#));
+    }
+
+    #[tokio::test]
+    async fn test_latest_values_changed_wakes_the_refresh() {
+        let metrics = Metrics::new().unwrap();
+        // Signals sent before the refresh waits are combined into one.
+        metrics.latest_values_changed();
+        metrics.latest_values_changed();
+        let wait = Duration::from_millis(100);
+        assert!(tokio::time::timeout(wait, metrics.latest_values_stale())
+            .await
+            .is_ok());
+        assert!(tokio::time::timeout(wait, metrics.latest_values_stale())
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Defines the scope, daily values and stored state of the alert rules (see `alerts`).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The measurements an alert rule watches. Unset fields do not restrict the scope.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AlertScope {
+    /// 2-letter country code.
+    #[serde(default)]
+    pub country: Option<String>,
+    /// Locality (city) name, as stored in `measurements.city`.
+    #[serde(default)]
+    pub locality: Option<String>,
+    /// ID of a single location.
+    #[serde(default)]
+    pub location_id: Option<i64>,
+}
+
+impl AlertScope {
+    /// Returns `true` if no field restricts the scope.
+    pub fn is_unrestricted(&self) -> bool {
+        self.country.is_none() && self.locality.is_none() && self.location_id.is_none()
+    }
+}
+
+impl fmt::Display for AlertScope {
+    /// Formats the scope as e.g. `NL/Utrecht` or `location 1234`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(country) = &self.country {
+            parts.push(country.clone());
+        }
+        if let Some(locality) = &self.locality {
+            parts.push(locality.clone());
+        }
+        if let Some(id) = self.location_id {
+            parts.push(format!("location {}", id));
+        }
+        if parts.is_empty() {
+            f.write_str("all locations")
+        } else {
+            f.write_str(&parts.join("/"))
+        }
+    }
+}
+
+/// The average normalised value of a parameter within an alert scope on one day.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ScopeDailyValue {
+    /// Start of the day (UTC).
+    pub day: DateTime<Utc>,
+    pub value: f64,
+    pub unit: Option<String>,
+}
+
+/// State of an alert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+impl AlertState {
+    /// Returns the lowercase label stored in `alerts.status`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertState::Firing => "firing",
+            AlertState::Resolved => "resolved",
+        }
+    }
+}
+
+/// An alert as stored in the `alerts` table.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct Alert {
+    pub id: i32,
+    /// Name of the rule that raised the alert.
+    pub rule: String,
+    /// Status label (`firing` or `resolved`).
+    pub status: String,
+    pub fired_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    /// First day of the breach that fired the alert.
+    pub first_day: DateTime<Utc>,
+    /// Latest day evaluated: the last breaching day, or the day that resolved the alert.
+    pub last_day: DateTime<Utc>,
+    /// Daily value of `last_day`.
+    pub value: Option<f64>,
+    pub threshold: f64,
+    pub unit: Option<String>,
+}
+
+/// A change of an alert's state, as sent to the notifiers.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AlertEvent {
+    pub state: AlertState,
+    pub parameter: String,
+    pub scope: AlertScope,
+    /// Comparison of the rule (`>`, `>=`, `<` or `<=`).
+    pub comparator: String,
+    /// Number of consecutive days the rule requires.
+    pub duration_days: u32,
+    pub alert: Alert,
+}
+
+impl AlertEvent {
+    /// Returns a one-line description, e.g.
+    /// `[FIRING] utrecht-pm25: pm25 in NL/Utrecht > 25 µg/m³ for 3 days (31.2 on 2024-03-10)`.
+    pub fn summary(&self) -> String {
+        let threshold = match &self.alert.unit {
+            Some(unit) => format!("{} {}", self.alert.threshold, unit),
+            None => self.alert.threshold.to_string(),
+        };
+        let value = self
+            .alert
+            .value
+            .map_or_else(|| "no value".to_string(), |v| format!("{:.1}", v));
+        format!(
+            "[{}] {}: {} in {} {} {} for {} day{} ({} on {})",
+            self.state.as_str().to_uppercase(),
+            self.alert.rule,
+            self.parameter,
+            self.scope,
+            self.comparator,
+            threshold,
+            self.duration_days,
+            if self.duration_days == 1 { "" } else { "s" },
+            value,
+            self.alert.last_day.format("%Y-%m-%d"),
+        )
+    }
+}
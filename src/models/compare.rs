@@ -0,0 +1,211 @@
+//! Defines the periods and results of the period comparison.
+
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, NaiveTime, Utc};
+use serde::Serialize;
+use std::fmt;
+
+/// An inclusive range of UTC days.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl DateRange {
+    pub fn new(start: NaiveDate, end: NaiveDate) -> Self {
+        Self { start, end }
+    }
+
+    /// The last `days` days up to and including `today`.
+    pub fn last_days(today: NaiveDate, days: i64) -> Self {
+        Self::new(today - Duration::days(days - 1), today)
+    }
+
+    /// The current week so far, from Monday up to and including `today`.
+    pub fn week_to_date(today: NaiveDate) -> Self {
+        let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+        Self::new(monday, today)
+    }
+
+    /// Number of days in the period.
+    pub fn days(&self) -> i64 {
+        (self.end - self.start).num_days() + 1
+    }
+
+    /// The period moved by `days` days (backwards for negative values).
+    pub fn shifted(&self, days: i64) -> Self {
+        Self::new(
+            self.start + Duration::days(days),
+            self.end + Duration::days(days),
+        )
+    }
+
+    /// The period of the same length right before this one.
+    pub fn preceding(&self) -> Self {
+        self.shifted(-self.days())
+    }
+
+    /// The same calendar days one year earlier (29 February becomes 28 February).
+    pub fn year_before(&self) -> Self {
+        let year_before = |day: NaiveDate| day - Months::new(12);
+        Self::new(year_before(self.start), year_before(self.end))
+    }
+
+    pub fn overlaps(&self, other: &DateRange) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    /// Start of the first day (UTC).
+    pub fn start_utc(&self) -> DateTime<Utc> {
+        self.start.and_time(NaiveTime::MIN).and_utc()
+    }
+
+    /// Start of the day after the last day (UTC), the exclusive upper bound.
+    pub fn end_utc(&self) -> DateTime<Utc> {
+        (self.end + Duration::days(1))
+            .and_time(NaiveTime::MIN)
+            .and_utc()
+    }
+}
+
+impl fmt::Display for DateRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} to {}", self.start, self.end)
+    }
+}
+
+/// The sum and number of the daily values of one sensor within one of the compared periods.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct SensorPeriodStats {
+    pub parameter: String,
+    /// Display name from the parameter catalogue, if the parameter is known.
+    pub display_name: Option<String>,
+    /// Normalised unit of the values.
+    pub unit: Option<String>,
+    pub sensor_id: i64,
+    pub location_name: String,
+    /// `true` for the current period, `false` for the baseline.
+    pub in_current: bool,
+    /// Sum of the normalised daily values.
+    pub total: f64,
+    /// Number of daily values (sensor days).
+    pub days: i64,
+}
+
+/// The average of a parameter over a period.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct PeriodAverage {
+    /// Average of the daily values, `None` without values.
+    pub value: Option<f64>,
+    pub sensor_days: i64,
+    pub sensors: usize,
+}
+
+/// Difference between the current and the baseline average.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Change {
+    pub absolute: f64,
+    /// Change relative to the baseline, `None` if the baseline is zero.
+    pub percent: Option<f64>,
+}
+
+impl Change {
+    /// Compares two averages, `None` unless both have a value.
+    pub fn between(baseline: &PeriodAverage, current: &PeriodAverage) -> Option<Self> {
+        let (baseline, current) = (baseline.value?, current.value?);
+        Some(Self {
+            absolute: current - baseline,
+            percent: (baseline != 0.0).then(|| (current - baseline) / baseline.abs() * 100.0),
+        })
+    }
+}
+
+/// The averages of a parameter in both periods, over all sensors and over the sensors that
+/// reported in both periods.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParameterComparison {
+    pub parameter: String,
+    pub display_name: Option<String>,
+    pub unit: Option<String>,
+    pub baseline: PeriodAverage,
+    pub current: PeriodAverage,
+    /// Baseline average of the sensors with values in both periods.
+    pub common_baseline: PeriodAverage,
+    /// Current average of the sensors with values in both periods.
+    pub common_current: PeriodAverage,
+}
+
+impl ParameterComparison {
+    /// Returns the display name, falling back to the upper-cased parameter name.
+    pub fn label(&self) -> String {
+        self.display_name
+            .clone()
+            .unwrap_or_else(|| self.parameter.to_uppercase())
+    }
+
+    /// Change over all sensors.
+    pub fn change(&self) -> Option<Change> {
+        Change::between(&self.baseline, &self.current)
+    }
+
+    /// Change over the sensors that reported in both periods, unaffected by sensor churn.
+    pub fn common_change(&self) -> Option<Change> {
+        Change::between(&self.common_baseline, &self.common_current)
+    }
+}
+
+/// The periods in which a sensor reported values.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SensorPresence {
+    pub sensor_id: i64,
+    pub location_name: String,
+    pub parameter: String,
+    pub in_baseline: bool,
+    pub in_current: bool,
+}
+
+/// The comparison of two periods for a country or locality.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeriodComparison {
+    /// Parameters in catalogue order.
+    pub parameters: Vec<ParameterComparison>,
+    /// Every sensor with values in at least one period.
+    pub sensors: Vec<SensorPresence>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_periods() {
+        // Thursday
+        let today = day(2024, 3, 7);
+        let week = DateRange::week_to_date(today);
+        assert_eq!(week, DateRange::new(day(2024, 3, 4), today));
+        assert_eq!(week.days(), 4);
+        assert_eq!(
+            week.shifted(-7),
+            DateRange::new(day(2024, 2, 26), day(2024, 2, 29))
+        );
+
+        let month = DateRange::last_days(today, 30);
+        assert_eq!(month.start, day(2024, 2, 7));
+        assert_eq!(
+            month.preceding(),
+            DateRange::new(day(2024, 1, 8), day(2024, 2, 6))
+        );
+        assert!(!month.overlaps(&month.preceding()));
+        assert!(month.overlaps(&week));
+
+        let leap = DateRange::new(day(2024, 2, 29), day(2024, 3, 1)).year_before();
+        assert_eq!(leap, DateRange::new(day(2023, 2, 28), day(2023, 3, 1)));
+
+        assert_eq!(week.to_string(), "2024-03-04 to 2024-03-07");
+        assert_eq!(week.end_utc() - week.start_utc(), Duration::days(4));
+    }
+}
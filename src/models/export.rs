@@ -0,0 +1,45 @@
+//! Defines the raw table rows read by the columnar export.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::types::Decimal;
+
+/// A complete row of the `measurements` table, as written by the columnar export.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct MeasurementRecord {
+    pub id: i32,
+    pub location_id: i64,
+    pub sensor_id: i64,
+    pub location_name: String,
+    pub parameter_id: i32,
+    pub parameter_name: String,
+    pub value_avg: Option<Decimal>,
+    pub value_min: Option<Decimal>,
+    pub value_max: Option<Decimal>,
+    pub value_q02: Option<Decimal>,
+    pub value_q25: Option<Decimal>,
+    pub value_median: Option<Decimal>,
+    pub value_q75: Option<Decimal>,
+    pub value_q98: Option<Decimal>,
+    pub value_sd: Option<Decimal>,
+    pub measurement_count: Option<i32>,
+    pub expected_count: Option<i32>,
+    pub percent_complete: Option<f64>,
+    pub percent_coverage: Option<f64>,
+    pub unit: String,
+    pub unit_normalized: Option<String>,
+    pub unit_factor: Option<f64>,
+    pub value_normalized: Option<Decimal>,
+    pub date_utc: DateTime<Utc>,
+    pub date_local: String,
+    pub country: String,
+    pub city: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub is_mobile: bool,
+    pub is_monitor: bool,
+    pub owner_name: Option<String>,
+    pub provider_name: Option<String>,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,165 @@
+//! Defines the thresholds and results of the sensor health check (`health` run mode).
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+/// When a sensor counts as stale and a locality's coverage as shrinking.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthThresholds {
+    /// Days without a stored value after which a sensor is silent.
+    pub stale_days: i64,
+    /// Days without a measurement according to OpenAQ after which a location is offline.
+    pub source_stale_days: i64,
+    /// Length of the two compared coverage windows, in days.
+    pub coverage_days: i64,
+    /// Drop of the stored sensor days, in percent, from which a locality's coverage shrinks.
+    pub shrink_percent: f64,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            stale_days: 3,
+            source_stale_days: 7,
+            coverage_days: 14,
+            shrink_percent: 25.0,
+        }
+    }
+}
+
+/// The latest data of a stored sensor, in our database and according to OpenAQ.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct SensorActivity {
+    pub sensor_id: i64,
+    pub location_id: i64,
+    pub location_name: Option<String>,
+    pub locality: Option<String>,
+    pub country_code: String,
+    pub parameter_name: String,
+    /// Day of the latest stored value, `None` if nothing was stored.
+    pub last_stored: Option<DateTime<Utc>>,
+    /// Latest measurement of the location according to OpenAQ (`datetime_last`).
+    pub source_last: Option<DateTime<Utc>>,
+    /// Set while the sensor is marked inactive and skipped by the imports.
+    pub inactive_since: Option<DateTime<Utc>>,
+}
+
+/// Health of a sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SensorHealth {
+    Healthy,
+    /// OpenAQ has recent data of the location, but we have not stored any from this sensor.
+    Silent,
+    /// OpenAQ itself has no recent data of the location.
+    Offline,
+}
+
+impl SensorHealth {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SensorHealth::Healthy => "healthy",
+            SensorHealth::Silent => "silent",
+            SensorHealth::Offline => "offline",
+        }
+    }
+}
+
+impl SensorActivity {
+    /// Classifies the sensor at `now`. Without a `datetime_last` from OpenAQ, only the stored
+    /// data is considered.
+    pub fn health(&self, now: DateTime<Utc>, thresholds: &HealthThresholds) -> SensorHealth {
+        let older_than = |time: Option<DateTime<Utc>>, days: i64| {
+            time.is_none_or(|time| now - time > Duration::days(days))
+        };
+        if self.source_last.is_some() && older_than(self.source_last, thresholds.source_stale_days)
+        {
+            SensorHealth::Offline
+        } else if older_than(self.last_stored, thresholds.stale_days) {
+            SensorHealth::Silent
+        } else {
+            SensorHealth::Healthy
+        }
+    }
+}
+
+/// The stored sensor days of a locality in the current and the preceding window.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct LocalityCoverage {
+    pub country: String,
+    pub locality: String,
+    /// Stored daily values (sensor days) in the preceding window.
+    pub previous_days: i64,
+    /// Stored daily values (sensor days) in the current window.
+    pub current_days: i64,
+    /// Number of sensors with values in the preceding window.
+    pub previous_sensors: i64,
+    /// Number of sensors with values in the current window.
+    pub current_sensors: i64,
+}
+
+impl LocalityCoverage {
+    /// Change of the sensor days relative to the preceding window, `None` if it had none.
+    pub fn change_percent(&self) -> Option<f64> {
+        (self.previous_days > 0).then(|| {
+            (self.current_days - self.previous_days) as f64 / self.previous_days as f64 * 100.0
+        })
+    }
+
+    pub fn is_shrinking(&self, thresholds: &HealthThresholds) -> bool {
+        self.change_percent()
+            .is_some_and(|change| change <= -thresholds.shrink_percent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn activity(last_stored: Option<i64>, source_last: Option<i64>) -> SensorActivity {
+        let now = Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        SensorActivity {
+            sensor_id: 1,
+            location_id: 1,
+            location_name: None,
+            locality: None,
+            country_code: "NL".to_string(),
+            parameter_name: "pm25".to_string(),
+            last_stored: last_stored.map(|days| now - Duration::days(days)),
+            source_last: source_last.map(|days| now - Duration::days(days)),
+            inactive_since: None,
+        }
+    }
+
+    #[test]
+    fn test_sensor_health() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        let thresholds = HealthThresholds::default();
+        let health =
+            |last_stored, source_last| activity(last_stored, source_last).health(now, &thresholds);
+        assert_eq!(health(Some(1), Some(0)), SensorHealth::Healthy);
+        assert_eq!(health(Some(3), None), SensorHealth::Healthy);
+        assert_eq!(health(Some(4), Some(0)), SensorHealth::Silent);
+        assert_eq!(health(None, Some(0)), SensorHealth::Silent);
+        assert_eq!(health(None, None), SensorHealth::Silent);
+        assert_eq!(health(Some(30), Some(8)), SensorHealth::Offline);
+    }
+
+    #[test]
+    fn test_locality_coverage() {
+        let thresholds = HealthThresholds::default();
+        let coverage = |previous_days, current_days| LocalityCoverage {
+            country: "NL".to_string(),
+            locality: "Utrecht".to_string(),
+            previous_days,
+            current_days,
+            previous_sensors: 2,
+            current_sensors: 1,
+        };
+        assert_eq!(coverage(40, 30).change_percent(), Some(-25.0));
+        assert!(coverage(40, 30).is_shrinking(&thresholds));
+        assert!(!coverage(40, 31).is_shrinking(&thresholds));
+        assert_eq!(coverage(0, 10).change_percent(), None);
+        assert!(!coverage(0, 0).is_shrinking(&thresholds));
+    }
+}
@@ -0,0 +1,49 @@
+//! Defines the versions of the location and sensor metadata (`location_versions` and
+//! `sensor_versions` tables) and the changes derived from them.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// The metadata of a location while it did not change, as stored in `location_versions`.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct LocationVersion {
+    pub location_id: i64,
+    pub name: Option<String>,
+    pub locality: Option<String>,
+    pub country_code: String,
+    pub timezone: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub is_mobile: bool,
+    pub is_monitor: bool,
+    pub owner_name: Option<String>,
+    pub provider_name: Option<String>,
+    /// Last measurement according to OpenAQ while this version was current.
+    pub datetime_last: Option<DateTime<Utc>>,
+    pub valid_from: DateTime<Utc>,
+    /// `None` for the current version.
+    pub valid_to: Option<DateTime<Utc>>,
+}
+
+/// The metadata of a sensor while it did not change, as stored in `sensor_versions`.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct SensorVersion {
+    pub sensor_id: i64,
+    pub location_id: i64,
+    pub name: String,
+    pub parameter_name: String,
+    pub units: String,
+    pub valid_from: DateTime<Utc>,
+    /// `None` for the current version. A closed version without a successor means the sensor
+    /// was removed from its location.
+    pub valid_to: Option<DateTime<Utc>>,
+}
+
+/// A change of the metadata, derived from two consecutive versions.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetadataChange {
+    pub at: DateTime<Utc>,
+    /// `None` for changes of the location itself.
+    pub sensor_id: Option<i64>,
+    pub description: String,
+}
@@ -0,0 +1,144 @@
+//! Defines the filters and rows of the location and sensor inventory (`locations` and `sensors`
+//! run modes).
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+/// Restricts the inventory listings. Every unset field matches everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InventoryFilter {
+    pub location_id: Option<i64>,
+    pub sensor_id: Option<i64>,
+    /// Two-letter country code, matched case-insensitively.
+    pub country: Option<String>,
+    /// Locality (city), matched case-insensitively.
+    pub locality: Option<String>,
+    /// Part of the provider name, matched case-insensitively.
+    pub provider: Option<String>,
+    /// Part of the owner name, matched case-insensitively.
+    pub owner: Option<String>,
+    /// Parameter name (e.g. `pm25`); locations match if any of their sensors measures it.
+    pub parameter: Option<String>,
+    /// `Some(true)` for reference monitors only, `Some(false)` for low-cost sensors only.
+    pub monitor: Option<bool>,
+    /// Only entries with values on or after this day (UTC), according to OpenAQ or our data.
+    pub active_since: Option<NaiveDate>,
+}
+
+/// Share of the days between the first and the last stored day that have a value, `None`
+/// without stored data.
+pub fn coverage_percent(
+    first_stored: Option<NaiveDate>,
+    last_stored: Option<NaiveDate>,
+    stored_days: i64,
+) -> Option<f64> {
+    let span = (last_stored? - first_stored?).num_days() + 1;
+    (span > 0).then(|| stored_days as f64 / span as f64 * 100.0)
+}
+
+/// A stored location with its sensors and the extent of its stored measurements.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct LocationSummary {
+    pub id: i64,
+    pub name: Option<String>,
+    pub locality: Option<String>,
+    pub country_code: String,
+    pub provider_name: Option<String>,
+    pub owner_name: Option<String>,
+    pub is_monitor: bool,
+    pub is_mobile: bool,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// First and last measurement according to OpenAQ.
+    pub datetime_first: Option<DateTime<Utc>>,
+    pub datetime_last: Option<DateTime<Utc>>,
+    /// Number of sensors in the `sensors` table.
+    pub sensors: i64,
+    /// Parameter names of the sensors, sorted.
+    pub parameters: Vec<String>,
+    pub first_stored: Option<NaiveDate>,
+    pub last_stored: Option<NaiveDate>,
+    /// Number of distinct days with at least one stored value.
+    pub stored_days: i64,
+}
+
+impl LocationSummary {
+    pub fn coverage_percent(&self) -> Option<f64> {
+        coverage_percent(self.first_stored, self.last_stored, self.stored_days)
+    }
+}
+
+/// A stored sensor with its location and the extent of its stored measurements.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct SensorSummary {
+    pub id: i64,
+    pub location_id: i64,
+    pub location_name: Option<String>,
+    pub locality: Option<String>,
+    pub country_code: String,
+    pub parameter_name: String,
+    pub units: String,
+    pub is_monitor: bool,
+    pub provider_name: Option<String>,
+    pub owner_name: Option<String>,
+    pub first_stored: Option<NaiveDate>,
+    pub last_stored: Option<NaiveDate>,
+    /// Number of stored daily values.
+    pub stored_days: i64,
+    /// Set while the health check marked the sensor inactive.
+    pub inactive_since: Option<DateTime<Utc>>,
+}
+
+impl SensorSummary {
+    pub fn coverage_percent(&self) -> Option<f64> {
+        coverage_percent(self.first_stored, self.last_stored, self.stored_days)
+    }
+}
+
+/// Number of stored days of a sensor in one calendar month.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct MonthlyCoverage {
+    /// First day of the month.
+    pub month: NaiveDate,
+    pub stored_days: i64,
+    /// Number of days in the month.
+    pub month_days: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sensor(first: Option<(u32, u32)>, last: Option<(u32, u32)>, days: i64) -> SensorSummary {
+        let day = |(month, day): (u32, u32)| NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+        SensorSummary {
+            id: 1,
+            location_id: 1,
+            location_name: None,
+            locality: None,
+            country_code: "NL".to_string(),
+            parameter_name: "pm25".to_string(),
+            units: "µg/m³".to_string(),
+            is_monitor: true,
+            provider_name: None,
+            owner_name: None,
+            first_stored: first.map(day),
+            last_stored: last.map(day),
+            stored_days: days,
+            inactive_since: None,
+        }
+    }
+
+    #[test]
+    fn test_coverage_percent() {
+        assert_eq!(
+            sensor(Some((3, 1)), Some((3, 10)), 5).coverage_percent(),
+            Some(50.0)
+        );
+        assert_eq!(
+            sensor(Some((3, 1)), Some((3, 1)), 1).coverage_percent(),
+            Some(100.0)
+        );
+        assert_eq!(sensor(None, None, 0).coverage_percent(), None);
+    }
+}
@@ -0,0 +1,48 @@
+//! Defines the records of the scheduled daemon jobs (see `daemon`).
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Outcome of a job run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum JobRunStatus {
+    Success,
+    Failed,
+    /// Not run because another instance held the import lock.
+    Skipped,
+}
+
+impl JobRunStatus {
+    /// Returns the lowercase label stored in `job_runs.status`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobRunStatus::Success => "success",
+            JobRunStatus::Failed => "failed",
+            JobRunStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// One run of a daemon job, as stored in the `job_runs` table.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobRun {
+    pub job: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub status: JobRunStatus,
+    /// Number of rows fetched (imports) or sensors found (discovery).
+    pub rows: i64,
+    pub error: Option<String>,
+}
+
+/// The latest run and the latest successful run of a job.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct JobStatus {
+    pub job: String,
+    pub last_started_at: DateTime<Utc>,
+    /// Status label of the latest run.
+    pub last_status: String,
+    pub last_error: Option<String>,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_success_rows: Option<i64>,
+}
@@ -3,6 +3,36 @@
 //! This typically includes structures representing data fetched from APIs,
 //! data stored in the database, and data used for internal processing or display.
 
+mod alerts;
+mod compare;
+mod export;
+mod health;
+mod history;
+mod inventory;
+mod jobs;
 mod openaq;
+mod overview;
+mod parameters;
+mod quality;
+mod query;
+mod report;
+mod retention;
+mod spatial;
+mod units;
 
+pub use alerts::*;
+pub use compare::*;
+pub use export::*;
+pub use health::*;
+pub use history::*;
+pub use inventory::*;
+pub use jobs::*;
 pub use openaq::*;
+pub use overview::*;
+pub use parameters::*;
+pub use quality::*;
+pub use query::*;
+pub use report::*;
+pub use retention::*;
+pub use spatial::*;
+pub use units::*;
@@ -5,8 +5,9 @@
 //! - Representing data stored in the database (`DbMeasurement`).
 //! - Structuring results for CLI output (`CityLatestMeasurements`, `CountryAirQuality`, `PollutionRanking`).
 
+use super::{normalization, ParameterValue, UnitConflict};
 use chrono::{DateTime, Utc};
-use num_traits::FromPrimitive;
+use num_traits::{FromPrimitive, ToPrimitive};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sqlx::types::Decimal;
@@ -185,7 +186,6 @@ pub struct Period {
 /// Represents summary statistics for an aggregated period.
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
-#[allow(dead_code)] // Allow unused fields like 'q02', 'median', etc.
 pub struct Summary {
     pub min: Option<f64>,
     pub q02: Option<f64>,
@@ -215,6 +215,9 @@ pub struct Coverage {
 
 // --- Database and Output Structs ---
 
+/// Name of the OpenAQ API data source, stored in the `source` column of its rows.
+pub const OPENAQ_SOURCE: &str = "openaq";
+
 /// Represents a daily aggregated measurement structured for storage in the PostgreSQL database.
 #[derive(Debug, Serialize, Clone, sqlx::FromRow)]
 pub struct DbMeasurement {
@@ -233,9 +236,33 @@ pub struct DbMeasurement {
     pub value_min: Option<Decimal>,
     /// Maximum value for the day (stored as Decimal).
     pub value_max: Option<Decimal>,
+    /// 2nd percentile of the day's observations.
+    pub value_q02: Option<Decimal>,
+    /// 25th percentile of the day's observations.
+    pub value_q25: Option<Decimal>,
+    /// Median of the day's observations.
+    pub value_median: Option<Decimal>,
+    /// 75th percentile of the day's observations.
+    pub value_q75: Option<Decimal>,
+    /// 98th percentile of the day's observations.
+    pub value_q98: Option<Decimal>,
+    /// Standard deviation of the day's observations.
+    pub value_sd: Option<Decimal>,
     /// Number of measurements observed during the day.
     pub measurement_count: Option<i32>,
+    /// Number of measurements expected during the day (e.g., 24 for hourly sensors).
+    pub expected_count: Option<i32>,
+    /// Share of the expected measurements that were observed (0-100).
+    pub percent_complete: Option<f64>,
+    /// Share of the day covered by the observed measurements (0-100).
+    pub percent_coverage: Option<f64>,
     pub unit: String,
+    /// Unit all values of this parameter are normalised to (see `models::units`).
+    pub unit_normalized: String,
+    /// Factor converting values in `unit` into `unit_normalized`.
+    pub unit_factor: f64,
+    /// `value_avg` converted into `unit_normalized`; used by the analytic queries.
+    pub value_normalized: Option<Decimal>,
     /// Start date/time (UTC) of the aggregation period (day).
     pub date_utc: DateTime<Utc>,
     /// Start date/time (local) of the aggregation period (day).
@@ -248,6 +275,8 @@ pub struct DbMeasurement {
     pub is_monitor: bool,
     pub owner_name: String,
     pub provider_name: String,
+    /// Name of the data source the measurement was imported from (e.g. "openaq").
+    pub source: String,
 }
 
 impl DbMeasurement {
@@ -261,7 +290,12 @@ impl DbMeasurement {
         let avg_val = m.summary.as_ref().and_then(|s| s.avg).unwrap_or(m.value);
         let min_val = m.summary.as_ref().and_then(|s| s.min);
         let max_val = m.summary.as_ref().and_then(|s| s.max);
+        let summary_val = |f: fn(&Summary) -> Option<f64>| m.summary.as_ref().and_then(f);
         let measurement_count = m.coverage.as_ref().and_then(|c| c.observed_count);
+        let expected_count = m.coverage.as_ref().and_then(|c| c.expected_count);
+        let percent_complete = m.coverage.as_ref().and_then(|c| c.percent_complete);
+        let percent_coverage = m.coverage.as_ref().and_then(|c| c.percent_coverage);
+        let (unit_normalized, unit_factor) = normalization(&m.parameter.name, &m.parameter.units);
 
         // Helper to convert Option<f64> to Option<Decimal>, filtering out negative values
         let to_decimal_opt = |val: Option<f64>| -> Option<Decimal> {
@@ -291,6 +325,15 @@ impl DbMeasurement {
             None // Store None (NULL) if avg_val is negative
         };
 
+        // Identity conversions keep the exact stored value.
+        let value_normalized = if unit_factor == 1.0 {
+            value_avg_decimal_opt
+        } else {
+            value_avg_decimal_opt
+                .and_then(|v| v.to_f64())
+                .and_then(|v| Decimal::from_f64(v * unit_factor))
+        };
+
         Self {
             id: None,
             location_id: location.id as i64,
@@ -306,8 +349,20 @@ impl DbMeasurement {
             value_avg: value_avg_decimal_opt, // Assign the Option<Decimal> directly
             value_min: to_decimal_opt(min_val), // Use helper which now filters negatives
             value_max: to_decimal_opt(max_val), // Use helper which now filters negatives
+            value_q02: to_decimal_opt(summary_val(|s| s.q02)),
+            value_q25: to_decimal_opt(summary_val(|s| s.q25)),
+            value_median: to_decimal_opt(summary_val(|s| s.median)),
+            value_q75: to_decimal_opt(summary_val(|s| s.q75)),
+            value_q98: to_decimal_opt(summary_val(|s| s.q98)),
+            value_sd: to_decimal_opt(summary_val(|s| s.sd)),
             measurement_count,
+            expected_count,
+            percent_complete,
+            percent_coverage,
             unit: m.parameter.units.clone(),
+            unit_normalized: unit_normalized.to_string(),
+            unit_factor,
+            value_normalized,
             date_utc: m.period.datetime_from.utc, // Use the start of the daily period
             date_local: m.period.datetime_from.local.clone(),
             country: location.country.code.clone(),
@@ -318,29 +373,91 @@ impl DbMeasurement {
             is_monitor: location.is_monitor,
             owner_name: location.owner.name.clone(),
             provider_name: location.provider.name.clone(),
+            source: OPENAQ_SOURCE.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+impl DbMeasurement {
+    /// A complete day of 10 µg/m³ measured by a fixed monitor in Utrecht, for tests. The
+    /// location shares the sensor's ID. Tests override the fields they check with struct
+    /// update syntax, or set the value with `with_value`/`with_value_in`.
+    pub(crate) fn test(
+        sensor_id: i64,
+        country: &str,
+        parameter: &str,
+        date_utc: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            id: None,
+            location_id: sensor_id,
+            sensor_id,
+            sensor_name: format!("Sensor {}", sensor_id),
+            location_name: format!("Station {}", sensor_id),
+            parameter_id: 2,
+            parameter_name: parameter.to_string(),
+            parameter_display_name: None,
+            value_avg: None,
+            value_min: None,
+            value_max: None,
+            value_q02: None,
+            value_q25: None,
+            value_median: None,
+            value_q75: None,
+            value_q98: None,
+            value_sd: None,
+            measurement_count: Some(24),
+            expected_count: Some(24),
+            percent_complete: Some(100.0),
+            percent_coverage: Some(100.0),
+            unit: String::new(),
+            unit_normalized: String::new(),
+            unit_factor: 1.0,
+            value_normalized: None,
+            date_utc,
+            date_local: date_utc.to_rfc3339(),
+            country: country.to_string(),
+            city: Some("Utrecht".to_string()),
+            latitude: Some(52.0),
+            longitude: Some(5.0),
+            is_mobile: false,
+            is_monitor: true,
+            owner_name: "Test Owner".to_string(),
+            provider_name: "Test Provider".to_string(),
+            source: OPENAQ_SOURCE.to_string(),
+        }
+        .with_value(10.0)
+    }
+
+    /// Sets the day's average to `value` µg/m³.
+    pub(crate) fn with_value(self, value: f64) -> Self {
+        self.with_value_in(value, "µg/m³")
+    }
+
+    /// Sets the day's average to `value` in the reported `unit`, normalised like an import.
+    pub(crate) fn with_value_in(self, value: f64, unit: &str) -> Self {
+        let (unit_normalized, unit_factor) = normalization(&self.parameter_name, unit);
+        Self {
+            value_avg: Decimal::from_f64(value),
+            unit: unit.to_string(),
+            unit_normalized: unit_normalized.to_string(),
+            unit_factor,
+            value_normalized: Decimal::from_f64(value * unit_factor),
+            ..self
         }
     }
 }
 
-/// Represents the latest measurement value for each pollutant within a specific city.
-/// Used as the result type for the "Get Measurements by City" query. Derives `sqlx::FromRow`.
-#[derive(Debug, Clone, sqlx::FromRow)]
+/// Represents the latest measurement value for each parameter within a specific city.
+/// Used as the result type for the "Get Measurements by City" query.
+#[derive(Debug, Clone, Serialize)]
 pub struct CityLatestMeasurements {
     /// The name of the locality (often a city).
-    #[sqlx(rename = "city")] // Map the 'city' column from the query result to this field
     pub locality: String,
-    /// Latest PM2.5 value (Decimal for precision).
-    pub pm25: Option<Decimal>,
-    /// Latest PM10 value (Decimal for precision).
-    pub pm10: Option<Decimal>,
-    /// Latest O3 value (Decimal for precision).
-    pub o3: Option<Decimal>,
-    /// Latest NO2 value (Decimal for precision).
-    pub no2: Option<Decimal>,
-    /// Latest SO2 value (Decimal for precision).
-    pub so2: Option<Decimal>,
-    /// Latest CO value (Decimal for precision).
-    pub co: Option<Decimal>,
+    /// Latest normalised value per parameter measured in the locality, in catalogue order.
+    pub values: Vec<ParameterValue>,
     /// Timestamp of the most recent measurement update among any parameter for this city.
     pub last_updated: DateTime<Utc>,
 }
@@ -350,14 +467,14 @@ pub struct CityLatestMeasurements {
 #[derive(Debug, Serialize, Clone)]
 pub struct CountryAirQuality {
     pub country: String,
-    pub avg_pm25: Option<f64>,
-    pub avg_pm10: Option<f64>,
-    pub avg_o3: Option<f64>,
-    pub avg_no2: Option<f64>,
-    pub avg_so2: Option<f64>,
-    pub avg_co: Option<f64>,
+    /// Average normalised value per parameter measured in the country, in catalogue order.
+    pub averages: Vec<ParameterValue>,
     /// The total number of measurements contributing to the averages within the period.
     pub measurement_count: i64,
+    /// The number of sensor days within the period excluded by the data quality filters.
+    pub excluded_days: i64,
+    /// Parameters left out of the averages because their units could not be normalised.
+    pub unit_conflicts: Vec<UnitConflict>,
 }
 
 /// Represents the pollution ranking for a country based on a calculated index.
@@ -372,6 +489,10 @@ pub struct PollutionRanking {
     pub pm25_avg: Option<f64>,
     /// The average PM10 value (µg/m³) used in the index calculation (if available).
     pub pm10_avg: Option<f64>,
+    /// The number of PM2.5/PM10 sensor days excluded by the data quality filters.
+    pub excluded_days: i64,
+    /// PM2.5/PM10 left out of the index because their units could not be normalised.
+    pub unit_conflicts: Vec<UnitConflict>,
 }
 
 impl PollutionRanking {
@@ -383,6 +504,44 @@ impl PollutionRanking {
             pollution_index: 0.0, // Default to 0 index when no data
             pm25_avg: None,
             pm10_avg: None,
+            excluded_days: 0,
+            unit_conflicts: Vec::new(),
         }
     }
 }
+
+/// Represents the average value of one parameter across all sensors of a country for a single day.
+/// Used as the data points of trend charts.
+#[derive(Debug, Serialize, Clone)]
+pub struct DailyAverage {
+    /// Start (UTC) of the day the average refers to.
+    pub day: DateTime<Utc>,
+    /// Average of the daily sensor averages for that day.
+    pub value: f64,
+    /// Number of sensors contributing to the average.
+    pub sensor_count: i64,
+}
+
+/// Summarises the distribution of the daily values of one parameter for a sensor or a locality
+/// over a period. Used as the result type for the "Show Distribution" query.
+///
+/// Quantiles are the means of the daily quantiles reported by OpenAQ over the period,
+/// and `peak_q98` is the highest daily 98th percentile.
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct DistributionSummary {
+    /// Sensor name or locality name, depending on the grouping.
+    pub label: String,
+    /// Locality of the sensor (`None` for locality rows).
+    pub locality: Option<String>,
+    /// Number of days with data in the period.
+    pub days: i64,
+    pub q02: Option<f64>,
+    pub q25: Option<f64>,
+    pub median: Option<f64>,
+    pub q75: Option<f64>,
+    pub q98: Option<f64>,
+    /// Highest daily 98th percentile in the period.
+    pub peak_q98: Option<f64>,
+    /// Mean of the daily averages in the period.
+    pub mean: Option<f64>,
+}
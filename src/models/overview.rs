@@ -0,0 +1,21 @@
+//! Defines the per-country overview of the stored data.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// The amount and time span of the data stored for one country.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct CountryOverview {
+    /// 2-letter country code.
+    pub code: String,
+    /// Country name, if any location of the country is stored.
+    pub name: Option<String>,
+    pub locations: i64,
+    pub sensors: i64,
+    /// Number of daily measurements.
+    pub measurements: i64,
+    /// Earliest measurement day (UTC).
+    pub first_day: Option<DateTime<Utc>>,
+    /// Latest measurement day (UTC).
+    pub last_day: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,60 @@
+//! Defines the parameter catalogue (pollutants and meteorological quantities measured by
+//! OpenAQ sensors) and the per-parameter values used by the dynamic analytic results.
+
+use super::MetaV3;
+use serde::{Deserialize, Serialize};
+
+/// A parameter from the OpenAQ `/v3/parameters` catalogue, stored in the `parameters` table.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Parameter {
+    /// OpenAQ parameter ID.
+    pub id: i32,
+    /// Short parameter name as used in measurements (e.g., "pm25", "relativehumidity").
+    pub name: String,
+    /// Unit the parameter is usually reported in.
+    pub units: String,
+    /// Human readable name (e.g., "PM2.5").
+    pub display_name: Option<String>,
+    /// Longer description of the parameter.
+    pub description: Option<String>,
+}
+
+/// Response structure for the `/v3/parameters` endpoint.
+#[derive(Debug, Deserialize, Clone)]
+#[allow(dead_code)] // Allow unused fields like 'meta'
+pub struct ParametersResponse {
+    pub meta: MetaV3,
+    pub results: Vec<Parameter>,
+}
+
+/// The value of one parameter within a dynamic result set (averages per country,
+/// latest values per locality).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParameterValue {
+    /// Parameter name (e.g., "pm25").
+    pub parameter: String,
+    /// Display name from the parameter catalogue, if the parameter is known.
+    pub display_name: Option<String>,
+    /// Normalised unit of the value.
+    pub unit: Option<String>,
+    /// The value itself (`None` if no usable value was stored).
+    pub value: Option<f64>,
+}
+
+impl ParameterValue {
+    /// Returns the display name, falling back to the upper-cased parameter name.
+    pub fn label(&self) -> String {
+        self.display_name
+            .clone()
+            .unwrap_or_else(|| self.parameter.to_uppercase())
+    }
+}
+
+/// Finds the value of `parameter` in a list of parameter values.
+pub fn parameter_value(values: &[ParameterValue], parameter: &str) -> Option<f64> {
+    values
+        .iter()
+        .find(|v| v.parameter == parameter)
+        .and_then(|v| v.value)
+}
@@ -0,0 +1,86 @@
+//! Defines data structures related to data quality.
+//!
+//! Includes:
+//! - `MeasurementFilter`: Options controlling which stored measurements analytic queries use.
+//! - `SensorReading`: A daily sensor value loaded for anomaly detection.
+//! - `AnomalyFlag` / `AnomalyKind`: Results of anomaly detection stored in `measurement_flags`.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Options controlling which rows of the `measurements` table are used by the analytic queries
+/// (rankings, averages, latest values and series).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasurementFilter {
+    /// Skip measurements that have at least one entry in `measurement_flags`.
+    pub exclude_flagged: bool,
+    /// Skip daily measurements whose `percent_complete` is below this percentage (0-100).
+    /// Measurements without coverage information are kept.
+    pub min_completeness: Option<f64>,
+}
+
+/// Default minimum share of expected observations (in percent) for a day to be used.
+pub const DEFAULT_MIN_COMPLETENESS: f64 = 75.0;
+
+impl Default for MeasurementFilter {
+    /// Flagged measurements and days less than 75% complete are excluded by default.
+    fn default() -> Self {
+        Self {
+            exclude_flagged: true,
+            min_completeness: Some(DEFAULT_MIN_COMPLETENESS),
+        }
+    }
+}
+
+/// A single daily sensor value, as loaded from the `measurements` table for anomaly detection.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SensorReading {
+    /// Primary key of the row in `measurements`.
+    pub measurement_id: i32,
+    pub sensor_id: i64,
+    pub parameter_name: String,
+    pub date_utc: DateTime<Utc>,
+    /// Daily average value.
+    pub value: f64,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+/// The kind of anomaly detected for a measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum AnomalyKind {
+    /// Value is far from the sensor's own median (robust z-score based on the MAD).
+    Outlier,
+    /// The sensor reported exactly the same value for several consecutive days.
+    FlatLine,
+    /// Value jumped by a large factor compared to the sensor's preceding days.
+    Spike,
+    /// Value differs strongly from nearby sensors measuring the same parameter on the same day.
+    NeighbourDeviation,
+}
+
+impl AnomalyKind {
+    /// Returns the identifier stored in the `flag` column of `measurement_flags`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnomalyKind::Outlier => "outlier",
+            AnomalyKind::FlatLine => "flat_line",
+            AnomalyKind::Spike => "spike",
+            AnomalyKind::NeighbourDeviation => "neighbour_deviation",
+        }
+    }
+}
+
+/// An anomaly detected for a single stored measurement.
+#[derive(Debug, Clone, Serialize)]
+pub struct AnomalyFlag {
+    /// Primary key of the flagged row in `measurements`.
+    pub measurement_id: i32,
+    pub sensor_id: i64,
+    pub date_utc: DateTime<Utc>,
+    pub kind: AnomalyKind,
+    /// Detector-specific score (e.g. the robust z-score or the spike factor).
+    pub score: f64,
+    /// Human readable explanation of why the value was flagged.
+    pub detail: String,
+}
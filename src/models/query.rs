@@ -0,0 +1,137 @@
+//! Defines the specification and result rows of the general long-format measurement query
+//! (see `Database::query_measurements`).
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// Spatial level measurements are grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ValueEnum)]
+pub enum SpatialGrouping {
+    Country,
+    Locality,
+    Location,
+    Sensor,
+}
+
+impl SpatialGrouping {
+    /// All groupings, in the order offered by the CLI.
+    pub const ALL: [SpatialGrouping; 4] = [
+        SpatialGrouping::Country,
+        SpatialGrouping::Locality,
+        SpatialGrouping::Location,
+        SpatialGrouping::Sensor,
+    ];
+
+    /// Returns a lowercase label for the grouping.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpatialGrouping::Country => "country",
+            SpatialGrouping::Locality => "locality",
+            SpatialGrouping::Location => "location",
+            SpatialGrouping::Sensor => "sensor",
+        }
+    }
+}
+
+/// Time bucket measurements are grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ValueEnum)]
+pub enum TimeBucket {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl TimeBucket {
+    /// All buckets, in the order offered by the CLI.
+    pub const ALL: [TimeBucket; 4] = [
+        TimeBucket::Day,
+        TimeBucket::Week,
+        TimeBucket::Month,
+        TimeBucket::Year,
+    ];
+
+    /// Returns the PostgreSQL `date_trunc` field name for the bucket.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeBucket::Day => "day",
+            TimeBucket::Week => "week",
+            TimeBucket::Month => "month",
+            TimeBucket::Year => "year",
+        }
+    }
+}
+
+/// Filters and grouping of a long-format measurement query.
+///
+/// Empty filter lists do not restrict the query. Rows are always grouped by parameter and
+/// normalised unit in addition to the spatial and temporal grouping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasurementQuery {
+    /// Country codes (e.g., "NL").
+    pub countries: Vec<String>,
+    /// Locality (city) names.
+    pub localities: Vec<String>,
+    /// OpenAQ location IDs.
+    pub location_ids: Vec<i64>,
+    /// OpenAQ sensor IDs.
+    pub sensor_ids: Vec<i64>,
+    /// Parameter names (e.g., "pm25").
+    pub parameters: Vec<String>,
+    /// Only include days starting at or after this instant.
+    pub date_from: Option<DateTime<Utc>>,
+    /// Only include days starting before this instant.
+    pub date_to: Option<DateTime<Utc>>,
+    /// Only include reference-grade monitors.
+    pub monitor_only: bool,
+    /// Only include measurements from this provider (exact name).
+    pub provider: Option<String>,
+    pub spatial: SpatialGrouping,
+    pub bucket: TimeBucket,
+    /// Maximum number of rows to return.
+    pub limit: Option<i64>,
+    /// Number of rows to skip before the first returned row.
+    pub offset: Option<i64>,
+}
+
+impl Default for MeasurementQuery {
+    /// An unrestricted query grouped per country and day.
+    fn default() -> Self {
+        Self {
+            countries: Vec::new(),
+            localities: Vec::new(),
+            location_ids: Vec::new(),
+            sensor_ids: Vec::new(),
+            parameters: Vec::new(),
+            date_from: None,
+            date_to: None,
+            monitor_only: false,
+            provider: None,
+            spatial: SpatialGrouping::Country,
+            bucket: TimeBucket::Day,
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
+/// One long-format result row: the aggregate of one parameter for one group and time bucket.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct QueryRow {
+    /// Label of the spatial group (country code, locality, location or sensor).
+    pub group_key: String,
+    /// Start of the time bucket (UTC).
+    pub period: DateTime<Utc>,
+    pub parameter: String,
+    /// Normalised unit of the values.
+    pub unit: Option<String>,
+    /// Mean of the daily averages in the bucket.
+    pub value: Option<f64>,
+    /// Lowest daily minimum in the bucket.
+    pub min: Option<f64>,
+    /// Highest daily maximum in the bucket.
+    pub max: Option<f64>,
+    /// Number of daily measurements aggregated.
+    pub count: i64,
+}
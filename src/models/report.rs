@@ -0,0 +1,69 @@
+//! Defines the air quality guidelines and their exceedances shown in the HTML report.
+
+use serde::Serialize;
+
+/// A 24-hour guideline level of a parameter, in the parameter's normalised unit (µg/m³).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Guideline {
+    pub parameter: &'static str,
+    pub limit: f64,
+}
+
+/// The WHO 2021 air quality guideline levels for 24-hour means.
+pub const WHO_DAILY_GUIDELINES: [Guideline; 5] = [
+    Guideline {
+        parameter: "pm25",
+        limit: 15.0,
+    },
+    Guideline {
+        parameter: "pm10",
+        limit: 45.0,
+    },
+    Guideline {
+        parameter: "no2",
+        limit: 25.0,
+    },
+    Guideline {
+        parameter: "so2",
+        limit: 40.0,
+    },
+    Guideline {
+        parameter: "co",
+        limit: 4000.0,
+    },
+];
+
+/// Returns the daily guideline level of `parameter`, if it has one.
+pub fn daily_guideline(parameter: &str) -> Option<f64> {
+    WHO_DAILY_GUIDELINES
+        .iter()
+        .find(|g| g.parameter == parameter)
+        .map(|g| g.limit)
+}
+
+/// How often the daily sensor averages of one parameter in a country exceeded its guideline
+/// level over a period.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct GuidelineExceedance {
+    pub country: String,
+    pub parameter: String,
+    /// Guideline level (µg/m³).
+    pub limit: f64,
+    /// Number of sensor days above the guideline level.
+    pub exceedance_days: i64,
+    /// Number of sensor days with a value.
+    pub sensor_days: i64,
+    /// Highest daily sensor average of the period.
+    pub max_value: f64,
+}
+
+impl GuidelineExceedance {
+    /// Returns the share of sensor days above the guideline level, in percent.
+    pub fn exceedance_percent(&self) -> f64 {
+        if self.sensor_days == 0 {
+            0.0
+        } else {
+            self.exceedance_days as f64 * 100.0 / self.sensor_days as f64
+        }
+    }
+}
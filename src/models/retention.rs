@@ -0,0 +1,237 @@
+//! Retention policies of the stored daily measurements and the rows a `prune` run removes.
+//!
+//! ```toml
+//! # Days of daily measurements kept when no rule matches.
+//! keep_days = 1825
+//!
+//! [[rules]]
+//! parameter = "pm25"
+//! country = "NL"
+//! keep_days = 3650
+//! ```
+
+use crate::error::{AppError, Result};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Days of daily measurements kept when neither the policies file nor `--keep-days` says
+/// otherwise (five years).
+pub const DEFAULT_KEEP_DAYS: i64 = 1825;
+
+/// Policies file used when `RETENTION_POLICIES` is not set, if it exists.
+pub const DEFAULT_RETENTION_POLICIES: &str = "retention.toml";
+
+/// How long measurements of a parameter and/or country are kept.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionRule {
+    /// Parameter name, e.g. pm25; all parameters if unset.
+    pub parameter: Option<String>,
+    /// Two-letter country code; all countries if unset.
+    pub country: Option<String>,
+    pub keep_days: i64,
+}
+
+/// The retention policies: the first matching rule decides how long a measurement is kept,
+/// `keep_days` applies to the measurements no rule matches.
+///
+/// There is a single tier, for daily measurements: the API import fetches daily aggregates and
+/// the file import aggregates its observations to one row per sensor and day, so no finer
+/// resolution is ever stored that would need a shorter retention of its own.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionPolicy {
+    #[serde(default = "default_keep_days")]
+    pub keep_days: i64,
+    #[serde(default)]
+    pub rules: Vec<RetentionRule>,
+}
+
+fn default_keep_days() -> i64 {
+    DEFAULT_KEEP_DAYS
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            keep_days: DEFAULT_KEEP_DAYS,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// The rules of a policy resolved to cutoff times, in the order they are matched. Unset
+/// parameters and countries match everything; the last entry is the policy's default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionCutoffs {
+    pub parameters: Vec<Option<String>>,
+    pub countries: Vec<Option<String>>,
+    /// Measurements of days before the cutoff are pruned.
+    pub cutoffs: Vec<DateTime<Utc>>,
+}
+
+impl RetentionPolicy {
+    /// Parses and validates a TOML policies file's contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Retention` if the contents are not valid TOML or a rule keeps less
+    /// than one day or has neither a parameter nor a country.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut policy: RetentionPolicy = toml::from_str(contents)
+            .map_err(|e| AppError::Retention(format!("invalid policies file: {}", e)))?;
+        for rule in &mut policy.rules {
+            if let Some(country) = &mut rule.country {
+                *country = country.to_uppercase();
+            }
+        }
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Reads and parses the policies file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AppError::Io` if the file cannot be read, or `AppError::Retention` if it is
+    /// invalid.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let policy = Self::parse(&contents)
+            .map_err(|e| AppError::Retention(format!("{}: {}", path.display(), e)))?;
+        info!(
+            "Loaded retention policy with {} rules from {}",
+            policy.rules.len(),
+            path.display()
+        );
+        Ok(policy)
+    }
+
+    /// Loads `path`, else the file named by `RETENTION_POLICIES`, else `retention.toml` if it
+    /// exists. Falls back to the default policy (five years for everything).
+    ///
+    /// # Errors
+    ///
+    /// Returns an `AppError` if the policies file cannot be loaded (see `load`).
+    pub fn from_file_or_env(path: Option<&Path>) -> Result<Self> {
+        let path = match (path, std::env::var("RETENTION_POLICIES")) {
+            (Some(path), _) => path.to_path_buf(),
+            (None, Ok(path)) => PathBuf::from(path),
+            (None, Err(_)) if Path::new(DEFAULT_RETENTION_POLICIES).exists() => {
+                PathBuf::from(DEFAULT_RETENTION_POLICIES)
+            },
+            (None, Err(_)) => return Ok(Self::default()),
+        };
+        Self::load(&path)
+    }
+
+    /// Checks that every rule keeps at least one day and is scoped to a parameter or country.
+    fn validate(&self) -> Result<()> {
+        if self.keep_days < 1 {
+            return Err(AppError::Retention(format!(
+                "keep_days must be at least 1, got {}",
+                self.keep_days
+            )));
+        }
+        for (index, rule) in self.rules.iter().enumerate() {
+            if rule.keep_days < 1 {
+                return Err(AppError::Retention(format!(
+                    "rule {} must keep at least 1 day, got {}",
+                    index + 1,
+                    rule.keep_days
+                )));
+            }
+            if rule.parameter.is_none() && rule.country.is_none() {
+                return Err(AppError::Retention(format!(
+                    "rule {} needs a parameter or a country; use keep_days for the default",
+                    index + 1
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves the rules to the start of the first kept day, counting back from `today`.
+    pub fn cutoffs(&self, today: NaiveDate) -> RetentionCutoffs {
+        let cutoff = |keep_days: i64| {
+            (today - Duration::days(keep_days))
+                .and_time(NaiveTime::MIN)
+                .and_utc()
+        };
+        let mut resolved = RetentionCutoffs::default();
+        for rule in &self.rules {
+            resolved.parameters.push(rule.parameter.clone());
+            resolved.countries.push(rule.country.clone());
+            resolved.cutoffs.push(cutoff(rule.keep_days));
+        }
+        resolved.parameters.push(None);
+        resolved.countries.push(None);
+        resolved.cutoffs.push(cutoff(self.keep_days));
+        resolved
+    }
+}
+
+/// The measurements of one country and parameter beyond their retention.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct PruneCount {
+    pub country: String,
+    pub parameter_name: String,
+    pub rows: i64,
+    pub first_day: DateTime<Utc>,
+    pub last_day: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policy() {
+        let policy = RetentionPolicy::parse(
+            r#"
+            keep_days = 90
+
+            [[rules]]
+            parameter = "pm25"
+            country = "nl"
+            keep_days = 3650
+            "#,
+        )
+        .unwrap();
+        assert_eq!(policy.keep_days, 90);
+        assert_eq!(policy.rules[0].country.as_deref(), Some("NL"));
+        assert_eq!(
+            RetentionPolicy::parse("").unwrap(),
+            RetentionPolicy::default()
+        );
+
+        let invalid = |contents: &str| RetentionPolicy::parse(contents).is_err();
+        assert!(invalid("keep_days = 0"));
+        assert!(invalid("keep = 10"));
+        assert!(invalid("[[rules]]\nkeep_days = 10"));
+        assert!(invalid("[[rules]]\nparameter = \"o3\"\nkeep_days = -1"));
+    }
+
+    #[test]
+    fn test_cutoffs() {
+        let policy = RetentionPolicy {
+            keep_days: 10,
+            rules: vec![RetentionRule {
+                parameter: Some("pm25".to_string()),
+                country: None,
+                keep_days: 30,
+            }],
+        };
+        let cutoffs = policy.cutoffs(NaiveDate::from_ymd_opt(2024, 3, 31).unwrap());
+        assert_eq!(cutoffs.parameters, [Some("pm25".to_string()), None]);
+        assert_eq!(cutoffs.countries, [None, None]);
+        let days: Vec<String> = cutoffs
+            .cutoffs
+            .iter()
+            .map(|c| c.format("%Y-%m-%d %H:%M").to_string())
+            .collect();
+        assert_eq!(days, ["2024-03-01 00:00", "2024-03-21 00:00"]);
+    }
+}
@@ -0,0 +1,98 @@
+//! Defines data structures for spatial lookups over the stored location coordinates.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// A location with coordinates and the latest and recent-average values of one parameter.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct LocationValues {
+    pub location_id: i64,
+    pub name: Option<String>,
+    pub locality: Option<String>,
+    pub country: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Normalised unit of the values (`None` if the location has no recent values).
+    pub unit: Option<String>,
+    /// Most recent daily average.
+    pub latest_value: Option<f64>,
+    /// Day of the most recent daily average (UTC).
+    pub latest_date: Option<DateTime<Utc>>,
+    /// Mean of the daily averages over the requested period.
+    pub recent_average: Option<f64>,
+    /// Number of daily sensor values in the period.
+    pub days: i64,
+}
+
+/// A location found near a point, with its great-circle distance to that point.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NearbyLocation {
+    pub location: LocationValues,
+    pub distance_km: f64,
+}
+
+/// The value of one parameter at one location on one day, used as an interpolation sample.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct PointValue {
+    pub location_id: i64,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Normalised unit of the value.
+    pub unit: String,
+    /// Mean of the daily averages of the location's sensors.
+    pub value: f64,
+}
+
+/// A row of the `locations` table.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct StoredLocation {
+    pub id: i64,
+    pub name: Option<String>,
+    pub locality: Option<String>,
+    pub country_code: String,
+    pub country_name: String,
+    pub timezone: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub is_mobile: bool,
+    pub is_monitor: bool,
+    pub owner_name: Option<String>,
+    pub provider_name: Option<String>,
+    /// Data source that provided the location (e.g. "openaq").
+    pub source: String,
+    /// First time the location reported a measurement, according to OpenAQ.
+    pub datetime_first: Option<DateTime<Utc>>,
+    /// Last time the location reported a measurement, according to OpenAQ.
+    pub datetime_last: Option<DateTime<Utc>>,
+}
+
+/// A row of the `sensors` table.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct StoredSensor {
+    pub id: i64,
+    pub location_id: i64,
+    pub name: String,
+    pub parameter_name: String,
+    pub units: String,
+    /// Data source that provided the sensor (e.g. "openaq").
+    pub source: String,
+}
+
+/// The latest and averaged recent values of one parameter at one location.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct LocationParameterValues {
+    pub location_id: i64,
+    pub parameter: String,
+    /// Display name from the parameter catalogue, if the parameter is known.
+    pub display_name: Option<String>,
+    /// Normalised unit of the values.
+    pub unit: Option<String>,
+    /// Daily average on the most recent day, averaged over the location's sensors.
+    pub latest: Option<f64>,
+    /// Most recent day with a value.
+    pub latest_date: DateTime<Utc>,
+    /// Mean of the daily averages over the requested period.
+    pub average: Option<f64>,
+    /// Number of daily sensor values in the period.
+    pub days: i64,
+}
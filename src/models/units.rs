@@ -0,0 +1,230 @@
+//! Parses OpenAQ unit strings and converts measurement values to a common unit per parameter.
+//!
+//! Gases are reported either as mixing ratios (ppm/ppb) or as mass concentrations (µg/m³, mg/m³).
+//! Mixing ratios are converted to µg/m³ using the molecular weight of the gas and the molar volume
+//! of an ideal gas at 25 °C and 1 atm (24.45 L/mol), the convention used by the EPA and the EEA.
+//! All conversions are linear, so a single multiplication factor maps a stored value to its
+//! normalised value.
+
+use serde::Serialize;
+use std::fmt;
+
+/// Molar volume of an ideal gas at 25 °C and 1 atm, in litres per mole.
+pub const MOLAR_VOLUME_L: f64 = 24.45;
+
+/// A unit of measurement reported by OpenAQ.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub enum Unit {
+    /// Micrograms per cubic metre (µg/m³).
+    MicrogramsPerCubicMetre,
+    /// Milligrams per cubic metre (mg/m³).
+    MilligramsPerCubicMetre,
+    /// Parts per million.
+    Ppm,
+    /// Parts per billion.
+    Ppb,
+    /// Any other unit (temperature, humidity, particle counts, ...), kept verbatim.
+    Other(String),
+}
+
+impl Unit {
+    /// Parses an OpenAQ unit string, accepting the common spellings (`µg/m³`, `ug/m3`, `PPM`, ...).
+    pub fn parse(unit: &str) -> Self {
+        let normalized: String = unit
+            .trim()
+            .to_lowercase()
+            .replace('³', "3")
+            .replace(['µ', 'μ'], "u")
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        match normalized.as_str() {
+            "ug/m3" => Unit::MicrogramsPerCubicMetre,
+            "mg/m3" => Unit::MilligramsPerCubicMetre,
+            "ppm" => Unit::Ppm,
+            "ppb" => Unit::Ppb,
+            _ => Unit::Other(unit.trim().to_string()),
+        }
+    }
+
+    /// Returns the display form of the unit, as stored in `measurements.unit_normalized`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Unit::MicrogramsPerCubicMetre => "µg/m³",
+            Unit::MilligramsPerCubicMetre => "mg/m³",
+            Unit::Ppm => "ppm",
+            Unit::Ppb => "ppb",
+            Unit::Other(unit) => unit,
+        }
+    }
+
+    /// Expresses the unit in µg/m³ (mass units) or ppb (mixing ratios), returning the
+    /// factor and whether it is a mass concentration. `None` for other units.
+    fn base(&self) -> Option<(f64, bool)> {
+        match self {
+            Unit::MicrogramsPerCubicMetre => Some((1.0, true)),
+            Unit::MilligramsPerCubicMetre => Some((1000.0, true)),
+            Unit::Ppb => Some((1.0, false)),
+            Unit::Ppm => Some((1000.0, false)),
+            Unit::Other(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Returns the molecular weight (g/mol) of the gaseous parameters that can be converted
+/// between mixing ratios and mass concentrations. NOx is expressed as NO2, by convention.
+pub fn molecular_weight(parameter: &str) -> Option<f64> {
+    match parameter {
+        "co" => Some(28.010),
+        "no" => Some(30.006),
+        "no2" | "nox" => Some(46.0055),
+        "o3" => Some(47.997),
+        "so2" => Some(64.066),
+        "nh3" => Some(17.031),
+        "ch4" => Some(16.043),
+        "co2" => Some(44.009),
+        _ => None,
+    }
+}
+
+/// Returns the factor that converts a value of `parameter` from `from` into `to`, or `None`
+/// when the units are incompatible (e.g. ppm for a parameter without a known molecular weight,
+/// or any conversion involving an unrecognised unit).
+pub fn conversion_factor(parameter: &str, from: &Unit, to: &Unit) -> Option<f64> {
+    if from == to {
+        return Some(1.0);
+    }
+    let (from_factor, from_mass) = from.base()?;
+    let (to_factor, to_mass) = to.base()?;
+    let factor = from_factor / to_factor;
+    match (from_mass, to_mass) {
+        (true, true) | (false, false) => Some(factor),
+        // ppb -> µg/m³: multiply by MW / molar volume
+        (false, true) => Some(factor * molecular_weight(parameter)? / MOLAR_VOLUME_L),
+        (true, false) => Some(factor * MOLAR_VOLUME_L / molecular_weight(parameter)?),
+    }
+}
+
+/// Returns the unit all values of `parameter` are normalised to: µg/m³ for particulates and
+/// for gases with a known molecular weight, otherwise the reported unit itself.
+pub fn canonical_unit(parameter: &str, reported: &Unit) -> Unit {
+    match reported {
+        Unit::Other(_) => reported.clone(),
+        Unit::Ppm | Unit::Ppb if molecular_weight(parameter).is_none() => reported.clone(),
+        _ => Unit::MicrogramsPerCubicMetre,
+    }
+}
+
+/// Parses the reported unit of a parameter and returns its canonical unit together with the
+/// factor converting reported values into it.
+pub fn normalization(parameter: &str, reported_unit: &str) -> (Unit, f64) {
+    let reported = Unit::parse(reported_unit);
+    let canonical = canonical_unit(parameter, &reported);
+    // canonical_unit only picks units reachable from the reported one, so this cannot fail.
+    let factor = conversion_factor(parameter, &reported, &canonical).unwrap_or(1.0);
+    (canonical, factor)
+}
+
+/// A parameter whose values in a country could not be normalised to a single unit. It is left
+/// out of the aggregations over that country rather than averaged across units.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct UnitConflict {
+    pub country: String,
+    pub parameter: String,
+    /// The distinct (normalised) units, sorted.
+    pub units: Vec<String>,
+}
+
+impl fmt::Display for UnitConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.parameter, self.units.join(", "))
+    }
+}
+
+/// Joins conflicts for log and error messages, e.g. `co (mol/l, µg/m³); no2 (ppb, °C)`.
+pub fn describe_unit_conflicts(conflicts: &[UnitConflict]) -> String {
+    conflicts
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn test_parse_common_spellings() {
+        assert_eq!(Unit::parse("µg/m³"), Unit::MicrogramsPerCubicMetre);
+        assert_eq!(Unit::parse("ug/m3"), Unit::MicrogramsPerCubicMetre);
+        assert_eq!(Unit::parse(" PPM "), Unit::Ppm);
+        assert_eq!(Unit::parse("mg/m³"), Unit::MilligramsPerCubicMetre);
+        assert_eq!(Unit::parse("°C"), Unit::Other("°C".to_string()));
+    }
+
+    #[test]
+    fn test_gas_conversions() {
+        // 1 ppm CO ≈ 1145.6 µg/m³ at 25 °C
+        let co = conversion_factor("co", &Unit::Ppm, &Unit::MicrogramsPerCubicMetre).unwrap();
+        assert!(approx(co, 1145.603));
+        // 1 ppb O3 ≈ 1.963 µg/m³
+        let o3 = conversion_factor("o3", &Unit::Ppb, &Unit::MicrogramsPerCubicMetre).unwrap();
+        assert!(approx(o3, 1.963));
+        // And back again
+        let back = conversion_factor("o3", &Unit::MicrogramsPerCubicMetre, &Unit::Ppb).unwrap();
+        assert!(approx(o3 * back, 1.0));
+        assert!(approx(
+            conversion_factor(
+                "pm25",
+                &Unit::MilligramsPerCubicMetre,
+                &Unit::MicrogramsPerCubicMetre
+            )
+            .unwrap(),
+            1000.0
+        ));
+    }
+
+    #[test]
+    fn test_incompatible_units_are_rejected() {
+        // No molecular weight for particulates
+        assert_eq!(
+            conversion_factor("pm25", &Unit::Ppm, &Unit::MicrogramsPerCubicMetre),
+            None
+        );
+        assert_eq!(
+            conversion_factor(
+                "temperature",
+                &Unit::Other("c".to_string()),
+                &Unit::MicrogramsPerCubicMetre
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_normalization() {
+        let (unit, factor) = normalization("no2", "ppb");
+        assert_eq!(unit, Unit::MicrogramsPerCubicMetre);
+        assert!(approx(factor, 1.882));
+
+        let (unit, factor) = normalization("relativehumidity", "%");
+        assert_eq!(unit, Unit::Other("%".to_string()));
+        assert_eq!(factor, 1.0);
+
+        // Mixing ratio of an unknown gas stays as reported
+        let (unit, factor) = normalization("voc", "ppb");
+        assert_eq!(unit, Unit::Ppb);
+        assert_eq!(factor, 1.0);
+    }
+}
@@ -0,0 +1,576 @@
+//! Static HTML reports of the stored data.
+//!
+//! A report combines the country ranking, the per-country averages, the latest values per
+//! locality, the exceedances of the WHO daily guideline levels and SVG charts of the recent
+//! PM2.5/PM10 trends into a single self-contained HTML file. It is built from the same queries
+//! as the interactive commands and rendered with a [MiniJinja](https://docs.rs/minijinja)
+//! template: the built-in `report.html`, or a custom template (e.g. with a company's logo and
+//! colours) passed with `--template` or the `REPORT_TEMPLATE` environment variable.
+
+mod svg;
+
+pub use svg::*;
+
+use crate::cli::{get_country_name_map, COUNTRIES};
+use crate::db::Database;
+use crate::error::{AppError, Result};
+use crate::models::{
+    daily_guideline, CityLatestMeasurements, CountryAirQuality, GuidelineExceedance,
+    MeasurementFilter, PollutionRanking, WHO_DAILY_GUIDELINES,
+};
+use chrono::{DateTime, Duration, Utc};
+use minijinja::{Environment, Value};
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+/// The built-in report template.
+pub const DEFAULT_TEMPLATE: &str = include_str!("report.html");
+
+/// Parameters charted in the trend section of every country.
+const TREND_PARAMETERS: [&str; 2] = ["pm25", "pm10"];
+
+/// Size of the trend charts in pixels.
+const CHART_WIDTH: u32 = 720;
+const CHART_HEIGHT: u32 = 240;
+
+/// Options of a report.
+#[derive(Debug, Clone)]
+pub struct ReportOptions {
+    /// Path of the HTML file to write.
+    pub output: PathBuf,
+    /// Number of past days covered by the ranking, averages, exceedances and trends.
+    pub days: i64,
+    /// Custom template to render instead of the built-in one.
+    pub template: Option<PathBuf>,
+    /// Title shown at the top of the report.
+    pub title: String,
+}
+
+/// Returns the custom template named by the `REPORT_TEMPLATE` environment variable, if set.
+pub fn template_from_env() -> Option<PathBuf> {
+    std::env::var("REPORT_TEMPLATE").ok().map(PathBuf::from)
+}
+
+/// A country in the ranking table.
+#[derive(Debug, Clone, Serialize)]
+pub struct RankingRow {
+    pub rank: usize,
+    pub name: String,
+    #[serde(flatten)]
+    pub ranking: PollutionRanking,
+}
+
+/// A parameter column of a locality table.
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalityColumn {
+    pub parameter: String,
+    /// Header with the display name and unit, e.g. `PM2.5 (µg/m³)`.
+    pub header: String,
+}
+
+/// A row of a locality table, with a value per column (`None` if not measured).
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalityRow {
+    pub locality: String,
+    pub values: Vec<Option<f64>>,
+    pub last_updated: String,
+}
+
+/// A guideline exceedance with its display label and share of the sensor days.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExceedanceRow {
+    pub label: String,
+    pub percent: f64,
+    #[serde(flatten)]
+    pub exceedance: GuidelineExceedance,
+}
+
+/// The section of one country.
+#[derive(Debug, Clone, Serialize)]
+pub struct CountryReport {
+    pub code: String,
+    pub name: String,
+    pub average: CountryAirQuality,
+    pub locality_columns: Vec<LocalityColumn>,
+    pub localities: Vec<LocalityRow>,
+    pub exceedances: Vec<ExceedanceRow>,
+    /// Inline `<svg>` chart of the daily PM2.5 and PM10 averages with their guidelines.
+    pub trend_svg: String,
+}
+
+/// Everything a report template can show.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportData {
+    pub title: String,
+    /// Generation time, formatted as `YYYY-MM-DD HH:MM UTC`.
+    pub generated_at: String,
+    pub days: i64,
+    /// First and last day of the reported period (`YYYY-MM-DD`).
+    pub period_start: String,
+    pub period_end: String,
+    /// Description of the data quality filter applied to all figures.
+    pub quality_note: String,
+    pub ranking: Vec<RankingRow>,
+    pub countries: Vec<CountryReport>,
+}
+
+/// Describes which measurements the data quality `filter` leaves out.
+fn quality_note(filter: &MeasurementFilter) -> String {
+    let mut excluded = Vec::new();
+    if filter.exclude_flagged {
+        excluded.push("flagged measurements".to_string());
+    }
+    if let Some(min) = filter.min_completeness {
+        excluded.push(format!("days less than {}% complete", min));
+    }
+    if excluded.is_empty() {
+        "All measurements are included.".to_string()
+    } else {
+        format!("Excludes {}.", excluded.join(" and "))
+    }
+}
+
+/// Lays out the latest values per locality as a table whose columns are the union of the
+/// parameters measured in any locality, in catalogue order.
+pub fn locality_table(
+    localities: &[CityLatestMeasurements],
+) -> (Vec<LocalityColumn>, Vec<LocalityRow>) {
+    let mut columns: Vec<LocalityColumn> = Vec::new();
+    for value in localities.iter().flat_map(|l| &l.values) {
+        if !columns.iter().any(|c| c.parameter == value.parameter) {
+            let header = match &value.unit {
+                Some(unit) => format!("{} ({})", value.label(), unit),
+                None => value.label(),
+            };
+            columns.push(LocalityColumn {
+                parameter: value.parameter.clone(),
+                header,
+            });
+        }
+    }
+    let rows = localities
+        .iter()
+        .map(|locality| LocalityRow {
+            locality: locality.locality.clone(),
+            values: columns
+                .iter()
+                .map(|column| {
+                    locality
+                        .values
+                        .iter()
+                        .find(|v| v.parameter == column.parameter)
+                        .and_then(|v| v.value)
+                })
+                .collect(),
+            last_updated: locality.last_updated.format("%Y-%m-%d %H:%M").to_string(),
+        })
+        .collect();
+    (columns, rows)
+}
+
+/// Returns the display label of `parameter` from the country's averages, falling back to the
+/// upper-cased name.
+fn parameter_label(average: &CountryAirQuality, parameter: &str) -> String {
+    average
+        .averages
+        .iter()
+        .find(|v| v.parameter == parameter)
+        .map_or_else(|| parameter.to_uppercase(), |v| v.label())
+}
+
+/// Queries everything shown in a report over the last `days` days, for every country in
+/// `COUNTRIES`.
+///
+/// # Errors
+///
+/// Returns `AppError::Db` if a query fails. Parameters in units that cannot be normalised are
+/// left out rather than failing the report.
+pub async fn build_report(
+    db: &Database,
+    title: &str,
+    days: i64,
+    filter: &MeasurementFilter,
+    now: DateTime<Utc>,
+) -> Result<ReportData> {
+    let names = get_country_name_map();
+    let name_of = |code: &str| names.get(code).copied().unwrap_or(code).to_string();
+
+    let ranking = db
+        .get_pollution_ranking_over(&COUNTRIES, days, filter)
+        .await?
+        .into_iter()
+        .enumerate()
+        .map(|(index, ranking)| RankingRow {
+            rank: index + 1,
+            name: name_of(&ranking.country),
+            ranking,
+        })
+        .collect::<Vec<_>>();
+    let exceedances = db
+        .get_guideline_exceedances(&COUNTRIES, &WHO_DAILY_GUIDELINES, days, filter)
+        .await?;
+
+    // Countries with data in ranking order, followed by the others.
+    let mut codes: Vec<&str> = ranking.iter().map(|r| r.ranking.country.as_str()).collect();
+    codes.extend(
+        COUNTRIES
+            .iter()
+            .filter(|c| !codes.contains(c))
+            .collect::<Vec<_>>(),
+    );
+
+    let mut countries = Vec::with_capacity(codes.len());
+    for code in codes {
+        let average = db.get_average_air_quality_over(code, days, filter).await?;
+        let localities = db.get_latest_measurements_by_locality(code, filter).await?;
+        let (locality_columns, localities) = locality_table(&localities);
+
+        let mut series = Vec::with_capacity(TREND_PARAMETERS.len());
+        for parameter in TREND_PARAMETERS {
+            // A trend in mixed units is left out, like the parameter is in the averages.
+            let points = match db
+                .get_daily_average_series(code, parameter, days, filter)
+                .await
+            {
+                Ok(series) => series
+                    .into_iter()
+                    .map(|point| (point.day, point.value))
+                    .collect(),
+                Err(AppError::IncompatibleUnits(_)) => Vec::new(),
+                Err(e) => return Err(e),
+            };
+            series.push(ChartSeries {
+                label: parameter_label(&average, parameter),
+                points,
+                guideline: daily_guideline(parameter),
+            });
+        }
+
+        countries.push(CountryReport {
+            code: code.to_string(),
+            name: name_of(code),
+            exceedances: exceedances
+                .iter()
+                .filter(|e| e.country == code)
+                .map(|e| ExceedanceRow {
+                    label: parameter_label(&average, &e.parameter),
+                    percent: e.exceedance_percent(),
+                    exceedance: e.clone(),
+                })
+                .collect(),
+            average,
+            locality_columns,
+            localities,
+            trend_svg: line_chart_svg(&series, "µg/m³", CHART_WIDTH, CHART_HEIGHT),
+        });
+    }
+
+    Ok(ReportData {
+        title: title.to_string(),
+        generated_at: now.format("%Y-%m-%d %H:%M UTC").to_string(),
+        days,
+        period_start: (now - Duration::days(days)).format("%Y-%m-%d").to_string(),
+        period_end: now.format("%Y-%m-%d").to_string(),
+        quality_note: quality_note(filter),
+        ranking,
+        countries,
+    })
+}
+
+/// Template filter formatting a number with `decimals` decimals (1 by default), or a dash if
+/// the value is missing.
+fn format_number(value: Value, decimals: Option<usize>) -> String {
+    match f64::try_from(value) {
+        Ok(number) if number.is_finite() => format!("{:.*}", decimals.unwrap_or(1), number),
+        _ => "–".to_string(),
+    }
+}
+
+/// Renders `data` with the template source `template`.
+///
+/// The template is rendered with HTML auto-escaping; the SVG charts must be marked `|safe`.
+/// Besides the built-in filters, `num(decimals=1)` formats numbers and shows a dash for
+/// missing values.
+///
+/// # Errors
+///
+/// Returns `AppError::Report` if the template has a syntax error or fails to render.
+pub fn render_report(data: &ReportData, template: &str) -> Result<String> {
+    let mut env = Environment::new();
+    env.add_filter("num", format_number);
+    env.add_template("report.html", template)?;
+    let html = env
+        .get_template("report.html")?
+        .render(Value::from_serialize(data))?;
+    Ok(html)
+}
+
+/// Writes `html` to `path`, creating missing parent directories.
+fn write_html(path: &Path, html: &str) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(html.as_bytes())?;
+    Ok(())
+}
+
+/// Builds a report and writes it to `options.output`.
+///
+/// The template is read before querying, so a missing custom template fails fast.
+///
+/// # Errors
+///
+/// Returns `AppError::Io` if the template cannot be read or the report cannot be written,
+/// `AppError::Report` if the template fails to render, or the errors of `build_report`.
+pub async fn generate_report(
+    db: &Database,
+    filter: &MeasurementFilter,
+    options: &ReportOptions,
+) -> Result<()> {
+    let template = match &options.template {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| {
+            error!("Failed to read report template {}: {}", path.display(), e);
+            e
+        })?,
+        None => DEFAULT_TEMPLATE.to_string(),
+    };
+    let data = build_report(db, &options.title, options.days, filter, Utc::now()).await?;
+    let html = render_report(&data, &template).map_err(|e| {
+        error!("Failed to render report: {}", e);
+        e
+    })?;
+    write_html(&options.output, &html)?;
+    info!(
+        "Wrote {}-day report of {} countries to {}",
+        options.days,
+        data.countries.len(),
+        options.output.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ParameterValue;
+    use chrono::TimeZone;
+
+    fn value(parameter: &str, display_name: &str, value: Option<f64>) -> ParameterValue {
+        ParameterValue {
+            parameter: parameter.to_string(),
+            display_name: Some(display_name.to_string()),
+            unit: Some("µg/m³".to_string()),
+            value,
+        }
+    }
+
+    fn report() -> ReportData {
+        let now = Utc.with_ymd_and_hms(2024, 3, 10, 6, 0, 0).unwrap();
+        let average = CountryAirQuality {
+            country: "NL".to_string(),
+            averages: vec![
+                value("pm25", "PM2.5", Some(18.25)),
+                value("pm10", "PM10", None),
+            ],
+            measurement_count: 42,
+            excluded_days: 3,
+            unit_conflicts: Vec::new(),
+        };
+        let (locality_columns, localities) = locality_table(&[
+            CityLatestMeasurements {
+                locality: "Utrecht".to_string(),
+                values: vec![value("pm25", "PM2.5", Some(12.0))],
+                last_updated: now,
+            },
+            CityLatestMeasurements {
+                locality: "Amsterdam <Noord>".to_string(),
+                values: vec![
+                    value("pm10", "PM10", Some(30.0)),
+                    value("pm25", "PM2.5", Some(9.5)),
+                ],
+                last_updated: now,
+            },
+        ]);
+        ReportData {
+            title: "Weekly Air Quality".to_string(),
+            generated_at: now.format("%Y-%m-%d %H:%M UTC").to_string(),
+            days: 7,
+            period_start: "2024-03-03".to_string(),
+            period_end: "2024-03-10".to_string(),
+            quality_note: quality_note(&MeasurementFilter::default()),
+            ranking: vec![RankingRow {
+                rank: 1,
+                name: "Netherlands".to_string(),
+                ranking: PollutionRanking {
+                    country: "NL".to_string(),
+                    pollution_index: 57.4,
+                    pm25_avg: Some(18.25),
+                    pm10_avg: None,
+                    excluded_days: 3,
+                    unit_conflicts: Vec::new(),
+                },
+            }],
+            countries: vec![CountryReport {
+                code: "NL".to_string(),
+                name: "Netherlands".to_string(),
+                exceedances: vec![ExceedanceRow {
+                    label: "PM2.5".to_string(),
+                    percent: 25.0,
+                    exceedance: GuidelineExceedance {
+                        country: "NL".to_string(),
+                        parameter: "pm25".to_string(),
+                        limit: 15.0,
+                        exceedance_days: 5,
+                        sensor_days: 20,
+                        max_value: 41.3,
+                    },
+                }],
+                average,
+                locality_columns,
+                localities,
+                trend_svg: line_chart_svg(
+                    &[ChartSeries {
+                        label: "PM2.5".to_string(),
+                        points: vec![(now, 18.0)],
+                        guideline: Some(15.0),
+                    }],
+                    "µg/m³",
+                    CHART_WIDTH,
+                    CHART_HEIGHT,
+                ),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_locality_table() {
+        let data = report();
+        let country = &data.countries[0];
+        let headers: Vec<&str> = country
+            .locality_columns
+            .iter()
+            .map(|c| c.header.as_str())
+            .collect();
+        assert_eq!(headers, ["PM2.5 (µg/m³)", "PM10 (µg/m³)"]);
+        assert_eq!(country.localities[0].values, [Some(12.0), None]);
+        assert_eq!(country.localities[1].values, [Some(9.5), Some(30.0)]);
+        assert_eq!(country.localities[0].last_updated, "2024-03-10 06:00");
+    }
+
+    #[test]
+    fn test_render_default_template() {
+        let html = render_report(&report(), DEFAULT_TEMPLATE).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Weekly Air Quality</title>"));
+        assert!(html.contains("Netherlands"));
+        assert!(html.contains("57.4"));
+        // Missing values are shown as a dash.
+        assert!(html.contains("<td>–</td>"));
+        // Text is escaped, the embedded chart is not.
+        assert!(html.contains("Amsterdam &lt;Noord&gt;"));
+        assert!(html.contains("<svg xmlns="));
+        assert!(html.contains("25.0%"));
+        assert!(html.contains("Excludes flagged measurements and days less than 75% complete."));
+    }
+
+    #[test]
+    fn test_render_custom_template() {
+        let template = "<h1>ACME – {{ title }}</h1>{% for row in ranking %}{{ row.rank }}. \
+                        {{ row.name }} {{ row.pollution_index|num(2) }} {{ row.pm10_avg|num }}{% endfor %}";
+        let html = render_report(&report(), template).unwrap();
+        assert_eq!(
+            html,
+            "<h1>ACME – Weekly Air Quality</h1>1. Netherlands 57.40 –"
+        );
+
+        let err = render_report(&report(), "{% for %}").unwrap_err();
+        assert!(err.to_string().starts_with("Report Template Error"));
+    }
+
+    #[test]
+    fn test_quality_note() {
+        assert_eq!(
+            quality_note(&MeasurementFilter {
+                exclude_flagged: false,
+                min_completeness: None,
+            }),
+            "All measurements are included."
+        );
+        assert_eq!(
+            quality_note(&MeasurementFilter {
+                exclude_flagged: true,
+                min_completeness: None,
+            }),
+            "Excludes flagged measurements."
+        );
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod integration_tests {
+    use super::*;
+    use crate::models::DbMeasurement;
+
+    use sqlx::PgPool;
+
+    fn measurement(sensor_id: i64, parameter: &str, days_ago: i64, value: f64) -> DbMeasurement {
+        DbMeasurement::test(
+            sensor_id,
+            "NL",
+            parameter,
+            Utc::now() - Duration::days(days_ago),
+        )
+        .with_value(value)
+    }
+
+    /// Tests generating a report file from the stored measurements.
+    #[sqlx::test]
+    async fn test_generate_report(pool: PgPool) {
+        let db = Database::from_pool(pool);
+        db.init_schema().await.expect("Failed to init schema");
+        db.insert_measurements(&[
+            measurement(1, "pm25", 1, 20.0),
+            measurement(1, "pm25", 2, 10.0),
+            measurement(2, "pm10", 1, 50.0),
+        ])
+        .await
+        .unwrap();
+
+        let filter = MeasurementFilter::default();
+        let data = build_report(&db, "Test", 7, &filter, Utc::now())
+            .await
+            .unwrap();
+        assert_eq!(data.ranking.len(), 1);
+        assert_eq!(data.countries.len(), COUNTRIES.len());
+        let nl = &data.countries[0];
+        assert_eq!(nl.code, "NL");
+        assert_eq!(nl.localities.len(), 1);
+        assert_eq!(nl.exceedances.len(), 2);
+        assert_eq!(nl.trend_svg.matches("<polyline").count(), 2);
+        assert!(data.countries[1].trend_svg.contains("No data to chart"));
+
+        let output = std::env::temp_dir()
+            .join(format!("report-{}", std::process::id()))
+            .join("report.html");
+        let options = ReportOptions {
+            output: output.clone(),
+            days: 7,
+            template: None,
+            title: "Test".to_string(),
+        };
+        generate_report(&db, &filter, &options).await.unwrap();
+        let html = std::fs::read_to_string(&output).unwrap();
+        std::fs::remove_dir_all(output.parent().unwrap()).unwrap();
+        assert!(html.contains("Utrecht"));
+
+        let missing = ReportOptions {
+            template: Some(PathBuf::from("/nonexistent/report.html")),
+            ..options
+        };
+        assert!(generate_report(&db, &filter, &missing).await.is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Renders line charts as inline SVG, so reports stay self-contained files without scripts or
+//! external images.
+
+use chrono::{DateTime, Utc};
+use std::fmt::Write;
+
+/// Colours of the consecutive series of a chart.
+const PALETTE: [&str; 6] = [
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b",
+];
+
+/// Maximum number of date labels on the x-axis.
+const MAX_X_LABELS: usize = 6;
+
+/// Number of intervals the y-axis is divided into (approximately).
+const Y_TICKS: f64 = 4.0;
+
+const MARGIN_LEFT: f64 = 52.0;
+const MARGIN_RIGHT: f64 = 16.0;
+const MARGIN_TOP: f64 = 28.0;
+const MARGIN_BOTTOM: f64 = 28.0;
+
+/// One line of a chart, with an optional horizontal guideline drawn dashed in the same colour.
+#[derive(Debug, Clone)]
+pub struct ChartSeries {
+    pub label: String,
+    pub points: Vec<(DateTime<Utc>, f64)>,
+    pub guideline: Option<f64>,
+}
+
+/// Escapes text for use in SVG (XML) content and attributes.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Rounds `value` up to a "nice" step of 1, 2 or 5 times a power of ten.
+fn nice_step(value: f64) -> f64 {
+    if value <= 0.0 || !value.is_finite() {
+        return 1.0;
+    }
+    let magnitude = 10f64.powf(value.log10().floor());
+    let normalized = value / magnitude;
+    let nice = if normalized <= 1.0 {
+        1.0
+    } else if normalized <= 2.0 {
+        2.0
+    } else if normalized <= 5.0 {
+        5.0
+    } else {
+        10.0
+    };
+    nice * magnitude
+}
+
+/// Formats a tick value with as many decimals as the step size needs.
+fn format_tick(value: f64, step: f64) -> String {
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    format!("{:.*}", decimals, value)
+}
+
+/// Renders a line chart of daily series as an `<svg>` element of `width` by `height` pixels.
+///
+/// The y-axis starts at zero and covers every value and guideline; the x-axis spans the days
+/// of all series. Every point carries a tooltip with its date and value in `unit`. Series
+/// without points are left out of the legend; if no series has points, the chart only shows
+/// a notice.
+pub fn line_chart_svg(series: &[ChartSeries], unit: &str, width: u32, height: u32) -> String {
+    let (width_f, height_f) = (f64::from(width), f64::from(height));
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" class="chart" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11">"#,
+        w = width,
+        h = height
+    );
+
+    let series: Vec<(usize, &ChartSeries)> = series
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.points.iter().any(|(_, v)| v.is_finite()))
+        .collect();
+    let mut days: Vec<DateTime<Utc>> = series
+        .iter()
+        .flat_map(|(_, s)| s.points.iter().map(|(day, _)| *day))
+        .collect();
+    days.sort();
+    days.dedup();
+    let (Some(&first), Some(&last)) = (days.first(), days.last()) else {
+        let _ = write!(
+            svg,
+            r##"<text x="{}" y="{}" text-anchor="middle" fill="#666">No data to chart</text></svg>"##,
+            width_f / 2.0,
+            height_f / 2.0
+        );
+        return svg;
+    };
+
+    let plot_width = (width_f - MARGIN_LEFT - MARGIN_RIGHT).max(1.0);
+    let plot_height = (height_f - MARGIN_TOP - MARGIN_BOTTOM).max(1.0);
+    let bottom = MARGIN_TOP + plot_height;
+
+    // Scale the y-axis to the largest value or guideline, rounded up to a whole step.
+    let max_value = series
+        .iter()
+        .flat_map(|(_, s)| {
+            s.points
+                .iter()
+                .map(|(_, v)| *v)
+                .chain(s.guideline)
+                .filter(|v| v.is_finite())
+        })
+        .fold(0.0, f64::max);
+    let step = nice_step(max_value / Y_TICKS);
+    let y_max = ((max_value / step).ceil() * step).max(step);
+    let y = |value: f64| bottom - value / y_max * plot_height;
+
+    let span = (last - first).num_seconds() as f64;
+    let x = |day: DateTime<Utc>| {
+        if span == 0.0 {
+            MARGIN_LEFT + plot_width / 2.0
+        } else {
+            MARGIN_LEFT + (day - first).num_seconds() as f64 / span * plot_width
+        }
+    };
+
+    // Horizontal grid lines with the y-axis labels.
+    let mut tick = 0.0;
+    while tick <= y_max + step / 2.0 {
+        let _ = write!(
+            svg,
+            r##"<line x1="{x1:.1}" y1="{y:.1}" x2="{x2:.1}" y2="{y:.1}" stroke="#ddd"/><text x="{lx:.1}" y="{ly:.1}" text-anchor="end" fill="#444">{label}</text>"##,
+            x1 = MARGIN_LEFT,
+            x2 = MARGIN_LEFT + plot_width,
+            y = y(tick),
+            lx = MARGIN_LEFT - 6.0,
+            ly = y(tick) + 4.0,
+            label = format_tick(tick, step)
+        );
+        tick += step;
+    }
+    let _ = write!(
+        svg,
+        r##"<text x="4" y="{:.1}" fill="#444">{}</text>"##,
+        MARGIN_TOP - 12.0,
+        escape(unit)
+    );
+
+    // Date labels along the x-axis, spread over the days with data.
+    let every = days.len().div_ceil(MAX_X_LABELS);
+    for day in days.iter().step_by(every) {
+        let _ = write!(
+            svg,
+            r##"<text x="{:.1}" y="{:.1}" text-anchor="middle" fill="#444">{}</text>"##,
+            x(*day),
+            bottom + 16.0,
+            day.format("%b %d")
+        );
+    }
+    let _ = write!(
+        svg,
+        r##"<line x1="{x1:.1}" y1="{y:.1}" x2="{x2:.1}" y2="{y:.1}" stroke="#888"/>"##,
+        x1 = MARGIN_LEFT,
+        x2 = MARGIN_LEFT + plot_width,
+        y = bottom
+    );
+
+    let mut legend_x = MARGIN_LEFT;
+    for (index, s) in &series {
+        let color = PALETTE[index % PALETTE.len()];
+        if let Some(guideline) = s.guideline.filter(|g| g.is_finite()) {
+            let _ = write!(
+                svg,
+                r#"<line x1="{x1:.1}" y1="{y:.1}" x2="{x2:.1}" y2="{y:.1}" stroke="{color}" stroke-dasharray="6 4" stroke-opacity="0.7"><title>{label} guideline: {value} {unit}</title></line>"#,
+                x1 = MARGIN_LEFT,
+                x2 = MARGIN_LEFT + plot_width,
+                y = y(guideline),
+                label = escape(&s.label),
+                value = guideline,
+                unit = escape(unit)
+            );
+        }
+
+        let points: Vec<&(DateTime<Utc>, f64)> =
+            s.points.iter().filter(|(_, v)| v.is_finite()).collect();
+        let path: Vec<String> = points
+            .iter()
+            .map(|(day, value)| format!("{:.1},{:.1}", x(*day), y(*value)))
+            .collect();
+        let _ = write!(
+            svg,
+            r#"<polyline fill="none" stroke="{}" stroke-width="2" points="{}"/>"#,
+            color,
+            path.join(" ")
+        );
+        for (day, value) in points {
+            let _ = write!(
+                svg,
+                r#"<circle cx="{:.1}" cy="{:.1}" r="2.5" fill="{}"><title>{} {}: {:.1} {}</title></circle>"#,
+                x(*day),
+                y(*value),
+                color,
+                escape(&s.label),
+                day.format("%Y-%m-%d"),
+                value,
+                escape(unit)
+            );
+        }
+
+        let _ = write!(
+            svg,
+            r#"<rect x="{:.1}" y="{:.1}" width="10" height="10" fill="{}"/><text x="{:.1}" y="{:.1}">{}</text>"#,
+            legend_x,
+            MARGIN_TOP - 22.0,
+            color,
+            legend_x + 14.0,
+            MARGIN_TOP - 13.0,
+            escape(&s.label)
+        );
+        legend_x += 30.0 + 7.0 * s.label.chars().count() as f64;
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn series(label: &str, values: &[f64], guideline: Option<f64>) -> ChartSeries {
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        ChartSeries {
+            label: label.to_string(),
+            points: values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (start + Duration::days(i as i64), *v))
+                .collect(),
+            guideline,
+        }
+    }
+
+    #[test]
+    fn test_nice_step() {
+        assert_eq!(nice_step(0.0), 1.0);
+        assert_eq!(nice_step(3.2), 5.0);
+        assert_eq!(nice_step(12.0), 20.0);
+        assert!((nice_step(0.07) - 0.1).abs() < 1e-12);
+        assert_eq!(format_tick(20.0, 10.0), "20");
+        assert_eq!(format_tick(0.25, 0.05), "0.25");
+    }
+
+    #[test]
+    fn test_line_chart_svg() {
+        let svg = line_chart_svg(
+            &[
+                series("PM2.5", &[10.0, 20.0, 12.0], Some(15.0)),
+                series("PM10 <x>", &[30.0, f64::NAN, 35.0], None),
+                series("NO₂", &[], Some(25.0)),
+            ],
+            "µg/m³",
+            600,
+            240,
+        );
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        // Non-finite values are skipped.
+        assert_eq!(svg.matches("<circle").count(), 5);
+        assert_eq!(svg.matches("stroke-dasharray").count(), 1);
+        assert!(svg.contains("PM2.5 2024-03-02: 20.0 µg/m³"));
+        assert!(svg.contains("PM10 &lt;x&gt;"));
+        // The empty series is not in the legend.
+        assert!(!svg.contains("NO₂"));
+        // The y-axis is scaled to the largest value (35), in steps of 10.
+        assert!(svg.contains(">40</text>"));
+        assert!(!svg.contains(">50</text>"));
+    }
+
+    #[test]
+    fn test_line_chart_svg_without_data() {
+        let svg = line_chart_svg(&[series("PM2.5", &[], Some(15.0))], "µg/m³", 600, 240);
+        assert!(svg.contains("No data to chart"));
+        assert!(!svg.contains("<polyline"));
+    }
+}
@@ -0,0 +1,416 @@
+//! Request handlers, query parameters and error responses of the REST API.
+
+use super::{openapi_document, ApiState};
+use crate::cli::COUNTRIES;
+use crate::db::{DEFAULT_AVERAGE_DAYS, DEFAULT_RANKING_DAYS};
+use crate::error::AppError;
+use crate::metrics::metrics;
+use crate::models::{
+    CityLatestMeasurements, CountryAirQuality, CountryOverview, MeasurementQuery, PollutionRanking,
+    QueryRow, SpatialGrouping, StoredLocation, TimeBucket,
+};
+use axum::extract::{FromRequestParts, Path, Query, State};
+use axum::http::request::Parts;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{Duration, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+/// Number of items per page unless `limit` is given.
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+/// Largest accepted `limit`.
+pub const MAX_PAGE_LIMIT: usize = 1000;
+/// Largest accepted `days` window.
+pub const MAX_WINDOW_DAYS: i64 = 3650;
+/// Number of past days covered by a sensor series unless `days` is given.
+pub const DEFAULT_SERIES_DAYS: i64 = 30;
+
+/// An `AppError` returned from a handler, rendered as a JSON `{"error": "..."}` body.
+///
+/// Invalid requests (`AppError::Cli`) map to 400 and incompatible units to 422; every other
+/// error is logged and maps to 500.
+#[derive(Debug)]
+pub struct ApiError(pub AppError);
+
+impl From<AppError> for ApiError {
+    fn from(err: AppError) -> Self {
+        Self(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self.0 {
+            AppError::Cli(_) => StatusCode::BAD_REQUEST,
+            AppError::IncompatibleUnits(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => {
+                error!("REST API request failed: {:?}", self.0);
+                StatusCode::INTERNAL_SERVER_ERROR
+            },
+        };
+        let body = Json(serde_json::json!({ "error": self.0.to_string() }));
+        (status, body).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<Json<T>, ApiError>;
+
+/// One page of a list result.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Page<T> {
+    /// Number of items across all pages.
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+    pub items: Vec<T>,
+}
+
+impl<T> Page<T> {
+    /// A page of `items` taken at `offset` from `total` items.
+    pub fn new(items: Vec<T>, total: i64, limit: usize, offset: usize) -> Self {
+        Self {
+            total: total.max(0) as usize,
+            limit,
+            offset,
+            items,
+        }
+    }
+}
+
+/// Query string extractor like `axum::extract::Query`, but a malformed query string (e.g.
+/// `limit=abc`) is rejected with the JSON error body of `ApiError` and status 400.
+#[derive(Debug)]
+pub struct ApiQuery<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Query::<T>::from_request_parts(parts, state).await {
+            Ok(Query(params)) => Ok(Self(params)),
+            Err(rejection) => Err(ApiError(AppError::Cli(rejection.body_text()))),
+        }
+    }
+}
+
+/// Path parameter extractor like `axum::extract::Path`, but a parameter of the wrong type
+/// (e.g. `/sensors/abc/series`) is rejected with the JSON error body of `ApiError` and
+/// status 400.
+#[derive(Debug)]
+pub struct ApiPath<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for ApiPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Path::<T>::from_request_parts(parts, state).await {
+            Ok(Path(params)) => Ok(Self(params)),
+            Err(rejection) => Err(ApiError(AppError::Cli(rejection.body_text()))),
+        }
+    }
+}
+
+/// Time window parameter: the number of past days to cover.
+#[derive(Debug, Default, Deserialize)]
+pub struct WindowParams {
+    pub days: Option<i64>,
+}
+
+/// Pagination parameters.
+#[derive(Debug, Default, Deserialize)]
+pub struct PageParams {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// Parameters of `/ranking`.
+#[derive(Debug, Default, Deserialize)]
+pub struct RankingParams {
+    pub days: Option<i64>,
+    /// Comma-separated country codes (defaults to the predefined countries).
+    pub countries: Option<String>,
+}
+
+/// Parameters of `/locations`.
+#[derive(Debug, Default, Deserialize)]
+pub struct LocationParams {
+    /// Country code to restrict the locations to.
+    pub country: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// Parameters of `/sensors/{id}/series`.
+#[derive(Debug, Default, Deserialize)]
+pub struct SeriesParams {
+    pub days: Option<i64>,
+    /// Time bucket: `day`, `week`, `month` or `year`.
+    pub bucket: Option<String>,
+    /// Parameter name to restrict the series to.
+    pub parameter: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// Validates the `days` window, falling back to `default`.
+pub fn validate_days(days: Option<i64>, default: i64) -> Result<i64, AppError> {
+    match days {
+        None => Ok(default),
+        Some(d) if (1..=MAX_WINDOW_DAYS).contains(&d) => Ok(d),
+        Some(d) => Err(AppError::Cli(format!(
+            "days must be between 1 and {}, got {}",
+            MAX_WINDOW_DAYS, d
+        ))),
+    }
+}
+
+/// Validates the pagination parameters, returning `(limit, offset)`.
+pub fn validate_page(
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<(usize, usize), AppError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(AppError::Cli(format!(
+            "limit must be between 1 and {}, got {}",
+            MAX_PAGE_LIMIT, limit
+        )));
+    }
+    let offset = offset.unwrap_or(0);
+    if offset > i64::MAX as usize {
+        return Err(AppError::Cli(format!(
+            "offset is too large, got {}",
+            offset
+        )));
+    }
+    Ok((limit, offset))
+}
+
+/// Validates and upper-cases a 2-letter country code.
+pub fn validate_country(code: &str) -> Result<String, AppError> {
+    let code = code.trim().to_uppercase();
+    if code.len() == 2 && code.chars().all(|c| c.is_ascii_uppercase()) {
+        Ok(code)
+    } else {
+        Err(AppError::Cli(format!(
+            "'{}' is not a 2-letter country code",
+            code
+        )))
+    }
+}
+
+/// Parses a time bucket name, defaulting to days.
+pub fn parse_bucket(bucket: Option<&str>) -> Result<TimeBucket, AppError> {
+    let Some(name) = bucket else {
+        return Ok(TimeBucket::Day);
+    };
+    TimeBucket::ALL
+        .into_iter()
+        .find(|b| b.as_str() == name.trim().to_lowercase())
+        .ok_or_else(|| {
+            AppError::Cli(format!(
+                "bucket must be one of day, week, month or year, got '{}'",
+                name
+            ))
+        })
+}
+
+/// `GET /countries`: stored locations, sensors and measurements per country.
+pub async fn list_countries(State(state): State<ApiState>) -> ApiResult<Vec<CountryOverview>> {
+    Ok(Json(state.db.get_country_overview().await?))
+}
+
+/// `GET /countries/{code}/average`: average per parameter over the last `days` days.
+pub async fn country_average(
+    State(state): State<ApiState>,
+    ApiPath(code): ApiPath<String>,
+    ApiQuery(params): ApiQuery<WindowParams>,
+) -> ApiResult<CountryAirQuality> {
+    let country = validate_country(&code)?;
+    let days = validate_days(params.days, DEFAULT_AVERAGE_DAYS)?;
+    Ok(Json(
+        state
+            .db
+            .get_average_air_quality_over(&country, days, &state.filter)
+            .await?,
+    ))
+}
+
+/// `GET /countries/{code}/localities`: latest values per parameter for each locality.
+pub async fn country_localities(
+    State(state): State<ApiState>,
+    ApiPath(code): ApiPath<String>,
+    ApiQuery(params): ApiQuery<PageParams>,
+) -> ApiResult<Page<CityLatestMeasurements>> {
+    let country = validate_country(&code)?;
+    let (limit, offset) = validate_page(params.limit, params.offset)?;
+    let (localities, total) = state
+        .db
+        .get_latest_measurements_by_locality_page(
+            &country,
+            &state.filter,
+            limit as i64,
+            offset as i64,
+        )
+        .await?;
+    Ok(Json(Page::new(localities, total, limit, offset)))
+}
+
+/// `GET /ranking`: countries ranked by pollution index over the last `days` days.
+pub async fn ranking(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<RankingParams>,
+) -> ApiResult<Vec<PollutionRanking>> {
+    let days = validate_days(params.days, DEFAULT_RANKING_DAYS)?;
+    let countries = match params.countries.as_deref() {
+        Some(list) => list
+            .split(',')
+            .map(validate_country)
+            .collect::<Result<Vec<_>, _>>()?,
+        None => COUNTRIES.iter().map(|c| c.to_string()).collect(),
+    };
+    let countries: Vec<&str> = countries.iter().map(String::as_str).collect();
+    Ok(Json(
+        state
+            .db
+            .get_pollution_ranking_over(&countries, days, &state.filter)
+            .await?,
+    ))
+}
+
+/// `GET /locations`: stored locations, optionally of one country.
+pub async fn list_locations(
+    State(state): State<ApiState>,
+    ApiQuery(params): ApiQuery<LocationParams>,
+) -> ApiResult<Page<StoredLocation>> {
+    let countries = match params.country.as_deref() {
+        Some(code) => vec![validate_country(code)?],
+        None => Vec::new(),
+    };
+    let (limit, offset) = validate_page(params.limit, params.offset)?;
+    let (locations, total) = state
+        .db
+        .get_locations_page(&countries, limit as i64, offset as i64)
+        .await?;
+    Ok(Json(Page::new(locations, total, limit, offset)))
+}
+
+/// `GET /sensors/{id}/series`: bucketed values of one sensor over the last `days` days.
+pub async fn sensor_series(
+    State(state): State<ApiState>,
+    ApiPath(id): ApiPath<i64>,
+    ApiQuery(params): ApiQuery<SeriesParams>,
+) -> ApiResult<Page<QueryRow>> {
+    let days = validate_days(params.days, DEFAULT_SERIES_DAYS)?;
+    let bucket = parse_bucket(params.bucket.as_deref())?;
+    let (limit, offset) = validate_page(params.limit, params.offset)?;
+    let query = MeasurementQuery {
+        sensor_ids: vec![id],
+        parameters: params.parameter.into_iter().collect(),
+        date_from: Some(Utc::now() - Duration::days(days)),
+        spatial: SpatialGrouping::Sensor,
+        bucket,
+        limit: Some(limit as i64),
+        offset: Some(offset as i64),
+        ..MeasurementQuery::default()
+    };
+    let total = state.db.count_query_rows(&query, &state.filter).await?;
+    let rows = state.db.query_measurements(&query, &state.filter).await?;
+    Ok(Json(Page::new(rows, total, limit, offset)))
+}
+
+/// `GET /metrics`: the Prometheus metrics in the text exposition format.
+///
+/// Only renders the registry; the latest value gauges are refreshed in the background (see
+/// `refresh_latest_values`).
+pub async fn prometheus_metrics() -> Result<Response, ApiError> {
+    let body = metrics().encode()?;
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        body,
+    )
+        .into_response())
+}
+
+/// `GET /openapi.json`: the OpenAPI document describing the API.
+pub async fn openapi() -> Json<serde_json::Value> {
+    Json(openapi_document())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_params() {
+        assert_eq!(validate_days(None, 7).unwrap(), 7);
+        assert_eq!(validate_days(Some(30), 7).unwrap(), 30);
+        assert!(matches!(validate_days(Some(0), 7), Err(AppError::Cli(_))));
+        assert!(validate_days(Some(MAX_WINDOW_DAYS + 1), 7).is_err());
+
+        assert_eq!(validate_page(None, None).unwrap(), (DEFAULT_PAGE_LIMIT, 0));
+        assert_eq!(validate_page(Some(10), Some(20)).unwrap(), (10, 20));
+        assert!(validate_page(Some(0), None).is_err());
+        assert!(validate_page(Some(MAX_PAGE_LIMIT + 1), None).is_err());
+        assert!(validate_page(None, Some(usize::MAX)).is_err());
+
+        assert_eq!(validate_country("nl").unwrap(), "NL");
+        assert!(validate_country("NLD").is_err());
+        assert!(validate_country("N1").is_err());
+
+        assert_eq!(parse_bucket(None).unwrap(), TimeBucket::Day);
+        assert_eq!(parse_bucket(Some("Month")).unwrap(), TimeBucket::Month);
+        assert!(parse_bucket(Some("hour")).is_err());
+    }
+
+    #[test]
+    fn test_error_status() {
+        let status = |e: AppError| ApiError(e).into_response().status();
+        assert_eq!(status(AppError::Cli("bad".into())), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            status(AppError::IncompatibleUnits("ppm".into())),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            status(AppError::Import("oops".into())),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_query_is_a_json_error() {
+        use axum::body::{to_bytes, Body};
+        use axum::http::Request;
+        use axum::routing::get;
+        use tower::ServiceExt;
+
+        async fn page(ApiQuery(params): ApiQuery<PageParams>) -> Json<Option<usize>> {
+            Json(params.limit)
+        }
+        let app = axum::Router::new().route("/", get(page));
+        let request = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+        let response = app.clone().oneshot(request("/?limit=5")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(request("/?limit=abc")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(body["error"].as_str().unwrap().contains("limit"));
+    }
+}
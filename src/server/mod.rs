@@ -0,0 +1,228 @@
+//! Embedded HTTP server exposing the analytic queries as a read-only JSON REST API and the
+//! Prometheus metrics.
+//!
+//! Started with `fizyr-assessment serve`. The routes are listed in the OpenAPI document served
+//! at `/openapi.json` (see the `openapi` submodule); the handlers live in `handlers`. The
+//! interactive mode and the daemon can serve `/metrics` alone (see `serve_metrics`). Both
+//! servers refresh the latest value gauges in the background (see `refresh_latest_values`), so
+//! a scrape only renders the registry.
+
+mod handlers;
+mod openapi;
+
+pub use handlers::*;
+pub use openapi::*;
+
+use crate::cli::COUNTRIES;
+use crate::db::Database;
+use crate::error::{AppError, Result};
+use crate::metrics::{metrics, LATEST_VALUES_REFRESH};
+use crate::models::MeasurementFilter;
+use axum::routing::get;
+use axum::Router;
+use std::net::SocketAddr;
+use tracing::{error, info, warn};
+
+/// State shared by all request handlers.
+#[derive(Clone)]
+pub struct ApiState {
+    pub db: Database,
+    /// Data quality filter applied to every query.
+    pub filter: MeasurementFilter,
+}
+
+/// Builds the router with every API route.
+pub fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/countries", get(list_countries))
+        .route("/countries/{code}/average", get(country_average))
+        .route("/countries/{code}/localities", get(country_localities))
+        .route("/ranking", get(ranking))
+        .route("/locations", get(list_locations))
+        .route("/sensors/{id}/series", get(sensor_series))
+        .route("/openapi.json", get(openapi))
+        .route("/metrics", get(prometheus_metrics))
+        .with_state(state)
+}
+
+/// Builds a router serving only `/metrics`.
+pub fn metrics_router(state: ApiState) -> Router {
+    Router::new()
+        .route("/metrics", get(prometheus_metrics))
+        .with_state(state)
+}
+
+/// Replaces the latest PM2.5/PM10 gauges with the latest values of the localities of the
+/// predefined countries. If a query fails, the gauges keep their previous values.
+pub async fn refresh_latest_values(db: &Database, filter: &MeasurementFilter) {
+    let mut latest = Vec::with_capacity(COUNTRIES.len());
+    for country in COUNTRIES {
+        match db
+            .get_latest_measurements_by_locality(country, filter)
+            .await
+        {
+            Ok(localities) => latest.push((country, localities)),
+            Err(e) => {
+                warn!("Failed to refresh latest value gauges: {}", e);
+                return;
+            },
+        }
+    }
+    metrics().set_latest_values(&latest);
+}
+
+/// Refreshes the latest value gauges now, after every import that signals
+/// `latest_values_changed`, and at least every `LATEST_VALUES_REFRESH`. Runs until the process
+/// exits.
+fn spawn_latest_values_refresh(state: ApiState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(LATEST_VALUES_REFRESH);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = metrics().latest_values_stale() => interval.reset(),
+            }
+            refresh_latest_values(&state.db, &state.filter).await;
+        }
+    });
+}
+
+/// Binds a TCP listener on `addr`.
+async fn bind(addr: SocketAddr) -> Result<tokio::net::TcpListener> {
+    tokio::net::TcpListener::bind(addr).await.map_err(|e| {
+        error!("Failed to bind {}: {}", addr, e);
+        AppError::from(e)
+    })
+}
+
+/// Serves the API on `addr` until Ctrl+C is pressed.
+///
+/// # Errors
+///
+/// Returns `AppError::Io` if the address cannot be bound or the server fails.
+pub async fn serve(addr: SocketAddr, state: ApiState) -> Result<()> {
+    let listener = bind(addr).await?;
+    info!("Serving the REST API on http://{}", addr);
+    spawn_latest_values_refresh(state.clone());
+    axum::serve(listener, router(state))
+        .with_graceful_shutdown(async {
+            tokio::signal::ctrl_c().await.ok();
+            info!("Shutting down the REST API");
+        })
+        .await
+        .map_err(|e| {
+            error!("REST API server failed: {}", e);
+            AppError::from(e)
+        })
+}
+
+/// Serves `/metrics` on `addr` until the process exits.
+///
+/// # Errors
+///
+/// Returns `AppError::Io` if the address cannot be bound or the server fails.
+pub async fn serve_metrics(addr: SocketAddr, state: ApiState) -> Result<()> {
+    let listener = bind(addr).await?;
+    info!("Serving metrics on http://{}/metrics", addr);
+    spawn_latest_values_refresh(state.clone());
+    axum::serve(listener, metrics_router(state))
+        .await
+        .map_err(|e| {
+            error!("Metrics server failed: {}", e);
+            AppError::from(e)
+        })
+}
+
+#[cfg(test)]
+#[cfg(feature = "integration-tests")]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use serde_json::Value;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    /// Sends a GET request to the router and returns the status and JSON body.
+    async fn get_json(app: &Router, uri: &str) -> (StatusCode, Value) {
+        let response = app
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    /// Tests the routes against a database with stored locations but no measurements.
+    #[sqlx::test]
+    async fn test_routes(pool: PgPool) {
+        let db = Database::from_pool(pool.clone());
+        db.init_schema().await.expect("Failed to init schema");
+        sqlx::query(
+            r#"
+            INSERT INTO locations (id, name, country_code, country_name, timezone, is_mobile, is_monitor)
+            VALUES (1, 'A', 'NL', 'Netherlands', 'UTC', false, true),
+                   (2, 'B', 'NL', 'Netherlands', 'UTC', false, true),
+                   (3, 'C', 'DE', 'Germany', 'UTC', false, true)
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .expect("Failed to insert locations");
+        let app = router(ApiState {
+            db,
+            filter: MeasurementFilter::default(),
+        });
+
+        let (status, body) = get_json(&app, "/countries").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), 2);
+
+        let (status, body) = get_json(&app, "/locations?country=nl&limit=1&offset=1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["items"][0]["id"], 2);
+
+        let (status, body) = get_json(&app, "/locations?offset=-1").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("offset"));
+
+        let (status, body) = get_json(&app, "/countries/NL/average?days=30").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["measurement_count"], 0);
+
+        let (status, body) = get_json(&app, "/ranking?countries=NL,DE").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.as_array().unwrap().is_empty());
+
+        let (status, body) = get_json(&app, "/sensors/11/series?bucket=week").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 0);
+
+        let (status, body) = get_json(&app, "/sensors/abc/series").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("abc"));
+
+        let (status, body) = get_json(&app, "/countries/NLD/localities").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("NLD"));
+
+        let (status, body) = get_json(&app, "/openapi.json").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["openapi"], "3.0.3");
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(text.contains(
+            r#"air_quality_db_query_duration_seconds_count{query="get_locations_page"}"#
+        ));
+    }
+}
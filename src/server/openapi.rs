@@ -0,0 +1,303 @@
+//! The OpenAPI 3 document describing the REST API, served at `/openapi.json`.
+
+use super::{DEFAULT_PAGE_LIMIT, DEFAULT_SERIES_DAYS, MAX_PAGE_LIMIT, MAX_WINDOW_DAYS};
+use crate::db::{DEFAULT_AVERAGE_DAYS, DEFAULT_RANKING_DAYS};
+use serde_json::{json, Value};
+
+/// Returns a query or path parameter object.
+fn parameter(name: &str, location: &str, description: &str, schema: Value) -> Value {
+    json!({
+        "name": name,
+        "in": location,
+        "required": location == "path",
+        "description": description,
+        "schema": schema,
+    })
+}
+
+fn days_parameter(default: i64) -> Value {
+    parameter(
+        "days",
+        "query",
+        "Number of past days to cover.",
+        json!({ "type": "integer", "minimum": 1, "maximum": MAX_WINDOW_DAYS, "default": default }),
+    )
+}
+
+fn page_parameters() -> [Value; 2] {
+    [
+        parameter(
+            "limit",
+            "query",
+            "Maximum number of items to return.",
+            json!({ "type": "integer", "minimum": 1, "maximum": MAX_PAGE_LIMIT, "default": DEFAULT_PAGE_LIMIT }),
+        ),
+        parameter(
+            "offset",
+            "query",
+            "Number of items to skip.",
+            json!({ "type": "integer", "minimum": 0, "default": 0 }),
+        ),
+    ]
+}
+
+fn country_parameter() -> Value {
+    parameter(
+        "code",
+        "path",
+        "2-letter country code.",
+        json!({ "type": "string", "pattern": "^[A-Za-z]{2}$" }),
+    )
+}
+
+/// Returns a JSON response object with the given schema.
+fn ok(description: &str, schema: Value) -> Value {
+    json!({
+        "200": {
+            "description": description,
+            "content": { "application/json": { "schema": schema } },
+        },
+        "400": { "$ref": "#/components/responses/BadRequest" },
+        "500": { "$ref": "#/components/responses/ServerError" },
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn page_of(name: &str) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "total": { "type": "integer" },
+            "limit": { "type": "integer" },
+            "offset": { "type": "integer" },
+            "items": { "type": "array", "items": schema_ref(name) },
+        },
+    })
+}
+
+fn parameter_value() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "parameter": { "type": "string" },
+            "display_name": { "type": "string", "nullable": true },
+            "unit": { "type": "string", "nullable": true },
+            "value": { "type": "number", "nullable": true },
+        },
+    })
+}
+
+/// Schema of a parameter left out of an aggregation because of mixed units.
+fn unit_conflict() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "country": { "type": "string" },
+            "parameter": { "type": "string" },
+            "units": { "type": "array", "items": { "type": "string" } },
+        },
+    })
+}
+
+/// Builds the OpenAPI 3 document of every route in `router`.
+pub fn openapi_document() -> Value {
+    let [limit, offset] = page_parameters();
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Air Quality Analysis API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Read-only access to the imported air quality measurements. Values are normalised per parameter and filtered by the configured data quality settings.",
+        },
+        "paths": {
+            "/countries": {
+                "get": {
+                    "summary": "Stored locations, sensors and measurements per country",
+                    "responses": ok("Countries ordered by code", json!({ "type": "array", "items": schema_ref("CountryOverview") })),
+                },
+            },
+            "/countries/{code}/average": {
+                "get": {
+                    "summary": "Average value per parameter of a country",
+                    "parameters": [country_parameter(), days_parameter(DEFAULT_AVERAGE_DAYS)],
+                    "responses": ok("Averages in catalogue order", schema_ref("CountryAirQuality")),
+                },
+            },
+            "/countries/{code}/localities": {
+                "get": {
+                    "summary": "Latest value per parameter for each locality of a country",
+                    "parameters": [country_parameter(), limit.clone(), offset.clone()],
+                    "responses": ok("Localities ordered by name", page_of("LocalityLatest")),
+                },
+            },
+            "/ranking": {
+                "get": {
+                    "summary": "Countries ranked by pollution index ((PM2.5 * 1.5) + PM10)",
+                    "parameters": [
+                        days_parameter(DEFAULT_RANKING_DAYS),
+                        parameter("countries", "query", "Comma-separated country codes; defaults to the predefined countries.", json!({ "type": "string" })),
+                    ],
+                    "responses": ok("Countries, most polluted first", json!({ "type": "array", "items": schema_ref("PollutionRanking") })),
+                },
+            },
+            "/locations": {
+                "get": {
+                    "summary": "Stored locations",
+                    "parameters": [
+                        parameter("country", "query", "2-letter country code to restrict the locations to.", json!({ "type": "string" })),
+                        limit.clone(),
+                        offset.clone(),
+                    ],
+                    "responses": ok("Locations ordered by country and ID", page_of("Location")),
+                },
+            },
+            "/sensors/{id}/series": {
+                "get": {
+                    "summary": "Time series of one sensor",
+                    "parameters": [
+                        parameter("id", "path", "Sensor ID.", json!({ "type": "integer", "format": "int64" })),
+                        days_parameter(DEFAULT_SERIES_DAYS),
+                        parameter("bucket", "query", "Time bucket.", json!({ "type": "string", "enum": ["day", "week", "month", "year"], "default": "day" })),
+                        parameter("parameter", "query", "Parameter name (e.g. pm25) to restrict the series to.", json!({ "type": "string" })),
+                        limit,
+                        offset,
+                    ],
+                    "responses": ok("Buckets per parameter", page_of("SeriesRow")),
+                },
+            },
+            "/metrics": {
+                "get": {
+                    "summary": "Prometheus metrics: imports, API requests, database latency and the latest PM2.5/PM10 per locality",
+                    "responses": {
+                        "200": {
+                            "description": "Metrics in the Prometheus text exposition format",
+                            "content": { "text/plain": { "schema": { "type": "string" } } },
+                        },
+                    },
+                },
+            },
+            "/openapi.json": {
+                "get": {
+                    "summary": "This document",
+                    "responses": { "200": { "description": "OpenAPI document" } },
+                },
+            },
+        },
+        "components": {
+            "responses": {
+                "BadRequest": {
+                    "description": "Invalid path or query parameter",
+                    "content": { "application/json": { "schema": schema_ref("Error") } },
+                },
+                "ServerError": {
+                    "description": "Query failed",
+                    "content": { "application/json": { "schema": schema_ref("Error") } },
+                },
+            },
+            "schemas": {
+                "Error": {
+                    "type": "object",
+                    "properties": { "error": { "type": "string" } },
+                },
+                "CountryOverview": {
+                    "type": "object",
+                    "properties": {
+                        "code": { "type": "string" },
+                        "name": { "type": "string", "nullable": true },
+                        "locations": { "type": "integer" },
+                        "sensors": { "type": "integer" },
+                        "measurements": { "type": "integer" },
+                        "first_day": { "type": "string", "format": "date-time", "nullable": true },
+                        "last_day": { "type": "string", "format": "date-time", "nullable": true },
+                    },
+                },
+                "CountryAirQuality": {
+                    "type": "object",
+                    "properties": {
+                        "country": { "type": "string" },
+                        "averages": { "type": "array", "items": parameter_value() },
+                        "measurement_count": { "type": "integer" },
+                        "excluded_days": { "type": "integer" },
+                        "unit_conflicts": { "type": "array", "items": unit_conflict() },
+                    },
+                },
+                "LocalityLatest": {
+                    "type": "object",
+                    "properties": {
+                        "locality": { "type": "string" },
+                        "values": { "type": "array", "items": parameter_value() },
+                        "last_updated": { "type": "string", "format": "date-time" },
+                    },
+                },
+                "PollutionRanking": {
+                    "type": "object",
+                    "properties": {
+                        "country": { "type": "string" },
+                        "pollution_index": { "type": "number" },
+                        "pm25_avg": { "type": "number", "nullable": true },
+                        "pm10_avg": { "type": "number", "nullable": true },
+                        "excluded_days": { "type": "integer" },
+                        "unit_conflicts": { "type": "array", "items": unit_conflict() },
+                    },
+                },
+                "Location": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "integer", "format": "int64" },
+                        "name": { "type": "string", "nullable": true },
+                        "locality": { "type": "string", "nullable": true },
+                        "country_code": { "type": "string" },
+                        "country_name": { "type": "string" },
+                        "timezone": { "type": "string" },
+                        "latitude": { "type": "number", "nullable": true },
+                        "longitude": { "type": "number", "nullable": true },
+                        "is_mobile": { "type": "boolean" },
+                        "is_monitor": { "type": "boolean" },
+                        "owner_name": { "type": "string", "nullable": true },
+                        "provider_name": { "type": "string", "nullable": true },
+                        "source": { "type": "string" },
+                        "datetime_first": { "type": "string", "format": "date-time", "nullable": true },
+                        "datetime_last": { "type": "string", "format": "date-time", "nullable": true },
+                    },
+                },
+                "SeriesRow": {
+                    "type": "object",
+                    "properties": {
+                        "group_key": { "type": "string" },
+                        "period": { "type": "string", "format": "date-time" },
+                        "parameter": { "type": "string" },
+                        "unit": { "type": "string", "nullable": true },
+                        "value": { "type": "number", "nullable": true },
+                        "min": { "type": "number", "nullable": true },
+                        "max": { "type": "number", "nullable": true },
+                        "count": { "type": "integer" },
+                    },
+                },
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every `$ref` in the document must point to a defined component.
+    #[test]
+    fn test_openapi_references_resolve() {
+        let doc = openapi_document();
+        let text = doc.to_string();
+        let mut refs = 0;
+        for part in text.split("\"$ref\":\"#/").skip(1) {
+            let path = &part[..part.find('"').unwrap()];
+            let target = path.split('/').try_fold(&doc, |node, key| node.get(key));
+            assert!(target.is_some(), "unresolved reference {}", path);
+            refs += 1;
+        }
+        assert!(refs > 0);
+        assert_eq!(doc["paths"].as_object().unwrap().len(), 8);
+    }
+}
@@ -0,0 +1,184 @@
+//! Full-screen terminal dashboard, started with `fizyr-assessment dashboard`.
+//!
+//! Shows the country ranking, the latest values per locality of the selected country and the
+//! daily trend of the selected locality, and imports the latest data on a key press with the
+//! progress shown in place of the `indicatif` bars. The state and its key bindings live in
+//! `state`, the drawing in `ui`; this module runs the event loop and the background queries.
+
+mod state;
+mod ui;
+
+pub use state::*;
+pub use ui::*;
+
+use crate::alerts::{evaluate_after_import, AlertEngine};
+use crate::api::OpenAQClient;
+use crate::cli::{
+    import_from_source, parameter_choices, NoticeLevel, Progress, ProgressEvent, COUNTRIES,
+};
+use crate::db::Database;
+use crate::error::Result;
+use crate::models::{AlertScope, AlertState, MeasurementFilter};
+use crossterm::event::{Event, EventStream, KeyEventKind};
+use futures::StreamExt;
+use ratatui::DefaultTerminal;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tracing::{error, info};
+
+/// What the background tasks need.
+struct Tasks {
+    db: Database,
+    filter: MeasurementFilter,
+    /// Client of the imports, `None` without an API key.
+    client: Option<Arc<OpenAQClient>>,
+    alerts: Option<Arc<AlertEngine>>,
+    /// Number of past days fetched by an import.
+    import_days: i64,
+    data: UnboundedSender<DataEvent>,
+    progress: UnboundedSender<ProgressEvent>,
+}
+
+impl Tasks {
+    /// Runs `action` in the background; its result arrives as a `DataEvent`.
+    fn spawn(&self, action: Action) {
+        let db = self.db.clone();
+        let filter = self.filter.clone();
+        let data = self.data.clone();
+        match action {
+            Action::LoadRanking { days } => {
+                tokio::spawn(async move {
+                    let result = db
+                        .get_pollution_ranking_over(&COUNTRIES, days, &filter)
+                        .await;
+                    let _ = data.send(DataEvent::Ranking(result));
+                });
+            },
+            Action::LoadLocalities { country } => {
+                tokio::spawn(async move {
+                    let result = db
+                        .get_latest_measurements_by_locality(&country, &filter)
+                        .await;
+                    let _ = data.send(DataEvent::Localities { country, result });
+                });
+            },
+            Action::LoadTrend(key) => {
+                tokio::spawn(async move {
+                    let scope = AlertScope {
+                        country: Some(key.country.clone()),
+                        locality: Some(key.locality.clone()),
+                        location_id: None,
+                    };
+                    let result = db
+                        .get_scope_daily_values(&key.parameter, &scope, key.days, &filter)
+                        .await;
+                    let _ = data.send(DataEvent::Trend { key, result });
+                });
+            },
+            Action::StartImport => {
+                let Some(client) = self.client.clone() else {
+                    return;
+                };
+                let alerts = self.alerts.clone();
+                let progress = self.progress.clone();
+                let days = self.import_days;
+                tokio::spawn(async move {
+                    let result = import_from_source(&db, client.as_ref(), days, &progress).await;
+                    if result.is_ok() {
+                        match evaluate_after_import(alerts.as_deref(), &db, &filter).await {
+                            Ok(events) => {
+                                for event in events {
+                                    let level = match event.state {
+                                        AlertState::Firing => NoticeLevel::Warning,
+                                        AlertState::Resolved => NoticeLevel::Info,
+                                    };
+                                    progress.notice(level, event.summary());
+                                }
+                            },
+                            Err(e) => progress.notice(NoticeLevel::Warning, e.to_string()),
+                        }
+                    }
+                    let _ = data.send(DataEvent::ImportFinished(result));
+                });
+            },
+            Action::Quit => {},
+        }
+    }
+}
+
+/// Runs the dashboard until the user quits, restoring the terminal afterwards (also on
+/// panics).
+///
+/// # Errors
+///
+/// Returns `AppError::Io` if the terminal cannot be drawn or read. Failing queries and
+/// imports are shown in the log pane instead.
+pub async fn run(
+    db: Database,
+    client: Option<OpenAQClient>,
+    alerts: Option<AlertEngine>,
+    filter: MeasurementFilter,
+    import_days: i64,
+) -> Result<()> {
+    let (data, data_rx) = mpsc::unbounded_channel();
+    let (progress, progress_rx) = mpsc::unbounded_channel();
+    let tasks = Tasks {
+        db,
+        filter,
+        client: client.map(Arc::new),
+        alerts: alerts.map(Arc::new),
+        import_days,
+        data,
+        progress,
+    };
+
+    info!("Starting the dashboard");
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &tasks, data_rx, progress_rx).await;
+    ratatui::restore();
+    if let Err(e) = &result {
+        error!("Dashboard stopped: {:?}", e);
+    }
+    result
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    tasks: &Tasks,
+    mut data_rx: mpsc::UnboundedReceiver<DataEvent>,
+    mut progress_rx: mpsc::UnboundedReceiver<ProgressEvent>,
+) -> Result<()> {
+    let parameters = parameter_choices(&tasks.db.get_parameters().await?);
+    let mut dashboard = Dashboard::new(tasks.client.is_some(), parameters);
+    let mut events = EventStream::new();
+    for action in dashboard.refresh() {
+        tasks.spawn(action);
+    }
+
+    loop {
+        terminal.draw(|frame| draw(frame, &dashboard))?;
+        // Both senders live in `tasks`, so the channels never close.
+        let actions = tokio::select! {
+            event = events.next() => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                    dashboard.handle_key(key)
+                },
+                // Other events (e.g. resizes) only need a redraw.
+                Some(Ok(_)) => Vec::new(),
+                Some(Err(e)) => return Err(e.into()),
+                None => return Ok(()),
+            },
+            Some(event) = data_rx.recv() => dashboard.apply(event),
+            Some(event) = progress_rx.recv() => {
+                dashboard.apply_progress(event);
+                Vec::new()
+            },
+        };
+        for action in actions {
+            match action {
+                Action::Quit => return Ok(()),
+                action => tasks.spawn(action),
+            }
+        }
+    }
+}
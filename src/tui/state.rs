@@ -0,0 +1,663 @@
+//! State of the dashboard. Key presses and the results of the background tasks update it and
+//! return the `Action`s to run next; it never touches the terminal or the database itself.
+
+use crate::cli::{get_country_name_map, NoticeLevel, ProgressEvent, COUNTRIES};
+use crate::error::Result;
+use crate::models::{CityLatestMeasurements, PollutionRanking, ScopeDailyValue};
+use crate::report::{locality_table, LocalityColumn, LocalityRow};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::VecDeque;
+
+/// Periods (in days) the `d` key cycles through.
+pub const PERIODS: [i64; 3] = [7, 30, 90];
+
+/// Maximum number of lines kept in the log pane.
+const LOG_CAPACITY: usize = 200;
+
+/// Pane receiving the arrow keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Countries,
+    Localities,
+}
+
+/// Identifies a trend, so results of outdated requests can be discarded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrendKey {
+    pub country: String,
+    pub locality: String,
+    pub parameter: String,
+    pub days: i64,
+}
+
+/// Work requested by the dashboard, run in the background by the event loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Rank the countries over the last `days` days.
+    LoadRanking {
+        days: i64,
+    },
+    /// Load the latest values per locality of a country.
+    LoadLocalities {
+        country: String,
+    },
+    /// Load the daily averages of a locality.
+    LoadTrend(TrendKey),
+    /// Import the latest data from OpenAQ.
+    StartImport,
+    Quit,
+}
+
+/// Result of a background task.
+#[derive(Debug)]
+pub enum DataEvent {
+    Ranking(Result<Vec<PollutionRanking>>),
+    Localities {
+        country: String,
+        result: Result<Vec<CityLatestMeasurements>>,
+    },
+    /// Daily averages of the trend, newest first.
+    Trend {
+        key: TrendKey,
+        result: Result<Vec<ScopeDailyValue>>,
+    },
+    /// The import finished, with the number of measurements fetched.
+    ImportFinished(Result<usize>),
+}
+
+/// A country in the ranking pane; `ranking` is `None` without PM data in the period.
+#[derive(Debug, Clone)]
+pub struct CountryRow {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub ranking: Option<PollutionRanking>,
+}
+
+/// Progress of the running import.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportProgress {
+    pub stage: String,
+    pub position: u64,
+    /// Number of steps of the stage, `None` if unknown.
+    pub len: Option<u64>,
+    pub message: String,
+}
+
+impl ImportProgress {
+    /// Completed share of the stage in `[0, 1]`, `None` for stages of unknown length.
+    pub fn ratio(&self) -> Option<f64> {
+        self.len
+            .filter(|len| *len > 0)
+            .map(|len| (self.position as f64 / len as f64).min(1.0))
+    }
+}
+
+/// A line of the log pane.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    pub level: NoticeLevel,
+    pub message: String,
+}
+
+/// Everything shown by the dashboard.
+#[derive(Debug)]
+pub struct Dashboard {
+    /// Countries by descending pollution index, followed by the countries without data.
+    pub countries: Vec<CountryRow>,
+    pub selected_country: usize,
+    pub locality_columns: Vec<LocalityColumn>,
+    pub localities: Vec<LocalityRow>,
+    pub selected_locality: usize,
+    /// Parameters the trend cycles through, see `parameter_choices`.
+    pub parameters: Vec<String>,
+    /// Index of the trend parameter in `parameters`.
+    pub parameter: usize,
+    /// Period of the ranking and the trend, one of `PERIODS`.
+    pub days: i64,
+    /// Daily averages of the trend, oldest first, with their unit.
+    pub trend: Vec<ScopeDailyValue>,
+    pub focus: Focus,
+    /// Progress of the running import, `None` if no import runs.
+    pub import: Option<ImportProgress>,
+    /// Whether imports are possible (an OpenAQ API key is set).
+    pub can_import: bool,
+    pub log: VecDeque<LogLine>,
+}
+
+impl Dashboard {
+    pub fn new(can_import: bool, parameters: Vec<String>) -> Self {
+        let names = get_country_name_map();
+        Self {
+            countries: COUNTRIES
+                .iter()
+                .map(|code| CountryRow {
+                    code,
+                    name: names.get(code).copied().unwrap_or(code),
+                    ranking: None,
+                })
+                .collect(),
+            selected_country: 0,
+            locality_columns: Vec::new(),
+            localities: Vec::new(),
+            selected_locality: 0,
+            parameters,
+            parameter: 0,
+            days: PERIODS[0],
+            trend: Vec::new(),
+            focus: Focus::Countries,
+            import: None,
+            can_import,
+            log: VecDeque::new(),
+        }
+    }
+
+    /// Loads the ranking and the localities of the selected country.
+    pub fn refresh(&self) -> Vec<Action> {
+        let mut actions = vec![Action::LoadRanking { days: self.days }];
+        actions.extend(self.load_localities());
+        actions
+    }
+
+    pub fn selected_country(&self) -> Option<&CountryRow> {
+        self.countries.get(self.selected_country)
+    }
+
+    pub fn selected_locality(&self) -> Option<&LocalityRow> {
+        self.localities.get(self.selected_locality)
+    }
+
+    pub fn parameter(&self) -> &str {
+        &self.parameters[self.parameter]
+    }
+
+    /// Key of the trend of the selected locality, `None` without localities.
+    pub fn trend_key(&self) -> Option<TrendKey> {
+        Some(TrendKey {
+            country: self.selected_country()?.code.to_string(),
+            locality: self.selected_locality()?.locality.clone(),
+            parameter: self.parameter().to_string(),
+            days: self.days,
+        })
+    }
+
+    /// Adds a line to the log pane, dropping the oldest line when full.
+    pub fn log(&mut self, level: NoticeLevel, message: impl Into<String>) {
+        if self.log.len() == LOG_CAPACITY {
+            self.log.pop_front();
+        }
+        self.log.push_back(LogLine {
+            level,
+            message: message.into(),
+        });
+    }
+
+    fn load_localities(&self) -> Option<Action> {
+        self.selected_country().map(|c| Action::LoadLocalities {
+            country: c.code.to_string(),
+        })
+    }
+
+    fn load_trend(&self) -> Option<Action> {
+        self.trend_key().map(Action::LoadTrend)
+    }
+
+    /// Handles a key press.
+    ///
+    /// * `↑`/`↓` (or `k`/`j`) move the selection of the focused pane, `Tab` switches panes.
+    /// * `[`/`]` (or `p`) select the previous/next parameter of the trend.
+    /// * `d` cycles the period through `PERIODS`.
+    /// * `i` starts an import, `r` reloads everything, `q`/`Esc`/`Ctrl+C` quit.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Vec<Action> {
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                vec![Action::Quit]
+            },
+            KeyCode::Char('q') | KeyCode::Esc => vec![Action::Quit],
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Countries => Focus::Localities,
+                    Focus::Localities => Focus::Countries,
+                };
+                Vec::new()
+            },
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Char(']') | KeyCode::Char('p') => {
+                self.parameter = (self.parameter + 1) % self.parameters.len();
+                self.trend.clear();
+                self.load_trend().into_iter().collect()
+            },
+            KeyCode::Char('[') => {
+                self.parameter =
+                    (self.parameter + self.parameters.len() - 1) % self.parameters.len();
+                self.trend.clear();
+                self.load_trend().into_iter().collect()
+            },
+            KeyCode::Char('d') => {
+                let next = PERIODS
+                    .iter()
+                    .position(|d| *d == self.days)
+                    .map_or(0, |i| i + 1);
+                self.days = PERIODS[next % PERIODS.len()];
+                self.trend.clear();
+                let mut actions = vec![Action::LoadRanking { days: self.days }];
+                actions.extend(self.load_trend());
+                actions
+            },
+            KeyCode::Char('r') => self.refresh(),
+            KeyCode::Char('i') => self.start_import(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) -> Vec<Action> {
+        let (selected, len) = match self.focus {
+            Focus::Countries => (&mut self.selected_country, self.countries.len()),
+            Focus::Localities => (&mut self.selected_locality, self.localities.len()),
+        };
+        if len == 0 {
+            return Vec::new();
+        }
+        let next = selected.saturating_add_signed(delta).min(len - 1);
+        if next == *selected {
+            return Vec::new();
+        }
+        *selected = next;
+        self.trend.clear();
+        match self.focus {
+            Focus::Countries => {
+                self.localities.clear();
+                self.locality_columns.clear();
+                self.selected_locality = 0;
+                self.load_localities().into_iter().collect()
+            },
+            Focus::Localities => self.load_trend().into_iter().collect(),
+        }
+    }
+
+    fn start_import(&mut self) -> Vec<Action> {
+        if !self.can_import {
+            self.log(
+                NoticeLevel::Error,
+                "Importing needs an OpenAQ API key: set OPENAQ_KEY and restart the dashboard.",
+            );
+            return Vec::new();
+        }
+        if self.import.is_some() {
+            self.log(NoticeLevel::Warning, "An import is already running.");
+            return Vec::new();
+        }
+        self.import = Some(ImportProgress {
+            stage: "Starting import".to_string(),
+            ..ImportProgress::default()
+        });
+        self.log(NoticeLevel::Info, "Import started.");
+        vec![Action::StartImport]
+    }
+
+    /// Applies the result of a background task. Results for a country, locality or period
+    /// that is no longer selected are discarded.
+    pub fn apply(&mut self, event: DataEvent) -> Vec<Action> {
+        match event {
+            DataEvent::Ranking(Ok(ranking)) => {
+                let selected = self.selected_country().map(|c| c.code);
+                for row in &mut self.countries {
+                    row.ranking = ranking.iter().find(|r| r.country == row.code).cloned();
+                }
+                // Stable sort: countries without data keep the order of `COUNTRIES`.
+                self.countries.sort_by(|a, b| {
+                    let index = |row: &CountryRow| row.ranking.as_ref().map(|r| r.pollution_index);
+                    index(b)
+                        .partial_cmp(&index(a))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                if let Some(selected) = selected {
+                    self.selected_country = self
+                        .countries
+                        .iter()
+                        .position(|c| c.code == selected)
+                        .unwrap_or(0);
+                }
+                Vec::new()
+            },
+            DataEvent::Ranking(Err(e)) => {
+                self.log(
+                    NoticeLevel::Error,
+                    format!("Loading the ranking failed: {}", e),
+                );
+                Vec::new()
+            },
+            DataEvent::Localities { country, result } => {
+                if self.selected_country().map(|c| c.code) != Some(country.as_str()) {
+                    return Vec::new();
+                }
+                match result {
+                    Ok(localities) => {
+                        let selected = self.selected_locality().map(|l| l.locality.clone());
+                        let (columns, rows) = locality_table(&localities);
+                        self.locality_columns = columns;
+                        self.localities = rows;
+                        self.selected_locality = selected
+                            .and_then(|s| self.localities.iter().position(|l| l.locality == s))
+                            .unwrap_or(0);
+                        self.load_trend().into_iter().collect()
+                    },
+                    Err(e) => {
+                        self.log(
+                            NoticeLevel::Error,
+                            format!("Loading the localities of {} failed: {}", country, e),
+                        );
+                        Vec::new()
+                    },
+                }
+            },
+            DataEvent::Trend { key, result } => {
+                if self.trend_key().as_ref() != Some(&key) {
+                    return Vec::new();
+                }
+                match result {
+                    Ok(mut values) => {
+                        values.reverse();
+                        self.trend = values;
+                    },
+                    Err(e) => self.log(
+                        NoticeLevel::Error,
+                        format!("Loading the {} trend failed: {}", key.parameter, e),
+                    ),
+                }
+                Vec::new()
+            },
+            DataEvent::ImportFinished(result) => {
+                self.import = None;
+                match result {
+                    Ok(count) => {
+                        self.log(
+                            NoticeLevel::Info,
+                            format!("Import finished: {} measurements fetched.", count),
+                        );
+                        self.refresh()
+                    },
+                    Err(e) => {
+                        self.log(NoticeLevel::Error, format!("Import failed: {}", e));
+                        Vec::new()
+                    },
+                }
+            },
+        }
+    }
+
+    /// Shows the progress of the running import.
+    pub fn apply_progress(&mut self, event: ProgressEvent) {
+        match event {
+            ProgressEvent::Notice { level, message } => self.log(level, message),
+            ProgressEvent::Finished(message) => self.log(NoticeLevel::Info, message),
+            event => {
+                let Some(import) = self.import.as_mut() else {
+                    return;
+                };
+                match event {
+                    ProgressEvent::Stage { name, len } => {
+                        *import = ImportProgress {
+                            stage: name,
+                            len,
+                            ..ImportProgress::default()
+                        };
+                    },
+                    ProgressEvent::Message(message) => import.message = message,
+                    ProgressEvent::Advance(n) => import.position += n,
+                    ProgressEvent::Notice { .. } | ProgressEvent::Finished(_) => {},
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parameter_choices;
+    use crate::error::AppError;
+    use crate::models::ParameterValue;
+    use chrono::{TimeZone, Utc};
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn ranking(country: &str, pollution_index: f64) -> PollutionRanking {
+        PollutionRanking {
+            country: country.to_string(),
+            pollution_index,
+            pm25_avg: Some(pollution_index / 2.0),
+            pm10_avg: None,
+            excluded_days: 0,
+            unit_conflicts: Vec::new(),
+        }
+    }
+
+    fn locality(name: &str, pm25: f64) -> CityLatestMeasurements {
+        CityLatestMeasurements {
+            locality: name.to_string(),
+            values: vec![ParameterValue {
+                parameter: "pm25".to_string(),
+                display_name: Some("PM2.5".to_string()),
+                value: Some(pm25),
+                unit: Some("µg/m³".to_string()),
+            }],
+            last_updated: Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(),
+        }
+    }
+
+    fn daily(day: u32, value: f64) -> ScopeDailyValue {
+        ScopeDailyValue {
+            day: Utc.with_ymd_and_hms(2024, 3, day, 0, 0, 0).unwrap(),
+            value,
+            unit: Some("µg/m³".to_string()),
+        }
+    }
+
+    /// A dashboard showing the localities of the Netherlands.
+    fn loaded() -> Dashboard {
+        let mut dashboard = Dashboard::new(true, parameter_choices(&[]));
+        let actions = dashboard.apply(DataEvent::Localities {
+            country: "NL".to_string(),
+            result: Ok(vec![locality("Amsterdam", 12.0), locality("Utrecht", 20.0)]),
+        });
+        assert_eq!(
+            actions,
+            [Action::LoadTrend(TrendKey {
+                country: "NL".to_string(),
+                locality: "Amsterdam".to_string(),
+                parameter: "pm25".to_string(),
+                days: 7,
+            })]
+        );
+        dashboard
+    }
+
+    #[test]
+    fn test_refresh_loads_ranking_and_localities() {
+        let dashboard = Dashboard::new(false, parameter_choices(&[]));
+        assert_eq!(
+            dashboard.refresh(),
+            [
+                Action::LoadRanking { days: 7 },
+                Action::LoadLocalities {
+                    country: "NL".to_string()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ranking_orders_countries_and_keeps_selection() {
+        let mut dashboard = Dashboard::new(true, parameter_choices(&[]));
+        dashboard.handle_key(key(KeyCode::Down)); // DE
+        dashboard.apply(DataEvent::Ranking(Ok(vec![
+            ranking("PK", 90.0),
+            ranking("DE", 30.0),
+        ])));
+
+        let codes: Vec<_> = dashboard.countries.iter().map(|c| c.code).collect();
+        assert_eq!(codes, ["PK", "DE", "NL", "FR", "GR", "ES"]);
+        assert_eq!(dashboard.selected_country().unwrap().code, "DE");
+        assert!(dashboard.countries[2].ranking.is_none());
+    }
+
+    #[test]
+    fn test_selecting_a_country_loads_its_localities() {
+        let mut dashboard = loaded();
+        let actions = dashboard.handle_key(key(KeyCode::Down));
+        assert_eq!(
+            actions,
+            [Action::LoadLocalities {
+                country: "DE".to_string()
+            }]
+        );
+        assert!(dashboard.localities.is_empty());
+
+        // The selection stops at the ends of the list.
+        dashboard.handle_key(key(KeyCode::Up));
+        assert!(dashboard.handle_key(key(KeyCode::Up)).is_empty());
+        assert_eq!(dashboard.selected_country, 0);
+    }
+
+    #[test]
+    fn test_outdated_results_are_discarded() {
+        let mut dashboard = loaded();
+        dashboard.handle_key(key(KeyCode::Down)); // DE
+
+        let actions = dashboard.apply(DataEvent::Localities {
+            country: "NL".to_string(),
+            result: Ok(vec![locality("Utrecht", 20.0)]),
+        });
+        assert!(actions.is_empty());
+        assert!(dashboard.localities.is_empty());
+
+        let stale = TrendKey {
+            country: "NL".to_string(),
+            locality: "Amsterdam".to_string(),
+            parameter: "pm25".to_string(),
+            days: 7,
+        };
+        dashboard.apply(DataEvent::Trend {
+            key: stale,
+            result: Ok(vec![daily(1, 10.0)]),
+        });
+        assert!(dashboard.trend.is_empty());
+    }
+
+    #[test]
+    fn test_locality_selection_and_parameter_load_the_trend() {
+        let mut dashboard = loaded();
+        dashboard.handle_key(key(KeyCode::Tab));
+        assert_eq!(dashboard.focus, Focus::Localities);
+
+        let actions = dashboard.handle_key(key(KeyCode::Down));
+        let Action::LoadTrend(trend) = &actions[0] else {
+            panic!("Expected a trend to load, got {:?}", actions);
+        };
+        assert_eq!(trend.locality, "Utrecht");
+
+        let actions = dashboard.handle_key(key(KeyCode::Char(']')));
+        let Action::LoadTrend(trend) = &actions[0] else {
+            panic!("Expected a trend to load, got {:?}", actions);
+        };
+        assert_eq!(trend.parameter, "pm10");
+
+        let actions = dashboard.handle_key(key(KeyCode::Char('[')));
+        let key_now = dashboard.trend_key().unwrap();
+        assert_eq!(actions, [Action::LoadTrend(key_now.clone())]);
+        assert_eq!(key_now.parameter, "pm25");
+
+        // Values arrive newest first and are shown oldest first.
+        dashboard.apply(DataEvent::Trend {
+            key: key_now,
+            result: Ok(vec![daily(3, 30.0), daily(2, 20.0)]),
+        });
+        assert_eq!(dashboard.trend, [daily(2, 20.0), daily(3, 30.0)]);
+    }
+
+    #[test]
+    fn test_period_cycles_and_reloads() {
+        let mut dashboard = loaded();
+        let actions = dashboard.handle_key(key(KeyCode::Char('d')));
+        assert_eq!(dashboard.days, 30);
+        assert_eq!(actions[0], Action::LoadRanking { days: 30 });
+        assert_eq!(actions.len(), 2);
+
+        dashboard.handle_key(key(KeyCode::Char('d')));
+        dashboard.handle_key(key(KeyCode::Char('d')));
+        assert_eq!(dashboard.days, 7);
+    }
+
+    #[test]
+    fn test_import_progress() {
+        let mut dashboard = loaded();
+        assert_eq!(
+            dashboard.handle_key(key(KeyCode::Char('i'))),
+            [Action::StartImport]
+        );
+        // Only one import runs at a time.
+        assert!(dashboard.handle_key(key(KeyCode::Char('i'))).is_empty());
+
+        dashboard.apply_progress(ProgressEvent::Stage {
+            name: "Fetching measurements".to_string(),
+            len: Some(4),
+        });
+        dashboard.apply_progress(ProgressEvent::Advance(1));
+        dashboard.apply_progress(ProgressEvent::Message("Sensor 1".to_string()));
+        dashboard.apply_progress(ProgressEvent::Notice {
+            level: NoticeLevel::Warning,
+            message: "Sensor 2 failed".to_string(),
+        });
+        let import = dashboard.import.clone().unwrap();
+        assert_eq!(import.stage, "Fetching measurements");
+        assert_eq!(import.ratio(), Some(0.25));
+        assert_eq!(import.message, "Sensor 1");
+        assert_eq!(dashboard.log.back().unwrap().level, NoticeLevel::Warning);
+
+        let actions = dashboard.apply(DataEvent::ImportFinished(Ok(10)));
+        assert!(dashboard.import.is_none());
+        assert_eq!(actions, dashboard.refresh());
+
+        dashboard.handle_key(key(KeyCode::Char('i')));
+        let actions = dashboard.apply(DataEvent::ImportFinished(Err(AppError::Import(
+            "no data".to_string(),
+        ))));
+        assert!(actions.is_empty());
+        assert_eq!(dashboard.log.back().unwrap().level, NoticeLevel::Error);
+    }
+
+    #[test]
+    fn test_import_needs_an_api_key() {
+        let mut dashboard = Dashboard::new(false, parameter_choices(&[]));
+        assert!(dashboard.handle_key(key(KeyCode::Char('i'))).is_empty());
+        assert!(dashboard.import.is_none());
+        assert_eq!(dashboard.log.back().unwrap().level, NoticeLevel::Error);
+    }
+
+    #[test]
+    fn test_log_is_bounded() {
+        let mut dashboard = Dashboard::new(true, parameter_choices(&[]));
+        for i in 0..LOG_CAPACITY + 5 {
+            dashboard.log(NoticeLevel::Info, format!("line {}", i));
+        }
+        assert_eq!(dashboard.log.len(), LOG_CAPACITY);
+        assert_eq!(dashboard.log.front().unwrap().message, "line 5");
+    }
+
+    #[test]
+    fn test_quit_keys() {
+        let mut dashboard = Dashboard::new(true, parameter_choices(&[]));
+        assert_eq!(
+            dashboard.handle_key(key(KeyCode::Char('q'))),
+            [Action::Quit]
+        );
+        assert_eq!(dashboard.handle_key(key(KeyCode::Esc)), [Action::Quit]);
+        assert_eq!(
+            dashboard.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            [Action::Quit]
+        );
+    }
+}
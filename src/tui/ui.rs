@@ -0,0 +1,352 @@
+//! Draws the dashboard: the country ranking, the locality table and the trend chart of the
+//! selected locality above the import progress, the log and the key bindings.
+
+use super::{Dashboard, Focus, ImportProgress};
+use crate::cli::NoticeLevel;
+use crate::models::daily_guideline;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::symbols;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    Axis, Block, Cell, Chart, Dataset, Gauge, GraphType, Paragraph, Row, Table, TableState,
+};
+use ratatui::Frame;
+
+const HELP: &str =
+    " ↑↓ select  Tab switch pane  [ ] parameter  d period  i import  r refresh  q quit";
+
+/// Draws the whole dashboard on the frame.
+pub fn draw(frame: &mut Frame, dashboard: &Dashboard) {
+    let [main, progress, log, help] = Layout::vertical([
+        Constraint::Min(12),
+        Constraint::Length(3),
+        Constraint::Length(8),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [countries, right] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+    let [localities, trend] =
+        Layout::vertical([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(right);
+
+    draw_countries(frame, dashboard, countries);
+    draw_localities(frame, dashboard, localities);
+    draw_trend(frame, dashboard, trend);
+    draw_progress(frame, dashboard, progress);
+    draw_log(frame, dashboard, log);
+    frame.render_widget(Paragraph::new(HELP).dark_gray(), help);
+}
+
+/// A bordered pane, highlighted when it has the focus.
+fn pane(title: String, focused: bool) -> Block<'static> {
+    let block = Block::bordered().title(title);
+    if focused {
+        block.border_style(Style::new().cyan())
+    } else {
+        block
+    }
+}
+
+fn format_value(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{:.1}", v))
+}
+
+fn highlight() -> Style {
+    Style::new().add_modifier(Modifier::REVERSED)
+}
+
+fn draw_countries(frame: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    let rows = dashboard.countries.iter().enumerate().map(|(i, country)| {
+        let ranking = country.ranking.as_ref();
+        Row::new([
+            Cell::from(ranking.map_or_else(String::new, |_| (i + 1).to_string())),
+            Cell::from(format!("{} ({})", country.name, country.code)),
+            Cell::from(format_value(ranking.map(|r| r.pollution_index))),
+            Cell::from(format_value(ranking.and_then(|r| r.pm25_avg))),
+            Cell::from(format_value(ranking.and_then(|r| r.pm10_avg))),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(2),
+            Constraint::Fill(1),
+            Constraint::Length(6),
+            Constraint::Length(6),
+            Constraint::Length(6),
+        ],
+    )
+    .header(
+        Row::new(["#", "Country", "Index", "PM2.5", "PM10"])
+            .bold()
+            .green(),
+    )
+    .row_highlight_style(highlight())
+    .block(pane(
+        format!(" Ranking, last {} days ", dashboard.days),
+        dashboard.focus == Focus::Countries,
+    ));
+    let mut state = TableState::default().with_selected(Some(dashboard.selected_country));
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+fn draw_localities(frame: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    let title = match dashboard.selected_country() {
+        Some(country) => format!(" Latest values in {} ", country.name),
+        None => " Latest values ".to_string(),
+    };
+    let block = pane(title, dashboard.focus == Focus::Localities);
+    if dashboard.localities.is_empty() {
+        frame.render_widget(
+            Paragraph::new("No localities with measurements.").block(block),
+            area,
+        );
+        return;
+    }
+
+    let header = std::iter::once("Locality".to_string())
+        .chain(dashboard.locality_columns.iter().map(|c| c.header.clone()))
+        .chain(std::iter::once("Updated (UTC)".to_string()));
+    let rows = dashboard.localities.iter().map(|row| {
+        std::iter::once(row.locality.clone())
+            .chain(row.values.iter().map(|v| format_value(*v)))
+            .chain(std::iter::once(row.last_updated.clone()))
+            .collect::<Row>()
+    });
+    let widths = std::iter::once(Constraint::Fill(2))
+        .chain(
+            dashboard
+                .locality_columns
+                .iter()
+                .map(|_| Constraint::Fill(1)),
+        )
+        .chain(std::iter::once(Constraint::Length(16)));
+    let table = Table::new(rows, widths)
+        .header(header.collect::<Row>().bold().green())
+        .row_highlight_style(highlight())
+        .block(block);
+    let mut state = TableState::default().with_selected(Some(dashboard.selected_locality));
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+fn draw_trend(frame: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    let parameter = dashboard.parameter();
+    let locality = dashboard
+        .selected_locality()
+        .map_or("-", |l| l.locality.as_str());
+    let block = Block::bordered().title(format!(
+        " {} in {}, latest {} days ",
+        parameter.to_uppercase(),
+        locality,
+        dashboard.days
+    ));
+    if dashboard.trend.is_empty() {
+        frame.render_widget(Paragraph::new("No daily values.").block(block), area);
+        return;
+    }
+
+    let points: Vec<(f64, f64)> = dashboard
+        .trend
+        .iter()
+        .map(|v| (v.day.timestamp() as f64, v.value))
+        .collect();
+    let (first, last) = (points[0].0, points[points.len() - 1].0);
+    // A single day still needs a non-empty x range.
+    let x_bounds = [first, last.max(first + 86_400.0)];
+    let unit = dashboard.trend.iter().find_map(|v| v.unit.as_deref());
+    // Guideline levels are in µg/m³, so only values in µg/m³ are compared with them.
+    let guideline = daily_guideline(parameter).filter(|_| unit == Some("µg/m³"));
+    let guideline_points: Vec<(f64, f64)> = guideline
+        .map(|level| vec![(x_bounds[0], level), (x_bounds[1], level)])
+        .unwrap_or_default();
+    let y_max = points
+        .iter()
+        .map(|p| p.1)
+        .chain(guideline)
+        .fold(0.0_f64, f64::max)
+        * 1.1;
+    let y_max = if y_max > 0.0 { y_max } else { 1.0 };
+
+    let mut datasets = vec![Dataset::default()
+        .name("Daily average")
+        .marker(symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .cyan()
+        .data(&points)];
+    if !guideline_points.is_empty() {
+        datasets.push(
+            Dataset::default()
+                .name("WHO guideline")
+                .marker(symbols::Marker::Dot)
+                .graph_type(GraphType::Line)
+                .red()
+                .data(&guideline_points),
+        );
+    }
+
+    let date = |i: usize| dashboard.trend[i].day.format("%m-%d").to_string();
+    let chart = Chart::new(datasets)
+        .block(block)
+        .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)))
+        .x_axis(
+            Axis::default()
+                .bounds(x_bounds)
+                .labels([date(0), date(dashboard.trend.len() - 1)]),
+        )
+        .y_axis(
+            Axis::default()
+                .title(unit.unwrap_or(""))
+                .bounds([0.0, y_max])
+                .labels([
+                    "0".to_string(),
+                    format!("{:.0}", y_max / 2.0),
+                    format!("{:.0}", y_max),
+                ]),
+        );
+    frame.render_widget(chart, area);
+}
+
+fn progress_label(import: &ImportProgress) -> String {
+    let mut label = import.stage.clone();
+    if let Some(len) = import.len {
+        label.push_str(&format!(" {}/{}", import.position, len));
+    }
+    if !import.message.is_empty() {
+        label.push_str(&format!(": {}", import.message));
+    }
+    label
+}
+
+fn draw_progress(frame: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    let block = Block::bordered().title(" Import ");
+    match &dashboard.import {
+        Some(import) => match import.ratio() {
+            Some(ratio) => frame.render_widget(
+                Gauge::default()
+                    .block(block)
+                    .gauge_style(Style::new().green())
+                    .ratio(ratio)
+                    .label(progress_label(import)),
+                area,
+            ),
+            None => frame.render_widget(Paragraph::new(progress_label(import)).block(block), area),
+        },
+        None => {
+            let text = if dashboard.can_import {
+                "No import running. Press i to import the latest data."
+            } else {
+                "Set OPENAQ_KEY to import data from the dashboard."
+            };
+            frame.render_widget(Paragraph::new(text).dark_gray().block(block), area);
+        },
+    }
+}
+
+fn draw_log(frame: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    let visible = area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = dashboard
+        .log
+        .iter()
+        .skip(dashboard.log.len().saturating_sub(visible))
+        .map(|line| {
+            let (prefix, color) = match line.level {
+                NoticeLevel::Info => ("", Color::Reset),
+                NoticeLevel::Warning => ("Warning: ", Color::Yellow),
+                NoticeLevel::Error => ("Error: ", Color::Red),
+            };
+            Line::from(vec![Span::raw(prefix).fg(color), Span::raw(&line.message)])
+        })
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Log ")),
+        area,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{parameter_choices, ProgressEvent};
+    use crate::models::{PollutionRanking, ScopeDailyValue};
+    use crate::report::{LocalityColumn, LocalityRow};
+    use chrono::{TimeZone, Utc};
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    /// Renders the dashboard and returns the text of the screen.
+    fn render(dashboard: &Dashboard) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        terminal.draw(|frame| draw(frame, dashboard)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content()
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn dashboard() -> Dashboard {
+        let mut dashboard = Dashboard::new(true, parameter_choices(&[]));
+        dashboard.countries[0].ranking = Some(PollutionRanking {
+            country: "NL".to_string(),
+            pollution_index: 42.5,
+            pm25_avg: Some(12.0),
+            pm10_avg: Some(24.5),
+            excluded_days: 0,
+            unit_conflicts: Vec::new(),
+        });
+        dashboard.locality_columns = vec![LocalityColumn {
+            parameter: "pm25".to_string(),
+            header: "PM2.5 (µg/m³)".to_string(),
+        }];
+        dashboard.localities = vec![LocalityRow {
+            locality: "Utrecht".to_string(),
+            values: vec![Some(17.25)],
+            last_updated: "2024-03-03 12:00".to_string(),
+        }];
+        dashboard
+    }
+
+    #[test]
+    fn test_draw_panes() {
+        let screen = render(&dashboard());
+        assert!(screen.contains("Ranking, last 7 days"));
+        assert!(screen.contains("Netherlands (NL)"));
+        assert!(screen.contains("42.5"));
+        assert!(screen.contains("Latest values in Netherlands"));
+        assert!(screen.contains("Utrecht"));
+        assert!(screen.contains("17.2"));
+        assert!(screen.contains("PM25 in Utrecht, latest 7 days"));
+        assert!(screen.contains("No daily values."));
+        assert!(screen.contains("Press i to import"));
+        assert!(screen.contains("q quit"));
+    }
+
+    #[test]
+    fn test_draw_trend_and_progress() {
+        let mut dashboard = dashboard();
+        dashboard.trend = (1..=3)
+            .map(|day| ScopeDailyValue {
+                day: Utc.with_ymd_and_hms(2024, 3, day, 0, 0, 0).unwrap(),
+                value: 10.0 * day as f64,
+                unit: Some("µg/m³".to_string()),
+            })
+            .collect();
+        dashboard.import = Some(ImportProgress::default());
+        dashboard.apply_progress(ProgressEvent::Stage {
+            name: "Fetching measurements".to_string(),
+            len: Some(8),
+        });
+        dashboard.apply_progress(ProgressEvent::Advance(2));
+        dashboard.log(NoticeLevel::Error, "Sensor 7 failed");
+
+        let screen = render(&dashboard);
+        assert!(screen.contains("03-01"));
+        assert!(screen.contains("03-03"));
+        assert!(screen.contains("WHO guideline"));
+        assert!(screen.contains("Fetching measurements 2/8"));
+        assert!(screen.contains("Error: Sensor 7 failed"));
+    }
+}